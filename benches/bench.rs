@@ -46,8 +46,8 @@ fn bench_anyhow(c: &mut Criterion) {
     }
 
     let mut group = c.benchmark_group("anyhow");
-    group.bench_function("Rust", |b| b.iter(|| rust()));
-    group.bench_function("Lithium", |b| b.iter(|| lithium()));
+    group.bench_function("Rust", |b| b.iter(rust));
+    group.bench_function("Lithium", |b| b.iter(lithium));
     group.finish();
 }
 
@@ -95,8 +95,8 @@ fn bench_simple(c: &mut Criterion) {
     }
 
     let mut group = c.benchmark_group("simple");
-    group.bench_function("Rust", |b| b.iter(|| rust()));
-    group.bench_function("Lithium", |b| b.iter(|| lithium()));
+    group.bench_function("Rust", |b| b.iter(rust));
+    group.bench_function("Lithium", |b| b.iter(lithium));
     group.finish();
 }
 
@@ -13,41 +13,135 @@ fn main() {
     let is_miri = has_cfg("miri");
     let is_tree_borrows =
         std::env::var("MIRIFLAGS").is_ok_and(|flags| flags.contains("-Zmiri-tree-borrows"));
-    if is_miri && !is_tree_borrows {
+    let is_strict_provenance = std::env::var_os("CARGO_FEATURE_STRICT_PROVENANCE").is_some();
+    if is_miri && !is_tree_borrows && !is_strict_provenance {
         println!("cargo::rustc-cfg=feature=\"sound-under-stacked-borrows\"");
     }
 
     let ac = autocfg::new();
     let is_nightly = version_meta().unwrap().channel == Channel::Nightly;
 
-    println!("cargo::rerun-if-env-changed=LITHIUM_THREAD_LOCAL");
-    if let Ok(thread_local) = std::env::var("LITHIUM_THREAD_LOCAL") {
-        println!("cargo::rustc-cfg=thread_local=\"{thread_local}\"");
-    } else if is_nightly && has_cfg("target_thread_local") {
-        println!("cargo::rustc-cfg=thread_local=\"attribute\"");
-    } else if ac
+    let has_std_thread_local = ac
         .probe_raw(
             r"
         #![no_std]
         extern crate std;
         std::thread_local! {
-            static FOO: () = ();
+            static FOO: u8 = const { 0 };
         }
     ",
         )
-        .is_ok()
-    {
+        .is_ok();
+    if has_std_thread_local {
+        // Even if the state is not stored in `std::thread_local!`, it can be used to register
+        // destructors that run on thread exit.
+        println!("cargo::rustc-cfg=thread_dtor=\"std\"");
+    }
+
+    // Wasm modules built with the `atomics` feature use shared memory, which lets several threads
+    // run the module concurrently, even on targets that don't otherwise know about threads.
+    let has_shared_memory = cfg("target_feature")
+        .split(',')
+        .any(|feature| feature == "atomics");
+
+    println!("cargo::rerun-if-env-changed=LITHIUM_THREAD_LOCAL");
+    if let Ok(thread_local) = std::env::var("LITHIUM_THREAD_LOCAL") {
+        println!("cargo::rustc-cfg=thread_local=\"{thread_local}\"");
+    } else if is_nightly && has_cfg("target_thread_local") {
+        println!("cargo::rustc-cfg=thread_local=\"attribute\"");
+    } else if has_std_thread_local {
         println!("cargo::rustc-cfg=thread_local=\"std\"");
+    } else if cfg("target_os") == "none" && !has_shared_memory {
+        // Bare-metal targets have a single thread of execution, so a global suffices. This is not
+        // the case for Wasm with shared memory, where several threads, e.g. web workers, can run
+        // the same module, and a global would silently be shared between them.
+        println!("cargo::rustc-cfg=thread_local=\"static\"");
     } else {
         println!("cargo::rustc-cfg=thread_local=\"unimplemented\"");
     }
 
+    // Capacities too small to hold an exception are rejected by `stacked_exceptions`, which knows
+    // the layout of exception objects.
+    println!("cargo::rerun-if-env-changed=LITHIUM_STACK_CAPACITY");
+    let stack_capacity = std::env::var("LITHIUM_STACK_CAPACITY").map_or(4096, |capacity| {
+        capacity
+            .parse::<usize>()
+            .expect("LITHIUM_STACK_CAPACITY must be a number of bytes")
+    });
+    println!("cargo::rustc-env=LITHIUM_STACK_CAPACITY={stack_capacity}");
+
+    println!("cargo::rerun-if-env-changed=LITHIUM_INLINE_THRESHOLD");
+    let inline_threshold =
+        std::env::var("LITHIUM_INLINE_THRESHOLD").map_or(stack_capacity, |threshold| {
+            threshold
+                .parse::<usize>()
+                .expect("LITHIUM_INLINE_THRESHOLD must be a number of bytes")
+        });
+    assert!(
+        inline_threshold <= stack_capacity,
+        "LITHIUM_INLINE_THRESHOLD must not exceed LITHIUM_STACK_CAPACITY",
+    );
+    println!("cargo::rustc-env=LITHIUM_INLINE_THRESHOLD={inline_threshold}");
+
+    println!("cargo::rerun-if-env-changed=LITHIUM_NO_HEAP");
+    let no_heap = std::env::var("LITHIUM_NO_HEAP").is_ok_and(|no_heap| match &*no_heap {
+        "0" => false,
+        "1" => true,
+        _ => panic!("LITHIUM_NO_HEAP must be 0 or 1"),
+    });
+    if no_heap {
+        println!("cargo::rustc-cfg=no_heap");
+    }
+
+    println!("cargo::rerun-if-env-changed=LITHIUM_MAX_RETHROWS");
+    // 0, the default, disables the limit.
+    let max_rethrows = std::env::var("LITHIUM_MAX_RETHROWS").map_or(0, |max| {
+        max.parse::<usize>()
+            .expect("LITHIUM_MAX_RETHROWS must be a number")
+    });
+    println!("cargo::rustc-env=LITHIUM_MAX_RETHROWS={max_rethrows}");
+
+    // The inlining strategy. Both features flip the same attributes in opposite directions.
+    // Features are unified across dependents, so both can be enabled at once, in which case
+    // `opt-size` wins: code size limits can be hard, while speed is just a preference.
+    if std::env::var_os("CARGO_FEATURE_OPT_SIZE").is_some() {
+        println!("cargo::rustc-cfg=opt=\"size\"");
+    } else if std::env::var_os("CARGO_FEATURE_OPT_SPEED").is_some() {
+        println!("cargo::rustc-cfg=opt=\"speed\"");
+    }
+
+    // Features are unified across dependents, so several backends can be forced at once. The panic
+    // backend wins, as it works on every target with `std`; the rest are ordered arbitrarily.
+    let forced_backend = ["panic", "itanium", "seh", "emscripten"]
+        .into_iter()
+        .find(|backend| {
+            std::env::var_os(format!(
+                "CARGO_FEATURE_FORCE_BACKEND_{}",
+                backend.to_uppercase()
+            ))
+            .is_some()
+        });
+
     println!("cargo::rerun-if-env-changed=LITHIUM_BACKEND");
     if let Ok(backend) = std::env::var("LITHIUM_BACKEND") {
         println!("cargo::rustc-cfg=backend=\"{backend}\"");
+    } else if let Some(backend) = forced_backend {
+        println!("cargo::rustc-cfg=backend=\"{backend}\"");
     } else if is_nightly && cfg("target_os") == "emscripten" && !has_cfg("emscripten_wasm_eh") {
+        // With `-fwasm-exceptions`, Emscripten uses native Wasm exceptions with Itanium-style
+        // exception objects, which the Itanium backend already throws like on other Wasm targets,
+        // so there is no dedicated backend for that mode; it's selected by the `wasm32` check
+        // below. Emscripten requires all objects linked into a program to use the same exception
+        // handling mode, so a single backend never has to handle a mix of both.
         println!("cargo::rustc-cfg=backend=\"emscripten\"");
+    } else if is_nightly && cfg("target_os") == "none" && cfg("panic") == "unwind" {
+        // Firmware built with `panic = "unwind"` has to link an Itanium-style unwinder, e.g. from
+        // the `unwinding` crate.
+        println!("cargo::rustc-cfg=backend=\"itanium\"");
     } else if is_nightly
+        // AIX uses its own exception ABI on top of the Itanium one, which we can't test. The panic
+        // backend is slower, but known to work wherever Rust panics do.
+        && cfg("target_os") != "aix"
         && (has_cfg("unix")
             || (has_cfg("windows") && cfg("target_env") == "gnu")
             || cfg("target_arch") == "wasm32")
@@ -60,7 +154,7 @@ fn main() {
             r"
         #![no_std]
         extern crate std;
-        use std::panic::{catch_unwind, resume_unwind};
+        pub use std::panic::{catch_unwind, resume_unwind};
         ",
         )
         .is_ok()
@@ -70,13 +164,72 @@ fn main() {
         println!("cargo::rustc-cfg=backend=\"unimplemented\"");
     }
 
+    // 32-bit ARM uses the ARM EH ABI variant of unwinding, except on Apple platforms.
+    if cfg("target_arch") == "arm" && cfg("target_vendor") != "apple" {
+        println!("cargo::rustc-cfg=ehabi");
+    }
+
+    // Rethrowing by resuming phase 2 relies on the layout of the private words, which is only
+    // uniform on 64-bit targets with two private words. Windows uses SEH-based unwinding even with
+    // GNU toolchains, where `_Unwind_Resume` has different semantics.
+    if matches!(&*cfg("target_arch"), "x86_64" | "aarch64")
+        && cfg("target_pointer_width") == "64"
+        && !has_cfg("windows")
+    {
+        println!("cargo::rustc-cfg=resume_rethrow");
+    }
+
+    // The bookkeeping of catch frames and in-flight handles is kept in release builds with the
+    // `checked` feature, which needs it for `try_throw`.
+    if has_cfg("debug_assertions") || std::env::var_os("CARGO_FEATURE_CHECKED").is_some() {
+        println!("cargo::rustc-cfg=checks");
+    }
+
+    // `cfg(sanitize)` is unstable, but Cargo forwards the sanitizers enabled with `-Zsanitizer`.
+    if cfg("sanitize")
+        .split(',')
+        .any(|sanitizer| sanitizer == "address")
+    {
+        println!("cargo::rustc-cfg=asan");
+    }
+
+    if ac
+        .probe_raw(
+            r"
+        #![no_std]
+        extern crate std;
+        pub use std::thread::{spawn, JoinHandle};
+        ",
+        )
+        .is_ok()
+    {
+        println!("cargo::rustc-cfg=thread_spawn=\"std\"");
+    } else {
+        println!("cargo::rustc-cfg=thread_spawn=\"unimplemented\"");
+    }
+
+    if ac
+        .probe_raw(
+            r"
+        #![no_std]
+        extern crate std;
+        pub use std::panic::{catch_unwind, resume_unwind};
+        ",
+        )
+        .is_ok()
+    {
+        println!("cargo::rustc-cfg=catch_unwind=\"std\"");
+    } else {
+        println!("cargo::rustc-cfg=catch_unwind=\"unimplemented\"");
+    }
+
     if ac
         .probe_raw(
             r#"
         #![no_std]
         extern crate std;
         use std::io::Write;
-        fn main() {
+        pub fn main() {
             let _ = std::io::stderr().write_all(b"hello");
             std::process::abort();
         }
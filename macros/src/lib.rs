@@ -0,0 +1,247 @@
+//! Procedural macros for Lithium.
+//!
+//! Don't depend on this crate directly. Enable the `macros` feature of `lithium` instead, which
+//! re-exports the macros from the crate root.
+
+#![warn(clippy::pedantic)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote,
+    visit_mut::VisitMut,
+    Attribute, Expr, ExprLit, ExprTry, Item, ItemFn, Lit, Meta, MetaNameValue, Token, Type,
+};
+
+/// Arguments of `#[throws]`.
+struct ThrowsArgs {
+    ty: Type,
+    rewrite_try: bool,
+}
+
+impl Parse for ThrowsArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ty = input.parse()?;
+        let mut rewrite_try = false;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            input.parse::<Token![try]>()?;
+            rewrite_try = true;
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Self { ty, rewrite_try })
+    }
+}
+
+/// Replaces `expr?` with a `throw` of the error, without descending into closures, async blocks, or
+/// nested items.
+struct RewriteTry<'a> {
+    ty: &'a Type,
+}
+
+impl VisitMut for RewriteTry<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Closure(_) | Expr::Async(_) => {}
+            Expr::Try(ExprTry { expr: inner, .. }) => {
+                self.visit_expr_mut(inner);
+                let ty = self.ty;
+                *expr = parse_quote! {
+                    match #inner {
+                        ::core::result::Result::Ok(value) => value,
+                        ::core::result::Result::Err(error) => unsafe {
+                            ::lithium::throw::<#ty>(::core::convert::From::from(error))
+                        },
+                    }
+                };
+            }
+            _ => syn::visit_mut::visit_expr_mut(self, expr),
+        }
+    }
+
+    fn visit_item_mut(&mut self, _item: &mut Item) {}
+}
+
+/// Render a type for documentation, without the spaces `to_string` inserts between tokens.
+fn type_to_doc_string(ty: &Type) -> String {
+    let mut s = ty.to_token_stream().to_string();
+    for (from, to) in [
+        (" < ", "<"),
+        (" <", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        (" :: ", "::"),
+        (":: ", "::"),
+        ("& ", "&"),
+    ] {
+        s = s.replace(from, to);
+    }
+    s
+}
+
+/// Get the text of a `#[doc = "..."]` attribute.
+fn doc_text(attr: &Attribute) -> Option<String> {
+    match &attr.meta {
+        Meta::NameValue(MetaNameValue {
+            path,
+            value:
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(text),
+                    ..
+                }),
+            ..
+        }) if path.is_ident("doc") => Some(text.value()),
+        _ => None,
+    }
+}
+
+/// Append a paragraph to the `# Safety` section of the documentation, adding the section at the
+/// end if there is none.
+///
+/// The section ends at the next top-level heading. Lines in code blocks are skipped, so that hidden
+/// lines of examples are not mistaken for headings.
+fn append_safety_doc(attrs: &mut Vec<Attribute>, paragraph: &str) {
+    let mut in_code = false;
+    let mut in_safety = false;
+    let mut end = None;
+    'attrs: for (i, attr) in attrs.iter().enumerate() {
+        let Some(text) = doc_text(attr) else {
+            continue;
+        };
+        for line in text.lines().map(str::trim) {
+            if line.starts_with("```") {
+                in_code = !in_code;
+            } else if !in_code && line.starts_with("# ") {
+                if in_safety {
+                    end = Some(i);
+                    break 'attrs;
+                }
+                in_safety = line == "# Safety";
+            }
+        }
+    }
+
+    if !in_safety {
+        attrs.push(parse_quote!(#[doc = ""]));
+        attrs.push(parse_quote!(#[doc = " # Safety"]));
+    }
+    if let Some(end) = end {
+        // The blank line before the next heading now separates the paragraph from the section.
+        attrs.splice(
+            end..end,
+            [parse_quote!(#[doc = #paragraph]), parse_quote!(#[doc = ""])],
+        );
+    } else {
+        attrs.push(parse_quote!(#[doc = ""]));
+        attrs.push(parse_quote!(#[doc = #paragraph]));
+    }
+}
+
+/// Mark a function as throwing exceptions of type `E`.
+///
+/// This attribute makes the function `unsafe` and adds the standard safety requirements to its
+/// documentation, stating that the function throws `E` and that the caller must uphold the safety
+/// requirements of `lithium::throw`. If the documentation already has a `# Safety` section, they
+/// are appended to it; otherwise, the section is added at the end.
+///
+/// With `#[throws(E, try)]`, the `?` operator in the function body is additionally rewritten to
+/// throw the error, converted with [`From`], instead of returning it. `?` inside closures, async
+/// blocks, and nested items is left untouched.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw, throws};
+///
+/// #[throws(String)]
+/// fn check(x: i32) -> i32 {
+///     if x < 0 {
+///         throw::<String>(format!("{x} is negative"));
+///     }
+///     x
+/// }
+///
+/// #[throws(String, try)]
+/// fn parse(s: &str) -> i32 {
+///     let x: i32 = s.parse().map_err(|_| format!("{s} is not a number"))?;
+///     check(x)
+/// }
+///
+/// // SAFETY: `parse` throws `String`
+/// assert_eq!(unsafe { catch::<_, String>(|| parse("-1")) }.unwrap_err(), "-1 is negative");
+/// ```
+#[proc_macro_attribute]
+pub fn throws(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let ThrowsArgs { ty, rewrite_try } = parse_macro_input!(attr as ThrowsArgs);
+    let mut func = parse_macro_input!(item as ItemFn);
+
+    if func.sig.unsafety.is_none() {
+        func.sig.unsafety = Some(Token![unsafe](Span::call_site()));
+    }
+
+    if rewrite_try {
+        RewriteTry { ty: &ty }.visit_block_mut(&mut func.block);
+    }
+
+    let safety = format!(
+        " This function throws exceptions of type `{}`. The caller must uphold the safety \
+         requirements of `lithium::throw` for this type: in particular, the exception must only be \
+         caught by Lithium functions expecting this exact type.",
+        type_to_doc_string(&ty),
+    );
+    append_safety_doc(&mut func.attrs, &safety);
+
+    quote!(#func).into()
+}
+
+#[cfg(test)]
+#[allow(clippy::missing_panics_doc, reason = "Tests panic on failure")]
+mod test {
+    use super::*;
+
+    fn docs(func: &ItemFn) -> Vec<String> {
+        func.attrs.iter().filter_map(doc_text).collect()
+    }
+
+    #[test]
+    fn add_safety_section() {
+        let mut func: ItemFn = parse_quote! {
+            /// Summary.
+            fn f() {}
+        };
+        append_safety_doc(&mut func.attrs, " Throws.");
+        assert_eq!(docs(&func), [" Summary.", "", " # Safety", "", " Throws."]);
+    }
+
+    #[test]
+    fn extend_safety_section() {
+        let mut func: ItemFn = parse_quote! {
+            /// Summary.
+            ///
+            /// # Safety
+            ///
+            /// Requirements.
+            ///
+            /// ```
+            /// # fn hidden() {}
+            /// ```
+            ///
+            /// # Panics
+            ///
+            /// Never.
+            #[inline]
+            fn f() {}
+        };
+        append_safety_doc(&mut func.attrs, " Throws.");
+        let docs = docs(&func);
+        assert_eq!(docs.iter().filter(|line| *line == " # Safety").count(), 1);
+        let throws = docs.iter().position(|line| line == " Throws.").unwrap();
+        let panics = docs.iter().position(|line| line == " # Panics").unwrap();
+        assert_eq!(
+            &docs[throws - 2..=panics],
+            [" ```", "", " Throws.", "", " # Panics"]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use lithium::{catch, throw, throws};
+
+#[derive(Debug, PartialEq)]
+struct Error(String);
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(err: std::num::ParseIntError) -> Self {
+        Self(err.to_string())
+    }
+}
+
+#[throws(Error)]
+fn fail(message: &str) -> i32 {
+    throw::<Error>(Error(message.to_string()));
+}
+
+#[throws(Error)]
+fn succeed() -> i32 {
+    1
+}
+
+#[throws(Error, try)]
+fn parse(s: &str) -> i32 {
+    let x: i32 = s.parse()?;
+    if x < 0 {
+        return 0;
+    }
+    // `?` inside closures is not rewritten.
+    let f = || -> Result<i32, Error> { Ok(s.parse::<i32>()? * 2) };
+    f().unwrap_or(-1) + x
+}
+
+struct Parser;
+
+impl Parser {
+    #[throws(Error, try)]
+    fn parse(&self, s: &str) -> i32 {
+        s.parse()?
+    }
+}
+
+#[test]
+fn throws_is_unsafe() {
+    let _: unsafe fn(&str) -> i32 = fail;
+    assert_eq!(
+        unsafe { catch::<_, Error>(|| fail("Oops!")) },
+        Err(Error(String::from("Oops!"))),
+    );
+    assert_eq!(unsafe { catch::<_, Error>(|| succeed()) }, Ok(1));
+}
+
+#[test]
+fn rewrite_try() {
+    assert_eq!(unsafe { catch::<_, Error>(|| parse("5")) }, Ok(15));
+    assert_eq!(unsafe { catch::<_, Error>(|| parse("-5")) }, Ok(0));
+    assert_eq!(
+        unsafe { catch::<_, Error>(|| parse("x")) },
+        Err(Error(String::from("invalid digit found in string"))),
+    );
+}
+
+#[test]
+fn method() {
+    assert_eq!(unsafe { catch::<_, Error>(|| Parser.parse("5")) }, Ok(5));
+    assert!(unsafe { catch::<_, Error>(|| Parser.parse("")) }.is_err());
+}
@@ -0,0 +1,95 @@
+//! Compatibility between separately built copies of Lithium.
+//!
+//! Cargo only lets one version of Lithium into a dependency graph, but a process can still contain
+//! several copies: dynamic libraries and static libraries built separately each bring their own.
+//! Such copies must either interoperate or stay out of each other's way, since a copy that reads an
+//! exception or the per-thread state of another one with a different layout corrupts both.
+//!
+//! The handshake consists of two parts, both derived from [`ABI_VERSION`]:
+//!
+//! - On the Itanium backend, the version is stored in the last byte of the exception class. Copies
+//!   with different versions consider each other's exceptions foreign, so exceptions pass through
+//!   the frames of an incompatible copy unchanged and are only caught by their own copy. An
+//!   exception that doesn't reach a frame of its own copy aborts the process, like any other
+//!   uncaught exception. Exceptions of the panic backend are told apart by type IDs, which already
+//!   differ between versions.
+//! - With `LITHIUM_THREAD_LOCAL=extern`, the name of the per-thread state provider symbol contains
+//!   the version, so copies with the same version share the per-thread state and interoperate,
+//!   while copies with different versions need providers of their own. As the layout of the state
+//!   also depends on the backend, the features and the build-time configuration, each
+//!   [`ThreadState`] records the build it was created for, and a copy given a state created for
+//!   another build aborts the process on first use instead of corrupting the state.
+//!
+//! Version 0 is the layout of Lithium 1.0, whose exception objects consist of the header and the
+//! cause alone, and whose exception class ends in a zero byte. Version 1 stores the type ID, the
+//! throw location and the description of the cause between the header and the cause, so the two
+//! versions treat each other's exceptions as foreign.
+//!
+//! This is not a stable C ABI, and nothing is checked at startup. The provider symbol uses the Rust
+//! ABI, so copies sharing it must also be built by compatible compilers, and without
+//! `LITHIUM_THREAD_LOCAL=extern`, copies keep separate per-thread state and only the exception
+//! class tells them apart. A mismatch is detected when a thread first touches its state, not when
+//! the library is loaded.
+
+use super::tls::ThreadState;
+use core::mem::{align_of, size_of};
+
+/// The version of the layout of exception objects and [`ThreadState`].
+///
+/// This must be bumped whenever either layout changes incompatibly, at the latest with the next
+/// major version. The name of the provider symbol in [`thread_state_provider!`] and in `tls`
+/// contains the version too, and must be updated along with it.
+///
+/// [`thread_state_provider!`]: crate::thread_state_provider
+pub const ABI_VERSION: u8 = 1;
+
+/// The Itanium exception class of Lithium exceptions, `RUSTIEX` followed by [`ABI_VERSION`].
+#[cfg(backend = "itanium")]
+pub const EXCEPTION_CLASS: u64 =
+    u64::from_ne_bytes([b'R', b'U', b'S', b'T', b'I', b'E', b'X', ABI_VERSION]);
+
+/// A description of the build of Lithium that created a [`ThreadState`].
+///
+/// The tag is the first field of [`ThreadState`], which is `#[repr(C)]`, so it can be read by any
+/// build.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AbiTag {
+    version: usize,
+    size: usize,
+    align: usize,
+}
+
+impl AbiTag {
+    /// The tag of the current build.
+    pub const CURRENT: Self = Self {
+        version: ABI_VERSION as usize,
+        size: size_of::<ThreadState>(),
+        align: align_of::<ThreadState>(),
+    };
+}
+
+/// Check that a thread state provided by the embedder was created for the current build.
+///
+/// Aborts the process otherwise.
+#[cfg(thread_local = "extern")]
+#[inline]
+pub fn check_thread_state(state: &ThreadState) {
+    if state.abi != AbiTag::CURRENT {
+        super::abort(
+            "Lithium was given per-thread state created by an incompatible build of Lithium. Copies \
+             of Lithium sharing a thread state provider must be built with the same version, \
+             backend, features, and configuration. The process will now terminate.\n",
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tag_recorded() {
+        assert!(ThreadState::new().abi == AbiTag::CURRENT);
+    }
+}
@@ -1,4 +1,15 @@
-use super::backend::{ActiveBackend, RethrowHandle, ThrowByValue};
+use super::backend::{ActiveBackend, CaughtException, FromCaught, RethrowHandle, ThrowByValue};
+use super::debug::{ExceptionType, Expected};
+use super::hooks::{on_throw, CatchFrame, InFlightMarker};
+#[cfg(feature = "stats")]
+use super::stats::{record, Event};
+#[cfg(any(feature = "tracing", feature = "log"))]
+use super::trace;
+use alloc::boxed::Box;
+use core::any::TypeId;
+use core::ops::ControlFlow;
+#[cfg(feature = "location")]
+use core::panic::Location;
 
 // Module invariant: thrown exceptions of type `E` are passed to the backend as instance of
 // `Exception<E>` with the cause filled, which is immediately read out upon catch.
@@ -13,10 +24,10 @@ use super::backend::{ActiveBackend, RethrowHandle, ThrowByValue};
 /// and not by the system runtime. The list of banned functions includes
 /// [`std::panic::catch_unwind`] and [`std::thread::spawn`], as well as throwing from `main`.
 ///
-/// For this reason, the caller must ensure no frames between [`throw`] and [`catch`] can catch the
-/// exception. This includes not passing throwing callbacks to foreign crates, but also not using
-/// [`throw`] in own code that might [`intercept`] an exception without cooperation with the
-/// throwing side.
+/// For this reason, the caller must ensure no frames between [`throw`](fn@throw) and [`catch`] can
+/// catch the exception. This includes not passing throwing callbacks to foreign crates, but also
+/// not using [`throw`](fn@throw) in own code that might [`intercept`] an exception without
+/// cooperation with the throwing side.
 ///
 /// # Example
 ///
@@ -27,8 +38,11 @@ use super::backend::{ActiveBackend, RethrowHandle, ThrowByValue};
 ///     throw::<&'static str>("Oops!");
 /// }
 /// ```
-#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+#[cfg_attr(not(opt = "speed"), inline(never))]
+#[cfg_attr(opt = "speed", inline)]
 pub unsafe fn throw<E>(cause: E) -> ! {
+    on_throw::<E>();
     // SAFETY: Required transitively.
     unsafe {
         ActiveBackend::throw(cause);
@@ -42,7 +56,7 @@ pub unsafe fn throw<E>(cause: E) -> ! {
 /// If `func` throws an exception, this function returns it, wrapped it in [`Err`].
 ///
 /// If you need to rethrow the exception, possibly modifying it in the process, consider using the
-/// more efficient [`intercept`] function instead of pairing [`catch`] with [`throw`].
+/// more efficient [`intercept`] function instead of pairing [`catch`] with [`throw`](fn@throw).
 ///
 /// Rust panics are propagated as-is and not caught.
 ///
@@ -76,6 +90,442 @@ pub unsafe fn catch<R, E>(func: impl FnOnce() -> R) -> Result<R, E> {
     unsafe { intercept(func) }.map_err(|(cause, _)| cause)
 }
 
+/// Catch an exception, along with the location it was thrown from.
+///
+/// This is equivalent to [`catch`], except that the location of the [`throw`](fn@throw) call that
+/// produced the exception is returned in [`Err`] too. If the exception was rethrown with a new
+/// cause, the location of the last rethrow is returned instead.
+///
+/// This is only available with the `location` feature enabled.
+///
+/// # Safety
+///
+/// The requirements of [`catch`] apply.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch_located, throw};
+///
+/// // SAFETY: the exception type matches
+/// let (res, line) = (unsafe { catch_located::<(), &'static str>(|| throw("Oops!")) }, line!());
+/// let (cause, location) = res.unwrap_err();
+/// assert_eq!(cause, "Oops!");
+/// assert_eq!(location.line(), line);
+/// ```
+#[cfg(feature = "location")]
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch_located<R, E>(
+    func: impl FnOnce() -> R,
+) -> Result<R, (E, &'static Location<'static>)> {
+    // SAFETY: Requirements forwarded. `in_flight` is dropped immediately.
+    unsafe { intercept(func) }.map_err(|(cause, in_flight)| (cause, in_flight.location()))
+}
+
+/// Throw a boxed exception.
+///
+/// Only the pointer is stored on the exception stack and copied during rethrows, so this is useful
+/// for huge or rarely thrown causes that would otherwise not fit in the statically allocated part
+/// of the exception stack. Use [`catch_boxed`] to catch the exception.
+///
+/// This is equivalent to `throw::<Box<E>>(cause)`.
+///
+/// # Safety
+///
+/// The exception must be caught with type `Box<E>`, e.g. by [`catch_boxed`]. The other
+/// requirements of [`throw`](fn@throw) apply.
+///
+/// # Example
+///
+/// ```should_panic
+/// use lithium::throw_boxed;
+///
+/// unsafe {
+///     throw_boxed::<[u8; 65536]>(Box::new([0; 65536]));
+/// }
+/// ```
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub unsafe fn throw_boxed<E>(cause: Box<E>) -> ! {
+    // SAFETY: Required transitively.
+    unsafe { throw(cause) }
+}
+
+/// Catch a boxed exception.
+///
+/// If `func` returns a value, this function wraps it in [`Ok`].
+///
+/// If `func` throws an exception, this function returns it, wrapped it in [`Err`].
+///
+/// This is equivalent to `catch::<R, Box<E>>(func)`.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `Box<E>`, e.g. with [`throw_boxed`]. See the safety
+/// section of [this crate](crate) for more information.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch_boxed, throw_boxed};
+///
+/// // SAFETY: the exception type matches
+/// let res = unsafe {
+///     catch_boxed::<(), [u8; 65536]>(|| throw_boxed(Box::new([1u8; 65536])))
+/// };
+///
+/// assert_eq!(res.unwrap_err()[0], 1);
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch_boxed<R, E>(func: impl FnOnce() -> R) -> Result<R, Box<E>> {
+    // SAFETY: `func` only throws `Box<E>` by the safety requirement.
+    unsafe { catch(func) }
+}
+
+/// Throw a boxed unsized exception, such as a trait object.
+///
+/// The fat pointer is stored in the exception object, so library authors can throw trait objects,
+/// e.g. `Box<dyn Error>`, without making callers agree on a concrete error type. Use
+/// [`catch_unsized`] to catch the exception.
+///
+/// This is equivalent to `throw::<Box<E>>(cause)`.
+///
+/// # Safety
+///
+/// The exception must be caught with type `Box<E>`, e.g. by [`catch_unsized`]. Note that trait
+/// objects with different auto traits or lifetimes are different types: an exception thrown as
+/// `Box<dyn Error + Send>` can't be caught as `Box<dyn Error>`. The other requirements of
+/// [`throw`](fn@throw) apply.
+///
+/// # Example
+///
+/// ```should_panic
+/// use lithium::throw_unsized;
+/// use std::error::Error;
+///
+/// unsafe {
+///     throw_unsized::<dyn Error>("Oops!".into());
+/// }
+/// ```
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub unsafe fn throw_unsized<E: ?Sized>(cause: Box<E>) -> ! {
+    // SAFETY: Required transitively.
+    unsafe { throw(cause) }
+}
+
+/// Catch a boxed unsized exception, such as a trait object.
+///
+/// If `func` returns a value, this function wraps it in [`Ok`].
+///
+/// If `func` throws an exception, this function returns it, wrapped it in [`Err`].
+///
+/// This is equivalent to `catch::<R, Box<E>>(func)`.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `Box<E>`, e.g. with [`throw_unsized`]. See the safety
+/// section of [this crate](crate) for more information.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch_unsized, throw_unsized};
+/// use std::error::Error;
+///
+/// // SAFETY: the exception type matches
+/// let res = unsafe {
+///     catch_unsized::<(), dyn Error>(|| throw_unsized::<dyn Error>("Oops!".into()))
+/// };
+///
+/// assert_eq!(res.unwrap_err().to_string(), "Oops!");
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch_unsized<R, E: ?Sized>(func: impl FnOnce() -> R) -> Result<R, Box<E>> {
+    // SAFETY: `func` only throws `Box<E>` by the safety requirement.
+    unsafe { catch(func) }
+}
+
+/// Catch an exception conditionally.
+///
+/// If `func` returns a value, this function wraps it in [`Ok`].
+///
+/// If `func` throws an exception, `pred` is invoked on the exception cause. If `pred` returns
+/// `true`, the exception is caught and returned in [`Err`]. Otherwise, the exception is rethrown
+/// as-is, as if `catch_if` wasn't there.
+///
+/// This mirrors C++ `catch` clauses with conditions. It is equivalent to, but more convenient and
+/// less error-prone than, pairing [`intercept`] with [`InFlightException::rethrow`] manually.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+///
+/// `pred` must not throw exceptions. Panics, however, are allowed.
+///
+/// In addition, as the exception may be rethrown, the caller must uphold the safety requirements
+/// of [`throw`](fn@throw) for exceptions of type `E`.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, catch_if, throw};
+///
+/// // SAFETY: the exception types match, and `throw` is only caught by Lithium
+/// let res = unsafe {
+///     catch::<(), i32>(|| {
+///         let res = catch_if::<(), i32>(|| throw::<i32>(1), |x| *x == 1);
+///         assert_eq!(res, Err(1));
+///         let _ = catch_if::<(), i32>(|| throw::<i32>(2), |x| *x == 1);
+///         unreachable!();
+///     })
+/// };
+///
+/// assert_eq!(res, Err(2));
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch_if<R, E>(
+    func: impl FnOnce() -> R,
+    pred: impl FnOnce(&E) -> bool,
+) -> Result<R, E> {
+    // SAFETY: `func` only throws `E` by the safety requirement.
+    match unsafe { intercept(func) } {
+        Ok(value) => Ok(value),
+        Err((cause, in_flight)) => {
+            // `pred` does not throw by the safety requirement, so no exceptions are thrown while
+            // `in_flight` is alive.
+            if pred(&cause) {
+                Err(cause)
+            } else {
+                // SAFETY: The exception type is unchanged, and the caller upholds the requirements
+                // of `throw` for `E`.
+                unsafe { in_flight.rethrow(cause) }
+            }
+        }
+    }
+}
+
+/// Convert an exception and rethrow it.
+///
+/// If `func` returns a value, this function returns it as-is.
+///
+/// If `func` throws an exception of type `E`, `map` is applied to its cause and the result is
+/// rethrown as an exception of type `F`, reusing the context of the original exception. This is
+/// the exception analogue of [`Result::map_err`] followed by `?`.
+///
+/// This function is equivalent to, but safer than, pairing [`intercept`] with
+/// [`InFlightException::rethrow`] manually.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+///
+/// `map` must not throw exceptions. Panics, however, are allowed.
+///
+/// In addition, the caller must uphold the safety requirements of [`throw`](fn@throw) for
+/// exceptions of type `F`.
+///
+/// # Example
+///
+/// ```rust
+/// use anyhow::{anyhow, Error};
+/// use lithium::{catch, intercept_map, throw};
+///
+/// /// Throws [`Error`].
+/// unsafe fn f() {
+///     throw::<Error>(anyhow!("f failed"));
+/// }
+///
+/// /// Throws [`Error`].
+/// unsafe fn g() {
+///     // SAFETY: f only ever throws Error, and so does g
+///     intercept_map::<_, Error, Error>(|| f(), |e| e.context("in g"));
+/// }
+///
+/// // SAFETY: g only ever throws Error
+/// println!("{}", unsafe { catch::<_, Error>(|| g()) }.unwrap_err());
+/// ```
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub unsafe fn intercept_map<R, E, F>(func: impl FnOnce() -> R, map: impl FnOnce(E) -> F) -> R {
+    // SAFETY: `func` only throws `E` by the safety requirement.
+    match unsafe { intercept(func) } {
+        Ok(value) => value,
+        Err((cause, in_flight)) => {
+            // `map` does not throw by the safety requirement, so no exceptions are thrown while
+            // `in_flight` is alive.
+            let new_cause = map(cause);
+            // SAFETY: The caller upholds the requirements of `throw` for `F`.
+            unsafe { in_flight.rethrow(new_cause) }
+        }
+    }
+}
+
+/// An exception of one of two types.
+///
+/// Returned by [`catch2`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[expect(
+    clippy::exhaustive_enums,
+    reason = "Adding variants would change the meaning of the type"
+)]
+pub enum Either<L, R> {
+    /// An exception of the first type.
+    Left(L),
+    /// An exception of the second type.
+    Right(R),
+}
+
+/// An exception of one of three types.
+///
+/// Returned by [`catch3`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[expect(
+    clippy::exhaustive_enums,
+    reason = "Adding variants would change the meaning of the type"
+)]
+pub enum Either3<A, B, C> {
+    /// An exception of the first type.
+    First(A),
+    /// An exception of the second type.
+    Second(B),
+    /// An exception of the third type.
+    Third(C),
+}
+
+impl<L, R> FromCaught for Either<L, R> {
+    #[inline]
+    unsafe fn from_caught(type_id: TypeId, ex: impl CaughtException) -> Self {
+        if type_id == typeid::of::<L>() {
+            // SAFETY: The type IDs match.
+            Self::Left(unsafe { ex.take() })
+        } else {
+            // SAFETY: The thrown type is one of `L` and `R` by the safety requirement.
+            Self::Right(unsafe { ex.take() })
+        }
+    }
+}
+
+impl<A, B, C> FromCaught for Either3<A, B, C> {
+    #[inline]
+    unsafe fn from_caught(type_id: TypeId, ex: impl CaughtException) -> Self {
+        if type_id == typeid::of::<A>() {
+            // SAFETY: The type IDs match.
+            Self::First(unsafe { ex.take() })
+        } else if type_id == typeid::of::<B>() {
+            // SAFETY: The type IDs match.
+            Self::Second(unsafe { ex.take() })
+        } else {
+            // SAFETY: The thrown type is one of `A`, `B`, and `C` by the safety requirement.
+            Self::Third(unsafe { ex.take() })
+        }
+    }
+}
+
+/// Catch an exception of one of two types.
+///
+/// This is a variant of [`catch`] for callbacks that can throw exceptions of either `E1` or `E2`.
+/// The thrown type is recorded by [`throw`](fn@throw) and checked at runtime, so the exception is
+/// wrapped in the matching [`Either`] variant.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of types `E1` and `E2`. See the safety section of
+/// [this crate](crate) for more information.
+///
+/// `E1` and `E2` must be distinct types. Types that only differ in lifetimes are considered equal.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch2, throw, Either};
+///
+/// /// Throws `i32` or `&'static str`.
+/// unsafe fn f(x: i32) {
+///     if x < 0 {
+///         throw::<&'static str>("negative");
+///     }
+///     throw::<i32>(x);
+/// }
+///
+/// // SAFETY: `f` only throws `i32` and `&'static str`
+/// let res = unsafe { catch2::<(), i32, &'static str>(|| f(-1)) };
+/// assert_eq!(res, Err(Either::Right("negative")));
+/// let res = unsafe { catch2::<(), i32, &'static str>(|| f(1)) };
+/// assert_eq!(res, Err(Either::Left(1)));
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch2<R, E1, E2>(func: impl FnOnce() -> R) -> Result<R, Either<E1, E2>> {
+    let expected =
+        Expected::Types(const { &[ExceptionType::of::<E1>(), ExceptionType::of::<E2>()] });
+    // SAFETY: `func` only throws `E1` or `E2`, which are distinct, by the safety requirement.
+    in_catch_frame(expected, || unsafe {
+        ActiveBackend::intercept_dispatch(func)
+    })
+}
+
+/// Catch an exception of one of three types.
+///
+/// This is a variant of [`catch2`] for three exception types. See its documentation for more
+/// information.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of types `E1`, `E2`, and `E3`. See the safety section of
+/// [this crate](crate) for more information.
+///
+/// `E1`, `E2`, and `E3` must be pairwise distinct types. Types that only differ in lifetimes are
+/// considered equal.
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch3<R, E1, E2, E3>(func: impl FnOnce() -> R) -> Result<R, Either3<E1, E2, E3>> {
+    let expected = Expected::Types(
+        const {
+            &[
+                ExceptionType::of::<E1>(),
+                ExceptionType::of::<E2>(),
+                ExceptionType::of::<E3>(),
+            ]
+        },
+    );
+    // SAFETY: `func` only throws `E1`, `E2`, or `E3`, which are pairwise distinct, by the safety
+    // requirement.
+    in_catch_frame(expected, || unsafe {
+        ActiveBackend::intercept_dispatch(func)
+    })
+}
+
 /// Not-quite-caught exception.
 ///
 /// This type is returned by [`intercept`] when an exception is caught. Exception handling is not
@@ -84,7 +534,12 @@ pub unsafe fn catch<R, E>(func: impl FnOnce() -> R) -> Result<R, E> {
 /// At this point, you can either drop the handle, which halts the Lithium machinery and brings you
 /// back to the sane land of [`Result`], or call [`InFlightException::rethrow`] to piggy-back on the
 /// contexts of the caught exception.
-pub struct InFlightException<E>(<ActiveBackend as ThrowByValue>::RethrowHandle<E>);
+pub struct InFlightException<E>(
+    // The marker is dropped before the handle, so that misuse is reported before the exception is
+    // released.
+    InFlightMarker,
+    <ActiveBackend as ThrowByValue>::RethrowHandle<E>,
+);
 
 impl<E> InFlightException<E> {
     /// Throw a new exception by reusing the existing context.
@@ -103,13 +558,85 @@ impl<E> InFlightException<E> {
     /// the exception. This includes not passing throwing callbacks to foreign crates, but also not
     /// using `rethrow` in own code that might [`intercept`] an exception without cooperation with
     /// the throwing side.
+    ///
+    /// With the `location` feature enabled, the location of the caller is recorded as the new
+    /// throw site.
+    #[cfg_attr(feature = "location", track_caller)]
     #[inline]
     pub unsafe fn rethrow<F>(self, new_cause: F) -> ! {
+        let Self(marker, handle) = self;
+        marker.rethrow();
+        #[cfg(feature = "stats")]
+        record(Event::Rethrow);
+        #[cfg(any(feature = "tracing", feature = "log"))]
+        trace::rethrow::<F>();
+        on_throw::<F>();
         // SAFETY: Requirements forwarded.
         unsafe {
-            self.0.rethrow(new_cause);
+            handle.rethrow(new_cause);
         }
     }
+
+    /// Throw a new exception, computing the new cause from the old one.
+    ///
+    /// This is equivalent to `self.rethrow(combine(cause))`, but makes it harder to throw between
+    /// [`intercept`] and the rethrow by accident: the caught cause is passed back in, and `combine`
+    /// runs while the handle is still held. [`Chain`](crate::Chain) and [`Error`](crate::Error)
+    /// exceptions also provide `rethrow_with_context` for the most common use, adding context.
+    ///
+    /// # Safety
+    ///
+    /// `combine` must not throw exceptions. Panics, however, are allowed, in which case the
+    /// exception is released.
+    ///
+    /// The requirements of [`InFlightException::rethrow`] apply.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use lithium::{catch, intercept, throw};
+    ///
+    /// // SAFETY: Only `String` is thrown, and the closure passed to `rethrow_with` doesn't throw.
+    /// let result = unsafe {
+    ///     catch::<(), String>(|| {
+    ///         let (cause, in_flight) =
+    ///             intercept::<(), String>(|| throw(String::from("EOF"))).unwrap_err();
+    ///         in_flight.rethrow_with(cause, |cause| cause + " in header");
+    ///     })
+    /// };
+    /// assert_eq!(result.unwrap_err(), "EOF in header");
+    /// ```
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub unsafe fn rethrow_with<F>(self, cause: E, combine: impl FnOnce(E) -> F) -> ! {
+        let new_cause = combine(cause);
+        // SAFETY: Requirements forwarded.
+        unsafe { self.rethrow(new_cause) }
+    }
+
+    /// Get the location the exception was thrown or last rethrown from.
+    ///
+    /// This is only available with the `location` feature enabled.
+    #[cfg(feature = "location")]
+    #[inline]
+    #[must_use]
+    pub fn location(&self) -> &'static Location<'static> {
+        self.1.location().get()
+    }
+
+    /// Finish handling the exception and start a Rust panic.
+    ///
+    /// The exception is released, and unwinding resumes via [`std::panic::resume_unwind`] with
+    /// `payload` boxed as the panic payload. This is useful at boundaries where the caller only
+    /// understands [`std::panic::catch_unwind`].
+    ///
+    /// Like with [`std::panic::resume_unwind`], the panic hook is not invoked.
+    #[cfg(catch_unwind = "std")]
+    #[inline]
+    pub fn into_panic<F: core::any::Any + Send>(self, payload: F) -> ! {
+        drop(self);
+        std::panic::resume_unwind(Box::new(payload))
+    }
 }
 
 /// Begin exception catching.
@@ -176,17 +703,109 @@ impl<E> InFlightException<E> {
     clippy::missing_errors_doc,
     reason = "`Err` value is described immediately"
 )]
-#[inline(always)]
+#[cfg_attr(not(opt = "size"), inline(always))]
+#[cfg_attr(opt = "size", inline)]
 pub unsafe fn intercept<R, E>(func: impl FnOnce() -> R) -> Result<R, (E, InFlightException<E>)> {
+    let expected = Expected::Types(const { &[ExceptionType::of::<E>()] });
     // SAFETY: Requirements forwarded.
-    unsafe { ActiveBackend::intercept(func) }
-        .map_err(|(cause, handle)| (cause, InFlightException(handle)))
+    in_catch_frame(expected, || unsafe { ActiveBackend::intercept(func) })
+        .map_err(|(cause, handle)| (cause, InFlightException(InFlightMarker::new(), handle)))
+}
+
+/// Begin exception catching, returning [`ControlFlow`].
+///
+/// This is a variant of [`intercept`] for code structured around [`ControlFlow`], such as visitors
+/// and graph traversals. If `func` returns a value, it's wrapped in [`ControlFlow::Continue`]. If
+/// `func` throws an exception, the cause and the handle are returned in [`ControlFlow::Break`].
+///
+/// # Safety
+///
+/// The requirements of [`intercept`] apply.
+///
+/// # Example
+///
+/// ```rust
+/// use core::ops::ControlFlow;
+/// use lithium::{intercept_cf, throw};
+///
+/// // SAFETY: the exception type matches, and the handle is dropped immediately
+/// let flow = unsafe { intercept_cf::<(), i32>(|| throw(1)) };
+/// assert!(matches!(flow, ControlFlow::Break((1, _))));
+/// ```
+#[inline]
+pub unsafe fn intercept_cf<R, E>(
+    func: impl FnOnce() -> R,
+) -> ControlFlow<(E, InFlightException<E>), R> {
+    // SAFETY: Requirements forwarded.
+    match unsafe { intercept(func) } {
+        Ok(value) => ControlFlow::Continue(value),
+        Err(ex) => ControlFlow::Break(ex),
+    }
+}
+
+/// Invoke a backend catch function inside a [`CatchFrame`].
+///
+/// In debug builds, this verifies that exceptions thrown inside `intercept` are not caught by
+/// foreign frames.
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is passed through from `intercept`"
+)]
+#[cfg_attr(not(opt = "size"), inline(always))]
+#[cfg_attr(opt = "size", inline)]
+pub(crate) fn in_catch_frame<R, T>(
+    expected: Expected,
+    intercept: impl FnOnce() -> Result<R, T>,
+) -> Result<R, T> {
+    let frame = CatchFrame::enter(expected);
+    let result = intercept();
+    if result.is_ok() {
+        frame.returned();
+    } else {
+        frame.caught();
+        #[cfg(any(feature = "tracing", feature = "log"))]
+        trace::caught(expected);
+    }
+    result
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use alloc::string::String;
+    use alloc::string::{String, ToString};
+    use core::{error::Error, fmt};
+
+    #[test]
+    fn catch_boxed_err() {
+        let result = unsafe {
+            catch_boxed::<(), String>(|| throw_boxed(Box::new(String::from("Hello, world!"))))
+        };
+        assert_eq!(*result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn catch_unsized_err() {
+        let result = unsafe {
+            catch_unsized::<(), dyn Error>(|| throw_unsized::<dyn Error>(Box::new(fmt::Error)))
+        };
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "an error occurred when formatting an argument"
+        );
+    }
+
+    #[test]
+    fn catch_unsized_slice() {
+        let result =
+            unsafe { catch_unsized::<(), [i32]>(|| throw_unsized::<[i32]>(Box::new([1, 2]))) };
+        assert_eq!(*result.unwrap_err(), [1, 2]);
+    }
+
+    #[test]
+    fn catch_boxed_ok() {
+        let result = unsafe { catch_boxed::<i32, String>(|| 123) };
+        assert_eq!(result, Ok(123));
+    }
 
     #[test]
     fn catch_ok() {
@@ -233,6 +852,102 @@ mod test {
         assert_eq!(result.unwrap_err(), "Hello, world! You look nice btw.");
     }
 
+    #[test]
+    fn catch_if_matches() {
+        let result: Result<(), String> =
+            unsafe { catch_if(|| throw(String::from("Hello, world!")), |_| true) };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn catch_if_rethrows() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let _: Result<(), String> = catch_if(
+                    || throw(String::from("Hello, world!")),
+                    |cause: &String| cause.is_empty(),
+                );
+                unreachable!();
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn catch2_ok() {
+        let result: Result<i32, Either<i32, String>> = unsafe { catch2(|| 123) };
+        assert_eq!(result, Ok(123));
+    }
+
+    #[test]
+    fn catch2_err() {
+        let result: Result<(), Either<i32, String>> = unsafe { catch2(|| throw::<i32>(123)) };
+        assert_eq!(result, Err(Either::Left(123)));
+        let result: Result<(), Either<i32, String>> =
+            unsafe { catch2(|| throw(String::from("Hello, world!"))) };
+        assert_eq!(result, Err(Either::Right(String::from("Hello, world!"))));
+    }
+
+    #[test]
+    fn catch3_err() {
+        let result: Result<(), Either3<u8, String, i32>> = unsafe { catch3(|| throw::<u8>(1)) };
+        assert_eq!(result, Err(Either3::First(1)));
+        let result: Result<(), Either3<u8, String, i32>> =
+            unsafe { catch3(|| throw(String::from("Hello, world!"))) };
+        assert_eq!(result, Err(Either3::Second(String::from("Hello, world!"))));
+        let result: Result<(), Either3<u8, String, i32>> = unsafe { catch3(|| throw::<i32>(3)) };
+        assert_eq!(result, Err(Either3::Third(3)));
+    }
+
+    #[test]
+    fn catch2_nested() {
+        let result: Result<(), Either<i32, String>> = unsafe {
+            catch2(|| {
+                let inner: Result<(), u8> = catch(|| throw::<u8>(1));
+                assert_eq!(inner, Err(1));
+                throw(String::from("Hello, world!"));
+            })
+        };
+        assert_eq!(result, Err(Either::Right(String::from("Hello, world!"))));
+    }
+
+    #[test]
+    fn intercept_cf_continue() {
+        let flow = unsafe { intercept_cf::<i32, String>(|| 123) };
+        assert!(matches!(flow, ControlFlow::Continue(123)));
+    }
+
+    #[test]
+    fn intercept_cf_break() {
+        let result: Result<(), String> = unsafe {
+            catch(|| match intercept_cf::<(), i32>(|| throw(1)) {
+                ControlFlow::Continue(()) => {}
+                ControlFlow::Break((cause, ex)) => ex.rethrow(alloc::format!("error {cause}")),
+            })
+        };
+        assert_eq!(result.unwrap_err(), "error 1");
+    }
+
+    #[test]
+    fn intercept_map_ok() {
+        let result: Result<i32, String> =
+            unsafe { catch(|| intercept_map(|| 123, |err: i32| alloc::format!("error {err}"))) };
+        assert_eq!(result, Ok(123));
+    }
+
+    #[test]
+    fn intercept_map_err() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                intercept_map(
+                    || throw::<i32>(123),
+                    |err: i32| alloc::format!("error {err}"),
+                );
+            })
+        };
+        assert_eq!(result.unwrap_err(), "error 123");
+    }
+
     #[test]
     fn panic_while_in_flight() {
         struct Dropper;
@@ -256,4 +971,51 @@ mod test {
         };
         assert_eq!(result.unwrap_err(), "Hello, world!");
     }
+
+    #[test]
+    fn rethrow_with() {
+        let result = unsafe {
+            catch::<(), usize>(|| {
+                let (cause, in_flight) =
+                    intercept::<(), &str>(|| throw("Hello, world!")).unwrap_err();
+                in_flight.rethrow_with(cause, str::len);
+            })
+        };
+        assert_eq!(result, Err(13));
+    }
+
+    #[cfg(catch_unwind = "std")]
+    #[test]
+    fn into_panic() {
+        let result = std::panic::catch_unwind(|| {
+            let (cause, in_flight) =
+                unsafe { intercept::<(), String>(|| throw(String::from("Oops!"))) }.unwrap_err();
+            in_flight.into_panic(cause);
+        });
+        assert_eq!(*result.unwrap_err().downcast::<String>().unwrap(), "Oops!");
+        let result: Result<(), i32> = unsafe { catch(|| throw(1)) };
+        assert_eq!(result, Err(1));
+    }
+
+    #[cfg(feature = "location")]
+    #[test]
+    fn location() {
+        let line = line!() + 1;
+        let result = unsafe { catch_located::<(), i32>(|| throw(1)) };
+        let (cause, location) = result.unwrap_err();
+        assert_eq!(cause, 1);
+        assert_eq!(location.file(), file!());
+        assert_eq!(location.line(), line);
+
+        let result = unsafe {
+            catch_located::<(), i32>(|| {
+                let (cause, in_flight) = intercept::<(), i32>(|| throw(2)).unwrap_err();
+                assert_eq!(in_flight.location().line(), line!() - 1);
+                in_flight.rethrow(cause + 1);
+            })
+        };
+        let (cause, location) = result.unwrap_err();
+        assert_eq!(cause, 3);
+        assert_eq!(location.line(), line + 10);
+    }
 }
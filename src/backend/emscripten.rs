@@ -2,7 +2,7 @@
 // - https://github.com/rust-lang/rust/blob/master/library/panic_unwind/src/emcc.rs
 
 use super::{
-    super::{abort, intrinsic::intercept},
+    super::{describe::abort_described, intrinsic::intercept, stacked_exceptions::Exception},
     ThrowByPointer,
 };
 
@@ -57,6 +57,12 @@ unsafe impl ThrowByPointer for ActiveBackend {
             Err(ptr) => ptr,
         };
 
+        // It's tempting to recognize Lithium exceptions by reading the header directly and to skip
+        // `__cxa_begin_catch` and `__cxa_end_catch` for them altogether. But `__cxa_throw`
+        // increments the count of uncaught exceptions, which is kept in JS and only decremented by
+        // `__cxa_begin_catch`, so skipping the calls would break `std::uncaught_exceptions` in C++
+        // code running in the same module. Foreign exceptions need `__cxa_begin_catch` before
+        // `__cxa_rethrow` anyway.
         // SAFETY: `ptr` was obtained from a `core::intrinsics::catch_unwind` call.
         let adjusted_ptr = unsafe { __cxa_begin_catch(ptr) };
 
@@ -149,6 +155,10 @@ extern "C-unwind" {
 /// # Safety
 ///
 /// `ex` must point at a valid exception object.
-unsafe extern "C" fn cleanup(_ex: *mut ()) -> *mut () {
-    abort("A Lithium exception was caught by a non-Lithium catch mechanism. This is undefined behavior. The process will now terminate.\n");
+unsafe extern "C" fn cleanup(ex: *mut ()) -> *mut () {
+    // SAFETY: `ex` points at the end of the header, as passed to `__cxa_throw` by `throw`.
+    let header = unsafe { ex.cast::<Header>().sub(1) };
+    // SAFETY: `header` points at a valid exception object, so its cause is alive.
+    let described = unsafe { Exception::describe_from_header(header) };
+    abort_described("A Lithium exception was caught by a non-Lithium catch mechanism. This is undefined behavior. The process will now terminate.\n", described);
 }
@@ -1,10 +1,15 @@
 use super::{
-    super::{abort, intrinsic::intercept},
+    super::{
+        abi::EXCEPTION_CLASS as LITHIUM_EXCEPTION_CLASS, describe::abort_described,
+        intrinsic::intercept, stacked_exceptions::Exception,
+    },
     ThrowByPointer,
 };
 use core::mem::MaybeUninit;
 
-pub const LITHIUM_EXCEPTION_CLASS: u64 = u64::from_ne_bytes(*b"RUSTIEX\0");
+/// The exception class used by Rust panics.
+#[cfg(not(target_arch = "wasm32"))]
+const RUST_EXCEPTION_CLASS: u64 = u64::from_ne_bytes(*b"MOZ\0RUST");
 
 pub(crate) struct ActiveBackend;
 
@@ -21,10 +26,8 @@ unsafe impl ThrowByPointer for ActiveBackend {
             // unwind routines see it. This is not necessary for other architectures (except C6x),
             // but being consistent doesn't hurt. In practice, libgcc uses this field to store force
             // unwinding information, so leaving this uninitialized leads to SIGILLs and SIGSEGVs
-            // because it uses the field as a callback address. Strictly speaking, we should
-            // reinitialize this field back to zero when we do `_Unwind_RaiseException` later, but
-            // this is unnecessary for libgcc, and libunwind uses the cross-platform mechanism for
-            // ARM too.
+            // because it uses the field as a callback address. We reinitialize this field back to
+            // zero in `throw`, too, as the header might be reused by `rethrow`.
             // [1]: https://github.com/ARM-software/abi-aa/blob/76d56124610302e645b66ac4e491be0c1a90ee11/ehabi32/ehabi32.rst#language-independent-unwinding-types-and-functions
             private1: core::ptr::null(),
             private_rest: MaybeUninit::uninit(),
@@ -33,12 +36,42 @@ unsafe impl ThrowByPointer for ActiveBackend {
 
     #[inline]
     unsafe fn throw(ex: *mut Header) -> ! {
+        // The header might be reused by `rethrow` after the unwinder has stored private data in it.
+        // Reset the first private word, so that the unwinder does not mistake a rethrown exception
+        // for a forced unwind (see `new_header`).
+        // SAFETY: `ex` points at a valid header by the safety requirement.
+        unsafe {
+            (*ex).private1 = core::ptr::null();
+        }
         // SAFETY: We provide a valid exception header.
         unsafe {
             raise(ex.cast());
         }
     }
 
+    #[inline]
+    unsafe fn rethrow(ex: *mut Header) -> ! {
+        #[cfg(resume_rethrow)]
+        // SAFETY: `ex` points at a valid header by the safety requirement, and the exception is
+        // caught by Lithium, whose catch frames land in phase 2 without a prior search.
+        unsafe {
+            resume(ex);
+        }
+
+        #[cfg(not(resume_rethrow))]
+        // SAFETY: Required transitively.
+        unsafe {
+            Self::throw(ex);
+        }
+    }
+
+    // We'd like to use our own personality routine for the catch frame, so that the unwinder can
+    // recognize Lithium exceptions without going through `rust_eh_personality` and its LSDA
+    // parsing. Unfortunately, the personality is chosen per crate via the `eh_personality` lang
+    // item, and Rust has no way to attach a different one to a single function, short of writing
+    // the frame in assembly together with its unwind tables. Pass-through frames without landing
+    // pads have no LSDA, so `rust_eh_personality` is cheap on them anyway; most of the time is
+    // spent walking the CFI, which a custom personality wouldn't save.
     #[inline(always)]
     fn intercept<Func: FnOnce() -> R, R>(func: Func) -> Result<R, *mut Header> {
         let ex = match intercept(func, |ex| ex) {
@@ -73,8 +106,13 @@ unsafe impl ThrowByPointer for ActiveBackend {
             //     exceptions to travel through Lithium frames.
             //   If project-ffi-unwind changes the rustc behavior, we might have to update this
             //   code.
+            // - Forced unwinding, used by `pthread_exit` and thread cancellation, enters catch
+            //   landing pads too. Such exceptions must be resumed with the original stop function
+            //   instead of being raised anew, which `rethrow_foreign` takes care of. In-flight
+            //   Lithium exceptions held by frames on the way are released by their destructors as
+            //   usual.
             unsafe {
-                raise(ex);
+                rethrow_foreign(ex);
             }
         }
 
@@ -89,6 +127,7 @@ unsafe impl ThrowByPointer for ActiveBackend {
 // i386 `__attribute__((aligned))` aligns to 16 bytes too. Therefore, the alignment of this
 // structure might be larger than the actual alignment when we access foreign exceptions, so we
 // can't use this type for that.
+#[cfg(not(ehabi))]
 #[repr(C, align(16))]
 pub struct Header {
     class: u64,
@@ -98,7 +137,51 @@ pub struct Header {
     private_rest: MaybeUninit<[*const (); get_unwinder_private_word_count() - 1]>,
 }
 
-// Data from https://github.com/rust-lang/rust/blob/master/library/unwind/src/libunwind.rs
+// ARM EH ABI uses `_Unwind_Control_Block` instead of `_Unwind_Exception`. The private words are
+// split into several caches with distinct semantics, and the structure is aligned to 8 bytes, as
+// specified by the ABI rather than guessed. We only ever touch `unwinder_cache.reserved1`, which we
+// still call `private1` for uniformity with other targets.
+#[cfg(ehabi)]
+#[repr(C, align(8))]
+pub struct Header {
+    class: u64,
+    cleanup: Option<unsafe extern "C" fn(i32, *mut Header)>,
+    // `unwinder_cache.reserved1`, stores the stop function during forced unwinding.
+    private1: *const (),
+    // `unwinder_cache.reserved2..=reserved5`, `barrier_cache`, `cleanup_cache`, `pr_cache`, and
+    // padding.
+    private_rest: MaybeUninit<EhabiPrivate>,
+}
+
+#[cfg(ehabi)]
+#[repr(C)]
+struct EhabiPrivate {
+    unwinder_cache_rest: [u32; 4],
+    barrier_cache: [u32; 6],
+    cleanup_cache: [u32; 4],
+    pr_cache: [u32; 4],
+    // The ABI specifies 19 private words, i.e. an 88-byte block, but `libunwind.rs` in std reserves
+    // 20, and we keep the block as large as Rust's own exceptions.
+    _padding: u32,
+}
+
+#[cfg(ehabi)]
+const _: () = {
+    // 8 bytes of class, 4 bytes of cleanup, 19 private words, and a word of padding.
+    assert!(
+        size_of::<Header>() == 96,
+        "Unexpected _Unwind_Control_Block size",
+    );
+    assert!(core::mem::offset_of!(Header, private1) == 12);
+};
+
+/// Get the number of private words in `_Unwind_Exception` on the target.
+///
+/// Data from <https://github.com/rust-lang/rust/blob/master/library/unwind/src/libunwind.rs>.
+///
+/// # Panics
+///
+/// Panics during const evaluation if the target architecture is not supported.
 const fn get_unwinder_private_word_count() -> usize {
     // The Itanium EH ABI says the structure contains 2 private uint64_t words. Some architectures
     // decided this means "2 private native words". So on some 32-bit architectures this is two
@@ -152,16 +235,28 @@ const fn get_unwinder_private_word_count() -> usize {
 
 /// Destruct an exception when caught by a foreign runtime.
 ///
+/// Making Lithium exceptions catchable from C++ would require more than a valid cleanup function.
+/// The C++ runtime only matches exceptions of class `GNUCC++\0`, expects a `__cxa_exception`
+/// immediately before the unwinder header, and manages the object's lifetime through
+/// `__cxa_begin_catch`/`__cxa_end_catch`, which conflicts with our exception objects living on the
+/// Lithium stack. So we don't attempt to cooperate and treat a foreign catch as a fatal error.
+///
 /// # Safety
 ///
 /// `ex` must point at a valid exception object.
-unsafe extern "C" fn cleanup(_code: i32, _ex: *mut Header) {
-    abort("A Lithium exception was caught by a non-Lithium catch mechanism. This is undefined behavior. The process will now terminate.\n");
+unsafe extern "C" fn cleanup(_code: i32, ex: *mut Header) {
+    // SAFETY: `ex` points at a valid exception object, so its cause is alive.
+    let described = unsafe { Exception::describe_from_header(ex) };
+    abort_described("A Lithium exception was caught by a non-Lithium catch mechanism. This is undefined behavior. The process will now terminate.\n", described);
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 extern "C-unwind" {
     fn _Unwind_RaiseException(ex: *mut u8) -> !;
+    fn _Unwind_Resume_or_Rethrow(ex: *mut u8) -> !;
+    #[cfg(resume_rethrow)]
+    fn _Unwind_Resume(ex: *mut u8) -> !;
+    fn _Unwind_DeleteException(ex: *mut u8);
 }
 
 /// Raise an Itanium EH ABI-compatible exception.
@@ -172,15 +267,221 @@ extern "C-unwind" {
 #[inline]
 unsafe fn raise(ex: *mut u8) -> ! {
     #[cfg(not(target_arch = "wasm32"))]
-    #[expect(clippy::used_underscore_items, reason = "External API")]
+    #[allow(clippy::used_underscore_items, reason = "External API")]
     // SAFETY: Passthrough.
     unsafe {
         _Unwind_RaiseException(ex);
     }
 
+    // Tag 0 is the C++ exception tag, which LLVM uses for Rust panics as well. The `throw`
+    // instruction is shared by the legacy exception handling proposal and the final one (`exnref`),
+    // and catching is lowered by the compiler according to the enabled target features, so this
+    // works with both encodings without changes.
+    //
+    // A dedicated tag would let foreign catchers skip Lithium exceptions structurally, but it's not
+    // expressible in Rust: the `throw` intrinsic only supports tag 0, and `catch_unwind` only
+    // catches that tag. We don't need one either, as the payload is an `_Unwind_Exception` just
+    // like on other targets, and `intercept` tells Lithium exceptions apart by their class rather
+    // than by the pointer.
     #[cfg(target_arch = "wasm32")]
     // SAFETY: Passthrough.
     unsafe {
         core::arch::wasm32::throw::<0>(ex);
     }
 }
+
+/// Continue unwinding a Lithium exception from the caller, skipping the search phase.
+///
+/// `_Unwind_RaiseException` walks the stack twice: first to find a handler, then to run cleanups
+/// and land in the handler. Lithium exceptions are always caught by Lithium, so the first walk only
+/// repeats what we know already, and it's the bulk of the cost of a rethrow. `_Unwind_Resume`,
+/// normally called by cleanup landing pads, only runs the second phase. `rust_eh_personality`
+/// lands in catch frames in phase 2 even if they were not selected as the handler during phase 1,
+/// so the exception is caught by the nearest Lithium frame, just like with
+/// `_Unwind_RaiseException`.
+///
+/// `_Unwind_Resume` reads two private words: the stop function, which must be null so that the
+/// exception is not considered forced, and the handler frame found in phase 1. We reset the latter
+/// too, as it may be stale and coincide with an unrelated frame, which the unwinder would then
+/// require to handle the exception. This layout is only guaranteed on targets with two 64-bit
+/// private words, see `get_unwinder_private_word_count`; elsewhere, we raise the exception anew.
+///
+/// # Safety
+///
+/// `ex` must point at a valid `Header`, and the exception must be caught by a Lithium frame.
+#[cfg(resume_rethrow)]
+#[inline]
+unsafe fn resume(ex: *mut Header) -> ! {
+    // SAFETY: `ex` points at a valid header by the safety requirement.
+    unsafe {
+        (*ex).private1 = core::ptr::null();
+    }
+    // SAFETY: As above. The second private word immediately follows the first one.
+    unsafe {
+        (*ex).private_rest = MaybeUninit::new([core::ptr::null()]);
+    }
+    #[allow(clippy::used_underscore_items, reason = "External API")]
+    // SAFETY: `ex` is a valid exception object with cleared private words, and the safety
+    // requirements of `_Unwind_Resume` are satisfied as described above.
+    unsafe {
+        _Unwind_Resume(ex.cast());
+    }
+}
+
+/// Rethrow a foreign exception caught by [`intercept`].
+///
+/// Unlike [`raise`], this continues forced unwinding if `ex` is a forced unwind exception, rather
+/// than starting a new search phase, which would fail with `_URC_END_OF_STACK` when no handler is
+/// found.
+///
+/// # Safety
+///
+/// `ex` must point at a valid instance of `_Unwind_Exception` that has been caught and not rethrown
+/// or deleted since.
+#[inline]
+pub(crate) unsafe fn rethrow_foreign(ex: *mut u8) -> ! {
+    #[cfg(not(target_arch = "wasm32"))]
+    #[allow(clippy::used_underscore_items, reason = "External API")]
+    // SAFETY: Passthrough.
+    unsafe {
+        _Unwind_Resume_or_Rethrow(ex);
+    }
+
+    // Wasm has no forced unwinding.
+    #[cfg(target_arch = "wasm32")]
+    // SAFETY: Passthrough.
+    unsafe {
+        raise(ex);
+    }
+}
+
+/// Catch foreign exceptions.
+///
+/// Lithium exceptions, Rust panics, and forced unwinding are propagated further. The returned
+/// pointer points at a foreign `_Unwind_Exception`, which must be either rethrown with
+/// [`rethrow_foreign`] or deleted with [`delete_foreign`].
+#[cfg(not(target_arch = "wasm32"))]
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline(always)]
+pub(crate) fn intercept_foreign<Func: FnOnce() -> R, R>(func: Func) -> Result<R, *mut u8> {
+    let ex = match intercept(func, |ex| ex) {
+        Ok(value) => return Ok(value),
+        Err(ex) => ex,
+    };
+
+    // SAFETY: As in `ActiveBackend::intercept`.
+    #[expect(clippy::cast_ptr_alignment, reason = "See the safety comment above")]
+    let class = unsafe { *ex.cast::<u64>() };
+
+    if class == LITHIUM_EXCEPTION_CLASS {
+        // SAFETY: This is a Lithium exception, so `ex` points at a `Header`, and it's rethrown
+        // immediately, so the enclosing interceptor sees the same exception.
+        unsafe { <ActiveBackend as ThrowByPointer>::rethrow(ex.cast()) }
+    }
+
+    // SAFETY: The private words follow the class and the cleanup function in the same structure,
+    // so the offset is the same for foreign exceptions, and the field is pointer-aligned.
+    let private1_ptr = unsafe { ex.byte_add(core::mem::offset_of!(Header, private1)) };
+    // SAFETY: As above. The unwinder stores the stop function here during forced unwinding, and
+    // zeroes the field when raising regular exceptions.
+    #[expect(clippy::cast_ptr_alignment, reason = "See the safety comment above")]
+    let private1 = unsafe { *private1_ptr.cast::<*const ()>() };
+
+    if class == RUST_EXCEPTION_CLASS || !private1.is_null() {
+        // SAFETY: As in `ActiveBackend::intercept`.
+        unsafe {
+            rethrow_foreign(ex);
+        }
+    }
+
+    Err(ex)
+}
+
+/// Destroy a foreign exception returned by [`intercept_foreign`].
+///
+/// This invokes the cleanup function of the exception, as if it was caught by its runtime.
+///
+/// # Safety
+///
+/// `ex` must be a foreign exception returned by [`intercept_foreign`] that hasn't been rethrown or
+/// deleted yet.
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+pub(crate) unsafe fn delete_foreign(ex: *mut u8) {
+    #[allow(clippy::used_underscore_items, reason = "External API")]
+    // SAFETY: Passthrough.
+    unsafe {
+        _Unwind_DeleteException(ex);
+    }
+}
+
+#[cfg(test)]
+#[cfg(ehabi)]
+mod test {
+    use super::{EhabiPrivate, Header};
+    use core::mem::{align_of, offset_of, size_of};
+
+    /// `_Unwind_Control_Block` as declared by the ARM EH ABI.
+    #[repr(C)]
+    struct ControlBlock {
+        exception_class: [u8; 8],
+        exception_cleanup: Option<unsafe extern "C" fn(i32, *mut ControlBlock)>,
+        unwinder_cache: [u32; 5],
+        barrier_cache: [u32; 6],
+        cleanup_cache: [u32; 4],
+        pr_cache: [u32; 4],
+    }
+
+    #[test]
+    fn control_block_layout() {
+        let private = offset_of!(Header, private_rest);
+        // Our offset, the offset in the declaration, and the offset given by the ABI.
+        let fields = [
+            (
+                offset_of!(Header, class),
+                offset_of!(ControlBlock, exception_class),
+                0,
+            ),
+            (
+                offset_of!(Header, cleanup),
+                offset_of!(ControlBlock, exception_cleanup),
+                8,
+            ),
+            (
+                offset_of!(Header, private1),
+                offset_of!(ControlBlock, unwinder_cache),
+                12,
+            ),
+            (
+                private + offset_of!(EhabiPrivate, barrier_cache),
+                offset_of!(ControlBlock, barrier_cache),
+                32,
+            ),
+            (
+                private + offset_of!(EhabiPrivate, cleanup_cache),
+                offset_of!(ControlBlock, cleanup_cache),
+                56,
+            ),
+            (
+                private + offset_of!(EhabiPrivate, pr_cache),
+                offset_of!(ControlBlock, pr_cache),
+                72,
+            ),
+        ];
+        for (ours, declared, abi) in fields {
+            assert_eq!(declared, abi);
+            assert_eq!(ours, abi);
+        }
+        assert_eq!(
+            private + offset_of!(EhabiPrivate, unwinder_cache_rest),
+            offset_of!(ControlBlock, unwinder_cache) + 4,
+        );
+
+        assert_eq!(size_of::<ControlBlock>(), 88);
+        assert!(size_of::<Header>() >= size_of::<ControlBlock>());
+        assert_eq!(align_of::<Header>(), 8);
+    }
+}
@@ -25,6 +25,15 @@
 //!
 //! During unwinding, all destructors of locals must be run, as if `return` was called. Exceptions
 //! may not be ignored or caught twice.
+//!
+//! Throw-by-value backends record the type of each thrown exception, as given by [`typeid::of`], so
+//! that the catching side can dispatch on it.
+
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::describe::Described;
+#[cfg(feature = "location")]
+use super::location::ThrowLocation;
+use core::any::TypeId;
 
 /// Throw-by-pointer backend.
 ///
@@ -76,6 +85,22 @@ pub unsafe trait ThrowByPointer {
     /// code that might `intercept` an exception without cooperation with the throwing side.
     unsafe fn throw(ex: *mut Self::ExceptionHeader) -> !;
 
+    /// Rethrow an exception caught by [`intercept`](Self::intercept).
+    ///
+    /// Backends may be able to continue unwinding more cheaply than [`throw`](Self::throw) starts
+    /// it, e.g. by skipping the search phase. By default, this is equivalent to `throw`.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of [`throw`](Self::throw) apply. In addition, the exception must be caught
+    /// by a nested `intercept` call: unlike `throw`, `rethrow` is not guaranteed to abort if no
+    /// frame catches the exception.
+    #[inline]
+    unsafe fn rethrow(ex: *mut Self::ExceptionHeader) -> ! {
+        // SAFETY: Required transitively.
+        unsafe { Self::throw(ex) }
+    }
+
     /// Catch an exception.
     ///
     /// This function returns `Ok` if the function returns normally, or `Err` if it throws (and the
@@ -118,6 +143,10 @@ pub unsafe trait ThrowByValue {
     /// For this reason, the caller must ensure no intermediate frames can affect unwinding. This
     /// includes not passing throwing callbacks to foreign crates, but also not using `throw` in own
     /// code that might `intercept` an exception without cooperation with the throwing side.
+    ///
+    /// With the `location` feature enabled, the location of the caller is recorded in the
+    /// exception.
+    #[cfg_attr(feature = "location", track_caller)]
     unsafe fn throw<E>(cause: E) -> !;
 
     /// Catch an exception.
@@ -140,6 +169,58 @@ pub unsafe trait ThrowByValue {
     unsafe fn intercept<Func: FnOnce() -> R, R, E>(
         func: Func,
     ) -> Result<R, (E, Self::RethrowHandle<E>)>;
+
+    /// Catch an exception of a type determined at runtime.
+    ///
+    /// This function returns `Ok` if the function returns normally, or `Err` if it throws (and the
+    /// thrown exception is not caught by a nested interceptor). The exception is finalized before
+    /// returning, so no rethrow handle is provided.
+    ///
+    /// The type of the exception is recorded by `throw` and passed to [`FromCaught::from_caught`],
+    /// which decides how to read the exception.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of [`FromCaught::from_caught`] must be satisfied for all exceptions
+    /// `func` may throw.
+    #[allow(
+        clippy::missing_errors_doc,
+        reason = "`Err` value is described immediately"
+    )]
+    unsafe fn intercept_dispatch<Func: FnOnce() -> R, R, D: FromCaught>(func: Func)
+        -> Result<R, D>;
+}
+
+/// A caught exception of a type that is only known at runtime.
+///
+/// This is passed to [`FromCaught::from_caught`] by [`ThrowByValue::intercept_dispatch`].
+pub trait CaughtException {
+    /// Read out the cause of the exception, finishing its handling.
+    ///
+    /// # Safety
+    ///
+    /// `E` must match the type of the thrown exception.
+    unsafe fn take<E>(self) -> E;
+
+    /// Describe the cause of the exception, if it was thrown by [`throw_err`](crate::throw_err).
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    fn describe(&self) -> Option<Described>;
+}
+
+/// A type constructible from an exception of one of several types.
+///
+/// This is implemented by sum types that [`ThrowByValue::intercept_dispatch`] can catch.
+pub trait FromCaught: Sized {
+    /// Construct a value from an exception with the given type ID.
+    ///
+    /// The implementation must call [`CaughtException::take`] exactly once, with the type matching
+    /// `type_id`.
+    ///
+    /// # Safety
+    ///
+    /// `type_id` must be the ID of the thrown type, as returned by [`typeid::of`], and the thrown
+    /// type must be one of the types this implementation recognizes.
+    unsafe fn from_caught(type_id: TypeId, ex: impl CaughtException) -> Self;
 }
 
 /// A rethrow handle.
@@ -158,9 +239,50 @@ pub trait RethrowHandle {
     /// # Safety
     ///
     /// All safety requirements of [`ThrowByValue::throw`] apply.
+    #[cfg_attr(feature = "location", track_caller)]
     unsafe fn rethrow<F>(self, new_cause: F) -> !;
+
+    /// Get the location the exception was thrown from.
+    #[cfg(feature = "location")]
+    fn location(&self) -> ThrowLocation;
 }
 
+/// The name of the backend Lithium was built with.
+///
+/// This is one of `"itanium"`, `"seh"`, `"emscripten"`, and `"panic"`, or `"unimplemented"` on
+/// targets that don't support unwinding. The backend is chosen by the build script according to
+/// the target and the toolchain, as described in [Platform support](crate#platform-support).
+pub const BACKEND_NAME: &str = if cfg!(backend = "itanium") {
+    "itanium"
+} else if cfg!(backend = "seh") {
+    "seh"
+} else if cfg!(backend = "emscripten") {
+    "emscripten"
+} else if cfg!(backend = "panic") {
+    "panic"
+} else {
+    "unimplemented"
+};
+
+/// Whether the backend throws exceptions natively, rather than via Rust panics.
+///
+/// The panic backend, used on stable Rust and on targets without a native backend, is
+/// considerably slower than the others. Crates that generate code for both exceptions and
+/// [`Result`]s can use this to fall back to plain `Result`s when exceptions are slow.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{BACKEND_NAME, IS_NATIVE_BACKEND};
+///
+/// assert_eq!(IS_NATIVE_BACKEND, BACKEND_NAME != "panic");
+/// ```
+pub const IS_NATIVE_BACKEND: bool = cfg!(any(
+    backend = "itanium",
+    backend = "seh",
+    backend = "emscripten"
+));
+
 #[cfg(backend = "itanium")]
 #[path = "itanium.rs"]
 mod imp;
@@ -182,6 +304,8 @@ mod imp;
 mod imp;
 
 pub(crate) use imp::ActiveBackend;
+#[cfg(all(backend = "itanium", not(target_arch = "wasm32")))]
+pub(crate) use imp::{delete_foreign, intercept_foreign, rethrow_foreign};
 
 #[cfg(test)]
 mod test {
@@ -198,7 +322,7 @@ mod test {
     #[test]
     fn intercept_err() {
         let result = unsafe {
-            ActiveBackend::intercept::<_, _, String>(|| {
+            ActiveBackend::intercept::<_, (), String>(|| {
                 ActiveBackend::throw(String::from("Hello, world!"));
             })
         };
@@ -219,11 +343,38 @@ mod test {
         );
     }
 
+    /// Catching as a wrong type aborts the process, so the test reruns itself in a child process.
+    #[test]
+    #[cfg(all(any(unix, windows), not(target_family = "wasm"), not(miri)))]
+    fn intercept_wrong_type() {
+        if std::env::var_os("LITHIUM_TEST_WRONG_TYPE").is_some() {
+            let _ = unsafe {
+                ActiveBackend::intercept::<_, (), u32>(|| {
+                    ActiveBackend::throw(String::from("Hello, world!"));
+                })
+            };
+            return;
+        }
+
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "backend::test::intercept_wrong_type",
+                "--nocapture",
+            ])
+            .env("LITHIUM_TEST_WRONG_TYPE", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("A Lithium exception was caught as a type it wasn't thrown with"));
+    }
+
     #[test]
     fn nested_intercept() {
         let result = unsafe {
             ActiveBackend::intercept::<_, _, ()>(|| {
-                ActiveBackend::intercept::<_, _, String>(|| {
+                ActiveBackend::intercept::<_, (), String>(|| {
                     ActiveBackend::throw(String::from("Hello, world!"));
                 })
             })
@@ -235,8 +386,8 @@ mod test {
     #[test]
     fn rethrow() {
         let result = unsafe {
-            ActiveBackend::intercept::<_, _, String>(|| {
-                let result = ActiveBackend::intercept::<_, _, String>(|| {
+            ActiveBackend::intercept::<_, (), String>(|| {
+                let result = ActiveBackend::intercept::<_, (), String>(|| {
                     ActiveBackend::throw(String::from("Hello, world!"));
                 });
                 let (ex2, handle) = result.unwrap_err();
@@ -248,6 +399,38 @@ mod test {
         assert_eq!(caught_ex, "Hello, world!");
     }
 
+    #[test]
+    fn rethrow_through_cleanup() {
+        struct Dropper<'a>(&'a mut usize);
+        impl Drop for Dropper<'_> {
+            fn drop(&mut self) {
+                *self.0 += 1;
+            }
+        }
+
+        let mut drops = 0;
+        let result = unsafe {
+            ActiveBackend::intercept::<_, (), String>(|| {
+                let _dropper = Dropper(&mut drops);
+                for _ in 0..2 {
+                    let result = ActiveBackend::intercept::<_, (), String>(|| {
+                        ActiveBackend::throw(String::from("Hello, world!"));
+                    });
+                    let (ex, _) = result.unwrap_err();
+                    assert_eq!(ex, "Hello, world!");
+                }
+                let (ex, handle) = ActiveBackend::intercept::<_, (), String>(|| {
+                    ActiveBackend::throw(String::from("Hello, world!"));
+                })
+                .unwrap_err();
+                handle.rethrow(ex + "!");
+            })
+        };
+        let (caught_ex, _) = result.unwrap_err();
+        assert_eq!(caught_ex, "Hello, world!!");
+        assert_eq!(drops, 1);
+    }
+
     #[test]
     fn destructors_are_run() {
         struct Dropper<'a>(&'a mut bool);
@@ -259,7 +442,7 @@ mod test {
 
         let mut destructor_was_run = false;
         let result = unsafe {
-            ActiveBackend::intercept::<_, _, String>(|| {
+            ActiveBackend::intercept::<_, (), String>(|| {
                 let _dropper = Dropper(&mut destructor_was_run);
                 ActiveBackend::throw(String::from("Hello, world!"));
             })
@@ -270,13 +453,37 @@ mod test {
         assert!(destructor_was_run);
     }
 
+    #[test]
+    fn panic_during_unwinding() {
+        struct Dropper;
+        impl Drop for Dropper {
+            fn drop(&mut self) {
+                let result = std::panic::catch_unwind(|| unsafe {
+                    ActiveBackend::intercept::<_, (), String>(|| {
+                        std::panic::resume_unwind(alloc::boxed::Box::new(1i32))
+                    })
+                });
+                assert_eq!(*result.unwrap_err().downcast_ref::<i32>().unwrap(), 1);
+            }
+        }
+
+        let result = unsafe {
+            ActiveBackend::intercept::<_, (), String>(|| {
+                let _dropper = Dropper;
+                ActiveBackend::throw(String::from("Hello, world!"));
+            })
+        };
+        let (caught_ex, _) = result.unwrap_err();
+        assert_eq!(caught_ex, "Hello, world!");
+    }
+
     #[test]
     fn nested_with_drop() {
         struct Dropper;
         impl Drop for Dropper {
             fn drop(&mut self) {
                 let result = unsafe {
-                    ActiveBackend::intercept::<_, _, String>(|| {
+                    ActiveBackend::intercept::<_, (), String>(|| {
                         ActiveBackend::throw(String::from("Awful idea"));
                     })
                 };
@@ -286,7 +493,7 @@ mod test {
         }
 
         let result = unsafe {
-            ActiveBackend::intercept::<_, _, String>(|| {
+            ActiveBackend::intercept::<_, (), String>(|| {
                 let _dropper = Dropper;
                 ActiveBackend::throw(String::from("Hello, world!"));
             })
@@ -1,4 +1,4 @@
-use super::ThrowByPointer;
+use super::{super::tls::get_state, ThrowByPointer};
 use alloc::boxed::Box;
 use core::mem::ManuallyDrop;
 use core::panic::AssertUnwindSafe;
@@ -13,8 +13,11 @@ pub(crate) struct ActiveBackend;
 /// but it's the next best thing.
 ///
 /// What we can't affect performance-wise is the allocation of an `_Unwind_Exception` (at least on
-/// Itanium), performed inside `std` with `Box`. What we *do* want to avoid is the allocation of
-/// `Box<dyn Any + Send + 'static>`, which stores the panic payload.
+/// Itanium), performed inside `std` with `Box`. `resume_unwind` allocates it in `panic_unwind`
+/// and frees it when the panic is caught, and neither step can be redirected to storage we
+/// provide, so we can't cache it across throws like the native backends do with their headers.
+/// What we *do* want to avoid is the allocation of `Box<dyn Any + Send + 'static>`, which stores
+/// the panic payload.
 ///
 /// Implementation-wise, the idea is simple. An unsized box stores two words, one used for data and
 /// one for RTTI. We can supply a unique value for the RTTI word to be able to recognize our panics,
@@ -43,7 +46,16 @@ pub(crate) struct ActiveBackend;
 /// So instead, we use a very similar approach based on exposed provenance. This is not UB under SB,
 /// but it cause deoptimizations elsewhere, so we only enable it conditionally. Pulling a Volkswagen
 /// is not something to be proud of, but at least we don't cheat under TB. If this approach turns
-/// out to not lead to deoptimizations in practice, we might enable it unconditionally.
+/// out to not lead to deoptimizations in practice, we might enable it unconditionally. The
+/// `strict-provenance` feature disables this detection for users who can't tolerate exposed
+/// provenance at all.
+///
+/// Recognizing our panics with `Box::<dyn Any>::is` takes a virtual call and a type ID comparison
+/// on each catch. To speed up the common case, `throw` also stores the address of the exception in
+/// a thread local, and `intercept` compares it with the data pointer of the payload first. The
+/// address is reset on each catch, so that it can't match a payload allocated at the same address
+/// after the exception is released. If the comparison fails, e.g. because another exception was
+/// thrown and caught while this one was unwinding, we fall back to `is`.
 ///
 /// [1]: https://github.com/rust-lang/unsafe-code-guidelines/issues/256
 // SAFETY: We basically use Rust's own mechanism for unwinding (panics), which satisfies all
@@ -59,6 +71,9 @@ unsafe impl ThrowByPointer for ActiveBackend {
     unsafe fn throw(ex: *mut LithiumMarker) -> ! {
         #[cfg(feature = "sound-under-stacked-borrows")]
         ex.expose_provenance();
+        // SAFETY: The reference is not used past the call frame.
+        let state = unsafe { get_state() };
+        state.pending_throw.set(ex.addr());
         // SAFETY: `LithiumMarker` is a ZST, so casting the pointer to a box is safe as long as the
         // pointer is aligned and valid, which it is by the safety requirements of this function.
         let ex = unsafe { Box::from_raw(ex) };
@@ -72,11 +87,17 @@ unsafe impl ThrowByPointer for ActiveBackend {
             // `ex`. This increases code size, and it's bad because `intercept` is inlined into
             // many callers. Fix this by temporarily storing `ex` in `ManuallyDrop`.
             let ex = ManuallyDrop::new(ex);
-            let is_marker = ex.is::<LithiumMarker>();
+            // SAFETY: The reference is not used past the call frame.
+            let state = unsafe { get_state() };
+            let pending = state.pending_throw.replace(0);
+            // Box pointers are non-null, so this never matches after a reset.
+            let is_marker = (&raw const **ex).addr() == pending || ex.is::<LithiumMarker>();
             let ex = ManuallyDrop::into_inner(ex);
             if is_marker {
-                // If this is a `LithiumMarker`, it must have been produced by `throw`, because this
-                // type is crate-local and we don't use it elsewhere. The safety requirements for
+                // If the address matches, the payload points at the exception object, which is
+                // alive, so it can't be another allocation. Otherwise, if this is a
+                // `LithiumMarker`, it must have been produced by `throw`, because this type is
+                // crate-local and we don't use it elsewhere. The safety requirements for
                 // `throw` require no messing with unwinding up to `intercept`, so this must have
                 // been our exception.
                 let ex: *mut LithiumMarker = Box::into_raw(ex).cast();
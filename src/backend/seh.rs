@@ -3,15 +3,20 @@
 // with exception constants and the throwing interface retrieved from ReactOS and Wine sources.
 
 use super::{
-    super::{abort, intrinsic::intercept},
-    RethrowHandle, ThrowByValue,
+    super::{
+        abort,
+        intrinsic::intercept,
+        location::{caller, ThrowLocation},
+    },
+    CaughtException, FromCaught, RethrowHandle, ThrowByValue,
 };
 use alloc::boxed::Box;
-use core::any::Any;
+use core::any::{Any, TypeId};
+use core::cell::UnsafeCell;
 use core::marker::{FnPtr, PhantomData};
 use core::mem::ManuallyDrop;
 use core::panic::PanicPayload;
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::sync::atomic::{AtomicU8, Ordering};
 
 pub(crate) struct ActiveBackend;
 
@@ -33,91 +38,145 @@ pub(crate) struct ActiveBackend;
 unsafe impl ThrowByValue for ActiveBackend {
     type RethrowHandle<E> = SehRethrowHandle;
 
+    #[cfg_attr(feature = "location", track_caller)]
     #[inline(always)]
     unsafe fn throw<E>(cause: E) -> ! {
-        // We have to initialize these variables late because we can't ask the linker to do the
-        // relative address computation for us. Using atomics for this removes races in Rust code,
-        // but atomic writes can still race with non-atomic reads in the vcruntime code. Luckily, we
-        // aren't going to LTO with vcruntime.
-        CATCHABLE_TYPE
-            .type_descriptor
-            .write(SmallPtr::new(&raw const TYPE_DESCRIPTOR));
-        CATCHABLE_TYPE.copy_function.write(SmallPtr::new_fn(copy));
-        CATCHABLE_TYPE_ARRAY.catchable_types[0].write(SmallPtr::new(&raw const CATCHABLE_TYPE));
-        THROW_INFO.destructor.write(SmallPtr::new_fn(cleanup));
-        THROW_INFO
-            .catchable_type_array
-            .write(SmallPtr::new(&raw const CATCHABLE_TYPE_ARRAY));
+        if TABLES_STATE.load(Ordering::Acquire) != INITIALIZED {
+            init_tables();
+        }
 
-        // SAFETY: We've just initialized the tables.
+        // SAFETY: We've just made sure the tables are initialized.
         unsafe {
-            do_throw(cause);
+            do_throw(cause, caller());
         }
     }
 
     #[inline(always)]
     unsafe fn intercept<Func: FnOnce() -> R, R, E>(func: Func) -> Result<R, (E, SehRethrowHandle)> {
-        enum CaughtUnwind<E> {
-            LithiumException(E),
-            RustPanic(Box<dyn Any + Send + 'static>),
-        }
-
-        let catch = |ex: *mut u8| {
-            // This callback is not allowed to unwind, so we can't rethrow exceptions.
-            if ex.is_null() {
-                // This is a foreign exception.
+        intercept_lithium(func, |ex| {
+            // SAFETY: `ex` points at a valid Lithium exception.
+            let location = unsafe { (*ex.ex).header.location };
+            // SAFETY: As above.
+            if unsafe { (*ex.ex).header.type_id } != typeid::of::<E>() {
+                // Catching as a wrong type would transmute the cause. Comparing type IDs is cheap
+                // enough to do in release builds too.
                 abort(
-                    "Lithium caught a foreign exception. This is unsupported. The process will now terminate.\n",
+                    "A Lithium exception was caught as a type it wasn't thrown with. This is \
+                     undefined behavior. The process will now terminate.\n",
                 );
             }
+            // SAFETY: By the safety requirement, the exception has type `E`, as checked above.
+            (unsafe { ex.take::<E>() }, location)
+        })
+        .map_err(|(cause, location)| (cause, SehRethrowHandle { location }))
+    }
 
-            let ex_lithium: *mut Exception<E> = ex.cast();
-
-            // SAFETY: If `ex` is non-null, it's a `rust_panic` exception, which can either be
-            // thrown by us or by the Rust runtime; both have the `header.canary` field as the first
-            // field in their structures.
-            if unsafe { (*ex_lithium).header.canary } != (&raw const THROW_INFO).cast() {
-                // This is a Rust exception. We can't rethrow it immediately from this nounwind
-                // callback, so let's catch it first.
-                // SAFETY: `ex` is the callback value of `core::intrinsics::catch_unwind`.
-                let payload = unsafe { __rust_panic_cleanup(ex) };
-                // SAFETY: `__rust_panic_cleanup` returns a Box.
-                let payload = unsafe { Box::from_raw(payload) };
-                return CaughtUnwind::RustPanic(payload);
-            }
+    #[inline(always)]
+    unsafe fn intercept_dispatch<Func: FnOnce() -> R, R, D: FromCaught>(
+        func: Func,
+    ) -> Result<R, D> {
+        intercept_lithium(func, |ex| {
+            // SAFETY: `ex` points at a valid Lithium exception.
+            let type_id = unsafe { (*ex.ex).header.type_id };
+            // SAFETY: `type_id` is the ID of the thrown type, and `D` recognizes it by the safety
+            // requirement.
+            unsafe { D::from_caught(type_id, ex) }
+        })
+    }
+}
 
-            // We catch the exception by reference, so the C++ runtime will drop it. Tell our
-            // destructor to calm down.
-            // SAFETY: This is our exception, so `ex_lithium` points at a valid instance of
-            // `Exception<E>`.
-            unsafe {
-                (*ex_lithium).header.caught = true;
-            }
-            // SAFETY: As above.
-            let cause = unsafe { &mut (*ex_lithium).cause };
-            // SAFETY: We only read the cause here, so no double copies.
-            CaughtUnwind::LithiumException(unsafe { ManuallyDrop::take(cause) })
-        };
-
-        match intercept(func, catch) {
-            Ok(value) => Ok(value),
-            Err(CaughtUnwind::LithiumException(cause)) => Err((cause, SehRethrowHandle)),
-            Err(CaughtUnwind::RustPanic(payload)) => throw_std_panic(payload),
+/// Catch Lithium exceptions, passing Rust panics through.
+///
+/// `read` is invoked inside the catch handler, while the exception object is still alive. It must
+/// not unwind.
+#[inline(always)]
+fn intercept_lithium<Func: FnOnce() -> R, R, T>(
+    func: Func,
+    read: impl FnOnce(CaughtSehException) -> T,
+) -> Result<R, T> {
+    enum CaughtUnwind<T> {
+        LithiumException(T),
+        RustPanic(Box<dyn Any + Send + 'static>),
+    }
+
+    let catch = |ex: *mut u8| {
+        // This callback is not allowed to unwind, so we can't rethrow exceptions.
+        if ex.is_null() {
+            // This is a foreign exception.
+            abort(
+                "Lithium caught a foreign exception. This is unsupported. The process will now terminate.\n",
+            );
         }
+
+        let ex_lithium: *mut Exception<()> = ex.cast();
+
+        // SAFETY: If `ex` is non-null, it's a `rust_panic` exception, which can either be thrown
+        // by us or by the Rust runtime; both have the `header.canary` field as the first field in
+        // their structures.
+        if unsafe { (*ex_lithium).header.canary } != (&raw const THROW_INFO).cast() {
+            // This is a Rust exception. We can't rethrow it immediately from this nounwind
+            // callback, so let's catch it first.
+            // SAFETY: `ex` is the callback value of `core::intrinsics::catch_unwind`.
+            let payload = unsafe { __rust_panic_cleanup(ex) };
+            // SAFETY: `__rust_panic_cleanup` returns a Box.
+            let payload = unsafe { Box::from_raw(payload) };
+            return CaughtUnwind::RustPanic(payload);
+        }
+
+        CaughtUnwind::LithiumException(read(CaughtSehException { ex: ex_lithium }))
+    };
+
+    match intercept(func, catch) {
+        Ok(value) => Ok(value),
+        Err(CaughtUnwind::LithiumException(value)) => Err(value),
+        Err(CaughtUnwind::RustPanic(payload)) => throw_std_panic(payload),
+    }
+}
+
+// Type invariant: `ex` points at a valid Lithium exception object, which is alive until the catch
+// handler returns.
+struct CaughtSehException {
+    ex: *mut Exception<()>,
+}
+
+impl CaughtException for CaughtSehException {
+    #[inline(always)]
+    unsafe fn take<E>(self) -> E {
+        let ex: *mut Exception<E> = self.ex.cast();
+        // We catch the exception by reference, so the C++ runtime will drop it. Tell our
+        // destructor to calm down.
+        // SAFETY: This is our exception, so `ex` points at a valid instance of `Exception<E>` by
+        // the safety requirement.
+        unsafe {
+            (*ex).header.caught = true;
+        }
+        // SAFETY: As above.
+        let cause = unsafe { &mut (*ex).cause };
+        // SAFETY: `take` consumes `self`, so no double copies.
+        unsafe { ManuallyDrop::take(cause) }
     }
 }
 
 #[derive(Debug)]
-pub(crate) struct SehRethrowHandle;
+pub(crate) struct SehRethrowHandle {
+    location: ThrowLocation,
+}
 
 impl RethrowHandle for SehRethrowHandle {
+    #[cfg_attr(feature = "location", track_caller)]
     #[inline(never)]
     unsafe fn rethrow<F>(self, new_cause: F) -> ! {
         // SAFETY: This is a rethrow, so the first throw must have initialized the tables.
         unsafe {
-            do_throw(new_cause);
+            do_throw(new_cause, caller());
         }
     }
+
+    #[cfg(feature = "location")]
+    #[inline]
+    fn location(&self) -> ThrowLocation {
+        self.location
+    }
 }
 
 /// Throw an exception as a C++ exception.
@@ -125,11 +184,13 @@ impl RethrowHandle for SehRethrowHandle {
 /// # Safety
 ///
 /// The caller must ensure all global tables are initialized.
-unsafe fn do_throw<E>(cause: E) -> ! {
+unsafe fn do_throw<E>(cause: E, location: ThrowLocation) -> ! {
     let mut exception = Exception {
         header: ExceptionHeader {
             canary: (&raw const THROW_INFO).cast(), // any static will work
             caught: false,
+            type_id: typeid::of::<E>(),
+            location,
         },
         cause: ManuallyDrop::new(cause),
     };
@@ -144,6 +205,8 @@ unsafe fn do_throw<E>(cause: E) -> ! {
 struct ExceptionHeader {
     canary: *const (), // From Rust ABI
     caught: bool,
+    type_id: TypeId,
+    location: ThrowLocation,
 }
 
 #[repr(C)]
@@ -152,6 +215,10 @@ struct Exception<E> {
     cause: ManuallyDrop<E>,
 }
 
+// `thiscall` is only a distinct calling convention on x86. On x86-64, AArch64, and Arm64EC, member
+// functions use the C calling convention. On Arm64EC, the compiler additionally emits entry thunks
+// for address-taken functions, so the C++ runtime can call them regardless of whether it's x64 or
+// Arm64EC code.
 #[cfg(target_arch = "x86")]
 macro_rules! thiscall {
     ($(#[$outer:meta])* fn $($tt:tt)*) => {
@@ -228,7 +295,7 @@ static TYPE_DESCRIPTOR: TypeDescriptor = TypeDescriptor {
 
 static CATCHABLE_TYPE: CatchableType = CatchableType {
     properties: 0,
-    type_descriptor: SmallPtr::null(), // filled by throw
+    type_descriptor: SmallPtr::null(), // filled by init_tables
     this_displacement: PointerToMemberData {
         member_displacement: 0,
         virtual_base_pointer_displacement: -1,
@@ -237,23 +304,92 @@ static CATCHABLE_TYPE: CatchableType = CatchableType {
     // We don't really have a good answer to this, and we don't let the C++ runtime catch our
     // exception, so it's not a big problem.
     size_or_offset: 1,
-    copy_function: SmallPtr::null(), // filled by throw
+    copy_function: SmallPtr::null(), // filled by init_tables
 };
 
 static CATCHABLE_TYPE_ARRAY: CatchableTypeArray = CatchableTypeArray {
     n_types: 1,
     catchable_types: [
-        SmallPtr::null(), // filled by throw
+        SmallPtr::null(), // filled by init_tables
     ],
 };
 
 static THROW_INFO: ThrowInfo = ThrowInfo {
     attributes: 0,
-    destructor: SmallPtr::null(), // filled by throw
+    destructor: SmallPtr::null(), // filled by init_tables
     forward_compat: SmallPtr::null(),
-    catchable_type_array: SmallPtr::null(), // filled by throw
+    catchable_type_array: SmallPtr::null(), // filled by init_tables
 };
 
+/// The state of the tables: [`UNINITIALIZED`], [`INITIALIZING`], or [`INITIALIZED`].
+static TABLES_STATE: AtomicU8 = AtomicU8::new(UNINITIALIZED);
+
+/// No thread has started filling in the tables.
+const UNINITIALIZED: u8 = 0;
+
+/// A thread is filling in the tables.
+const INITIALIZING: u8 = 1;
+
+/// The tables are filled in and may be read.
+const INITIALIZED: u8 = 2;
+
+/// Fill in the addresses in the tables, or wait until another thread does it.
+///
+/// We have to initialize these variables late because we can't ask the linker to do the relative
+/// address computation for us. This only happens on the first throw, so that the hot path only
+/// checks the state.
+///
+/// The tables are read by the vcruntime code with plain loads, so they are written with plain
+/// stores too, by a single thread, before publishing them with a release store. Each throw observes
+/// the published state with an acquire load before passing the tables to the runtime, so the reads
+/// never race with the writes, even if the runtime is inlined into Rust code by cross-language LTO.
+#[cold]
+#[inline(never)]
+fn init_tables() {
+    if TABLES_STATE
+        .compare_exchange(
+            UNINITIALIZED,
+            INITIALIZING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        // Another thread is filling in the tables. This can't take long, as it doesn't block.
+        while TABLES_STATE.load(Ordering::Acquire) != INITIALIZED {
+            core::hint::spin_loop();
+        }
+        return;
+    }
+
+    // SAFETY: We've moved the state from `UNINITIALIZED` to `INITIALIZING`, so we're the only
+    // thread writing the tables, and no thread reads them until the state is `INITIALIZED`.
+    unsafe {
+        CATCHABLE_TYPE
+            .type_descriptor
+            .set(SmallPtr::new(&raw const TYPE_DESCRIPTOR));
+    }
+    // SAFETY: As above.
+    unsafe {
+        CATCHABLE_TYPE.copy_function.set(SmallPtr::new_fn(copy));
+    }
+    // SAFETY: As above.
+    unsafe {
+        CATCHABLE_TYPE_ARRAY.catchable_types[0].set(SmallPtr::new(&raw const CATCHABLE_TYPE));
+    }
+    // SAFETY: As above.
+    unsafe {
+        THROW_INFO.destructor.set(SmallPtr::new_fn(cleanup));
+    }
+    // SAFETY: As above.
+    unsafe {
+        THROW_INFO
+            .catchable_type_array
+            .set(SmallPtr::new(&raw const CATCHABLE_TYPE_ARRAY));
+    }
+    TABLES_STATE.store(INITIALIZED, Ordering::Release);
+}
+
 fn abort_on_caught_by_cxx() -> ! {
     abort("A Lithium exception was caught by a non-Lithium catch mechanism. This is undefined behavior. The process will now terminate.\n");
 }
@@ -293,13 +429,19 @@ extern "C" {
     static TYPE_INFO_VTABLE: *const ();
 }
 
+/// A 32-bit pointer, as used in MSVC exception tables.
+///
+/// On 32-bit targets, this is an absolute address. On 64-bit targets, including Arm64EC, this is an
+/// offset from `__ImageBase`, which the runtime receives in `ExceptionRecordParameters`.
+///
+/// The value is only modified by [`init_tables`], which synchronizes with all readers.
 #[repr(transparent)]
 struct SmallPtr<P> {
-    value: AtomicU32,
+    value: UnsafeCell<u32>,
     phantom: PhantomData<P>,
 }
 
-// SAFETY: `!Sync` for pointers is stupid.
+// SAFETY: `!Sync` for pointers is stupid. Writes are synchronized by `init_tables`.
 unsafe impl<P> Sync for SmallPtr<P> {}
 
 impl<P> SmallPtr<P> {
@@ -310,31 +452,43 @@ impl<P> SmallPtr<P> {
     /// Panics if `p` is too far from the image base.
     #[inline]
     fn from_erased(p: *const ()) -> Self {
+        // The runtime reads these addresses from outside the AM, so exposing provenance is the
+        // conservative choice, unless the user opted into strict provenance.
+        #[cfg(not(feature = "strict-provenance"))]
+        let addr = p.expose_provenance();
+        #[cfg(feature = "strict-provenance")]
+        let addr = p.addr();
         #[cfg(target_pointer_width = "32")]
-        let value = p.expose_provenance() as u32;
+        let value = addr as u32;
         #[cfg(target_pointer_width = "64")]
         #[expect(
             clippy::cast_possible_truncation,
             reason = "PE images are at most 4 GiB long"
         )]
-        let value = p
-            .expose_provenance()
-            .wrapping_sub((&raw const __ImageBase).addr()) as u32;
+        let value = addr.wrapping_sub((&raw const __ImageBase).addr()) as u32;
         Self {
-            value: AtomicU32::new(value),
+            value: UnsafeCell::new(value),
             phantom: PhantomData,
         }
     }
 
     const fn null() -> Self {
         Self {
-            value: AtomicU32::new(0),
+            value: UnsafeCell::new(0),
             phantom: PhantomData,
         }
     }
 
-    fn write(&self, rhs: SmallPtr<P>) {
-        self.value.store(rhs.value.into_inner(), Ordering::Relaxed);
+    /// Replace the value.
+    ///
+    /// # Safety
+    ///
+    /// The write must not race with other accesses, including reads by the C++ runtime.
+    unsafe fn set(&self, rhs: SmallPtr<P>) {
+        // SAFETY: Required transitively.
+        unsafe {
+            *self.value.get() = rhs.value.into_inner();
+        }
     }
 }
 
@@ -1,4 +1,6 @@
-use super::{RethrowHandle, ThrowByValue};
+#[cfg(feature = "location")]
+use super::super::location::ThrowLocation;
+use super::{FromCaught, RethrowHandle, ThrowByValue};
 
 pub(crate) struct ActiveBackend;
 
@@ -16,6 +18,12 @@ unsafe impl ThrowByValue for ActiveBackend {
     ) -> Result<R, (E, Self::RethrowHandle<E>)> {
         unimplemented!()
     }
+
+    unsafe fn intercept_dispatch<Func: FnOnce() -> R, R, D: FromCaught>(
+        _func: Func,
+    ) -> Result<R, D> {
+        unimplemented!()
+    }
 }
 
 #[derive(Debug)]
@@ -25,4 +33,9 @@ impl RethrowHandle for UnimplementedRethrowHandle {
     unsafe fn rethrow<F>(self, _new_cause: F) -> ! {
         unimplemented!()
     }
+
+    #[cfg(feature = "location")]
+    fn location(&self) -> ThrowLocation {
+        unimplemented!()
+    }
 }
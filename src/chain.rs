@@ -0,0 +1,210 @@
+use super::{api::InFlightException, in_place::intercept_in_place};
+use alloc::{borrow::Cow, vec::Vec};
+use core::fmt;
+
+/// An exception cause with a chain of context messages.
+///
+/// [`with_context`] appends a message to the chain as the exception propagates through a frame.
+/// The cause is kept in place inside the exception object while doing so, so adding context costs
+/// no more than pushing to a [`Vec`], rather than allocating a new error per frame as
+/// `anyhow::Error::context` does.
+///
+/// Messages are ordered from the innermost, added closest to the throw site, to the outermost.
+/// [`Display`](fmt::Display) prints them in the reverse order, followed by the cause, separated by
+/// colons.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw, with_context, Chain};
+///
+/// // SAFETY: Only `Chain<&str>` is thrown.
+/// let result = unsafe {
+///     catch::<(), Chain<&str>>(|| {
+///         with_context::<_, &str>("reading config", || {
+///             with_context::<_, &str>("parsing line 3", || throw(Chain::new("unexpected `=`")))
+///         })
+///     })
+/// };
+/// assert_eq!(
+///     result.unwrap_err().to_string(),
+///     "reading config: parsing line 3: unexpected `=`",
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Chain<E> {
+    cause: E,
+    context: Vec<Cow<'static, str>>,
+}
+
+impl<E> Chain<E> {
+    /// Wrap a cause, with no context.
+    #[inline]
+    pub const fn new(cause: E) -> Self {
+        Self {
+            cause,
+            context: Vec::new(),
+        }
+    }
+
+    /// Get a reference to the cause.
+    #[inline]
+    pub const fn cause(&self) -> &E {
+        &self.cause
+    }
+
+    /// Take the cause, discarding the context.
+    #[inline]
+    pub fn into_cause(self) -> E {
+        self.cause
+    }
+
+    /// Get the context messages, from the innermost to the outermost.
+    #[inline]
+    pub fn context(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.context.iter().map(|message| &**message)
+    }
+
+    /// Append a context message.
+    #[inline]
+    pub fn push_context(&mut self, message: impl Into<Cow<'static, str>>) {
+        self.context.push(message.into());
+    }
+
+    /// Get the context messages, from the innermost to the outermost, without borrowing each one.
+    pub(crate) fn context_messages(&self) -> &[Cow<'static, str>] {
+        &self.context
+    }
+}
+
+/// An exception cause carrying a chain of context messages, such as [`Chain`] or
+/// [`Error`](crate::Error).
+pub(crate) trait Contextual {
+    /// Append a context message.
+    fn push_message(&mut self, message: Cow<'static, str>);
+}
+
+impl<E> Contextual for Chain<E> {
+    #[inline]
+    fn push_message(&mut self, message: Cow<'static, str>) {
+        self.context.push(message);
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Chain<E> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for message in self.context().rev() {
+            write!(f, "{message}: ")?;
+        }
+        self.cause.fmt(f)
+    }
+}
+
+impl<E> InFlightException<Chain<E>> {
+    /// Rethrow the exception, appending a context message to the chain.
+    ///
+    /// This collapses the `Err((chain, in_flight)) => in_flight.rethrow(chain.context(...))`
+    /// pattern into one call. To add context without moving the cause out of the exception in the
+    /// first place, use [`with_context`].
+    ///
+    /// # Safety
+    ///
+    /// The requirements of [`InFlightException::rethrow`] apply.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub unsafe fn rethrow_with_context(
+        self,
+        mut cause: Chain<E>,
+        message: impl Into<Cow<'static, str>>,
+    ) -> ! {
+        cause.push_context(message);
+        // SAFETY: Requirements forwarded.
+        unsafe { self.rethrow(cause) }
+    }
+}
+
+/// Call a function, adding a context message to the [`Chain`] it throws.
+///
+/// If `func` returns a value, it's returned as-is. If `func` throws, `message` is appended to the
+/// chain and the exception is rethrown without moving the cause.
+///
+/// `E` can't be inferred from `func` and usually needs to be specified explicitly.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `Chain<E>`, and the requirements of
+/// [`InFlightException::rethrow`](crate::InFlightException::rethrow) apply to the rethrown
+/// exception. See the safety section of [this crate](crate) for more information.
+#[inline]
+pub unsafe fn with_context<R, E>(
+    message: impl Into<Cow<'static, str>>,
+    func: impl FnOnce() -> R,
+) -> R {
+    // SAFETY: Requirements forwarded.
+    unsafe { add_context::<R, Chain<E>>(message.into(), func) }
+}
+
+/// Call a function, appending a context message to the cause of type `C` it throws in place.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `C`, and the requirements of
+/// [`InFlightException::rethrow`] apply to the rethrown exception.
+#[inline]
+pub(crate) unsafe fn add_context<R, C: Contextual>(
+    message: Cow<'static, str>,
+    func: impl FnOnce() -> R,
+) -> R {
+    // SAFETY: `func` only throws `C` by the safety requirements.
+    match unsafe { intercept_in_place::<R, C>(func) } {
+        Ok(value) => value,
+        Err(mut ex) => {
+            ex.peek_mut().push_message(message);
+            // SAFETY: No exceptions were thrown since `intercept_in_place` returned. The rest is
+            // required transitively.
+            unsafe { ex.rethrow_same() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, intercept, throw};
+    use alloc::{format, string::ToString};
+
+    #[test]
+    fn returns() {
+        let result = unsafe { catch::<i32, Chain<i32>>(|| with_context::<_, i32>("unused", || 1)) };
+        assert_eq!(result, Ok(1));
+    }
+
+    #[test]
+    fn accumulates() {
+        let result = unsafe {
+            catch::<(), Chain<i32>>(|| {
+                with_context::<_, i32>("outer", || {
+                    with_context::<_, i32>(format!("inner {}", 2), || throw(Chain::new(3)));
+                });
+            })
+        };
+        let chain = result.unwrap_err();
+        assert_eq!(*chain.cause(), 3);
+        assert!(chain.context().eq(["inner 2", "outer"]));
+        assert_eq!(chain.to_string(), "outer: inner 2: 3");
+        assert_eq!(chain.into_cause(), 3);
+    }
+
+    #[test]
+    fn rethrow_with_context() {
+        let result = unsafe {
+            catch::<(), Chain<i32>>(|| {
+                let (chain, in_flight) =
+                    intercept::<(), Chain<i32>>(|| throw(Chain::new(1))).unwrap_err();
+                in_flight.rethrow_with_context(chain, "outer");
+            })
+        };
+        assert_eq!(result.unwrap_err().to_string(), "outer: 1");
+    }
+}
@@ -0,0 +1,156 @@
+//! Runtime-checked throwing.
+//!
+//! With the `checked` feature enabled, the bookkeeping that debug builds use to detect misplaced
+//! exceptions is kept in release builds too, and [`try_throw`] uses it to throw safely: the
+//! exception is only thrown if the nearest catch frame is known to accept it, and handed back
+//! otherwise. This trades a few thread-local accesses per throw and catch for not having to prove
+//! the safety requirements of [`throw`](fn@crate::throw) by hand.
+
+use super::{api::throw, debug::can_throw};
+use core::convert::Infallible;
+
+/// Throw an exception if a matching catch frame is ready to receive it.
+///
+/// The exception is thrown if the nearest Lithium catch frame on the current thread accepts
+/// exceptions of type `E`, i.e. it's a [`catch`](crate::catch) or [`intercept`](crate::intercept)
+/// of `E` or a similar typed frame, and no exception handle or unwinding exception stands between
+/// it and the caller. Otherwise, the cause is returned in [`Err`] and nothing is thrown.
+///
+/// Frames that accept exceptions of any type are rejected, as are throws while an
+/// [`ExceptionStack`](crate::ExceptionStack) is entered, since neither can be verified.
+///
+/// As with [`catch_scoped`](crate::catch_scoped), foreign frames, such as
+/// [`std::panic::catch_unwind`], are invisible to the check and must not be placed between the call
+/// and the catch frame. Such frames can't cause undefined behavior here, but the exception is then
+/// lost or the process aborts, depending on the backend.
+///
+/// # Errors
+///
+/// Returns `cause` back if the exception can't be thrown safely.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, try_throw};
+///
+/// fn parse(s: &str) -> i32 {
+///     s.parse().unwrap_or_else(|_| {
+///         let Err(cause) = try_throw(format!("invalid number: {s}"));
+///         panic!("uncaught error: {cause}");
+///     })
+/// }
+///
+/// // SAFETY: Only `String` is thrown.
+/// let result = unsafe { catch::<_, String>(|| parse("x")) };
+/// assert_eq!(result.unwrap_err(), "invalid number: x");
+///
+/// // No catch frame is active, so nothing is thrown.
+/// assert_eq!(try_throw(2i32), Err(2));
+/// ```
+///
+/// Type IDs don't distinguish lifetimes, so only `'static` causes can be thrown. Otherwise, a
+/// borrowed cause could be caught as `'static` and outlive its referent:
+///
+/// ```compile_fail
+/// use lithium::try_throw;
+///
+/// let s = String::from("short-lived");
+/// let _ = try_throw(s.as_str());
+/// ```
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub fn try_throw<E: 'static>(cause: E) -> Result<Infallible, E> {
+    if !can_throw::<E>() {
+        return Err(cause);
+    }
+    // SAFETY: The nearest catch frame accepts `E`, and no in-flight exception or handle stands
+    // between it and the caller, as checked above.
+    unsafe { throw(cause) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, intercept};
+    use alloc::string::String;
+
+    #[test]
+    fn throws_into_matching_frame() {
+        let result = unsafe {
+            catch::<(), String>(|| {
+                let Err(_) = try_throw(String::from("x"));
+                unreachable!();
+            })
+        };
+        assert_eq!(result.unwrap_err(), "x");
+    }
+
+    #[test]
+    fn no_frame() {
+        assert_eq!(try_throw(1i32), Err(1));
+    }
+
+    #[test]
+    fn mismatched_frame() {
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                assert_eq!(try_throw(1u8), Err(1));
+            })
+        };
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn frames_past_capacity() {
+        fn nest(depth: usize) -> Result<(), i32> {
+            unsafe {
+                catch(|| {
+                    if depth > 0 {
+                        nest(depth - 1).unwrap();
+                    } else {
+                        assert_eq!(try_throw(1i32), Err(1));
+                    }
+                })
+            }
+        }
+        assert_eq!(nest(100), Ok(()));
+    }
+
+    #[test]
+    fn past_handle() {
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                let (_, ex) = intercept::<(), i32>(|| {
+                    let Err(_) = try_throw(1i32);
+                    unreachable!();
+                })
+                .unwrap_err();
+                assert_eq!(try_throw(2i32), Err(2));
+                ex.rethrow(3)
+            })
+        };
+        assert_eq!(result, Err(3));
+    }
+
+    #[test]
+    fn during_unwinding() {
+        struct Guard;
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                // The frame below belongs to the exception being unwound, so rejecting is
+                // conservative here, but the frame can't tell throws before and after apart.
+                assert_eq!(try_throw(2i32), Err(2));
+            }
+        }
+
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                let _guard = Guard;
+                let Err(_) = try_throw(1i32);
+                unreachable!();
+            })
+        };
+        assert_eq!(result, Err(1));
+    }
+}
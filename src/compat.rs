@@ -0,0 +1,113 @@
+//! Analogues of the [`std::panic`](mod@std::panic) unwinding API.
+//!
+//! Code that uses panics as its error channel can migrate to Lithium incrementally by replacing
+//! `std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe}` imports with imports from this
+//! module. The functions have the same shape as their counterparts, with the following differences:
+//!
+//! - The payload is typed: [`resume_unwind`] throws a value of any type `P`, and [`catch_unwind`]
+//!   returns it as-is instead of as `Box<dyn Any + Send>`. Nothing is boxed, and `P` doesn't have
+//!   to be [`Send`] or `'static`. Since there is no downcasting, both sides must agree on `P`.
+//! - Both functions are `unsafe`: as with [`catch`] and [`throw`](fn@throw), the payload types are
+//!   not checked, and the safety requirements of [this crate](crate) apply.
+//! - Rust panics are not caught by [`catch_unwind`] and propagate through it unchanged, so panics
+//!   still mean bugs, and exceptions mean recoverable errors.
+//!
+//! As with [`std::panic::catch_unwind`], the callback must be [`UnwindSafe`], and
+//! [`AssertUnwindSafe`] opts out of the check. Unlike with panics, this is not required for
+//! soundness, but keeping the bound means that migrated code is checked for the same logic errors.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::compat::{catch_unwind, resume_unwind, AssertUnwindSafe};
+//!
+//! let mut attempts = 0;
+//! // SAFETY: Only `&str` is thrown.
+//! let result = unsafe {
+//!     catch_unwind::<_, (), &str>(AssertUnwindSafe(|| {
+//!         attempts += 1;
+//!         resume_unwind("connection refused");
+//!     }))
+//! };
+//! assert_eq!(result, Err("connection refused"));
+//! assert_eq!(attempts, 1);
+//! ```
+
+use super::api::{catch, throw};
+pub use core::panic::AssertUnwindSafe;
+use core::panic::UnwindSafe;
+
+/// Invoke a closure, catching the exception it throws, if any.
+///
+/// If `func` returns a value, it's wrapped in [`Ok`]. If `func` throws an exception of type `P`,
+/// e.g. with [`resume_unwind`], the payload is returned in [`Err`]. Rust panics are propagated
+/// as-is.
+///
+/// This is [`catch`] with an [`UnwindSafe`] bound. See [the module documentation](self) for how
+/// this differs from [`std::panic::catch_unwind`].
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `P`. See the safety section of [this crate](crate)
+/// for more information.
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R, P>(func: F) -> Result<R, P> {
+    // SAFETY: Requirements forwarded.
+    unsafe { catch::<R, P>(func) }
+}
+
+/// Throw an exception with the given payload.
+///
+/// This is [`throw`](fn@throw) under the name of [`std::panic::resume_unwind`]. Like its
+/// counterpart, it doesn't invoke the panic hook.
+///
+/// # Safety
+///
+/// See the safety section of [this crate](crate): the exception must be caught by a Lithium frame
+/// expecting `P`, such as [`catch_unwind`].
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub unsafe fn resume_unwind<P>(payload: P) -> ! {
+    // SAFETY: Requirements forwarded.
+    unsafe { throw(payload) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::String;
+    use core::cell::Cell;
+
+    #[test]
+    fn returns() {
+        let result = unsafe { catch_unwind::<_, _, String>(|| 1) };
+        assert_eq!(result, Ok(1));
+    }
+
+    #[test]
+    fn resumes() {
+        let result =
+            unsafe { catch_unwind::<_, (), String>(|| resume_unwind(String::from("Oops!"))) };
+        assert_eq!(result.unwrap_err(), "Oops!");
+    }
+
+    #[test]
+    fn not_static() {
+        let cell = Cell::new(1);
+        let result =
+            unsafe { catch_unwind::<_, (), &Cell<i32>>(AssertUnwindSafe(|| resume_unwind(&cell))) };
+        assert_eq!(result.unwrap_err().get(), 1);
+    }
+
+    #[cfg(catch_unwind = "std")]
+    #[test]
+    fn panics_propagate() {
+        let result =
+            std::panic::catch_unwind(|| unsafe { catch_unwind::<_, (), i32>(|| panic!("Oops!")) });
+        result.unwrap_err();
+    }
+}
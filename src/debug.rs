@@ -0,0 +1,603 @@
+//! Debug-mode detection of foreign frames.
+//!
+//! Exceptions must only be caught by Lithium. If a `std::panic::catch_unwind` or a similar frame
+//! sits between `throw` and `catch`, the exception is swallowed by the foreign frame and never
+//! reaches Lithium, which is UB we can't prevent statically.
+//!
+//! In debug builds, we track two thread-local counters: the number of active Lithium catch frames,
+//! and the number of exceptions in flight. `throw` verifies that some Lithium frame is ready to
+//! catch the exception, and each catch frame verifies that exactly the exceptions thrown inside it
+//! have been caught by Lithium. If an exception disappears on the way, the nearest catch frame
+//! notices the mismatch and panics.
+//!
+//! This is a best-effort check: it detects swallowed exceptions after the fact, and it cannot
+//! detect foreign frames that rethrow the exception as-is.
+//!
+//! In addition, each catch frame records the exception types it expects in a thread-local list,
+//! and `throw` verifies that the nearest frame accepts the thrown type. Catching an exception as a
+//! wrong type would otherwise silently transmute the cause. In release builds, this check is
+//! replaced by a cheaper one at the catch site, which aborts the process on a type ID mismatch.
+//!
+//! Finally, live `InFlightException`s and `InPlaceException`s are tracked in another thread-local
+//! list. Handles must be released in the reverse order of creation, and no exception may be thrown
+//! past a live handle, i.e. unless it's caught by a frame entered after the handle was created.
+//! Both rules are checked when handles are released and when exceptions are thrown, respectively.
+//! Handles created while an `ExceptionStack` is entered are not tracked, as they may be released on
+//! another thread.
+//!
+//! Both lists are stored inline in the thread state rather than on the heap, so that the checks
+//! work with `LITHIUM_NO_HEAP`. Frames and handles nested deeper than the capacity of the lists are
+//! only counted, and the checks that need them are skipped until the nesting gets shallower again.
+//!
+//! The checks are invoked through `hooks`, along with the rethrow limit in `rethrows`. In release
+//! builds, all checks compile to nothing, unless the `checked` feature is enabled: the `checks` cfg
+//! covers both cases. The bookkeeping is then also used by `try_throw` to verify that a matching
+//! catch frame is ready before throwing.
+
+#[cfg(all(
+    checks,
+    any(backend = "itanium", backend = "emscripten", backend = "panic")
+))]
+use super::stacked_exceptions::is_owned_stack_current;
+use super::tls::{get_state, without_interrupts};
+use core::any::{type_name, TypeId};
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+/// Per-thread frame counters.
+pub struct Counters {
+    /// The number of active Lithium catch frames.
+    depth: Cell<usize>,
+    /// The number of thrown exceptions not yet caught by Lithium.
+    in_flight: Cell<usize>,
+    /// The active catch frames, from the outermost to the innermost.
+    frames: RefCell<Records<Frame>>,
+    /// The IDs of live in-flight exception handles, in the order of creation.
+    handles: RefCell<Records<usize>>,
+    /// The ID to assign to the next catch frame or handle.
+    next_id: Cell<usize>,
+}
+
+impl Counters {
+    pub const fn new() -> Self {
+        Self {
+            depth: Cell::new(0),
+            in_flight: Cell::new(0),
+            frames: RefCell::new(Records::new()),
+            handles: RefCell::new(Records::new()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    fn next_id(&self) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        id
+    }
+
+    /// Move the catch frames and handles out, leaving the counters empty.
+    ///
+    /// IDs keep increasing across saves, so that frames and handles created later still have
+    /// greater IDs.
+    pub fn save(&self) -> SavedCounters {
+        SavedCounters {
+            depth: self.depth.replace(0),
+            in_flight: self.in_flight.replace(0),
+            frames: self.frames.take(),
+            handles: self.handles.take(),
+        }
+    }
+
+    /// Put the catch frames and handles moved out by [`Counters::save`] back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if catch frames are active or handles are live.
+    pub fn restore(&self, saved: SavedCounters) {
+        assert!(
+            self.depth.get() == 0 && self.handles.borrow().is_empty(),
+            "Lithium state restored while catch frames are active on the current thread",
+        );
+        self.depth.set(saved.depth);
+        self.in_flight.set(saved.in_flight);
+        *self.frames.borrow_mut() = saved.frames;
+        *self.handles.borrow_mut() = saved.handles;
+    }
+
+    /// Exchange the catch frames and handles with another set of counters.
+    ///
+    /// IDs are not exchanged, see [`Counters::save`].
+    pub fn swap(&self, other: &Self) {
+        self.depth.swap(&other.depth);
+        self.in_flight.swap(&other.in_flight);
+        self.frames.swap(&other.frames);
+        self.handles.swap(&other.handles);
+    }
+}
+
+/// The counters moved out by [`Counters::save`].
+pub struct SavedCounters {
+    depth: usize,
+    in_flight: usize,
+    frames: Records<Frame>,
+    handles: Records<usize>,
+}
+
+/// The number of catch frames and handles whose records are kept in builds with checks.
+const CAPACITY: usize = if cfg!(checks) { 32 } else { 0 };
+
+/// A stack of records with a fixed capacity, stored inline.
+///
+/// Records pushed past the capacity are only counted. [`Records::last`] doesn't know the top record
+/// while there are any, so the checks that need it have to be skipped.
+struct Records<T> {
+    items: [Option<T>; CAPACITY],
+    /// The number of records, including those past the capacity.
+    len: usize,
+}
+
+impl<T: Copy> Records<T> {
+    const fn new() -> Self {
+        Self {
+            items: [None; CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        if let Some(slot) = self.items.get_mut(self.len) {
+            *slot = Some(item);
+        }
+        self.len = self.len.wrapping_add(1);
+    }
+
+    const fn pop(&mut self) {
+        self.len = self.len.saturating_sub(1);
+    }
+
+    /// Get the top record, or `None` if there are no records or the top one is past the capacity.
+    fn last(&self) -> Option<&T> {
+        self.items.get(self.len.checked_sub(1)?)?.as_ref()
+    }
+
+    const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check whether some records are past the capacity.
+    #[cfg(checks)]
+    const fn is_overflowed(&self) -> bool {
+        self.len > CAPACITY
+    }
+}
+
+impl<T: Copy> Default for Records<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An active catch frame.
+#[derive(Clone, Copy)]
+struct Frame {
+    /// Frames and handles created later have greater IDs.
+    id: usize,
+    expected: Expected,
+    /// The number of exceptions in flight when the frame was entered.
+    #[cfg(feature = "checked")]
+    in_flight_on_entry: usize,
+}
+
+/// The type of an exception, as recorded by catch frames.
+#[derive(Clone, Copy)]
+pub struct ExceptionType {
+    id: fn() -> TypeId,
+    name: fn() -> &'static str,
+}
+
+impl ExceptionType {
+    pub const fn of<E>() -> Self {
+        Self {
+            id: typeid::of::<E>,
+            name: type_name::<E>,
+        }
+    }
+}
+
+/// The exception types a catch frame accepts.
+#[derive(Clone, Copy)]
+pub enum Expected {
+    /// Exceptions of all types, e.g. because the frame dispatches on the type at runtime.
+    Any,
+    /// Exceptions of one of the listed types.
+    Types(&'static [ExceptionType]),
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Any => write!(f, "any type"),
+            Self::Types(types) => Names(types).fmt(f),
+        }
+    }
+}
+
+/// Formats the names of expected types as `A` or `B`.
+struct Names(&'static [ExceptionType]);
+
+impl fmt::Display for Names {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, ty) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " or ")?;
+            }
+            write!(f, "`{}`", (ty.name)())?;
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn with_counters<T>(f: impl FnOnce(&Counters) -> T) -> T {
+    // SAFETY: The reference is not used past the call frame.
+    without_interrupts(|| f(unsafe { &get_state().debug }))
+}
+
+/// The checks of an active Lithium catch frame, entered and left by [`hooks::CatchFrame`].
+///
+/// [`hooks::CatchFrame`]: super::hooks::CatchFrame
+pub struct CatchFrame {
+    in_flight_on_entry: usize,
+}
+
+impl CatchFrame {
+    /// Enter a catch frame accepting exceptions of `expected` types.
+    #[inline]
+    pub fn enter(expected: Expected) -> Self {
+        if !cfg!(checks) {
+            return Self {
+                in_flight_on_entry: 0,
+            };
+        }
+        with_counters(|counters| {
+            counters.depth.set(counters.depth.get().wrapping_add(1));
+            let id = counters.next_id();
+            let in_flight_on_entry = counters.in_flight.get();
+            counters.frames.borrow_mut().push(Frame {
+                id,
+                expected,
+                #[cfg(feature = "checked")]
+                in_flight_on_entry,
+            });
+            Self { in_flight_on_entry }
+        })
+    }
+
+    /// Leave the catch frame after the callback returned normally.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if an exception thrown inside the frame was caught by a foreign
+    /// frame.
+    #[inline]
+    pub fn returned(self) {
+        if !cfg!(checks) {
+            return;
+        }
+        let in_flight = with_counters(|counters| counters.in_flight.get());
+        assert!(
+            in_flight == self.in_flight_on_entry,
+            "Lithium exception was caught by a foreign frame, such as `std::panic::catch_unwind`. \
+             This is undefined behavior.",
+        );
+    }
+
+    /// Leave the catch frame after the callback threw an exception, which was caught by this frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if another exception thrown inside the frame was caught by a foreign
+    /// frame.
+    #[inline]
+    pub fn caught(self) {
+        if !cfg!(checks) {
+            return;
+        }
+        let in_flight = with_counters(|counters| {
+            let in_flight = counters.in_flight.get();
+            counters.in_flight.set(in_flight.wrapping_sub(1));
+            in_flight
+        });
+        assert!(
+            in_flight == self.in_flight_on_entry.wrapping_add(1),
+            "Lithium exception was caught by a foreign frame, such as `std::panic::catch_unwind`. \
+             This is undefined behavior.",
+        );
+    }
+}
+
+impl Drop for CatchFrame {
+    #[inline]
+    fn drop(&mut self) {
+        if cfg!(checks) {
+            with_counters(|counters| {
+                counters.depth.set(counters.depth.get().wrapping_sub(1));
+                counters.frames.borrow_mut().pop();
+            });
+        }
+    }
+}
+
+/// Check a thrown exception of type `E`.
+///
+/// # Panics
+///
+/// Panics in debug builds if no Lithium catch frame is active on the current thread, since the
+/// exception would then escape to the system runtime, or if the nearest catch frame doesn't accept
+/// `E`.
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub fn on_throw<E>() {
+    if !cfg!(checks) {
+        return;
+    }
+    with_counters(|counters| {
+        assert!(
+            counters.depth.get() > 0,
+            "Lithium exception thrown without an enclosing Lithium catch frame. This is undefined \
+             behavior.",
+        );
+        if let Some(frame) = counters.frames.borrow().last() {
+            if let Some(&handle) = counters.handles.borrow().last() {
+                assert!(
+                    frame.id > handle,
+                    "Lithium exception thrown past an exception in flight. This is undefined \
+                     behavior.",
+                );
+            }
+            if let Expected::Types(types) = frame.expected {
+                let id = typeid::of::<E>();
+                assert!(
+                    types.iter().any(|ty| (ty.id)() == id),
+                    "Lithium exception of type `{}` thrown into a catch frame expecting {}. This \
+                     is undefined behavior.",
+                    type_name::<E>(),
+                    Names(types),
+                );
+            }
+        }
+        counters
+            .in_flight
+            .set(counters.in_flight.get().wrapping_add(1));
+    });
+}
+
+/// Check whether an exception of type `E` can be thrown safely.
+///
+/// This holds if the nearest catch frame accepts `E` by type, no in-flight handle was created
+/// after the frame was entered, and no exception thrown after the frame was entered is unwinding,
+/// i.e. the frame doesn't belong to an exception being propagated through a destructor. Frames
+/// accepting any type are rejected, as they might abort on a mismatch. Handles are not tracked
+/// while an `ExceptionStack` is entered, so throws are rejected there too.
+#[cfg(feature = "checked")]
+#[inline]
+pub fn can_throw<E>() -> bool {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    if is_owned_stack_current() {
+        return false;
+    }
+
+    with_counters(|counters| {
+        let frames = counters.frames.borrow();
+        let Some(frame) = frames.last() else {
+            return false;
+        };
+        let handles = counters.handles.borrow();
+        if handles.is_overflowed() || handles.last().is_some_and(|&handle| handle > frame.id) {
+            return false;
+        }
+        if counters.in_flight.get() != frame.in_flight_on_entry {
+            return false;
+        }
+        let Expected::Types(types) = frame.expected else {
+            return false;
+        };
+        let id = typeid::of::<E>();
+        types.iter().any(|ty| (ty.id)() == id)
+    })
+}
+
+/// The checks of a live in-flight exception handle, held by [`hooks::InFlightMarker`].
+///
+/// [`hooks::InFlightMarker`]: super::hooks::InFlightMarker
+pub struct InFlightMarker {
+    // The marker is stored in public handles, so it's zero-sized in release builds. `None` if the
+    // handle is not tracked.
+    #[cfg(checks)]
+    id: Option<usize>,
+}
+
+#[cfg(checks)]
+impl InFlightMarker {
+    /// Register a new in-flight exception.
+    #[inline]
+    pub fn new() -> Self {
+        // Exceptions on an `ExceptionStack` may be released on another thread, so their handles
+        // can't be tracked per thread.
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        if is_owned_stack_current() {
+            return Self { id: None };
+        }
+
+        with_counters(|counters| {
+            let id = counters.next_id();
+            counters.handles.borrow_mut().push(id);
+            Self { id: Some(id) }
+        })
+    }
+
+    /// Release the in-flight exception before rethrowing it.
+    ///
+    /// Call this right before [`on_throw`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a handle created later is still live.
+    #[inline]
+    pub fn rethrow(self) {
+        drop(self);
+    }
+}
+
+#[cfg(not(checks))]
+impl InFlightMarker {
+    /// Register a new in-flight exception.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Release the in-flight exception before rethrowing it.
+    #[expect(
+        clippy::unused_self,
+        reason = "Consumes the marker like in debug builds"
+    )]
+    #[inline]
+    pub const fn rethrow(self) {}
+}
+
+#[cfg(checks)]
+impl Drop for InFlightMarker {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            with_counters(|counters| {
+                let mut handles = counters.handles.borrow_mut();
+                assert!(
+                    handles.is_overflowed() || handles.last() == Some(&id),
+                    "Lithium in-flight exceptions were released out of order. This is undefined \
+                     behavior.",
+                );
+                handles.pop();
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(checks)]
+    use super::InFlightMarker;
+    #[cfg(checks)]
+    use crate::intercept;
+    use crate::{catch, throw};
+    use alloc::string::String;
+    #[cfg(checks)]
+    use alloc::vec::Vec;
+
+    #[cfg(checks)]
+    #[test]
+    #[should_panic = "without an enclosing Lithium catch frame"]
+    fn throw_outside_catch() {
+        unsafe {
+            throw(String::from("Oops!"));
+        }
+    }
+
+    #[cfg(checks)]
+    #[test]
+    #[should_panic = "of type `i32` thrown into a catch frame expecting `alloc::string::String`"]
+    fn type_mismatch() {
+        let _: Result<(), String> = unsafe { catch(|| throw(1i32)) };
+    }
+
+    #[cfg(checks)]
+    #[test]
+    #[should_panic = "thrown past an exception in flight"]
+    fn throw_past_in_flight() {
+        let _: Result<(), i32> = unsafe {
+            catch(|| {
+                let _in_flight = intercept::<(), i32>(|| throw(1));
+                throw(2);
+            })
+        };
+    }
+
+    #[cfg(checks)]
+    #[test]
+    #[should_panic = "released out of order"]
+    fn released_out_of_order() {
+        let first = InFlightMarker::new();
+        let _second = InFlightMarker::new();
+        drop(first);
+    }
+
+    #[cfg(checks)]
+    #[test]
+    fn nested_past_capacity() {
+        fn nest(depth: usize) -> Result<(), usize> {
+            unsafe {
+                catch(|| {
+                    if depth > 0 {
+                        assert_eq!(nest(depth - 1), Err(depth - 1));
+                    }
+                    throw(depth);
+                })
+            }
+        }
+        assert_eq!(nest(super::CAPACITY * 2), Err(super::CAPACITY * 2));
+    }
+
+    #[cfg(checks)]
+    #[test]
+    fn handles_past_capacity() {
+        let markers: Vec<InFlightMarker> = (0..super::CAPACITY * 2)
+            .map(|_| InFlightMarker::new())
+            .collect();
+        for marker in markers.into_iter().rev() {
+            drop(marker);
+        }
+    }
+
+    #[cfg(checks)]
+    #[test]
+    #[should_panic = "released out of order"]
+    fn handles_tracked_after_capacity() {
+        handles_past_capacity();
+        let first = InFlightMarker::new();
+        let _second = InFlightMarker::new();
+        drop(first);
+    }
+
+    #[test]
+    fn nested_ok() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let inner: Result<(), i32> = catch(|| throw(1));
+                assert_eq!(inner, Err(1));
+                throw(String::from("Oops!"));
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Oops!");
+    }
+
+    // Only the panic backend lets `catch_unwind` catch Lithium exceptions without aborting.
+    #[cfg(all(checks, backend = "panic"))]
+    #[test]
+    fn foreign_catch_detected() {
+        let result = std::thread::spawn(|| {
+            let _: Result<(), String> = unsafe {
+                catch(|| {
+                    let _ = std::panic::catch_unwind(|| throw::<String>(String::from("Oops!")));
+                })
+            };
+        })
+        .join();
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<&str>().copied().unwrap_or_default();
+        let message = payload
+            .downcast_ref::<String>()
+            .map_or(message, String::as_str);
+        assert!(message.contains("caught by a foreign frame"));
+    }
+}
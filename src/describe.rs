@@ -0,0 +1,255 @@
+//! Descriptions of exceptions in abort messages.
+//!
+//! When an exception ends up somewhere it must not be, e.g. in a foreign catch handler or in a
+//! scoped catch of a different type, the process is aborted. By default, the abort message only
+//! says what went wrong, as the exception type is erased by then. Exceptions thrown by
+//! [`throw_err`] additionally store a type-erased formatter in the exception object, so that the
+//! error and its sources are printed too.
+
+#[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+use super::api::throw;
+use super::{abort, backend::CaughtException};
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::{
+    hooks::on_throw,
+    location::caller,
+    stacked_exceptions::{push, throw_pushed, Exception},
+};
+use core::error::Error;
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use core::fmt;
+
+/// Throw an error, describing it if the process is aborted.
+///
+/// This is equivalent to [`throw`](fn@crate::throw), except that if the exception reaches an abort
+/// path, such as a non-Lithium catch mechanism or a [`catch_scoped`](crate::catch_scoped) of a
+/// different type, the abort message includes the [`Display`](core::fmt::Display) output of the
+/// error, followed by its [`source`](Error::source) chain.
+///
+/// The description is only recorded on backends that use an exception stack. Elsewhere, this
+/// function is equivalent to [`throw`](fn@crate::throw).
+///
+/// # Safety
+///
+/// The requirements of [`throw`](fn@crate::throw) apply.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw_err};
+/// use std::fmt;
+///
+/// #[derive(Debug)]
+/// struct NotFound;
+///
+/// impl fmt::Display for NotFound {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "not found")
+///     }
+/// }
+///
+/// impl std::error::Error for NotFound {}
+///
+/// // SAFETY: Only `NotFound` is thrown.
+/// let result = unsafe { catch::<(), NotFound>(|| throw_err(NotFound)) };
+/// assert!(result.is_err());
+/// ```
+#[cfg_attr(feature = "location", track_caller)]
+#[cfg_attr(not(opt = "speed"), inline(never))]
+#[cfg_attr(opt = "speed", inline)]
+pub unsafe fn throw_err<E: Error>(cause: E) -> ! {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    {
+        on_throw::<E>();
+        let ex = push(cause, caller());
+        // SAFETY: Just allocated.
+        unsafe {
+            Exception::set_describe(ex, describe::<E>);
+        }
+        // SAFETY: `ex` was just returned by `push`. The rest is required transitively.
+        unsafe { throw_pushed(ex) }
+    }
+
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    {
+        // SAFETY: Required transitively.
+        unsafe { throw(cause) }
+    }
+}
+
+/// A type-erased formatter for the cause of an exception.
+///
+/// The first argument points at the `cause` field of the exception object. For over-aligned types,
+/// the cause itself is located at the next aligned address, and the formatter realigns the pointer.
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+pub type Describe = unsafe fn(*const (), &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Format an error, followed by its sources.
+///
+/// # Errors
+///
+/// Formatter errors are propagated.
+///
+/// # Safety
+///
+/// `cause` must point at a live instance of `E`, or at the `cause` field of an exception object
+/// holding an over-aligned `E`, see [`Describe`].
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+unsafe fn describe<E: Error>(cause: *const (), f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let cause = cause.cast::<u8>();
+    // SAFETY: The cause is located at the first address aligned for `E`, which is in bounds by the
+    // safety requirements.
+    let cause = unsafe { cause.add(cause.align_offset(align_of::<E>())) };
+    // SAFETY: Required transitively.
+    let cause = unsafe { &*cause.cast::<E>() };
+    write!(f, "{cause}")?;
+    let mut source = cause.source();
+    while let Some(error) = source {
+        write!(f, "\nCaused by: {error}")?;
+        source = error.source();
+    }
+    Ok(())
+}
+
+/// The cause of an exception, formatted via its [`Describe`] function.
+// Type invariant: `cause` points at a live cause of the type `describe` expects.
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+pub struct Described {
+    describe: Describe,
+    cause: *const (),
+}
+
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+impl Described {
+    /// # Safety
+    ///
+    /// `cause` must point at a live cause of the type `describe` expects, and stay alive while the
+    /// object is used.
+    pub const unsafe fn new(describe: Describe, cause: *const ()) -> Self {
+        Self { describe, cause }
+    }
+}
+
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+impl fmt::Display for Described {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // SAFETY: By the type invariant.
+        unsafe { (self.describe)(self.cause, f) }
+    }
+}
+
+/// Abort the process with a message, followed by the description of the offending exception, if
+/// any.
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+#[cold]
+#[inline(never)]
+pub fn abort_described(message: &str, described: Option<Described>) -> ! {
+    #[cfg(abort = "std")]
+    if let Some(described) = described {
+        use std::io::Write;
+        #[cfg(any(feature = "tracing", feature = "log"))]
+        super::trace::abort(message, Some(&described));
+        let _ = writeln!(std::io::stderr(), "{message}The exception was: {described}");
+        std::process::abort();
+    }
+
+    #[cfg(not(abort = "std"))]
+    let _ = described;
+
+    abort(message)
+}
+
+/// Abort the process because `ex` was caught where it must not be, describing it if possible.
+#[cold]
+#[inline(never)]
+pub fn abort_caught(message: &str, ex: &impl CaughtException) -> ! {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    abort_described(message, ex.describe());
+
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    {
+        let _ = ex;
+        abort(message);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catch;
+    use alloc::string::ToString;
+    use core::fmt;
+
+    #[derive(Debug)]
+    struct Outer(Inner);
+
+    #[derive(Debug)]
+    struct Inner;
+
+    impl fmt::Display for Outer {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "outer")
+        }
+    }
+
+    impl fmt::Display for Inner {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "inner")
+        }
+    }
+
+    impl Error for Outer {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    impl Error for Inner {}
+
+    #[test]
+    fn throws() {
+        let result = unsafe { catch::<(), Outer>(|| throw_err(Outer(Inner))) };
+        assert_eq!(result.unwrap_err().to_string(), "outer");
+    }
+
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    #[test]
+    fn describes_sources() {
+        let error = Outer(Inner);
+        let described = unsafe { Described::new(describe::<Outer>, (&raw const error).cast()) };
+        assert_eq!(described.to_string(), "outer\nCaused by: inner");
+    }
+
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    #[test]
+    fn describes_overaligned() {
+        #[derive(Debug)]
+        #[repr(align(64))]
+        struct Overaligned(Inner);
+
+        impl fmt::Display for Overaligned {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "overaligned")
+            }
+        }
+
+        impl Error for Overaligned {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let ex = push(Overaligned(Inner), caller());
+        unsafe {
+            Exception::set_describe(ex, describe::<Overaligned>);
+        }
+        let header = unsafe { Exception::header(ex) };
+        let described = unsafe { Exception::<()>::describe_from_header(header) }.unwrap();
+        assert_eq!(described.to_string(), "overaligned\nCaused by: inner");
+        let _ = unsafe { (*ex).cause() };
+        unsafe {
+            crate::stacked_exceptions::pop(ex);
+        }
+    }
+}
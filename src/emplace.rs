@@ -0,0 +1,365 @@
+#[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+use super::api::{catch, throw};
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::{
+    api::in_catch_frame,
+    debug::{ExceptionType, Expected},
+    hooks::on_throw,
+    location::caller,
+    stacked_exceptions::{intercept_in_place, pop, push_uninit, throw_pushed, Exception},
+};
+#[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+use core::marker::PhantomData;
+use core::mem::{ManuallyDrop, MaybeUninit};
+
+/// Storage for an exception that is constructed in place.
+///
+/// Created by [`emplace`]. Initialize the cause via [`ExceptionSlot::as_uninit`] and throw it with
+/// [`ExceptionSlot::throw`].
+///
+/// Dropping the slot without throwing releases the storage, but does not drop the cause.
+///
+/// A slot can also be reused across multiple catches. [`ExceptionSlot::throw_with`] throws a cause
+/// from the slot's storage without consuming the slot, and [`ExceptionSlot::catch`] moves the cause
+/// out while keeping the storage for the next throw. Loops that repeatedly fail this way don't
+/// allocate and release an exception object on each iteration.
+///
+/// ```rust
+/// use lithium::emplace;
+///
+/// // SAFETY: The slot is not thrown, and no exceptions are in flight.
+/// let slot = unsafe { emplace::<u32>() };
+/// let mut failures = 0;
+/// for i in 0..10 {
+///     // SAFETY: Only `u32` is thrown, and exceptions thrown by the slot are caught by the slot.
+///     if let Err(i) = unsafe { slot.catch(|| if i % 2 == 0 { slot.throw_with(i) }) } {
+///         failures += i;
+///     }
+/// }
+/// assert_eq!(failures, 20);
+/// ```
+pub struct ExceptionSlot<E> {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    ex: *mut Exception<E>,
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    cause: MaybeUninit<E>,
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    phantom: PhantomData<*mut ()>,
+}
+
+/// Allocate an exception to be constructed in place.
+///
+/// [`throw`](fn@crate::throw) moves the cause into the exception object, which can be costly for
+/// multi-kilobyte types. [`emplace`] allocates the exception object on the exception stack first,
+/// so that the cause can be written directly to its final location.
+///
+/// On backends that don't use an exception stack, such as SEH, the slot stores the cause inline and
+/// [`ExceptionSlot::throw`] is equivalent to [`throw`](fn@crate::throw).
+///
+/// # Safety
+///
+/// Slots must be consumed, i.e. thrown or dropped, in the reverse order of creation, and before any
+/// exception that was in flight when the slot was created is rethrown or dropped. Exceptions thrown
+/// and caught while the slot is alive don't affect this.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, emplace};
+///
+/// struct Report {
+///     lines: [u32; 256],
+/// }
+///
+/// let result = unsafe {
+///     catch::<(), Report>(|| {
+///         let mut slot = emplace::<Report>();
+///         let report = slot.as_uninit().as_mut_ptr();
+///         for i in 0..256 {
+///             (&raw mut (*report).lines[i]).write(i as u32);
+///         }
+///         slot.throw();
+///     })
+/// };
+/// assert_eq!(result.unwrap_err().lines[42], 42);
+/// ```
+#[inline]
+#[must_use]
+pub unsafe fn emplace<E>() -> ExceptionSlot<E> {
+    ExceptionSlot {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        ex: push_uninit(),
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        cause: MaybeUninit::uninit(),
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        phantom: PhantomData,
+    }
+}
+
+impl<E> ExceptionSlot<E> {
+    /// Get the storage for the cause.
+    ///
+    /// The storage is uninitialized when the slot is created.
+    #[inline]
+    pub fn as_uninit(&mut self) -> &mut MaybeUninit<E> {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: `ex` is a unique pointer to the exception object allocated by `emplace`, which is
+        // alive until the slot is consumed.
+        let cause = unsafe { Exception::cause_ptr(self.ex) };
+
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: `cause_ptr` returns an aligned pointer to storage for `E`, which is valid for
+        // `MaybeUninit<E>`. The storage is borrowed for as long as the slot.
+        return unsafe { &mut *cause.cast::<MaybeUninit<E>>() };
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        return &mut self.cause;
+    }
+
+    /// Throw the constructed exception.
+    ///
+    /// # Safety
+    ///
+    /// The cause must have been initialized via [`ExceptionSlot::as_uninit`].
+    ///
+    /// The requirements of [`throw`](fn@crate::throw) apply.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub unsafe fn throw(self) -> ! {
+        let this = ManuallyDrop::new(self);
+
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        {
+            // SAFETY: `ex` is a unique pointer to the exception object allocated by `emplace`.
+            unsafe {
+                Exception::set_location(this.ex, caller());
+            }
+            on_throw::<E>();
+            // SAFETY: The exception was allocated by `emplace`, it's at the top of the stack by the
+            // safety requirements of `emplace`, and its cause is initialized. The rest is required
+            // transitively.
+            unsafe { throw_pushed(this.ex) }
+        }
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        {
+            // SAFETY: The cause is initialized by the safety requirements, and `this` is not used
+            // afterwards.
+            let cause = unsafe { core::ptr::read(&this.cause).assume_init() };
+            // SAFETY: Required transitively.
+            unsafe { throw(cause) }
+        }
+    }
+}
+
+impl<E> ExceptionSlot<E> {
+    /// Throw an exception from the slot's storage, keeping the slot alive.
+    ///
+    /// This is similar to [`ExceptionSlot::throw`], but `cause` is moved into the storage
+    /// first, and the slot is not consumed, so it can be thrown again after the exception is
+    /// caught by [`ExceptionSlot::catch`].
+    ///
+    /// On backends that don't use an exception stack, this is equivalent to
+    /// [`throw`](fn@crate::throw).
+    ///
+    /// # Safety
+    ///
+    /// The exception must be caught by [`ExceptionSlot::catch`] of the same slot, and the slot must
+    /// not be thrown again until then. The cause must not have been initialized via
+    /// [`ExceptionSlot::as_uninit`], as it's overwritten without being dropped.
+    ///
+    /// The requirements of [`throw`](fn@crate::throw) apply.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub unsafe fn throw_with(&self, cause: E) -> ! {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        {
+            // SAFETY: `ex` points at the exception object allocated by `emplace`, which is not
+            // in flight by the safety requirements, so the storage is not aliased.
+            let cause_ptr = unsafe { Exception::cause_ptr(self.ex) };
+            // SAFETY: `cause_ptr` returns an aligned pointer to storage for `E`.
+            unsafe {
+                cause_ptr.write(cause);
+            }
+            // SAFETY: As above.
+            unsafe {
+                Exception::set_location(self.ex, caller());
+            }
+            on_throw::<E>();
+            // SAFETY: The exception was allocated by `emplace`, it's at the top of the stack by the
+            // safety requirements of `emplace`, and its cause is initialized. The rest is required
+            // transitively.
+            unsafe { throw_pushed(self.ex) }
+        }
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        // SAFETY: Required transitively.
+        unsafe {
+            throw(cause)
+        }
+    }
+
+    /// Catch an exception, keeping the slot's storage for reuse.
+    ///
+    /// This is equivalent to [`catch`](crate::catch), except that if the exception was thrown by
+    /// [`ExceptionSlot::throw_with`], its storage is not released, so the slot can be thrown again.
+    /// Exceptions of type `E` thrown by other means are caught and released as usual.
+    ///
+    /// # Safety
+    ///
+    /// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate)
+    /// for more information.
+    #[expect(
+        clippy::missing_errors_doc,
+        reason = "`Err` value is described immediately"
+    )]
+    #[inline]
+    pub unsafe fn catch<R>(&self, func: impl FnOnce() -> R) -> Result<R, E> {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        {
+            let expected = Expected::Types(const { &[ExceptionType::of::<E>()] });
+            // SAFETY: `func` only throws `E` by the safety requirement, and the exception is
+            // released before returning.
+            let ex = match in_catch_frame(expected, || unsafe { intercept_in_place(func) }) {
+                Ok(value) => return Ok(value),
+                Err(ex) => ex,
+            };
+            // SAFETY: `ex` is a unique pointer to a live exception object.
+            let ex_ref = unsafe { &mut *ex };
+            // SAFETY: The cause was initialized by the thrower, and it's only read once.
+            let cause = unsafe { ex_ref.cause() };
+            if ex != self.ex {
+                // SAFETY: The exception was caught just now, so it's at the top of the stack.
+                unsafe {
+                    pop(ex);
+                }
+            }
+            Err(cause)
+        }
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        // SAFETY: Requirements forwarded.
+        unsafe {
+            catch(func)
+        }
+    }
+}
+
+impl<E> Drop for ExceptionSlot<E> {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: The exception was allocated by `emplace` and it's at the top of the stack by the
+        // safety requirements of `emplace`. The cause is never read.
+        unsafe {
+            pop(self.ex);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, intercept, throw};
+    use alloc::string::String;
+
+    struct Large {
+        values: [u64; 100],
+        name: String,
+    }
+
+    #[test]
+    fn construct_in_place() {
+        let result: Result<(), Large> = unsafe {
+            catch(|| {
+                let mut slot = emplace::<Large>();
+                let large = slot.as_uninit().as_mut_ptr();
+                for i in 0..100 {
+                    (&raw mut (*large).values[i]).write(i as u64);
+                }
+                (&raw mut (*large).name).write(String::from("Hello, world!"));
+                slot.throw();
+            })
+        };
+        let large = result.unwrap_err();
+        assert_eq!(large.values[99], 99);
+        assert_eq!(large.name, "Hello, world!");
+    }
+
+    #[test]
+    fn overaligned() {
+        #[repr(align(64))]
+        struct Cause(i32);
+
+        let result: Result<(), Cause> = unsafe {
+            catch(|| {
+                let mut slot = emplace::<Cause>();
+                slot.as_uninit().write(Cause(1));
+                slot.throw();
+            })
+        };
+        assert_eq!(result.err().unwrap().0, 1);
+    }
+
+    #[test]
+    fn drop_slot() {
+        let result: Result<(), i32> = unsafe {
+            catch(|| {
+                let slot = emplace::<String>();
+                let inner: Result<(), i32> = catch(|| throw(1));
+                assert_eq!(inner, Err(1));
+                drop(slot);
+                throw(2);
+            })
+        };
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    fn reuse() {
+        let slot = unsafe { emplace::<String>() };
+        let in_flight = crate::in_flight_count();
+        for i in 0..10 {
+            let result = unsafe {
+                slot.catch(|| {
+                    if i % 3 == 0 {
+                        slot.throw_with(alloc::format!("{i}"));
+                    }
+                    i
+                })
+            };
+            if i % 3 == 0 {
+                assert_eq!(result.unwrap_err(), alloc::format!("{i}"));
+            } else {
+                assert_eq!(result, Ok(i));
+            }
+            assert_eq!(crate::in_flight_count(), in_flight);
+        }
+    }
+
+    #[test]
+    fn reuse_foreign_throw() {
+        let slot = unsafe { emplace::<i32>() };
+        assert_eq!(unsafe { slot.catch::<()>(|| throw(1)) }, Err(1));
+        assert_eq!(unsafe { slot.catch::<()>(|| slot.throw_with(2)) }, Err(2));
+        assert_eq!(unsafe { slot.catch::<()>(|| throw(3)) }, Err(3));
+        drop(slot);
+        assert_eq!(unsafe { catch::<(), i32>(|| throw(4)) }, Err(4));
+    }
+
+    #[test]
+    fn intercept_and_rethrow() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let (cause, in_flight) = intercept::<(), i32>(|| {
+                    let mut slot = emplace::<i32>();
+                    slot.as_uninit().write(1);
+                    slot.throw();
+                })
+                .unwrap_err();
+                in_flight.rethrow(alloc::format!("{cause}"));
+            })
+        };
+        assert_eq!(result.unwrap_err(), "1");
+    }
+}
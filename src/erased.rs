@@ -0,0 +1,132 @@
+//! Catching with a type-erased slow path.
+//!
+//! `catch` and the backend glue behind it are instantiated for each closure and exception type,
+//! which adds up in crates with hundreds of call sites. [`catch_dyn`] only keeps a thin wrapper
+//! generic and shares the rest between call sites.
+
+#[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+use super::api::catch;
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::{
+    api::in_catch_frame,
+    debug::{ExceptionType, Expected},
+    stacked_exceptions::{intercept_erased, take_caught, Exception},
+};
+
+/// Catch an exception, sharing the catching code between call sites.
+///
+/// This is equivalent to [`catch`](crate::catch), but generates less code per call site: the
+/// callback is invoked through `&mut dyn FnMut()`, so the catch frame, the backend call, and the
+/// debug bookkeeping are only compiled once, at the cost of an indirect call. Prefer `catch` on hot
+/// paths, where inlining the catch frame pays off, and [`catch_dyn`] in code that is instantiated
+/// with many closures or exception types.
+///
+/// On backends that don't use an exception stack, such as SEH, this is the same as `catch`.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// The requirements of [`catch`](crate::catch) apply.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch_dyn, throw};
+///
+/// // SAFETY: the exception type matches
+/// let res = unsafe { catch_dyn::<(), &'static str>(|| throw("Oops!")) };
+/// assert_eq!(res, Err("Oops!"));
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn catch_dyn<R, E>(func: impl FnOnce() -> R) -> Result<R, E> {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    {
+        let mut func = Some(func);
+        let mut value = None;
+        let expected = Expected::Types(const { &[ExceptionType::of::<E>()] });
+        match intercept_in_frame(expected, &mut || {
+            if let Some(func) = func.take() {
+                value = Some(func());
+            }
+        }) {
+            // SAFETY: The callback is invoked exactly once if `intercept_in_frame` returns `Ok`.
+            Ok(()) => Ok(unsafe { value.unwrap_unchecked() }),
+            // SAFETY: `func` only throws `E` by the safety requirement, and the exception is
+            // released right away.
+            Err(ex) => Err(unsafe { take_caught::<E>(ex) }),
+        }
+    }
+
+    // SAFETY: Requirements forwarded.
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    unsafe {
+        catch(func)
+    }
+}
+
+/// The non-generic part of [`catch_dyn`].
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is passed through from `intercept_erased`"
+)]
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+#[inline(never)]
+fn intercept_in_frame(
+    expected: Expected,
+    func: &mut dyn FnMut(),
+) -> Result<(), *mut Exception<()>> {
+    in_catch_frame(expected, || intercept_erased(func))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{intercept, throw};
+    use alloc::string::String;
+
+    #[test]
+    fn returns() {
+        let result = unsafe { catch_dyn::<String, i32>(|| String::from("ok")) };
+        assert_eq!(result.unwrap(), "ok");
+    }
+
+    #[test]
+    fn throws() {
+        let result = unsafe { catch_dyn::<(), String>(|| throw(String::from("Hello, world!"))) };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn nested() {
+        let result = unsafe {
+            catch_dyn::<(), i32>(|| {
+                let inner = catch_dyn::<(), u8>(|| throw(1u8));
+                assert_eq!(inner, Err(1));
+                throw(2i32)
+            })
+        };
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    fn rethrow_across() {
+        let result = unsafe {
+            catch_dyn::<(), i32>(|| {
+                let (cause, ex) = intercept::<(), i32>(|| throw(1)).unwrap_err();
+                ex.rethrow(cause + 1)
+            })
+        };
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    #[should_panic = "Hello, world!"]
+    fn panic() {
+        let _ = unsafe { catch_dyn::<(), ()>(|| panic!("Hello, world!")) };
+    }
+}
@@ -0,0 +1,259 @@
+use super::{
+    api::InFlightException,
+    chain::{add_context, Chain, Contextual},
+    message::Message,
+};
+use alloc::{borrow::Cow, boxed::Box};
+use core::any::TypeId;
+use core::fmt;
+use core::mem::{transmute_copy, ManuallyDrop};
+
+/// A dynamic error type designed for throwing.
+///
+/// `Error` is an analogue of `anyhow::Error` that keeps Lithium propagation free of allocations
+/// where possible:
+///
+/// - Messages, e.g. created with [`Error::msg`], are stored inline, and static strings are not
+///   copied.
+/// - Other errors are converted from any [`core::error::Error`] type, which is boxed.
+/// - Context messages are stored in a [`Chain`] in the error, which lives in the exception object
+///   while the exception propagates. [`Error::with_context`] appends to them in place, so adding
+///   context to a thrown error costs no more than pushing to a `Vec`; if no context is added, the
+///   `Vec` doesn't allocate.
+///
+/// Like `anyhow::Error`, `Error` doesn't implement [`core::error::Error`] itself, so that it can be
+/// converted from any error with [`From`]. Use [`Error::as_error`] to access the underlying error.
+///
+/// [`Display`](fmt::Display) prints the context messages from the outermost to the innermost,
+/// followed by the underlying error, separated by colons.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, tri, Error};
+///
+/// /// Throws `Error`.
+/// unsafe fn parse_port(s: &str) -> u16 {
+///     Error::with_context("parsing port", || tri!(s.parse::<u16>(), Error))
+/// }
+///
+/// // SAFETY: `parse_port` only throws `Error`.
+/// let error = unsafe { catch::<_, Error>(|| parse_port("http")) }.unwrap_err();
+/// assert_eq!(error.to_string(), "parsing port: invalid digit found in string");
+/// ```
+pub struct Error {
+    chain: Chain<Source>,
+}
+
+/// The underlying error.
+enum Source {
+    Message(Message),
+    Boxed(Box<dyn core::error::Error + Send + Sync>),
+}
+
+impl Source {
+    #[inline]
+    fn as_error(&self) -> &(dyn core::error::Error + Send + Sync + 'static) {
+        match self {
+            Self::Message(message) => message,
+            Self::Boxed(error) => &**error,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_error(), f)
+    }
+}
+
+impl Error {
+    /// Wrap an error.
+    ///
+    /// A [`Message`] is stored inline; other errors are boxed.
+    #[inline]
+    #[must_use]
+    pub fn new<E: core::error::Error + Send + Sync + 'static>(error: E) -> Self {
+        if TypeId::of::<E>() == TypeId::of::<Message>() {
+            let error = ManuallyDrop::new(error);
+            // SAFETY: `E` is `Message`, and the original is not dropped.
+            let message = unsafe { transmute_copy::<E, Message>(&error) };
+            return Self::msg(message);
+        }
+        Self {
+            chain: Chain::new(Source::Boxed(Box::new(error))),
+        }
+    }
+
+    /// Create an error from a message, without boxing it.
+    #[inline]
+    #[must_use]
+    pub fn msg(message: impl Into<Message>) -> Self {
+        Self {
+            chain: Chain::new(Source::Message(message.into())),
+        }
+    }
+
+    /// Get the underlying error, without the context.
+    #[inline]
+    #[must_use]
+    pub fn as_error(&self) -> &(dyn core::error::Error + Send + Sync + 'static) {
+        self.chain.cause().as_error()
+    }
+
+    /// Downcast the underlying error to a concrete type.
+    #[inline]
+    #[must_use]
+    pub fn downcast_ref<E: core::error::Error + 'static>(&self) -> Option<&E> {
+        self.as_error().downcast_ref()
+    }
+
+    /// Get the context messages, from the innermost to the outermost.
+    #[inline]
+    #[must_use]
+    pub fn context(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.chain.context()
+    }
+
+    /// Append a context message.
+    #[inline]
+    pub fn push_context(&mut self, message: impl Into<Cow<'static, str>>) {
+        self.chain.push_context(message);
+    }
+
+    /// Call a function, adding a context message to the [`Error`] it throws.
+    ///
+    /// If `func` returns a value, it's returned as-is. If `func` throws, `message` is appended to
+    /// the error and the exception is rethrown without moving the error.
+    ///
+    /// This is the analogue of [`with_context`](crate::with_context) for [`Error`].
+    ///
+    /// # Safety
+    ///
+    /// `func` must only throw exceptions of type [`Error`], and the requirements of
+    /// [`InFlightException::rethrow`](crate::InFlightException::rethrow) apply to the rethrown
+    /// exception. See the safety section of [this crate](crate) for more information.
+    #[inline]
+    pub unsafe fn with_context<R>(
+        message: impl Into<Cow<'static, str>>,
+        func: impl FnOnce() -> R,
+    ) -> R {
+        // SAFETY: Requirements forwarded.
+        unsafe { add_context::<R, Self>(message.into(), func) }
+    }
+}
+
+impl Contextual for Error {
+    #[inline]
+    fn push_message(&mut self, message: Cow<'static, str>) {
+        self.chain.push_message(message);
+    }
+}
+
+impl InFlightException<Error> {
+    /// Rethrow the exception, appending a context message to the error.
+    ///
+    /// To add context without moving the error out of the exception in the first place, use
+    /// [`Error::with_context`].
+    ///
+    /// # Safety
+    ///
+    /// The requirements of [`InFlightException::rethrow`] apply.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub unsafe fn rethrow_with_context(
+        self,
+        mut error: Error,
+        message: impl Into<Cow<'static, str>>,
+    ) -> ! {
+        error.push_context(message);
+        // SAFETY: Requirements forwarded.
+        unsafe { self.rethrow(error) }
+    }
+}
+
+impl<E: core::error::Error + Send + Sync + 'static> From<E> for Error {
+    #[inline]
+    fn from(error: E) -> Self {
+        Self::new(error)
+    }
+}
+
+impl fmt::Display for Error {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.chain, f)
+    }
+}
+
+impl fmt::Debug for Error {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("source", &self.as_error())
+            .field("context", &self.chain.context_messages())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, intercept, throw};
+    use alloc::string::{String, ToString};
+    use core::num::ParseIntError;
+
+    fn parse_error() -> ParseIntError {
+        "x".parse::<i32>().unwrap_err()
+    }
+
+    #[test]
+    fn message_inline() {
+        let error = Error::new(Message::new("Oops!"));
+        assert!(matches!(error.chain.cause(), Source::Message(_)));
+        assert_eq!(error.to_string(), "Oops!");
+        let error = Error::from(Message::from(String::from("Oops!")));
+        assert!(matches!(error.chain.cause(), Source::Message(_)));
+    }
+
+    #[test]
+    fn boxed() {
+        let error = Error::from(parse_error());
+        assert!(matches!(error.chain.cause(), Source::Boxed(_)));
+        assert_eq!(error.downcast_ref::<ParseIntError>(), Some(&parse_error()));
+        assert!(error.downcast_ref::<Message>().is_none());
+    }
+
+    #[test]
+    fn context_in_place() {
+        let result = unsafe {
+            catch::<(), Error>(|| {
+                Error::with_context("outer", || {
+                    Error::with_context("inner", || throw(Error::msg("Oops!")));
+                });
+            })
+        };
+        let error = result.unwrap_err();
+        assert!(error.context().eq(["inner", "outer"]));
+        assert_eq!(error.to_string(), "outer: inner: Oops!");
+    }
+
+    #[test]
+    fn rethrow_with_context() {
+        let result = unsafe {
+            catch::<(), Error>(|| {
+                let (error, in_flight) =
+                    intercept::<(), Error>(|| throw(Error::msg("Oops!"))).unwrap_err();
+                in_flight.rethrow_with_context(error, "outer");
+            })
+        };
+        assert_eq!(result.unwrap_err().to_string(), "outer: Oops!");
+    }
+
+    #[test]
+    fn returns() {
+        let result = unsafe { catch::<i32, Error>(|| Error::with_context("unused", || 1)) };
+        assert_eq!(result.unwrap(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::stacked_exceptions::OwnedStack;
+use super::{
+    debug::Counters,
+    guard::finally,
+    rethrows::RethrowCounts,
+    tls::{get_state, ThreadState},
+};
+use core::cell::Cell;
+
+/// An exception stack independent of threads.
+///
+/// On most backends, exceptions are stored on a thread-local stack until they're caught. This
+/// breaks stackful coroutines and work-stealing executors that migrate a task between threads
+/// while an [`InFlightException`](crate::InFlightException) is alive: the exception would be
+/// released from the stack of the wrong thread.
+///
+/// [`ExceptionStack`] allocates an exception stack that can be owned by a task instead.
+/// [`ExceptionStack::enter`] installs it as the current stack of the thread for the duration of a
+/// scope, e.g. a single resumption of a coroutine. All exceptions thrown within the scope are
+/// stored on this stack, so the task can be resumed on another thread later.
+///
+/// The rest of Lithium's per-thread bookkeeping, i.e. the count of unwinding exceptions reported by
+/// [`is_unwinding`](crate::is_unwinding) and the catch frames tracked in debug builds, is kept in
+/// the [`ExceptionStack`] too, and swapped in by [`ExceptionStack::enter`]. Green-thread runtimes
+/// can thus give each fiber its own [`ExceptionStack`] and resume the fiber inside
+/// [`ExceptionStack::enter`]. Unlike `fiber::save` and `fiber::restore`, this doesn't copy
+/// exceptions on each switch, and the exceptions of a fiber live exactly as long as its
+/// [`ExceptionStack`]: dropping the stack releases exceptions leaked by the fiber.
+///
+/// On backends that don't use an exception stack, only the bookkeeping is swapped.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw, ExceptionStack};
+///
+/// let stack = ExceptionStack::new();
+/// // SAFETY: no exceptions escape `enter`
+/// let res = unsafe {
+///     stack.enter(|| catch::<(), &'static str>(|| throw::<&'static str>("Oops!")))
+/// };
+/// assert_eq!(res, Err("Oops!"));
+/// ```
+pub struct ExceptionStack {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    stack: OwnedStack,
+    /// The debug counters of the task while it's not entered.
+    debug: Counters,
+    /// The rethrow counts of the task while it's not entered.
+    rethrows: RethrowCounts,
+    /// The number of unwinding exceptions of the task while it's not entered.
+    unwinding: Cell<usize>,
+}
+
+impl ExceptionStack {
+    /// Allocate an empty exception stack.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+            stack: OwnedStack::new(),
+            debug: Counters::new(),
+            rethrows: RethrowCounts::new(),
+            unwinding: Cell::new(0),
+        }
+    }
+
+    /// Get the number of exceptions in flight on this stack.
+    ///
+    /// Runtimes can use this to check that a finished task didn't leak exceptions. On backends that
+    /// don't use an exception stack, this function always returns zero.
+    #[inline]
+    #[must_use]
+    pub fn in_flight_count(&self) -> usize {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        return self.stack.in_flight_count();
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        return 0;
+    }
+
+    /// Exchange the bookkeeping of the task with that of the thread.
+    fn swap_state(&self, thread: &ThreadState) {
+        thread.debug.swap(&self.debug);
+        thread.rethrows.swap(&self.rethrows);
+        thread.unwinding.swap(&self.unwinding);
+    }
+
+    /// Use this stack for exceptions thrown on the current thread for the duration of `func`.
+    ///
+    /// Scopes can be nested, in which case the innermost stack is used. The previous stack is
+    /// restored when `func` returns or panics. Catch frames entered outside of `func` are not
+    /// visible inside `func`, and exceptions unwinding outside of `func` are not reported by
+    /// [`is_unwinding`](crate::is_unwinding) inside it.
+    ///
+    /// # Safety
+    ///
+    /// Exceptions thrown inside `func` must not escape `enter`: they have to be caught inside
+    /// `func`. Conversely, exceptions that were caught outside of `func`, i.e. with a different
+    /// stack installed, must not be rethrown or released inside `func`.
+    ///
+    /// An [`InFlightException`](crate::InFlightException) obtained inside a scope of `enter` may
+    /// only be rethrown or dropped inside a scope of `enter` on the same [`ExceptionStack`], but
+    /// not necessarily on the same thread.
+    #[inline]
+    pub unsafe fn enter<R>(&self, func: impl FnOnce() -> R) -> R {
+        // SAFETY: The reference is not used past the call frame.
+        self.swap_state(unsafe { get_state() });
+        // SAFETY: Ditto. `func` returns on the thread it was called on.
+        let _guard = finally(|| self.swap_state(unsafe { get_state() }));
+
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: Requirements forwarded.
+        return unsafe { self.stack.with_stack(func) };
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        return func();
+    }
+}
+
+impl Default for ExceptionStack {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: The stack is only accessed within `enter`, which borrows it from a single thread at a
+// time, because `ExceptionStack` is not `Sync`. Exception objects stored on the stack are only
+// accessed through `InFlightException`, whose migration is governed by the safety requirements of
+// `enter`.
+unsafe impl Send for ExceptionStack {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, intercept, is_unwinding, throw, InFlightException};
+    use alloc::string::String;
+
+    struct AssertSend<T>(T);
+    unsafe impl<T> Send for AssertSend<T> {}
+
+    #[test]
+    fn scoped() {
+        let stack = ExceptionStack::new();
+        let result: Result<(), String> = unsafe {
+            stack.enter(|| {
+                let inner: Result<(), i32> = catch(|| throw(1));
+                assert_eq!(inner, Err(1));
+                catch(|| throw(String::from("Hello, world!")))
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn migrate() {
+        let stack = ExceptionStack::new();
+        let (cause, in_flight): (String, InFlightException<String>) = unsafe {
+            stack.enter(|| intercept::<(), String>(|| throw(String::from("Hello, world!"))))
+        }
+        .unwrap_err();
+        assert_eq!(cause, "Hello, world!");
+
+        let task = AssertSend((stack, in_flight));
+        let result = std::thread::spawn(move || {
+            let task = task;
+            let AssertSend((stack, in_flight)) = task;
+            // Keep an exception alive on the thread-local stack to catch mismatches.
+            let (_, outer) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+            let result: Result<(), String> =
+                unsafe { stack.enter(|| catch(|| in_flight.rethrow(String::from("Rethrown")))) };
+            assert_eq!(result.unwrap_err(), "Rethrown");
+            drop(outer);
+        })
+        .join();
+        result.unwrap();
+    }
+
+    #[test]
+    fn interleaved() {
+        let a = ExceptionStack::new();
+        let b = ExceptionStack::new();
+        let (_, ex_a) = unsafe { a.enter(|| intercept::<(), i32>(|| throw(1))) }.unwrap_err();
+        let (_, ex_b) = unsafe { b.enter(|| intercept::<(), i32>(|| throw(2))) }.unwrap_err();
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        assert_eq!((a.in_flight_count(), b.in_flight_count()), (1, 1));
+        unsafe { a.enter(|| drop(ex_a)) };
+        assert_eq!((a.in_flight_count(), b.in_flight_count()), (0, 1));
+        let result = unsafe { b.enter(|| catch::<(), i32>(|| ex_b.rethrow(3))) };
+        assert_eq!(result, Err(3));
+        assert_eq!(b.in_flight_count(), 0);
+    }
+
+    #[test]
+    fn unwinding() {
+        struct Resume(ExceptionStack);
+
+        impl Drop for Resume {
+            fn drop(&mut self) {
+                assert!(is_unwinding());
+                unsafe {
+                    self.0.enter(|| {
+                        assert!(!is_unwinding());
+                        let result = catch::<(), i32>(|| throw(2));
+                        assert_eq!(result, Err(2));
+                    });
+                }
+                assert!(is_unwinding());
+            }
+        }
+
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                let _resume = Resume(ExceptionStack::new());
+                throw(1)
+            })
+        };
+        assert_eq!(result, Err(1));
+    }
+
+    #[cfg(checks)]
+    #[test]
+    #[should_panic = "without an enclosing Lithium catch frame"]
+    fn outer_frames_hidden() {
+        let stack = ExceptionStack::new();
+        let _: Result<(), i32> = unsafe { catch(|| stack.enter(|| throw(1))) };
+    }
+}
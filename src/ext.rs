@@ -0,0 +1,171 @@
+use super::api::throw;
+
+mod private {
+    pub trait Sealed {}
+    impl<T, E> Sealed for Result<T, E> {}
+    impl<T> Sealed for Option<T> {}
+}
+
+/// Throwing adapters for [`Result`] and [`Option`].
+///
+/// This trait bridges `Result`-returning code with Lithium propagation, replacing the
+/// `match`/[`throw`](fn@throw) boilerplate.
+///
+/// For [`Option`], the error type is `()`, mirroring [`Option::ok_or`]`(())`.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, ThrowExt};
+///
+/// // SAFETY: `or_throw` throws `ParseIntError`, which is the type `catch` expects
+/// let res = unsafe {
+///     catch::<i32, core::num::ParseIntError>(|| "12a".parse::<i32>().or_throw())
+/// };
+/// assert!(res.is_err());
+///
+/// // SAFETY: `ok_or_throw` throws `&str`, which is the type `catch` expects
+/// let res = unsafe {
+///     catch::<i32, &'static str>(|| [1, 2, 3].into_iter().max().ok_or_throw("empty"))
+/// };
+/// assert_eq!(res, Ok(3));
+/// ```
+pub trait ThrowExt<T>: private::Sealed {
+    /// The error type, thrown by [`ThrowExt::or_throw`].
+    type Error;
+
+    /// Unwrap the success value or throw the error.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of [`throw`](fn@throw) for [`ThrowExt::Error`] apply.
+    #[cfg_attr(feature = "location", track_caller)]
+    unsafe fn or_throw(self) -> T;
+
+    /// Unwrap the success value or throw the error, converted with `f`.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of [`throw`](fn@throw) for `F` apply. `f` must not throw exceptions.
+    #[cfg_attr(feature = "location", track_caller)]
+    unsafe fn or_throw_with<F>(self, f: impl FnOnce(Self::Error) -> F) -> T;
+
+    /// Unwrap the success value or throw `cause`, discarding the error.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of [`throw`](fn@throw) for `F` apply.
+    #[cfg_attr(feature = "location", track_caller)]
+    unsafe fn ok_or_throw<F>(self, cause: F) -> T;
+}
+
+impl<T, E> ThrowExt<T> for Result<T, E> {
+    type Error = E;
+
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    unsafe fn or_throw(self) -> T {
+        match self {
+            Ok(value) => value,
+            // SAFETY: Requirements forwarded.
+            Err(err) => unsafe { throw(err) },
+        }
+    }
+
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    unsafe fn or_throw_with<F>(self, f: impl FnOnce(E) -> F) -> T {
+        match self {
+            Ok(value) => value,
+            // SAFETY: Requirements forwarded.
+            Err(err) => unsafe { throw(f(err)) },
+        }
+    }
+
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    unsafe fn ok_or_throw<F>(self, cause: F) -> T {
+        match self {
+            Ok(value) => value,
+            // SAFETY: Requirements forwarded.
+            Err(_) => unsafe { throw(cause) },
+        }
+    }
+}
+
+impl<T> ThrowExt<T> for Option<T> {
+    type Error = ();
+
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    unsafe fn or_throw(self) -> T {
+        match self {
+            Some(value) => value,
+            // SAFETY: Requirements forwarded.
+            None => unsafe { throw(()) },
+        }
+    }
+
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    unsafe fn or_throw_with<F>(self, f: impl FnOnce(()) -> F) -> T {
+        match self {
+            Some(value) => value,
+            // SAFETY: Requirements forwarded.
+            None => unsafe { throw(f(())) },
+        }
+    }
+
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    unsafe fn ok_or_throw<F>(self, cause: F) -> T {
+        match self {
+            Some(value) => value,
+            // SAFETY: Requirements forwarded.
+            None => unsafe { throw(cause) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catch;
+    use alloc::string::String;
+
+    #[test]
+    fn result() {
+        let ok: Result<i32, String> = Ok(1);
+        assert_eq!(unsafe { catch::<_, String>(|| ok.or_throw()) }, Ok(1));
+        let err: Result<i32, String> = Err(String::from("Oops!"));
+        assert_eq!(
+            unsafe { catch::<_, String>(|| err.or_throw()) },
+            Err(String::from("Oops!")),
+        );
+    }
+
+    #[test]
+    fn result_with() {
+        let err: Result<i32, i32> = Err(1);
+        assert_eq!(
+            unsafe { catch::<_, String>(|| err.or_throw_with(|x| alloc::format!("error {x}"))) },
+            Err(String::from("error 1")),
+        );
+        let err: Result<i32, i32> = Err(1);
+        assert_eq!(unsafe { catch::<_, u8>(|| err.ok_or_throw(2u8)) }, Err(2));
+    }
+
+    #[test]
+    fn option() {
+        assert_eq!(unsafe { catch::<_, ()>(|| Some(1).or_throw()) }, Ok(1));
+        assert_eq!(unsafe { catch::<i32, ()>(|| None.or_throw()) }, Err(()));
+        assert_eq!(
+            unsafe { catch::<i32, u8>(|| None.ok_or_throw(5u8)) },
+            Err(5)
+        );
+        assert_eq!(
+            unsafe { catch::<i32, u8>(|| None.or_throw_with(|()| 6u8)) },
+            Err(6),
+        );
+    }
+}
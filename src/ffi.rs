@@ -0,0 +1,112 @@
+//! C ABI for throwing and catching exceptions.
+//!
+//! This module exports two unmangled functions that let C, C++, or other languages embedded into
+//! the same binary participate in Lithium-based control flow. Exceptions thrown through this
+//! interface carry an opaque pointer-sized payload, and are distinct from all Rust exception types.
+//!
+//! The corresponding C declarations are:
+//!
+//! ```c
+//! _Noreturn void lithium_throw(void *payload);
+//! bool lithium_intercept(void (*func)(void *data), void *data, void **payload);
+//! ```
+//!
+//! Foreign frames between [`lithium_throw`] and [`lithium_intercept`] must be unwind-safe: C++
+//! frames compiled with exceptions enabled are fine, while C code needs to be compiled with
+//! `-fexceptions` or an equivalent. Destructors of C++ frames run during unwinding, but C++
+//! `catch (...)` blocks must not intercept Lithium exceptions.
+//!
+//! The functions are only exported if the `ffi` feature is enabled.
+
+use super::api::{catch, throw};
+use core::ffi::c_void;
+
+/// The exception type used by the C ABI.
+///
+/// This is a private type, so Rust code can't accidentally catch FFI exceptions with a mismatched
+/// type.
+struct Payload(*mut c_void);
+
+/// Throw an exception with an opaque payload.
+///
+/// # Safety
+///
+/// The exception must be caught by [`lithium_intercept`], possibly after passing through Rust and
+/// foreign frames. See the safety section of [this crate](crate) for more information.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn lithium_throw(payload: *mut c_void) -> ! {
+    // SAFETY: Required transitively.
+    unsafe { throw(Payload(payload)) }
+}
+
+/// Call `func(data)`, catching exceptions thrown by [`lithium_throw`].
+///
+/// Returns `true` if `func` returned normally. If `func` threw an exception, writes its payload to
+/// `*payload` and returns `false`.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// `func` must be safe to call with `data`, and must only throw exceptions via [`lithium_throw`].
+///
+/// `payload` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn lithium_intercept(
+    func: unsafe extern "C-unwind" fn(*mut c_void),
+    data: *mut c_void,
+    payload: *mut *mut c_void,
+) -> bool {
+    // SAFETY: `func` is safe to call with `data` by the safety requirements.
+    let call = || unsafe { func(data) };
+    // SAFETY: `func` only throws `Payload`s by the safety requirements.
+    match unsafe { catch::<(), Payload>(call) } {
+        Ok(()) => true,
+        Err(Payload(value)) => {
+            // SAFETY: `payload` is valid for writes by the safety requirements.
+            unsafe {
+                payload.write(value);
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::ptr::null_mut;
+
+    unsafe extern "C-unwind" fn double(data: *mut c_void) {
+        // SAFETY: The tests pass a pointer to `usize`.
+        let value = unsafe { data.cast::<usize>().read() };
+        if value > 100 {
+            // SAFETY: Only called by `lithium_intercept`.
+            unsafe { lithium_throw(data) }
+        }
+        // SAFETY: As above.
+        unsafe {
+            data.cast::<usize>().write(value * 2);
+        }
+    }
+
+    #[test]
+    fn returns() {
+        let mut value = 5usize;
+        let mut payload = null_mut();
+        let data = (&raw mut value).cast();
+        assert!(unsafe { lithium_intercept(double, data, &raw mut payload) });
+        assert_eq!(value, 10);
+        assert!(payload.is_null());
+    }
+
+    #[test]
+    fn throws() {
+        let mut value = 500usize;
+        let mut payload = null_mut();
+        let data = (&raw mut value).cast();
+        assert!(!unsafe { lithium_intercept(double, data, &raw mut payload) });
+        assert_eq!(value, 500);
+        assert_eq!(payload, data);
+    }
+}
@@ -0,0 +1,234 @@
+//! Stackful coroutine support.
+//!
+//! Lithium keeps per-thread bookkeeping about exceptions: the exception stack on most backends, the
+//! number of unwinding exceptions, and the active catch frames in debug builds. This bookkeeping
+//! assumes that the thread runs on a single machine stack. Stackful coroutines, such as those
+//! provided by `corosensei` or `generator`, break this assumption: if a fiber is suspended while an
+//! [`InFlightException`](crate::InFlightException) is alive, and another fiber throws or releases
+//! exceptions in the meantime, the exceptions of the two fibers are mixed up.
+//!
+//! To prevent this, the coroutine library should move the bookkeeping out with [`save`] whenever a
+//! fiber is switched out, and put it back with [`restore`] when the fiber is resumed. Fibers that
+//! start afresh don't need to restore anything, as [`save`] leaves the thread with empty state.
+//!
+//! Exceptions in flight in the statically allocated part of the thread's exception stack are copied
+//! to a new heap allocation by [`save`] and copied back by [`restore`], so while exceptions are in
+//! flight, a switch costs an allocation and a copy of up to `LITHIUM_STACK_CAPACITY` bytes. With
+//! `LITHIUM_NO_HEAP`, [`save`] thus panics while such exceptions are in flight. Runtimes that
+//! switch fibers often, or migrate them between threads, can give each fiber an
+//! [`ExceptionStack`](crate::ExceptionStack) instead.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::{fiber, in_flight_count, intercept, throw};
+//!
+//! # if option_env!("LITHIUM_NO_HEAP") == Some("1") {
+//! #     return;
+//! # }
+//! // Fiber A catches an exception and is suspended before releasing it.
+//! // SAFETY: Only `i32` is thrown.
+//! let (_, a) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+//! // SAFETY: `a` is not used until the state is restored.
+//! let state_a = unsafe { fiber::save() };
+//!
+//! // Fiber B runs with clean state.
+//! # #[cfg(not(target_env = "msvc"))]
+//! assert_eq!(in_flight_count(), 0);
+//! // SAFETY: Only `i32` is thrown.
+//! let (_, b) = unsafe { intercept::<(), i32>(|| throw(2)) }.unwrap_err();
+//! // SAFETY: `b` is not used until the state is restored.
+//! let state_b = unsafe { fiber::save() };
+//!
+//! // Fiber A is resumed and releases its exception, even though fiber B caught one later.
+//! // SAFETY: `state_a` was saved when switching from fiber A.
+//! unsafe { fiber::restore(state_a) };
+//! drop(a);
+//! // SAFETY: Fiber A holds no exceptions, so dropping its state is fine.
+//! drop(unsafe { fiber::save() });
+//!
+//! // SAFETY: `state_b` was saved when switching from fiber B.
+//! unsafe { fiber::restore(state_b) };
+//! drop(b);
+//! ```
+
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::stacked_exceptions::SavedExceptions;
+use super::{debug::SavedCounters, tls::get_state};
+use core::marker::PhantomData;
+
+/// The Lithium state of a suspended fiber.
+///
+/// Returned by [`save`] and consumed by [`restore`]. The state refers to the storage of the thread
+/// it was saved on, so it can't be sent to other threads.
+///
+/// Dropping a state without restoring it leaks the exceptions in flight, if any: their causes are
+/// never dropped.
+#[must_use = "Dropping a saved state leaks its exceptions"]
+pub struct StackState {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    exceptions: SavedExceptions,
+    debug: SavedCounters,
+    rethrows: usize,
+    unwinding: usize,
+    /// Make the type `!Send` and `!Sync`.
+    _thread: PhantomData<*const ()>,
+}
+
+/// Move the Lithium state of the current thread out, leaving it empty.
+///
+/// Call this when switching away from a fiber. Afterwards, the thread behaves as if no exceptions
+/// were in flight and no catch frames were active, so another fiber can run on it.
+///
+/// # Panics
+///
+/// With `LITHIUM_NO_HEAP`, panics if exceptions are in flight in the statically allocated part of
+/// the thread's exception stack, as moving them out needs a heap allocation. Exceptions in an
+/// [`ExceptionStack`](crate::ExceptionStack) entered by the fiber are not moved, so they don't
+/// cause a panic.
+///
+/// # Safety
+///
+/// Until the returned state is passed to [`restore`], exceptions caught before the call must not be
+/// rethrown or released, and exceptions thrown before the call must not be caught. In other words,
+/// the fiber that was running must stay suspended.
+#[inline]
+pub unsafe fn save() -> StackState {
+    // SAFETY: The reference is not used past the call frame.
+    let state = unsafe { get_state() };
+    StackState {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        exceptions: state.exceptions.save(),
+        debug: state.debug.save(),
+        rethrows: state.rethrows.save(),
+        unwinding: state.unwinding.replace(0),
+        _thread: PhantomData,
+    }
+}
+
+/// Put the Lithium state of a fiber back.
+///
+/// Call this when switching to a fiber that was suspended with [`save`].
+///
+/// # Panics
+///
+/// Panics if the current thread has state of its own, i.e. exceptions are unwinding or in flight,
+/// or catch frames are active in debug builds. Call [`save`] before restoring another state.
+///
+/// # Safety
+///
+/// `state` must have been saved when switching away from the fiber that is being resumed, and the
+/// machine stack of the fiber must still be alive.
+#[inline]
+pub unsafe fn restore(state: StackState) {
+    // SAFETY: The reference is not used past the call frame.
+    let thread = unsafe { get_state() };
+    assert!(
+        thread.unwinding.get() == 0,
+        "Lithium state restored while exceptions are unwinding on the current thread",
+    );
+    // SAFETY: `state.exceptions` was saved on this thread, as `StackState` is `!Send`. The stack
+    // installed by `ExceptionStack::enter` at that point, if any, lives in a frame of the resumed
+    // fiber, which is alive by the safety requirements.
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    unsafe {
+        thread.exceptions.restore(&state.exceptions);
+    }
+    thread.debug.restore(state.debug);
+    thread.rethrows.restore(state.rethrows);
+    thread.unwinding.set(state.unwinding);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, in_flight_count, intercept, is_unwinding, throw, ExceptionStack};
+    use core::mem::ManuallyDrop;
+
+    #[test]
+    #[cfg(not(no_heap))]
+    fn interleaved() {
+        let (_, a) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+        let state_a = unsafe { save() };
+        assert_eq!(in_flight_count(), 0);
+
+        let (_, b) = unsafe { intercept::<(), i32>(|| throw(2)) }.unwrap_err();
+        let (_, c) = unsafe { intercept::<(), i32>(|| throw(3)) }.unwrap_err();
+        let state_bc = unsafe { save() };
+
+        unsafe { restore(state_a) };
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        assert_eq!(in_flight_count(), 1);
+        assert_eq!(unsafe { catch::<(), i32>(|| a.rethrow(4)) }, Err(4));
+        drop(unsafe { save() });
+
+        unsafe { restore(state_bc) };
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        assert_eq!(in_flight_count(), 2);
+        drop(c);
+        drop(b);
+        assert_eq!(in_flight_count(), 0);
+    }
+
+    #[test]
+    #[cfg(not(no_heap))]
+    fn unwinding() {
+        struct Switch;
+
+        impl Drop for Switch {
+            fn drop(&mut self) {
+                assert!(is_unwinding());
+                let state = unsafe { save() };
+                assert!(!is_unwinding());
+                assert_eq!(unsafe { catch::<(), i32>(|| throw(2)) }, Err(2));
+                unsafe { restore(state) };
+                assert!(is_unwinding());
+            }
+        }
+
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                let _switch = Switch;
+                throw(1)
+            })
+        };
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn inside_exception_stack() {
+        let stack = ExceptionStack::new();
+        let result = unsafe {
+            stack.enter(|| {
+                let (_, ex) = intercept::<(), i32>(|| throw(1)).unwrap_err();
+                let state = save();
+                assert_eq!(catch::<(), i32>(|| throw(2)), Err(2));
+                restore(state);
+                catch::<(), i32>(|| ex.rethrow(3))
+            })
+        };
+        assert_eq!(result, Err(3));
+    }
+
+    #[test]
+    #[should_panic = "Lithium exception stack restored while exceptions are in flight"]
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    #[cfg(not(no_heap))]
+    fn restore_over_exceptions() {
+        // `a` can't be released after the panic, so leak it along with `state`.
+        let _a = ManuallyDrop::new(unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err());
+        let state = unsafe { save() };
+        let (_, _b) = unsafe { intercept::<(), i32>(|| throw(2)) }.unwrap_err();
+        unsafe { restore(state) };
+    }
+
+    #[test]
+    #[should_panic = "forbidden by `LITHIUM_NO_HEAP`"]
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    #[cfg(no_heap)]
+    fn save_without_heap() {
+        // `a` can't be released after the panic, so leak it.
+        let _a = ManuallyDrop::new(unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err());
+        drop(unsafe { save() });
+    }
+}
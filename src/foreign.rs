@@ -0,0 +1,300 @@
+use super::{
+    abort,
+    api::{intercept, InFlightException},
+    backend::{delete_foreign, intercept_foreign, rethrow_foreign},
+};
+
+/// A foreign exception caught by [`catch_foreign`].
+///
+/// This is an opaque handle to an exception thrown by another runtime, such as a C++ `throw`. It
+/// can be rethrown with [`ForeignException::rethrow`]. Dropping the handle destroys the exception
+/// by invoking its cleanup function, as if it was caught by its own runtime.
+///
+/// The handle is tied to the thread it was caught on.
+#[derive(Debug)]
+pub struct ForeignException {
+    ex: *mut u8,
+}
+
+/// Catch foreign exceptions.
+///
+/// If `func` returns a value, this function wraps it in [`Ok`].
+///
+/// If `func` throws a foreign exception, e.g. a C++ exception that unwinds through an
+/// `extern "C-unwind"` function, a handle to it is returned in [`Err`]. Use this to stop foreign
+/// exceptions at a known boundary rather than letting them propagate through the whole program.
+///
+/// Lithium exceptions and Rust panics are propagated as-is and not caught. Forced unwinding, such
+/// as that performed by `pthread_exit`, is not stopped either.
+///
+/// This function is only available on the Itanium backend.
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub fn catch_foreign<R>(func: impl FnOnce() -> R) -> Result<R, ForeignException> {
+    intercept_foreign(func).map_err(|ex| ForeignException { ex })
+}
+
+/// What to do with a foreign exception reaching [`intercept_with_policy`].
+///
+/// By default, [`intercept`] and other catching functions propagate foreign exceptions on the
+/// Itanium backend, which corresponds to [`ForeignPolicy::Rethrow`].
+#[non_exhaustive]
+pub enum ForeignPolicy<'a, R> {
+    /// Abort the process.
+    Abort,
+    /// Let the exception continue unwinding.
+    Rethrow,
+    /// Pass the exception to a callback, the return value of which is treated as if it was returned
+    /// by the intercepted function. The callback may throw exceptions of the intercepted type.
+    Handle(&'a mut dyn FnMut(ForeignException) -> R),
+}
+
+/// Begin exception catching, with a custom policy for foreign exceptions.
+///
+/// This is equivalent to [`intercept`], except that foreign exceptions, i.e. exceptions that are
+/// neither Lithium exceptions nor Rust panics, are handled according to `policy`.
+///
+/// This function is only available on the Itanium backend. Other backends don't support resuming
+/// foreign exceptions.
+///
+/// # Safety
+///
+/// The requirements of [`intercept`] apply.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{intercept_with_policy, ForeignPolicy};
+///
+/// // SAFETY: The closure doesn't throw Lithium exceptions.
+/// let result = unsafe {
+///     intercept_with_policy::<i32, ()>(ForeignPolicy::Handle(&mut |_ex| -1), || 1)
+/// };
+/// assert_eq!(result.ok(), Some(1));
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn intercept_with_policy<R, E>(
+    policy: ForeignPolicy<'_, R>,
+    func: impl FnOnce() -> R,
+) -> Result<R, (E, InFlightException<E>)> {
+    let guarded = || match catch_foreign(func) {
+        Ok(value) => value,
+        Err(ex) => match policy {
+            ForeignPolicy::Abort => {
+                abort("Lithium caught a foreign exception. The process will now terminate.\n")
+            }
+            ForeignPolicy::Rethrow => ex.rethrow(),
+            ForeignPolicy::Handle(handler) => handler(ex),
+        },
+    };
+    // SAFETY: Requirements forwarded. The handler may only throw `E`.
+    unsafe { intercept(guarded) }
+}
+
+impl ForeignException {
+    /// Get the exception class.
+    ///
+    /// This is an 8-byte identifier of the runtime that threw the exception, e.g.
+    /// `u64::from_be_bytes(*b"GNUCC++\0")` for C++ exceptions thrown by libstdc++.
+    #[inline]
+    #[must_use]
+    pub fn class(&self) -> u64 {
+        // SAFETY: `ex` points at a live `_Unwind_Exception`, which starts with the class. It's at
+        // least 8-byte aligned.
+        #[expect(clippy::cast_ptr_alignment, reason = "See the safety comment above")]
+        let class = unsafe { *self.ex.cast::<u64>() };
+        class
+    }
+
+    /// Rethrow the exception.
+    ///
+    /// The exception continues unwinding from the current frame, as if it was never caught.
+    #[inline]
+    pub fn rethrow(self) -> ! {
+        let ex = core::mem::ManuallyDrop::new(self).ex;
+        // SAFETY: `ex` was returned by `intercept_foreign` and hasn't been rethrown or deleted,
+        // since `self` is consumed.
+        unsafe { rethrow_foreign(ex) }
+    }
+}
+
+impl Drop for ForeignException {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: `ex` was returned by `intercept_foreign` and hasn't been rethrown or deleted.
+        unsafe {
+            delete_foreign(self.ex);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, throw};
+    use core::cell::Cell;
+    use std::thread_local;
+
+    const CLASS: u64 = u64::from_be_bytes(*b"TEST\0EXC");
+
+    #[repr(C, align(16))]
+    struct FakeException {
+        class: u64,
+        cleanup: Option<unsafe extern "C" fn(i32, *mut FakeException)>,
+        // Enough for the private words of all supported architectures.
+        private: [usize; 35],
+        payload: i32,
+    }
+
+    thread_local! {
+        static CLEANED_UP: Cell<i32> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn cleanup(_code: i32, ex: *mut FakeException) {
+        // SAFETY: `ex` was allocated by `throw_fake`.
+        let ex = unsafe { alloc::boxed::Box::from_raw(ex) };
+        CLEANED_UP.set(ex.payload);
+    }
+
+    extern "C-unwind" {
+        fn _Unwind_RaiseException(ex: *mut u8) -> !;
+    }
+
+    fn throw_fake(payload: i32) -> ! {
+        let ex = alloc::boxed::Box::new(FakeException {
+            class: CLASS,
+            cleanup: Some(cleanup),
+            private: [0; 35],
+            payload,
+        });
+        #[expect(clippy::used_underscore_items, reason = "External API")]
+        // SAFETY: `ex` is a valid `_Unwind_Exception`.
+        unsafe {
+            _Unwind_RaiseException(alloc::boxed::Box::into_raw(ex).cast())
+        }
+    }
+
+    #[test]
+    fn ok() {
+        assert_eq!(catch_foreign(|| 123).unwrap(), 123);
+    }
+
+    #[test]
+    fn catch_and_drop() {
+        let ex = catch_foreign(|| throw_fake(1)).unwrap_err();
+        assert_eq!(ex.class(), CLASS);
+        assert_eq!(CLEANED_UP.get(), 0);
+        drop(ex);
+        assert_eq!(CLEANED_UP.get(), 1);
+    }
+
+    #[test]
+    fn rethrow() {
+        let ex = catch_foreign(|| catch_foreign(|| throw_fake(2)).unwrap_err().rethrow());
+        drop(ex.unwrap_err());
+        assert_eq!(CLEANED_UP.get(), 2);
+    }
+
+    #[test]
+    fn lithium_passthrough() {
+        let result = unsafe { catch::<_, i32>(|| catch_foreign(|| throw(3))) };
+        assert_eq!(result.unwrap_err(), 3);
+    }
+
+    #[test]
+    fn policy_handle() {
+        let mut handler = |ex: ForeignException| {
+            assert_eq!(ex.class(), CLASS);
+            5
+        };
+        let result = unsafe {
+            intercept_with_policy::<i32, ()>(ForeignPolicy::Handle(&mut handler), || throw_fake(4))
+        };
+        assert_eq!(result.ok(), Some(5));
+        assert_eq!(CLEANED_UP.get(), 4);
+    }
+
+    #[test]
+    fn policy_rethrow() {
+        let ex = catch_foreign(|| unsafe {
+            intercept_with_policy::<(), ()>(ForeignPolicy::Rethrow, || throw_fake(6))
+        });
+        drop(ex.err().unwrap());
+        assert_eq!(CLEANED_UP.get(), 6);
+    }
+
+    #[test]
+    fn panic_passthrough() {
+        let result = std::panic::catch_unwind(|| catch_foreign(|| std::panic::panic_any(4)));
+        assert_eq!(*result.unwrap_err().downcast::<i32>().unwrap(), 4);
+    }
+
+    /// `pthread_exit` unwinds the thread with a forced unwind on glibc. Lithium catch frames must
+    /// resume it rather than swallow it, and the frames in between must still be cleaned up.
+    #[test]
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    fn forced_unwind() {
+        use core::ffi::{c_int, c_ulong, c_void};
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        static RESUMED: AtomicBool = AtomicBool::new(false);
+        static CLEANED_UP: AtomicBool = AtomicBool::new(false);
+        static EXIT_VALUE: u8 = 0;
+
+        extern "C" {
+            fn pthread_create(
+                thread: *mut c_ulong,
+                attr: *const c_void,
+                start: extern "C-unwind" fn(*mut c_void) -> *mut c_void,
+                arg: *mut c_void,
+            ) -> c_int;
+            fn pthread_join(thread: c_ulong, retval: *mut *mut c_void) -> c_int;
+        }
+
+        extern "C-unwind" {
+            fn pthread_exit(retval: *mut c_void) -> !;
+        }
+
+        struct Guard;
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                CLEANED_UP.store(true, Ordering::Relaxed);
+            }
+        }
+
+        extern "C-unwind" fn start(_arg: *mut c_void) -> *mut c_void {
+            let _guard = Guard;
+            let _ = catch_foreign(|| unsafe {
+                catch::<(), i32>(|| pthread_exit((&raw const EXIT_VALUE).cast_mut().cast()))
+            });
+            RESUMED.store(true, Ordering::Relaxed);
+            core::ptr::null_mut()
+        }
+
+        let mut thread = 0;
+        assert_eq!(
+            unsafe {
+                pthread_create(
+                    &raw mut thread,
+                    core::ptr::null(),
+                    start,
+                    core::ptr::null_mut(),
+                )
+            },
+            0,
+        );
+        let mut retval = core::ptr::null_mut();
+        assert_eq!(unsafe { pthread_join(thread, &raw mut retval) }, 0);
+        assert_eq!(retval.cast_const(), (&raw const EXIT_VALUE).cast());
+        assert!(!RESUMED.load(Ordering::Relaxed));
+        assert!(CLEANED_UP.load(Ordering::Relaxed));
+    }
+}
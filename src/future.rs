@@ -0,0 +1,152 @@
+//! Catching exceptions in asynchronous code.
+//!
+//! An exception cannot cross an `.await` point: it's thrown and has to be caught within a single
+//! call to [`Future::poll`]. [`CatchFuture`] wraps each poll of the inner future in [`catch`], so
+//! exceptions thrown by the future are returned as its output instead of unwinding into the
+//! executor.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::{future::CatchFuture, throw};
+//!
+//! async fn parse(s: &str) -> i32 {
+//!     match s.parse() {
+//!         Ok(x) => x,
+//!         // SAFETY: the exception is caught by `CatchFuture` expecting `&'static str`
+//!         Err(_) => unsafe { throw::<&'static str>("not a number") },
+//!     }
+//! }
+//!
+//! // SAFETY: `parse` only throws `&'static str`
+//! let fut = unsafe { CatchFuture::<_, &'static str>::new(parse("abc")) };
+//! # let _ = fut;
+//! ```
+
+use super::api::catch;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// A future that catches exceptions thrown by the inner future.
+///
+/// Resolves to `Ok(output)` if the inner future completes, and to `Err(cause)` if the inner future
+/// throws an exception while being polled. After an exception, the inner future is not polled
+/// again.
+///
+/// Rust panics are propagated as-is and not caught.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct CatchFuture<F, E> {
+    future: F,
+    terminated: bool,
+    phantom: PhantomData<fn() -> E>,
+}
+
+impl<F: Future, E> CatchFuture<F, E> {
+    /// Wrap a future.
+    ///
+    /// # Safety
+    ///
+    /// `future` must only throw exceptions of type `E` when polled. See the safety section of
+    /// [this crate](crate) for more information.
+    #[inline]
+    pub const unsafe fn new(future: F) -> Self {
+        Self {
+            future,
+            terminated: false,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: Future, E> Future for CatchFuture<F, E> {
+    type Output = Result<F::Output, E>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `future` is structurally pinned, and `terminated` is not. We never move out of
+        // `future`.
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(
+            !this.terminated,
+            "`CatchFuture` polled after an exception was caught",
+        );
+        // SAFETY: As above.
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        // SAFETY: `future` only throws `E` by the safety requirement of `new`.
+        match unsafe { catch(|| future.poll(cx)) } {
+            Ok(Poll::Ready(value)) => Poll::Ready(Ok(value)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(cause) => {
+                this.terminated = true;
+                Poll::Ready(Err(cause))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::throw;
+    use alloc::string::String;
+    use core::task::Waker;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+                return value;
+            }
+        }
+    }
+
+    /// Returns `Pending` once before completing.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn ready() {
+        let future = unsafe { CatchFuture::<_, String>::new(async { 123 }) };
+        assert_eq!(block_on(future), Ok(123));
+    }
+
+    #[test]
+    fn throws_after_pending() {
+        let future = unsafe {
+            CatchFuture::<_, String>::new(async {
+                YieldOnce(false).await;
+                throw::<String>(String::from("Hello, world!"));
+            })
+        };
+        assert_eq!(block_on(future).unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    #[should_panic = "polled after an exception was caught"]
+    fn poll_after_exception() {
+        let mut future = core::pin::pin!(unsafe {
+            CatchFuture::<_, i32>::new(async {
+                throw::<i32>(1);
+            })
+        });
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(Err(1)));
+        let _ = future.as_mut().poll(&mut cx);
+    }
+}
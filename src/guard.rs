@@ -0,0 +1,196 @@
+//! Scope guards that cooperate with exceptions.
+//!
+//! Lithium unwinding runs destructors of locals just like panics do, so any drop-based guard
+//! technically works with exceptions. This module provides guards that are documented and tested to
+//! run correctly during Lithium unwinding on all backends. This includes SEH, where the stack is
+//! not unwound until the catch handler returns.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::{catch, guard::finally, throw};
+//!
+//! let mut cleaned_up = false;
+//! // SAFETY: the exception type matches
+//! let res = unsafe {
+//!     catch::<(), i32>(|| {
+//!         let _guard = finally(|| cleaned_up = true);
+//!         throw::<i32>(1);
+//!     })
+//! };
+//! assert_eq!(res, Err(1));
+//! assert!(cleaned_up);
+//! ```
+
+use core::mem::ManuallyDrop;
+
+/// A guard that runs a callback when dropped.
+///
+/// Created by [`finally`].
+#[must_use = "the callback is invoked immediately if the guard is not bound to a variable"]
+pub struct Guard<F: FnOnce()> {
+    callback: ManuallyDrop<F>,
+}
+
+/// Run `callback` when the returned guard is dropped.
+///
+/// The callback is invoked both on normal scope exit and during unwinding, whether the unwinding
+/// was caused by a Lithium exception or a Rust panic. This is the equivalent of a `finally` block.
+///
+/// The callback must not throw exceptions: if it's invoked during unwinding, a thrown exception
+/// would escape the destructor. Catching exceptions inside the callback is allowed.
+///
+/// Use [`Guard::defuse`] to cancel the guard.
+#[inline]
+pub const fn finally<F: FnOnce()>(callback: F) -> Guard<F> {
+    Guard {
+        callback: ManuallyDrop::new(callback),
+    }
+}
+
+impl<F: FnOnce()> Guard<F> {
+    /// Cancel the guard without invoking the callback.
+    ///
+    /// The callback is returned back to the caller.
+    #[inline]
+    pub fn defuse(self) -> F {
+        let mut guard = ManuallyDrop::new(self);
+        // SAFETY: `guard` is never dropped, so the callback is only taken once.
+        unsafe { ManuallyDrop::take(&mut guard.callback) }
+    }
+}
+
+impl<F: FnOnce()> Drop for Guard<F> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: `drop` is only invoked once, and `defuse` forgets `self`, so the callback has not
+        // been taken yet.
+        let callback = unsafe { ManuallyDrop::take(&mut self.callback) };
+        callback();
+    }
+}
+
+/// Run `func`, invoking `cleanup` if it unwinds.
+///
+/// If `func` returns normally, its return value is passed through and `cleanup` is dropped without
+/// being invoked. If `func` throws an exception or panics, `cleanup` is invoked during unwinding,
+/// and unwinding then continues as usual.
+///
+/// The cleanup callback must not throw exceptions, as it may be invoked during unwinding. Catching
+/// exceptions inside the callback is allowed.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, guard::on_unwind, throw};
+///
+/// let mut rolled_back = false;
+/// // SAFETY: the exception type matches
+/// let res = unsafe {
+///     catch::<(), i32>(|| {
+///         on_unwind(|| throw::<i32>(1), || rolled_back = true);
+///     })
+/// };
+/// assert_eq!(res, Err(1));
+/// assert!(rolled_back);
+/// ```
+#[inline]
+pub fn on_unwind<R>(func: impl FnOnce() -> R, cleanup: impl FnOnce()) -> R {
+    let guard = finally(cleanup);
+    let value = func();
+    drop(guard.defuse());
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, throw};
+    use alloc::string::String;
+
+    #[test]
+    fn finally_on_return() {
+        let mut called = false;
+        {
+            let _guard = finally(|| called = true);
+        }
+        assert!(called);
+    }
+
+    #[test]
+    fn finally_on_throw() {
+        let mut called = false;
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let _guard = finally(|| called = true);
+                throw(String::from("Hello, world!"));
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+        assert!(called);
+    }
+
+    #[test]
+    fn finally_on_panic() {
+        let mut called = false;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = finally(|| called = true);
+            panic!("Hello, world!");
+        }))
+        .unwrap_err();
+        assert!(called);
+    }
+
+    #[test]
+    fn finally_with_nested_catch() {
+        let mut called = false;
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let _guard = finally(|| {
+                    let inner: Result<(), i32> = catch(|| throw(1));
+                    assert_eq!(inner, Err(1));
+                    called = true;
+                });
+                throw(String::from("Hello, world!"));
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+        assert!(called);
+    }
+
+    #[test]
+    fn defuse() {
+        let mut called = false;
+        let _ = finally(|| called = true).defuse();
+        assert!(!called);
+    }
+
+    #[test]
+    fn on_unwind_on_return() {
+        let mut called = false;
+        assert_eq!(on_unwind(|| 123, || called = true), 123);
+        assert!(!called);
+    }
+
+    #[test]
+    fn on_unwind_on_throw() {
+        let mut called = false;
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                on_unwind(|| throw(String::from("Hello, world!")), || called = true);
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+        assert!(called);
+    }
+
+    #[test]
+    fn on_unwind_on_panic() {
+        let mut called = false;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            on_unwind(|| panic!("Hello, world!"), || called = true);
+        }))
+        .unwrap_err();
+        assert!(called);
+    }
+}
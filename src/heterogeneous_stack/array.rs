@@ -1,4 +1,8 @@
-use super::align::assert_aligned;
+use super::{
+    align::assert_aligned,
+    sanitizer::{poison, unpoison},
+};
+use alloc::boxed::Box;
 use core::cell::{Cell, UnsafeCell};
 use core::mem::MaybeUninit;
 
@@ -8,11 +12,16 @@ use core::mem::MaybeUninit;
 /// guaranteeing alignment to `align_of::<AlignAs>()`.
 ///
 /// The allocated bytes are always consecutive.
+///
+/// In debug builds, each non-empty element is followed by [`Stack::CANARY`] bytes of canary, which
+/// are checked when the element is popped. Writing out of the bounds of an element or popping an
+/// element with a wrong size aborts the process instead of silently corrupting the stack.
 // Safety invariants:
 // - len is a factor of `align_of::<AlignAs>()`
 // - `len <= CAPACITY`
 // - References to `data[len..]` are not used; `data[..len]` may be arbitrarily referenced
-// - All elements are consecutive, with the last element ending at `len`
+// - All elements are consecutive, each non-empty element followed by `CANARY` bytes of canary, with
+//   the last canary ending at `len`
 // - Element sizes are multiples of `align_of::<AlignAs>()`
 // - Allocation necessarily succeeds if there is enough capacity left
 #[repr(C)]
@@ -22,7 +31,18 @@ pub struct Stack<AlignAs, const CAPACITY: usize> {
     pub(super) len: Cell<usize>,
 }
 
+/// The value of canary bytes.
+const CANARY_BYTE: u8 = 0xca;
+
 impl<AlignAs, const CAPACITY: usize> Stack<AlignAs, CAPACITY> {
+    /// The number of canary bytes after each element: 8 bytes rounded up to alignment in debug
+    /// builds, 0 in release builds.
+    pub(super) const CANARY: usize = if cfg!(debug_assertions) {
+        size_of::<u64>().next_multiple_of(align_of::<AlignAs>())
+    } else {
+        0
+    };
+
     /// Create an empty stack.
     pub const fn new() -> Self {
         Self {
@@ -38,7 +58,8 @@ impl<AlignAs, const CAPACITY: usize> Stack<AlignAs, CAPACITY> {
     /// reads/writes for `n` bytes. It is also guaranteed to be unique.
     ///
     /// Returns `None` if there isn't enough space. It is guaranteed that allocation always succeeds
-    /// if there's at least `n` free capacity. In particular, allocating 0 bytes always succeeds.
+    /// if there's at least `n + CANARY` free capacity. In particular, allocating 0 bytes always
+    /// succeeds.
     ///
     /// # Panics
     ///
@@ -54,7 +75,7 @@ impl<AlignAs, const CAPACITY: usize> Stack<AlignAs, CAPACITY> {
 
         // SAFETY: len <= CAPACITY is an invariant
         let capacity_left = unsafe { CAPACITY.unchecked_sub(self.len.get()) };
-        if n > capacity_left {
+        if n.saturating_add(Self::CANARY) > capacity_left {
             // Type invariant: not enough capacity left
             return None;
         }
@@ -67,17 +88,60 @@ impl<AlignAs, const CAPACITY: usize> Stack<AlignAs, CAPACITY> {
         // - `ptr` is unique by the type invariant
         let ptr: *mut u8 = ptr.cast();
 
-        // SAFETY: n <= capacity - len implies len + n <= capacity < usize::MAX
-        self.len.set(unsafe { self.len.get().unchecked_add(n) });
+        // SAFETY: n + CANARY <= capacity - len implies len + n + CANARY <= capacity < usize::MAX
+        let len = unsafe { self.len.get().unchecked_add(n.wrapping_add(Self::CANARY)) };
+        self.len.set(len);
+
+        unpoison(ptr, n.wrapping_add(Self::CANARY));
+
+        // SAFETY: The canary is located within `data[..len']`, right after the element.
+        let canary = unsafe { ptr.add(n) };
+        // SAFETY: References to the canary are not used, as it was not allocated before.
+        unsafe {
+            canary.write_bytes(CANARY_BYTE, Self::CANARY);
+        }
 
         // Type invariants:
-        // - len' is a factor of align_of::<AlignAs>(), as n is a factor of alignment
+        // - len' is a factor of align_of::<AlignAs>(), as n and CANARY are factors of alignment
         // - len' <= CAPACITY still holds
         // - References to data[len'..] are not used by the invariant as len' >= len
-        // - The new element is located immediately at len with no empty space, len' is minimal
+        // - The new element is located immediately at len with no empty space, followed by the
+        //   canary, len' is minimal
         Some(ptr)
     }
 
+    /// Check whether the canary ending at `end` is intact.
+    ///
+    /// # Safety
+    ///
+    /// `end` must be the end of a canary within `data[..len]`.
+    pub(super) unsafe fn is_canary_intact(&self, end: usize) -> bool {
+        // SAFETY: `end` is the end of a canary, so it's at least `CANARY`.
+        let start = unsafe { end.unchecked_sub(Self::CANARY) };
+        // SAFETY: The canary is located within `data[..len]` by the safety requirement.
+        let canary = unsafe { self.data.get().byte_add(start) };
+        // SAFETY: `data[..len]` may be referenced by the invariant, and canaries are never
+        // referenced mutably.
+        let canary = unsafe { core::slice::from_raw_parts(canary.cast::<u8>(), Self::CANARY) };
+        canary.iter().all(|&byte| byte == CANARY_BYTE)
+    }
+
+    /// Abort the process if the canary ending at `end` is overwritten.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`Stack::is_canary_intact`].
+    unsafe fn check_canary(&self, end: usize) {
+        // SAFETY: Required transitively.
+        if !unsafe { self.is_canary_intact(end) } {
+            crate::abort(
+                "Lithium exception stack is corrupted: an element was written out of bounds or \
+                 popped with a wrong size. This is a bug in Lithium or a misuse of its unsafe \
+                 API. The process will now terminate.\n",
+            );
+        }
+    }
+
     /// Remove `n` bytes from the top of the stack.
     ///
     /// # Panics
@@ -87,21 +151,96 @@ impl<AlignAs, const CAPACITY: usize> Stack<AlignAs, CAPACITY> {
     /// # Safety
     ///
     /// The caller must ensure that:
-    /// - The stack has at least `n` bytes allocated.
+    /// - `n` is 0, or the top element of the stack was allocated with `n` bytes.
     /// - References to the top `n` bytes, both immutable or mutable, are not used after
     ///   `pop_unchecked` is called.
     pub unsafe fn pop_unchecked(&self, n: usize) {
         assert_aligned::<AlignAs>(n);
 
         // For ZSTs, this is a no-op.
-        // SAFETY: len >= n by the safety requirement
-        self.len.set(unsafe { self.len.get().unchecked_sub(n) });
+        if n == 0 {
+            return;
+        }
+
+        self.check_top();
+
+        // SAFETY: The element occupies `n + CANARY <= len` bytes by the safety requirement.
+        let len = unsafe { self.len.get().unchecked_sub(n.wrapping_add(Self::CANARY)) };
+        self.len.set(len);
+
+        // This catches a wrong `n`, as `len'` then points into an element, unless it happens to
+        // contain canary bytes at that position. A wrong `n` is UB, but reporting it is still
+        // better than corrupting the stack.
+        if cfg!(debug_assertions) && len > 0 {
+            // SAFETY: By the safety requirement, the element below is followed by a canary ending
+            // at `len'`.
+            unsafe {
+                self.check_canary(len);
+            }
+        }
+
+        // SAFETY: `len'` is in bounds for `data`, as `len' <= len <= CAPACITY`.
+        let popped = unsafe { self.data.get().byte_add(len) };
+        poison(popped.cast(), n.wrapping_add(Self::CANARY));
 
         // Type invariants:
-        // - len' is a factor of align_of::<AlignAs>(), as n is a factor of alignment
+        // - len' is a factor of align_of::<AlignAs>(), as n and CANARY are factors of alignment
         // - len' <= len <= CAPACITY holds
         // - References to data[len'..len] are not used by the safety requirement
-        // - The previous allocation ends at len'
+        // - The previous canary ends at len'
+    }
+
+    /// Abort the process if the canary of the top element is overwritten.
+    ///
+    /// This is a no-op in release builds or if the stack is empty.
+    pub fn check_top(&self) {
+        if cfg!(debug_assertions) && self.len.get() > 0 {
+            // SAFETY: The top element is followed by a canary ending at `len`.
+            unsafe {
+                self.check_canary(self.len.get());
+            }
+        }
+    }
+
+    /// Move the allocated bytes out of the stack, leaving it empty.
+    ///
+    /// Pointers to the elements stay valid, but the elements must not be accessed until the bytes
+    /// are put back with [`Stack::restore`].
+    pub fn save(&self) -> Box<[MaybeUninit<u8>]> {
+        let len = self.len.replace(0);
+        let mut saved = Box::new_uninit_slice(len);
+        // SAFETY: `data[..len]` is allocated by the invariant, and `saved` is a new allocation of
+        // `len` bytes. The elements are not accessed concurrently, as the stack is thread-unsafe.
+        unsafe {
+            saved
+                .as_mut_ptr()
+                .copy_from_nonoverlapping(self.data.get().cast(), len);
+        }
+        poison(self.data.get().cast(), len);
+        // Type invariants:
+        // - len' = 0 is a factor of align_of::<AlignAs>() and is <= CAPACITY
+        // - References to data[..len] are not used by the requirement of `restore`
+        saved
+    }
+
+    /// Put the bytes moved out by [`Stack::save`] back.
+    ///
+    /// # Safety
+    ///
+    /// The stack must be empty, and `saved` must have been returned by [`Stack::save`] on this
+    /// instance.
+    pub unsafe fn restore(&self, saved: &[MaybeUninit<u8>]) {
+        unpoison(self.data.get().cast(), saved.len());
+        // SAFETY: `saved` is at most `CAPACITY` bytes long, as it was copied from `data`. The stack
+        // is empty, so no references to `data` are used.
+        unsafe {
+            self.data
+                .get()
+                .cast::<MaybeUninit<u8>>()
+                .copy_from_nonoverlapping(saved.as_ptr(), saved.len());
+        }
+        // Type invariants: The elements are located at the same addresses as before `save`.
+        self.len.set(saved.len());
     }
 
     /// Check whether an allocation is within the stack.
@@ -129,11 +268,22 @@ impl<AlignAs, const CAPACITY: usize> Stack<AlignAs, CAPACITY> {
     }
 }
 
+// Popped regions stay poisoned after the stack is dropped, which would trip up whoever reuses the
+// memory, e.g. a later frame on the machine stack.
+#[cfg(any(asan, feature = "valgrind"))]
+impl<AlignAs, const CAPACITY: usize> Drop for Stack<AlignAs, CAPACITY> {
+    fn drop(&mut self) {
+        unpoison(self.data.get().cast(), CAPACITY);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use alloc::boxed::Box;
 
+    const CANARY: usize = Stack::<u8, 0>::CANARY;
+
     #[test]
     #[should_panic]
     fn unaligned_push() {
@@ -152,7 +302,7 @@ mod test {
     fn overaligned() {
         #[repr(align(256))]
         struct Overaligned;
-        let stack = Stack::<Overaligned, 256>::new();
+        let stack = Stack::<Overaligned, 512>::new();
         let ptr = stack.try_push(256).expect("failed to allocate");
         assert_eq!(ptr.addr() % 256, 0);
     }
@@ -162,15 +312,15 @@ mod test {
         let stack = Stack::<u8, 256>::new();
         assert_eq!(stack.len.get(), 0);
         let ptr1 = stack.try_push(5).expect("failed to allocate");
-        assert_eq!(stack.len.get(), 5);
+        assert_eq!(stack.len.get(), 5 + CANARY);
         let ptr2 = stack.try_push(8).expect("failed to allocate");
-        assert_eq!(stack.len.get(), 13);
+        assert_eq!(stack.len.get(), 13 + 2 * CANARY);
         let ptr3 = stack.try_push(1).expect("failed to allocate");
-        assert_eq!(stack.len.get(), 14);
-        assert_eq!(ptr2.addr() - ptr1.addr(), 5);
-        assert_eq!(ptr3.addr() - ptr2.addr(), 8);
+        assert_eq!(stack.len.get(), 14 + 3 * CANARY);
+        assert_eq!(ptr2.addr() - ptr1.addr(), 5 + CANARY);
+        assert_eq!(ptr3.addr() - ptr2.addr(), 8 + CANARY);
         unsafe { stack.pop_unchecked(1) };
-        assert_eq!(stack.len.get(), 13);
+        assert_eq!(stack.len.get(), 13 + 2 * CANARY);
         let ptr4 = stack.try_push(2).expect("failed to allocate");
         assert_eq!(ptr3.addr(), ptr4.addr());
     }
@@ -182,6 +332,21 @@ mod test {
         assert!(stack.try_push(12).is_none(), "allocation fit");
     }
 
+    #[test]
+    #[cfg(asan)]
+    fn poison_popped() {
+        extern "C" {
+            fn __asan_address_is_poisoned(addr: *const u8) -> i32;
+        }
+        let stack = Stack::<u64, 64>::new();
+        let ptr = stack.try_push(8).expect("failed to allocate");
+        assert_eq!(unsafe { __asan_address_is_poisoned(ptr) }, 0);
+        unsafe { stack.pop_unchecked(8) };
+        assert_eq!(unsafe { __asan_address_is_poisoned(ptr) }, 1);
+        let ptr = stack.try_push(8).expect("failed to allocate");
+        assert_eq!(unsafe { __asan_address_is_poisoned(ptr) }, 0);
+    }
+
     #[test]
     fn pop_zero() {
         let stack = Stack::<u8, 16>::new();
@@ -193,14 +358,14 @@ mod test {
     #[test]
     fn push_zero() {
         let stack = Stack::<u8, 16>::new();
-        stack.try_push(16).expect("failed to allocate");
+        stack.try_push(16 - CANARY).expect("failed to allocate");
         stack.try_push(0).expect("failed to allocate");
         stack.try_push(0).expect("failed to allocate");
     }
 
     #[test]
     fn contains_allocated() {
-        let stack = Stack::<u8, 16>::new();
+        let stack = Stack::<u8, { 16 + 3 * CANARY }>::new();
         let ptr = stack.try_push(1).expect("failed to allocate");
         assert!(stack.contains_allocated(ptr, 1));
         let ptr = stack.try_push(14).expect("failed to allocate");
@@ -228,4 +393,55 @@ mod test {
         }
         assert_eq!(*ptr1, 1);
     }
+
+    #[test]
+    fn save_restore() {
+        let stack = Stack::<u8, { 16 + 3 * CANARY }>::new();
+        let ptr1 = stack.try_push(1).expect("failed to allocate");
+        unsafe {
+            ptr1.write(1);
+        }
+        let ptr2 = stack.try_push(2).expect("failed to allocate");
+        unsafe {
+            ptr2.write(2);
+        }
+        let saved = stack.save();
+        assert_eq!(stack.len.get(), 0);
+        let ptr3 = stack.try_push(1).expect("failed to allocate");
+        unsafe {
+            ptr3.write(3);
+        }
+        unsafe {
+            stack.pop_unchecked(1);
+        }
+        unsafe {
+            stack.restore(&saved);
+        }
+        assert_eq!(stack.len.get(), 3 + 2 * CANARY);
+        assert_eq!(unsafe { ptr1.read() }, 1);
+        assert_eq!(unsafe { ptr2.read() }, 2);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn canary() {
+        let stack = Stack::<u8, 256>::new();
+        let ptr1 = stack.try_push(4).expect("failed to allocate");
+        let ptr2 = stack.try_push(4).expect("failed to allocate");
+        assert!(unsafe { stack.is_canary_intact(4 + CANARY) });
+        assert!(unsafe { stack.is_canary_intact(8 + 2 * CANARY) });
+        // Overflow the first element.
+        unsafe {
+            ptr1.add(4).write(0);
+        }
+        assert!(!unsafe { stack.is_canary_intact(4 + CANARY) });
+        // Underflow the second element.
+        unsafe {
+            ptr2.sub(1).write(0);
+        }
+        unsafe {
+            ptr1.add(4).write(CANARY_BYTE);
+        }
+        assert!(!unsafe { stack.is_canary_intact(4 + CANARY) });
+    }
 }
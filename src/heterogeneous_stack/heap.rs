@@ -1,18 +1,204 @@
 use super::align::assert_aligned;
+#[cfg(not(feature = "allocator-api"))]
 use alloc::alloc;
+#[cfg(feature = "allocator-api")]
+pub use alloc::alloc::Global;
 use core::alloc::Layout;
+#[cfg(feature = "allocator-api")]
+use core::alloc::{AllocError, Allocator};
+use core::cell::Cell;
 use core::marker::PhantomData;
+#[cfg(feature = "allocator-api")]
+use core::ptr::NonNull;
+
+/// The number of size classes of recycled allocations.
+///
+/// Allocations of up to `1 << (POOLED_CLASSES - 1)` bytes, i.e. 1 MiB, are rounded up to a power
+/// of two and recycled on deallocation. Larger allocations go straight to the underlying allocator.
+const POOLED_CLASSES: usize = 21;
+
+/// Get the size class of an `n`-byte allocation, or `None` if it's too large to be pooled.
+///
+/// An allocation of class `k` takes `1 << k` bytes, which is at least the size of a pointer, so
+/// that a free allocation can store the free list link.
+fn size_class(n: usize) -> Option<usize> {
+    if n > 1 << (POOLED_CLASSES - 1) {
+        return None;
+    }
+    Some(
+        n.max(size_of::<*mut u8>())
+            .next_power_of_two()
+            .trailing_zeros() as usize,
+    )
+}
+
+/// The memory source of a [`Heap`].
+///
+/// With the `allocator-api` feature enabled, this is implemented by all
+/// [`Allocator`]s. Otherwise, only the global allocator is supported.
+pub trait RawAlloc {
+    /// Allocate memory with the given layout, returning null on failure.
+    ///
+    /// # Safety
+    ///
+    /// `layout` must have a non-zero size.
+    unsafe fn raw_alloc(&self, layout: Layout) -> *mut u8;
+
+    /// Deallocate memory.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`RawAlloc::raw_alloc`] on the same allocator with the same
+    /// layout.
+    unsafe fn raw_dealloc(&self, ptr: *mut u8, layout: Layout);
+}
+
+/// The global allocator.
+#[cfg(not(feature = "allocator-api"))]
+pub struct Global;
+
+#[cfg(not(feature = "allocator-api"))]
+impl RawAlloc for Global {
+    unsafe fn raw_alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: Required transitively.
+        unsafe { alloc::alloc(layout) }
+    }
+
+    unsafe fn raw_dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: Required transitively.
+        unsafe { alloc::dealloc(ptr, layout) }
+    }
+}
+
+#[cfg(feature = "allocator-api")]
+impl<A: Allocator> RawAlloc for A {
+    unsafe fn raw_alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocate(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr().cast())
+    }
+
+    unsafe fn raw_dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was returned by `allocate` by the safety requirements, so it's non-null.
+        let ptr = unsafe { NonNull::new_unchecked(ptr) };
+        // SAFETY: `ptr` was returned by `allocate` on this allocator with this layout by the safety
+        // requirements, and it's currently allocated.
+        unsafe {
+            self.deallocate(ptr, layout);
+        }
+    }
+}
+
+/// The allocator used for exceptions spilled to the heap.
+///
+/// This is the global allocator, unless the `allocator-api` feature is enabled, in which case the
+/// allocator is registered with [`exception_allocator!`](crate::exception_allocator).
+#[cfg(not(feature = "allocator-api"))]
+pub use Global as ExceptionAllocator;
+
+/// The allocator used for exceptions spilled to the heap.
+///
+/// This forwards to the allocator registered with
+/// [`exception_allocator!`](crate::exception_allocator).
+#[cfg(feature = "allocator-api")]
+pub struct ExceptionAllocator;
+
+#[cfg(feature = "allocator-api")]
+extern "Rust" {
+    fn __lithium_exception_allocator() -> &'static dyn Allocator;
+}
+
+#[cfg(feature = "allocator-api")]
+impl ExceptionAllocator {
+    /// Get the registered allocator.
+    fn get() -> &'static dyn Allocator {
+        // SAFETY: The function is defined by `exception_allocator!`, which has no preconditions.
+        unsafe { __lithium_exception_allocator() }
+    }
+}
+
+// SAFETY: All calls are forwarded to the registered allocator, which is always the same.
+#[cfg(feature = "allocator-api")]
+unsafe impl Allocator for ExceptionAllocator {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Self::get().allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: Required transitively, as the registered allocator is always the same.
+        unsafe {
+            Self::get().deallocate(ptr, layout);
+        }
+    }
+}
 
 /// A heap-backed allocator.
 ///
 /// This allocator can allocate values with sizes that are multiples of `align_of::<AlignAs>()`,
-/// guaranteeing alignment to `align_of::<AlignAs>()`.
-pub struct Heap<AlignAs>(PhantomData<AlignAs>);
+/// guaranteeing alignment to `align_of::<AlignAs>()`. Memory is obtained from `A`.
+///
+/// Deallocated memory is kept in per-size-class free lists and reused by later allocations of the
+/// same class, so that repeatedly throwing large exceptions doesn't hit the underlying allocator
+/// every time. The cached memory is released by [`Heap::release`] or when the allocator is dropped.
+// Safety invariants:
+// - `free[k]` is either null or a pointer to an unused allocation of `1 << k` bytes, obtained from
+//   `allocator` with alignment `align_of::<AlignAs>()`. Its first bytes store the next element of
+//   the list, possibly unaligned.
+pub struct Heap<AlignAs, A: RawAlloc = Global> {
+    /// Free lists of recycled allocations, indexed by size class.
+    free: [Cell<*mut u8>; POOLED_CLASSES],
+    /// The number of live allocations.
+    #[cfg(feature = "stats")]
+    live: Cell<usize>,
+    allocator: A,
+    phantom: PhantomData<AlignAs>,
+}
 
 impl<AlignAs> Heap<AlignAs> {
-    /// Create an allocator.
+    /// Create an allocator backed by the global allocator.
+    #[cfg(test)]
     pub const fn new() -> Self {
-        Self(PhantomData)
+        Self::new_in(Global)
+    }
+}
+
+impl<AlignAs, A: RawAlloc> Heap<AlignAs, A> {
+    /// Create an allocator backed by `allocator`.
+    pub const fn new_in(allocator: A) -> Self {
+        Self {
+            free: [const { Cell::new(core::ptr::null_mut()) }; POOLED_CLASSES],
+            #[cfg(feature = "stats")]
+            live: Cell::new(0),
+            allocator,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get the number of live allocations.
+    #[cfg(feature = "stats")]
+    pub const fn live(&self) -> usize {
+        self.live.get()
+    }
+
+    /// Replace the number of live allocations, returning the old value.
+    #[cfg(feature = "stats")]
+    pub const fn replace_live(&self, live: usize) -> usize {
+        self.live.replace(live)
+    }
+
+    /// Get the layout of an allocation of `n` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is too large.
+    fn layout(n: usize) -> Layout {
+        let size = size_class(n).map_or(n, |class| 1 << class);
+        isize::try_from(size.next_multiple_of(align_of::<AlignAs>())).expect("Too big allocation");
+        // SAFETY:
+        // - `align` is a power of two, as `align_of` returns a power of two
+        // - We've checked that `size` fits in `isize` after rounding up
+        unsafe { Layout::from_size_align_unchecked(size, align_of::<AlignAs>()) }
     }
 
     /// Allocate `n` bytes.
@@ -23,40 +209,80 @@ impl<AlignAs> Heap<AlignAs> {
     /// # Panics
     ///
     /// Panics if `n` is not a multiple of `align_of::<AlignAs>()` or `n` is 0, or if out of memory.
-    #[expect(
-        clippy::unused_self,
-        reason = "Using a static method is harder in presence of generic parameters"
+    #[cfg_attr(
+        no_heap,
+        allow(
+            dead_code,
+            reason = "Exceptions are never spilled with `LITHIUM_NO_HEAP`"
+        )
     )]
     pub fn alloc(&self, n: usize) -> *mut u8 {
         assert_aligned::<AlignAs>(n);
         assert_ne!(n, 0, "Allocating 0 bytes is invalid");
-        isize::try_from(n.next_multiple_of(align_of::<AlignAs>())).expect("Too big allocation");
-        // SAFETY:
-        // - `align` is a power of two, as `align_of` returns a power of two
-        // - We've checked that `n` fits in `isize` after rounding up
-        let layout = unsafe { Layout::from_size_align_unchecked(n, align_of::<AlignAs>()) };
-        // SAFETY: n != 0 has been checked
-        unsafe { alloc::alloc(layout) }
+        #[cfg(feature = "stats")]
+        self.live.set(self.live.get().wrapping_add(1));
+        if let Some(free) = size_class(n).and_then(|class| self.free.get(class)) {
+            let ptr = free.get();
+            if !ptr.is_null() {
+                // SAFETY: By the invariant, `ptr` is an unused allocation storing the next element
+                // of the list, which is thus a valid value for `free`. The allocation is large
+                // enough for `n` bytes, as `n` has the same size class.
+                free.set(unsafe { ptr.cast::<*mut u8>().read_unaligned() });
+                return ptr;
+            }
+        }
+        // SAFETY: n != 0 has been checked, and rounding up to the size class keeps it non-zero
+        unsafe { self.allocator.raw_alloc(Self::layout(n)) }
     }
 
     /// Deallocate `n` bytes.
     ///
     /// # Safety
     ///
-    /// The caller must ensure that the pointer was produced by a call to [`Heap::alloc`] with the
-    /// same value of `n`. In addition, references to the deallocated memory must not be used after
-    /// `dealloc` is called.
-    #[expect(
-        clippy::unused_self,
-        reason = "Using a static method is harder in presence of generic parameters"
-    )]
+    /// The caller must ensure that the pointer was produced by a call to [`Heap::alloc`] on the
+    /// same instance of [`Heap`] with the same value of `n`. In addition, references to the
+    /// deallocated memory must not be used after `dealloc` is called.
     pub unsafe fn dealloc(&self, ptr: *mut u8, n: usize) {
-        // SAFETY: alloc would fail if the preconditions for this weren't established
-        let layout = unsafe { Layout::from_size_align_unchecked(n, align_of::<AlignAs>()) };
+        #[cfg(feature = "stats")]
+        self.live.set(self.live.get().wrapping_sub(1));
+        if let Some(free) = size_class(n).and_then(|class| self.free.get(class)) {
+            // SAFETY: `ptr` was allocated with the layout of its size class, so it's valid for
+            // writing a pointer. The allocation is unused from now on, so the invariant holds.
+            unsafe {
+                ptr.cast::<*mut u8>().write_unaligned(free.get());
+            }
+            free.set(ptr);
+            return;
+        }
         // SAFETY:
-        // - ptr was allocated with the same allocator
+        // - ptr was allocated with the same allocator and layout, and `layout` would have panicked
+        //   in `alloc` if `n` was too large
         // - alloc would fail if n == 0, so we know n != 0 holds here
-        unsafe { alloc::dealloc(ptr, layout) }
+        unsafe { self.allocator.raw_dealloc(ptr, Self::layout(n)) }
+    }
+
+    /// Return recycled allocations to the underlying allocator.
+    pub fn release(&self) {
+        for (class, free) in self.free.iter().enumerate() {
+            let mut ptr = free.replace(core::ptr::null_mut());
+            while !ptr.is_null() {
+                // SAFETY: By the invariant, `ptr` is an unused allocation storing the next element
+                // of the list.
+                let next = unsafe { ptr.cast::<*mut u8>().read_unaligned() };
+                // SAFETY: By the invariant, `ptr` was obtained from `allocator` with this layout,
+                // and it's not referenced elsewhere.
+                unsafe {
+                    self.allocator.raw_dealloc(ptr, Self::layout(1 << class));
+                }
+                ptr = next;
+            }
+        }
+    }
+}
+
+impl<AlignAs, A: RawAlloc> Drop for Heap<AlignAs, A> {
+    fn drop(&mut self) {
+        self.release();
     }
 }
 
@@ -64,6 +290,9 @@ impl<AlignAs> Heap<AlignAs> {
 mod test {
     use super::*;
 
+    #[cfg(feature = "allocator-api")]
+    crate::exception_allocator!(Global);
+
     #[test]
     #[should_panic]
     fn alloc_zero() {
@@ -94,6 +323,38 @@ mod test {
         }
     }
 
+    #[test]
+    fn recycle() {
+        let heap = Heap::<u8>::new();
+        let ptr1 = heap.alloc(5000);
+        unsafe {
+            heap.dealloc(ptr1, 5000);
+        }
+        let ptr2 = heap.alloc(6000);
+        assert_eq!(ptr1, ptr2);
+        let ptr3 = heap.alloc(5000);
+        assert_ne!(ptr2, ptr3);
+        unsafe {
+            heap.dealloc(ptr2, 6000);
+        }
+        unsafe {
+            heap.dealloc(ptr3, 5000);
+        }
+        heap.release();
+        assert!(heap.free.iter().all(|free| free.get().is_null()));
+    }
+
+    #[test]
+    fn not_pooled() {
+        let heap = Heap::<u8>::new();
+        let n = (1 << POOLED_CLASSES) + 1;
+        let ptr = heap.alloc(n);
+        unsafe {
+            heap.dealloc(ptr, n);
+        }
+        assert!(heap.free.iter().all(|free| free.get().is_null()));
+    }
+
     #[test]
     fn unique() {
         let heap = Heap::<u8>::new();
@@ -110,4 +371,32 @@ mod test {
             heap.dealloc(ptr2, 1);
         }
     }
+
+    #[cfg(feature = "allocator-api")]
+    #[test]
+    fn custom_allocator() {
+        struct Counting(Cell<usize>);
+
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + 1);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.0.set(self.0.get() - 1);
+                unsafe { Global.deallocate(ptr, layout) }
+            }
+        }
+
+        let counting = Counting(Cell::new(0));
+        let heap = Heap::<u8, _>::new_in(&counting);
+        let ptr = heap.alloc(5000);
+        assert_eq!(counting.0.get(), 1);
+        unsafe {
+            heap.dealloc(ptr, 5000);
+        }
+        heap.release();
+        assert_eq!(counting.0.get(), 0);
+    }
 }
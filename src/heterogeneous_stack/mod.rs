@@ -1,4 +1,39 @@
-pub mod align;
-pub mod array;
-pub mod heap;
-pub mod unbounded;
+//! A stack allocator for values of different types.
+//!
+//! This is the allocator Lithium stores exceptions in, published for other uses, such as scratch
+//! allocations in parsers or temporary buffers. Values are allocated in a statically sized buffer
+//! inside the [`Stack`] when they fit, and on the heap otherwise, so that short-lived allocations
+//! are cheap in the common case and still succeed when the buffer is exhausted.
+//!
+//! The allocator is configured in the same way as the exception stack: the size of the buffer and
+//! the size of the largest value stored in it are set by the `LITHIUM_STACK_CAPACITY` and
+//! `LITHIUM_INLINE_THRESHOLD` environment variables at build time, heap memory is obtained from the
+//! exception allocator with the `allocator-api` feature, `LITHIUM_NO_HEAP` aborts instead of
+//! spilling to the heap, and spills are counted by `stats` with the `stats` feature.
+//!
+//! Values are pushed and popped in LIFO order. To enforce this without `unsafe`, each value only
+//! lives for the duration of a closure.
+//!
+//! This module is only available with the `heterogeneous-stack` feature enabled.
+
+#![cfg_attr(
+    not(any(backend = "itanium", backend = "emscripten", backend = "panic")),
+    allow(
+        dead_code,
+        reason = "Only the public wrapper is used without stacked exceptions"
+    )
+)]
+
+pub(crate) mod align;
+pub(crate) mod array;
+pub(crate) mod heap;
+pub(crate) mod sanitizer;
+pub(crate) mod segmented;
+pub(crate) mod unbounded;
+#[cfg(feature = "valgrind")]
+pub(crate) mod valgrind;
+#[cfg(feature = "heterogeneous-stack")]
+mod wrapper;
+
+#[cfg(feature = "heterogeneous-stack")]
+pub use wrapper::Stack;
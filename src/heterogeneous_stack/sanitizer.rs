@@ -0,0 +1,58 @@
+//! Sanitizer and Valgrind annotations.
+//!
+//! Exception objects are allocated by Lithium's own allocators, which the address sanitizer can't
+//! see into: a stale pointer to a popped exception reads leftover bytes instead of being reported.
+//! When the crate is built with `-Zsanitizer=address`, `build.rs` enables the `asan` cfg, and the
+//! bounded exception stack poisons the regions it pops and unpoisons the regions it pushes.
+//!
+//! Throwing and unwinding don't need annotations of their own. The address sanitizer pass of LLVM
+//! calls `__asan_handle_no_return` before each call to a diverging function, such as
+//! `_Unwind_RaiseException`, so the redzones of the frames that are unwound are cleared, and the
+//! thread sanitizer pass emits landing pads that call `__tsan_func_exit` for each unwound frame.
+//! The exception stack is thread-local, so the thread sanitizer has nothing else to track.
+//!
+//! With the `valgrind` feature enabled, the same regions are reported to Memcheck with client
+//! requests, as implemented in the [`valgrind`](super::valgrind) module. Without the `asan` cfg or
+//! the `valgrind` feature, the functions in this module do nothing.
+
+#[cfg(asan)]
+extern "C" {
+    fn __asan_poison_memory_region(addr: *const u8, size: usize);
+    fn __asan_unpoison_memory_region(addr: *const u8, size: usize);
+}
+
+/// Mark `n` bytes starting at `ptr` as inaccessible.
+#[cfg(any(asan, feature = "valgrind"))]
+#[inline]
+pub fn poison(ptr: *const u8, n: usize) {
+    // SAFETY: Poisoning has no effect on the program other than reporting accesses.
+    #[cfg(asan)]
+    unsafe {
+        __asan_poison_memory_region(ptr, n);
+    }
+    #[cfg(feature = "valgrind")]
+    super::valgrind::make_mem_noaccess(ptr, n);
+}
+
+/// Mark `n` bytes starting at `ptr` as accessible. The contents are considered uninitialized.
+#[cfg(any(asan, feature = "valgrind"))]
+#[inline]
+pub fn unpoison(ptr: *const u8, n: usize) {
+    // SAFETY: Unpoisoning has no effect on the program other than reporting accesses.
+    #[cfg(asan)]
+    unsafe {
+        __asan_unpoison_memory_region(ptr, n);
+    }
+    #[cfg(feature = "valgrind")]
+    super::valgrind::make_mem_undefined(ptr, n);
+}
+
+/// Mark `n` bytes starting at `ptr` as inaccessible.
+#[cfg(not(any(asan, feature = "valgrind")))]
+#[inline]
+pub const fn poison(_ptr: *const u8, _n: usize) {}
+
+/// Mark `n` bytes starting at `ptr` as accessible. The contents are considered uninitialized.
+#[cfg(not(any(asan, feature = "valgrind")))]
+#[inline]
+pub const fn unpoison(_ptr: *const u8, _n: usize) {}
@@ -0,0 +1,483 @@
+#[cfg(test)]
+use super::heap::Global;
+use super::{align::assert_aligned, heap::RawAlloc};
+use alloc::alloc::handle_alloc_error;
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::marker::PhantomData;
+
+/// The header of a segment.
+///
+/// The data of the segment follows the header, aligned to `AlignAs`.
+struct Segment {
+    /// The segment below this one in the chain, or null.
+    below: *mut Segment,
+    /// The number of data bytes.
+    capacity: usize,
+    /// The number of allocated data bytes.
+    len: usize,
+}
+
+/// The elements of a [`Stack`] moved out by [`Stack::save`].
+pub struct SavedSegments {
+    top: *mut Segment,
+    #[cfg(feature = "stats")]
+    live: usize,
+}
+
+/// A thread-unsafe growable stack allocator, backed by a chain of heap-allocated segments.
+///
+/// This allocator can allocate values with sizes that are multiples of `align_of::<AlignAs>()`,
+/// guaranteeing alignment to `align_of::<AlignAs>()`. Memory is obtained from `A`.
+///
+/// Elements are allocated consecutively within the top segment. When an element doesn't fit, a new
+/// segment twice as large as the previous one is allocated on top of it, so the number of
+/// allocations is logarithmic in the peak usage. Segments are never moved or reallocated, so
+/// pointers to elements stay valid until they're popped.
+///
+/// A segment that becomes empty is cached and reused by the next segment allocation if it's large
+/// enough, so that repeatedly crossing a segment boundary doesn't hit the underlying allocator
+/// every time. The cached segment is released by [`Stack::release`] or when the allocator is
+/// dropped.
+// Safety invariants:
+// - `top` is either null or a pointer to a segment obtained from `allocator` with
+//   `Self::layout(capacity)`. The same applies to the `below` links of the segments in the chain.
+// - All segments in the chain are non-empty.
+// - Within each segment, the elements are consecutive, starting at the beginning of the data, with
+//   the last element ending at `len`. References to data past `len` are not used.
+// - `spare` is either null or a pointer to an empty segment obtained from `allocator`, which is not
+//   in the chain.
+pub struct Stack<AlignAs, A: RawAlloc> {
+    /// The segment elements are allocated in, or null if the stack is empty.
+    top: Cell<*mut Segment>,
+    /// A cached empty segment, or null.
+    spare: Cell<*mut Segment>,
+    /// The capacity of the first segment.
+    first_capacity: usize,
+    /// The number of live elements.
+    #[cfg(feature = "stats")]
+    live: Cell<usize>,
+    allocator: A,
+    phantom: PhantomData<AlignAs>,
+}
+
+#[cfg(test)]
+impl<AlignAs> Stack<AlignAs, Global> {
+    /// Create an allocator backed by the global allocator.
+    pub const fn new(first_capacity: usize) -> Self {
+        Self::new_in(first_capacity, Global)
+    }
+}
+
+impl<AlignAs, A: RawAlloc> Stack<AlignAs, A> {
+    /// The offset of the data within a segment.
+    const DATA_OFFSET: usize = size_of::<Segment>().next_multiple_of(align_of::<AlignAs>());
+
+    /// Create an empty allocator backed by `allocator`, with the first segment of `first_capacity`
+    /// bytes.
+    pub const fn new_in(first_capacity: usize, allocator: A) -> Self {
+        Self {
+            top: Cell::new(core::ptr::null_mut()),
+            spare: Cell::new(core::ptr::null_mut()),
+            first_capacity,
+            #[cfg(feature = "stats")]
+            live: Cell::new(0),
+            allocator,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get the number of live elements.
+    #[cfg(feature = "stats")]
+    pub const fn live(&self) -> usize {
+        self.live.get()
+    }
+
+    /// Get the layout of a segment with `capacity` data bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is too large.
+    fn layout(capacity: usize) -> Layout {
+        Self::DATA_OFFSET
+            .checked_add(capacity)
+            .and_then(|size| {
+                Layout::from_size_align(size, align_of::<Segment>().max(align_of::<AlignAs>())).ok()
+            })
+            .expect("Too big allocation")
+    }
+
+    /// Get a pointer to the data of a segment.
+    ///
+    /// # Safety
+    ///
+    /// `segment` must point at a live segment.
+    const unsafe fn data(segment: *mut Segment) -> *mut u8 {
+        // SAFETY: The data is located within the same allocation by the safety requirement.
+        unsafe { segment.byte_add(Self::DATA_OFFSET) }.cast()
+    }
+
+    /// Get the number of unallocated data bytes in a segment.
+    ///
+    /// # Safety
+    ///
+    /// `segment` must point at a live segment.
+    const unsafe fn free_space(segment: *mut Segment) -> usize {
+        // SAFETY: Required transitively.
+        let Segment { capacity, len, .. } = unsafe { segment.read() };
+        // `len <= capacity` by the invariant.
+        capacity.wrapping_sub(len)
+    }
+
+    /// Allocate `n` bytes.
+    ///
+    /// The returned pointer is guaranteed to be aligned to `align_of::<AlignAs>()` and valid for
+    /// reads/writes for `n` bytes. It is also guaranteed to be unique.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not a multiple of `align_of::<AlignAs>()` or `n` is 0, or if out of memory.
+    #[cfg_attr(
+        no_heap,
+        allow(
+            dead_code,
+            reason = "Exceptions are never spilled with `LITHIUM_NO_HEAP`"
+        )
+    )]
+    pub fn push(&self, n: usize) -> *mut u8 {
+        assert_aligned::<AlignAs>(n);
+        assert_ne!(n, 0, "Allocating 0 bytes is invalid");
+        #[cfg(feature = "stats")]
+        self.live.set(self.live.get().wrapping_add(1));
+
+        let mut top = self.top.get();
+        // SAFETY: If `top` is not null, it points at a live segment by the invariant.
+        if top.is_null() || n > unsafe { Self::free_space(top) } {
+            top = self.grow(n);
+        }
+
+        // SAFETY: `top` points at a live segment, either by the invariant or as just allocated.
+        let len = unsafe { (*top).len };
+        // SAFETY: As above.
+        let data = unsafe { Self::data(top) };
+        // SAFETY: `len` is in bounds of the data of the segment by the invariant.
+        let ptr = unsafe { data.add(len) };
+        // SAFETY: As above. `n <= capacity - len` has been checked, or the segment is new and empty
+        // with `n <= capacity`, so this doesn't overflow.
+        unsafe {
+            (*top).len = len.wrapping_add(n);
+        }
+
+        // - `ptr` is aligned because the data and `len` are aligned
+        // - `ptr` is valid for reads/writes for `n` bytes because `len + n <= capacity`
+        // - `ptr` is unique, as references to data past `len` are not used by the invariant
+        // Type invariants: the segment is non-empty now, and the new element ends at `len`.
+        ptr
+    }
+
+    /// Put a new empty segment that can fit `n` bytes on top of the chain, and return it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if out of memory.
+    #[cold]
+    fn grow(&self, n: usize) -> *mut Segment {
+        let top = self.top.get();
+        let capacity = if top.is_null() {
+            self.first_capacity
+        } else {
+            // SAFETY: `top` points at a live segment by the invariant.
+            unsafe { (*top).capacity }.saturating_mul(2)
+        }
+        .max(n);
+
+        let mut segment = self.spare.replace(core::ptr::null_mut());
+        // SAFETY: If `spare` is not null, it points at a live segment by the invariant.
+        if !segment.is_null() && unsafe { (*segment).capacity } < n {
+            // SAFETY: The spare segment is empty and not referenced elsewhere by the invariant.
+            unsafe {
+                self.free(segment);
+            }
+            segment = core::ptr::null_mut();
+        }
+        if segment.is_null() {
+            let layout = Self::layout(capacity);
+            // SAFETY: The layout has a non-zero size, as the header is non-empty.
+            segment = unsafe { self.allocator.raw_alloc(layout) }.cast();
+            if segment.is_null() {
+                handle_alloc_error(layout);
+            }
+            // SAFETY: The allocation is large enough and aligned for the header.
+            unsafe {
+                segment.write(Segment {
+                    below: core::ptr::null_mut(),
+                    capacity,
+                    len: 0,
+                });
+            }
+        }
+
+        // SAFETY: `segment` points at a live empty segment.
+        unsafe {
+            (*segment).below = top;
+        }
+        self.top.set(segment);
+        // Type invariants: the segment is empty for a moment, but `push` allocates in it right
+        // away.
+        segment
+    }
+
+    /// Return a segment to the underlying allocator.
+    ///
+    /// # Safety
+    ///
+    /// `segment` must point at a segment obtained from `allocator` that is not referenced anywhere.
+    unsafe fn free(&self, segment: *mut Segment) {
+        // SAFETY: `segment` is live by the safety requirement.
+        let layout = Self::layout(unsafe { (*segment).capacity });
+        // SAFETY: The segment was obtained from `allocator` with this layout.
+        unsafe {
+            self.allocator.raw_dealloc(segment.cast(), layout);
+        }
+    }
+
+    /// Check whether an allocation is within the top segment.
+    ///
+    /// If `ptr` was produced by [`Stack::push`] on this instance and is the last element pushed and
+    /// not yet popped, this returns `true`. If `ptr` was produced by another allocator, this
+    /// returns `false`.
+    pub fn contains_allocated(&self, ptr: *const u8) -> bool {
+        let top = self.top.get();
+        if top.is_null() {
+            return false;
+        }
+        // SAFETY: `top` points at a live segment by the invariant.
+        let data = unsafe { Self::data(top) };
+        // SAFETY: As above.
+        let len = unsafe { (*top).len };
+        ptr.addr().wrapping_sub(data.addr()) < len
+    }
+
+    /// Remove the `n`-byte element from the top of the stack.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that:
+    /// - The top element of the stack was allocated with `n` bytes.
+    /// - References to the element, both immutable or mutable, are not used after `pop_unchecked`
+    ///   is called.
+    pub unsafe fn pop_unchecked(&self, n: usize) {
+        #[cfg(feature = "stats")]
+        self.live.set(self.live.get().wrapping_sub(1));
+
+        let top = self.top.get();
+        // SAFETY: The stack is non-empty by the safety requirement, so `top` points at a live
+        // segment by the invariant, and the element is located within it.
+        let len = unsafe { (*top).len };
+        // SAFETY: The element is located within the segment, so it's at most `len` bytes large.
+        let len = unsafe { len.unchecked_sub(n) };
+        // SAFETY: `top` points at a live segment, as above.
+        unsafe {
+            (*top).len = len;
+        }
+        if len > 0 {
+            return;
+        }
+
+        // Type invariant: the segment is empty, so move it out of the chain.
+        // SAFETY: As above.
+        self.top.set(unsafe { (*top).below });
+        let spare = self.spare.replace(top);
+        if !spare.is_null() {
+            // SAFETY: The spare segment is empty and not referenced elsewhere by the invariant.
+            unsafe {
+                self.free(spare);
+            }
+        }
+    }
+
+    /// Move the elements out of the stack, leaving it empty.
+    ///
+    /// The elements are not moved in memory, but they must not be accessed until they're put back
+    /// with [`Stack::restore`].
+    pub const fn save(&self) -> SavedSegments {
+        SavedSegments {
+            top: self.top.replace(core::ptr::null_mut()),
+            #[cfg(feature = "stats")]
+            live: self.live.replace(0),
+        }
+    }
+
+    /// Put the elements moved out by [`Stack::save`] back.
+    ///
+    /// # Safety
+    ///
+    /// The stack must be empty, and `saved` must have been returned by [`Stack::save`] on this
+    /// instance.
+    pub unsafe fn restore(&self, saved: &SavedSegments) {
+        // Type invariants: the chain is the same as before `save`, and the segments haven't been
+        // touched in the meantime, as the chain is only accessible via `top`.
+        self.top.set(saved.top);
+        #[cfg(feature = "stats")]
+        self.live.set(saved.live);
+    }
+
+    /// Return the cached segment to the underlying allocator.
+    pub fn release(&self) {
+        let spare = self.spare.replace(core::ptr::null_mut());
+        if !spare.is_null() {
+            // SAFETY: The spare segment is empty and not referenced elsewhere by the invariant.
+            unsafe {
+                self.free(spare);
+            }
+        }
+    }
+}
+
+impl<AlignAs, A: RawAlloc> Drop for Stack<AlignAs, A> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[should_panic]
+    fn push_zero() {
+        Stack::<u8, _>::new(16).push(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_unaligned() {
+        Stack::<u16, _>::new(16).push(3);
+    }
+
+    #[test]
+    fn overaligned() {
+        #[repr(align(256))]
+        struct Overaligned;
+        let stack = Stack::<Overaligned, _>::new(256);
+        let ptr1 = stack.push(256);
+        assert_eq!(ptr1.addr() % 256, 0);
+        let ptr2 = stack.push(256);
+        assert_eq!(ptr2.addr() % 256, 0);
+        unsafe {
+            stack.pop_unchecked(256);
+        }
+        unsafe {
+            stack.pop_unchecked(256);
+        }
+    }
+
+    #[test]
+    fn consecutive() {
+        let stack = Stack::<u8, _>::new(16);
+        let ptr1 = stack.push(5);
+        let ptr2 = stack.push(8);
+        assert_eq!(ptr2.addr() - ptr1.addr(), 5);
+        assert!(stack.contains_allocated(ptr2));
+        unsafe {
+            stack.pop_unchecked(8);
+        }
+        let ptr3 = stack.push(3);
+        assert_eq!(ptr2, ptr3);
+        unsafe {
+            stack.pop_unchecked(3);
+        }
+        unsafe {
+            stack.pop_unchecked(5);
+        }
+        assert!(stack.top.get().is_null());
+    }
+
+    #[test]
+    fn grow() {
+        let stack = Stack::<u8, _>::new(16);
+        let ptr1 = stack.push(12);
+        let ptr2 = stack.push(12);
+        assert_eq!(unsafe { (*stack.top.get()).capacity }, 32);
+        assert!(stack.contains_allocated(ptr2));
+        assert!(!stack.contains_allocated(ptr1));
+        let ptr3 = stack.push(100);
+        assert_eq!(unsafe { (*stack.top.get()).capacity }, 100);
+        unsafe {
+            stack.pop_unchecked(100);
+        }
+        unsafe {
+            stack.pop_unchecked(12);
+        }
+        assert!(stack.contains_allocated(ptr1));
+        unsafe {
+            stack.pop_unchecked(12);
+        }
+        let _ = ptr3;
+    }
+
+    #[test]
+    fn reuse_spare() {
+        let stack = Stack::<u8, _>::new(16);
+        let ptr1 = stack.push(16);
+        let ptr2 = stack.push(16);
+        unsafe {
+            stack.pop_unchecked(16);
+        }
+        assert!(!stack.spare.get().is_null());
+        let ptr3 = stack.push(16);
+        assert_eq!(ptr2, ptr3);
+        assert!(stack.spare.get().is_null());
+        unsafe {
+            stack.pop_unchecked(16);
+        }
+        unsafe {
+            stack.pop_unchecked(16);
+        }
+        stack.release();
+        assert!(stack.spare.get().is_null());
+        let _ = ptr1;
+    }
+
+    #[test]
+    fn unique() {
+        let stack = Stack::<u8, _>::new(1);
+        let ptr1 = unsafe { &mut *stack.push(1) };
+        let ptr2 = unsafe { &mut *stack.push(1) };
+        *ptr1 = 1;
+        *ptr2 = 2;
+        assert_eq!(*ptr1, 1);
+        assert_eq!(*ptr2, 2);
+        unsafe {
+            stack.pop_unchecked(1);
+        }
+        assert_eq!(*ptr1, 1);
+        unsafe {
+            stack.pop_unchecked(1);
+        }
+    }
+
+    #[test]
+    fn save_restore() {
+        let stack = Stack::<u8, _>::new(16);
+        let ptr1 = stack.push(4);
+        unsafe {
+            ptr1.write(1);
+        }
+        let saved = stack.save();
+        let ptr2 = stack.push(4);
+        assert_ne!(ptr1, ptr2);
+        unsafe {
+            stack.pop_unchecked(4);
+        }
+        unsafe {
+            stack.restore(&saved);
+        }
+        assert!(stack.contains_allocated(ptr1));
+        assert_eq!(unsafe { ptr1.read() }, 1);
+        unsafe {
+            stack.pop_unchecked(4);
+        }
+    }
+}
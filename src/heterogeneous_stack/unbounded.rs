@@ -1,25 +1,166 @@
-use super::{align::assert_aligned, array::Stack as BoundedStack, heap::Heap};
+use super::segmented::SavedSegments;
+use super::{
+    align::assert_aligned,
+    array::Stack as BoundedStack,
+    heap::{ExceptionAllocator, Heap},
+    segmented::Stack as SegmentedStack,
+};
+#[cfg(feature = "stats")]
+use crate::stats::{record, Event};
+use alloc::boxed::Box;
+use core::cell::Cell;
+use core::mem::MaybeUninit;
+
+/// The size of the statically allocated part of the stack, in bytes.
+///
+/// Configured with the `LITHIUM_STACK_CAPACITY` environment variable at build time, 4096 by
+/// default. `build.rs` validates the value, so parsing here can't fail.
+pub const CAPACITY: usize = parse_usize(env!("LITHIUM_STACK_CAPACITY"));
+
+/// The size of the largest element stored in the statically allocated part of the stack, in bytes.
+///
+/// Larger elements are always allocated on the heap, so that they don't take up space needed by
+/// smaller ones. Configured with the `LITHIUM_INLINE_THRESHOLD` environment variable at build time,
+/// equal to [`CAPACITY`] by default. `build.rs` validates that it doesn't exceed [`CAPACITY`].
+pub const INLINE_THRESHOLD: usize = parse_usize(env!("LITHIUM_INLINE_THRESHOLD"));
+
+const fn parse_usize(s: &str) -> usize {
+    let s = s.as_bytes();
+    let mut value: usize = 0;
+    let mut i = 0;
+    while i < s.len() {
+        value = value
+            .wrapping_mul(10)
+            .wrapping_add((s[i].wrapping_sub(b'0')) as usize);
+        i = i.wrapping_add(1);
+    }
+    value
+}
 
 /// A thread-unsafe heterogeneous stack, using statically allocated space when possible.
 ///
+/// Elements of up to `THRESHOLD` bytes are allocated in the statically allocated part if they fit,
+/// and in heap-allocated segments otherwise. The first segment is twice as large as the statically
+/// allocated part, and each next segment doubles in size. Larger elements are allocated on the heap
+/// individually.
+///
 /// Although the stack doesn't track runtime types, all elements are considered independent. Stack
 /// operations must be consistent, i.e. pushing 2 bytes and then popping 1 byte twice is unsound.
 // Safety invariants:
 // - ZSTs are always allocated on the bounded stack.
-pub struct Stack<AlignAs> {
-    bounded_stack: BoundedStack<AlignAs, 4096>,
-    heap: Heap<AlignAs>,
+pub struct Stack<AlignAs, const THRESHOLD: usize = INLINE_THRESHOLD> {
+    #[expect(clippy::struct_field_names, reason = "`stack` is the natural name")]
+    bounded_stack: BoundedStack<AlignAs, CAPACITY>,
+    segments: SegmentedStack<AlignAs, ExceptionAllocator>,
+    heap: Heap<AlignAs, ExceptionAllocator>,
+    /// The number of elements, both on the bounded stack and on the heap.
+    elements: Cell<usize>,
+}
+
+/// The elements of a [`Stack`] moved out by [`Stack::save`].
+pub struct SavedStack {
+    bounded: Box<[MaybeUninit<u8>]>,
+    segments: SavedSegments,
+    elements: usize,
+    #[cfg(feature = "stats")]
+    heap_live: usize,
 }
 
-impl<AlignAs> Stack<AlignAs> {
+impl<AlignAs, const THRESHOLD: usize> Stack<AlignAs, THRESHOLD> {
     /// Create an empty stack.
     pub const fn new() -> Self {
         Self {
             bounded_stack: BoundedStack::new(),
-            heap: Heap::new(),
+            segments: SegmentedStack::new_in(CAPACITY.saturating_mul(2), ExceptionAllocator),
+            heap: Heap::new_in(ExceptionAllocator),
+            elements: Cell::new(0),
+        }
+    }
+
+    /// Get the number of bytes used on the bounded stack.
+    #[cfg(feature = "stats")]
+    pub const fn bounded_len(&self) -> usize {
+        self.bounded_stack.len.get()
+    }
+
+    /// Get the number of elements allocated on the heap, either in segments or individually.
+    #[cfg(feature = "stats")]
+    pub const fn heap_elements(&self) -> usize {
+        self.segments.live().wrapping_add(self.heap.live())
+    }
+
+    /// Get the total number of elements.
+    pub const fn elements(&self) -> usize {
+        self.elements.get()
+    }
+
+    /// Assert that the stack is empty and release cached memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stack is not empty.
+    pub fn reset(&self) {
+        assert!(
+            self.elements.get() == 0,
+            "Lithium exception stack reset while exceptions are in flight",
+        );
+        self.segments.release();
+        self.heap.release();
+    }
+
+    /// Move the elements out of the stack, leaving it empty.
+    ///
+    /// Elements allocated on the heap are not moved. Pointers to all elements stay valid, but the
+    /// elements must not be accessed until they're put back with [`Stack::restore`].
+    ///
+    /// # Panics
+    ///
+    /// With `LITHIUM_NO_HEAP`, panics if elements are in the statically allocated part, as moving
+    /// them out requires a heap allocation.
+    pub fn save(&self) -> SavedStack {
+        #[cfg(no_heap)]
+        assert!(
+            self.bounded_stack.len.get() == 0,
+            "Lithium state saved while exceptions are in flight, which needs a heap allocation \
+             forbidden by `LITHIUM_NO_HEAP`",
+        );
+        SavedStack {
+            bounded: self.bounded_stack.save(),
+            segments: self.segments.save(),
+            elements: self.elements.replace(0),
+            #[cfg(feature = "stats")]
+            heap_live: self.heap.replace_live(0),
         }
     }
 
+    /// Put the elements moved out by [`Stack::save`] back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stack is not empty.
+    ///
+    /// # Safety
+    ///
+    /// `saved` must have been returned by [`Stack::save`] on this instance.
+    pub unsafe fn restore(&self, saved: &SavedStack) {
+        assert!(
+            self.elements.get() == 0,
+            "Lithium exception stack restored while exceptions are in flight",
+        );
+        // SAFETY: The bounded stack is empty, as there are no elements. `saved.bounded` was
+        // returned by `save` on the same bounded stack by the safety requirement.
+        unsafe {
+            self.bounded_stack.restore(&saved.bounded);
+        }
+        // SAFETY: As above.
+        unsafe {
+            self.segments.restore(&saved.segments);
+        }
+        self.elements.set(saved.elements);
+        #[cfg(feature = "stats")]
+        self.heap.replace_live(saved.heap_live);
+    }
+
     /// Push an `n`-byte object.
     ///
     /// The returned pointer is guaranteed to be aligned to `align_of::<AlignAs>()` and valid for
@@ -31,9 +172,41 @@ impl<AlignAs> Stack<AlignAs> {
     /// fails.
     #[inline]
     pub fn push(&self, n: usize) -> *mut u8 {
+        self.elements.set(self.elements.get().wrapping_add(1));
+        if n > THRESHOLD {
+            return self.spill(n);
+        }
         self.bounded_stack
             .try_push(n)
-            .unwrap_or_else(|| self.heap.alloc(n))
+            .unwrap_or_else(|| self.spill(n))
+    }
+
+    /// Allocate an `n`-byte object that doesn't fit on the bounded stack, or is too large for it,
+    /// on the heap: in a segment if it's at most `THRESHOLD` bytes, individually otherwise.
+    ///
+    /// With `LITHIUM_NO_HEAP`, this aborts the process instead.
+    #[cold]
+    fn spill(&self, n: usize) -> *mut u8 {
+        #[cfg(feature = "stats")]
+        record(Event::HeapSpill);
+
+        #[cfg(no_heap)]
+        {
+            let _ = (self, n);
+            crate::abort(
+                "Lithium exceptions overflowed the exception stack, and `LITHIUM_NO_HEAP` forbids \
+                 spilling them to the heap. The process will now terminate. Increase \
+                 `LITHIUM_STACK_CAPACITY` and `LITHIUM_INLINE_THRESHOLD` or reduce the nesting of \
+                 exceptions.\n",
+            );
+        }
+
+        #[cfg(not(no_heap))]
+        if n > THRESHOLD {
+            self.heap.alloc(n)
+        } else {
+            self.segments.push(n)
+        }
     }
 
     /// Remove an `n`-byte object from the top of the stack.
@@ -46,6 +219,7 @@ impl<AlignAs> Stack<AlignAs> {
     ///   address, and provenance.
     /// - The element is not accessed after the call to `pop`.
     pub unsafe fn pop(&self, ptr: *mut u8, n: usize) {
+        self.elements.set(self.elements.get().wrapping_sub(1));
         if self.bounded_stack.contains_allocated(ptr, n) {
             // SAFETY:
             // - `contains_allocated` returned `true`, so either the element is allocated on the
@@ -56,10 +230,18 @@ impl<AlignAs> Stack<AlignAs> {
             unsafe {
                 self.bounded_stack.pop_unchecked(n);
             }
+        } else if self.segments.contains_allocated(ptr) {
+            // SAFETY: The element is in the top segment, so it's the top element of the segmented
+            // stack, as the elements allocated there are a subsequence of the elements of this
+            // stack. It was allocated with `n` bytes and is not accessed after the call by the
+            // safety requirements.
+            unsafe {
+                self.segments.pop_unchecked(n);
+            }
         } else {
-            // SAFETY: `contains_allocated` returned `false`, so the allocation is not on the stack.
-            // By the requirements, the pointer was produced by `push`, so the allocation has to be
-            // on the heap.
+            // SAFETY: `contains_allocated` returned `false` for both stacks, so the allocation is
+            // not on either. By the requirements, the pointer was produced by `push`, so the
+            // allocation has to be on the heap.
             unsafe {
                 self.heap.dealloc(ptr, n);
             }
@@ -93,23 +275,29 @@ impl<AlignAs> Stack<AlignAs> {
         assert_aligned::<AlignAs>(new_n);
         if old_n == new_n {
             // Can reuse the allocation
+            if old_n > 0 && self.bounded_stack.contains_allocated(old_ptr, old_n) {
+                self.bounded_stack.check_top();
+            }
             return old_ptr;
         }
         let was_on_stack = self.bounded_stack.contains_allocated(old_ptr, old_n);
+        let was_in_segment = !was_on_stack && self.segments.contains_allocated(old_ptr);
         // SAFETY: Valid by transitive requirements.
         unsafe {
             self.pop(old_ptr, old_n);
         }
         if was_on_stack && new_n < old_n {
+            self.elements.set(self.elements.get().wrapping_add(1));
             let new_ptr = self.bounded_stack.try_push(new_n);
             // SAFETY: If the previous allocation was on the stack and the new allocation is
             // smaller, it must necessarily succeed.
             return unsafe { new_ptr.unwrap_unchecked() };
         }
-        if !was_on_stack && new_n > old_n {
-            // If the previous allocation was on the heap and the new allocation is bigger, it won't
-            // fit on stack either.
-            return self.heap.alloc(new_n);
+        if !was_on_stack && !was_in_segment && new_n > old_n {
+            // If the previous allocation was on the heap, it's larger than `THRESHOLD`, and if the
+            // new allocation is bigger, it's larger than `THRESHOLD` too.
+            self.elements.set(self.elements.get().wrapping_add(1));
+            return self.spill(new_n);
         }
         self.push(new_n)
     }
@@ -118,6 +306,16 @@ impl<AlignAs> Stack<AlignAs> {
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(not(no_heap))]
+    use alloc::vec::Vec;
+
+    const CANARY: usize = BoundedStack::<u8, CAPACITY>::CANARY;
+
+    /// Check whether an element of `n` bytes fits on an empty bounded stack. Tests that rely on it
+    /// pass trivially when `LITHIUM_STACK_CAPACITY` is too small.
+    const fn fits(n: usize) -> bool {
+        n + CANARY <= CAPACITY
+    }
 
     #[test]
     #[should_panic]
@@ -126,6 +324,7 @@ mod test {
     }
 
     #[test]
+    #[cfg(not(no_heap))]
     fn overaligned() {
         #[repr(align(256))]
         struct Overaligned;
@@ -143,17 +342,39 @@ mod test {
     }
 
     #[test]
+    #[cfg(not(no_heap))]
     fn allocate() {
-        let stack = Stack::<u8>::new();
+        let stack = Stack::<u8, CAPACITY>::new();
         stack.push(5);
         unsafe {
-            stack.pop(stack.push(4097), 4097);
+            stack.pop(stack.push(CAPACITY + 1), CAPACITY + 1);
+        }
+    }
+
+    #[test]
+    #[cfg(not(no_heap))]
+    fn threshold() {
+        if !fits(16) {
+            return;
+        }
+        let stack = Stack::<u8, 16>::new();
+        let ptr1 = stack.push(17);
+        assert_eq!(stack.bounded_stack.len.get(), 0);
+        let ptr2 = stack.push(16);
+        assert_eq!(stack.bounded_stack.len.get(), 16 + CANARY);
+        let ptr3 = unsafe { stack.replace_last(ptr2, 16, 32) };
+        assert_eq!(stack.bounded_stack.len.get(), 0);
+        unsafe {
+            stack.pop(ptr3, 32);
+        }
+        unsafe {
+            stack.pop(ptr1, 17);
         }
     }
 
     #[test]
     fn simple() {
-        let stack = Stack::<u8>::new();
+        let stack = Stack::<u8, CAPACITY>::new();
         let ptr = stack.push(5);
         unsafe {
             stack.pop(ptr, 5);
@@ -161,9 +382,10 @@ mod test {
     }
 
     #[test]
+    #[cfg(not(no_heap))]
     fn push_zero() {
-        let stack = Stack::<u8>::new();
-        let ptr1 = stack.push(4096);
+        let stack = Stack::<u8, CAPACITY>::new();
+        let ptr1 = stack.push(CAPACITY);
         let ptr2 = stack.push(0);
         let ptr3 = stack.push(1);
         unsafe {
@@ -173,14 +395,20 @@ mod test {
             stack.pop(ptr2, 0);
         }
         unsafe {
-            stack.pop(ptr1, 4096);
+            stack.pop(ptr1, CAPACITY);
         }
     }
 
     #[test]
+    #[cfg(not(no_heap))]
     fn spill_over() {
-        let stack = Stack::<u8>::new();
-        let ptr1 = stack.push(4095);
+        // The first element leaves room for exactly one more byte, which needs a stack of at least
+        // `1 + 2 * CANARY` bytes.
+        let Some(n) = CAPACITY.checked_sub(1 + 2 * CANARY) else {
+            return;
+        };
+        let stack = Stack::<u8, CAPACITY>::new();
+        let ptr1 = stack.push(n);
         let ptr2 = stack.push(1);
         let ptr3 = stack.push(1);
         unsafe {
@@ -190,13 +418,37 @@ mod test {
             stack.pop(ptr2, 1);
         }
         unsafe {
-            stack.pop(ptr1, 4095);
+            stack.pop(ptr1, n);
+        }
+    }
+
+    #[test]
+    #[cfg(not(no_heap))]
+    fn segments() {
+        let n = CAPACITY / 2 + 1;
+        if !fits(n) {
+            return;
         }
+        let stack = Stack::<u8, CAPACITY>::new();
+        let ptrs: Vec<*mut u8> = (0..8)
+            .map(|i| {
+                let ptr = stack.push(n);
+                // The first element fits on the bounded stack, the rest are in segments.
+                assert_eq!(stack.segments.contains_allocated(ptr), i > 0);
+                ptr
+            })
+            .collect();
+        for &ptr in ptrs.iter().rev() {
+            unsafe {
+                stack.pop(ptr, n);
+            }
+        }
+        assert_eq!(stack.elements(), 0);
     }
 
     #[test]
     fn unique() {
-        let stack = Stack::<u8>::new();
+        let stack = Stack::<u8, CAPACITY>::new();
         let ptr1 = unsafe { &mut *stack.push(1) };
         *ptr1 = 1;
         let ptr2 = unsafe { &mut *stack.push(1) };
@@ -228,83 +480,131 @@ mod test {
 
     #[test]
     fn replace_last_on_stack() {
-        let stack = Stack::<u8>::new();
+        if !fits(5) {
+            return;
+        }
+        let stack = Stack::<u8, CAPACITY>::new();
         assert_eq!(stack.bounded_stack.len.get(), 0);
         let ptr1 = stack.push(2);
         unsafe {
             assert_unique(ptr1, 2);
         }
-        assert_eq!(stack.bounded_stack.len.get(), 2);
+        assert_eq!(stack.bounded_stack.len.get(), 2 + CANARY);
         let ptr2 = unsafe { stack.replace_last(ptr1, 2, 2) };
         unsafe {
             assert_unique(ptr2, 2);
         }
-        assert_eq!(stack.bounded_stack.len.get(), 2);
+        assert_eq!(stack.bounded_stack.len.get(), 2 + CANARY);
         assert_eq!(ptr1, ptr2);
         let ptr3 = unsafe { stack.replace_last(ptr2, 2, 5) };
         unsafe {
             assert_unique(ptr3, 5);
         }
-        assert_eq!(stack.bounded_stack.len.get(), 5);
+        assert_eq!(stack.bounded_stack.len.get(), 5 + CANARY);
         assert_eq!(ptr2.addr(), ptr3.addr());
         let ptr4 = unsafe { stack.replace_last(ptr3, 5, 3) };
         unsafe {
             assert_unique(ptr4, 3);
         }
-        assert_eq!(stack.bounded_stack.len.get(), 3);
+        assert_eq!(stack.bounded_stack.len.get(), 3 + CANARY);
         assert_eq!(ptr3.addr(), ptr4.addr());
     }
 
     #[test]
+    #[cfg(not(no_heap))]
     fn replace_last_on_heap() {
-        let stack = Stack::<u8>::new();
+        let stack = Stack::<u8, CAPACITY>::new();
         assert_eq!(stack.bounded_stack.len.get(), 0);
-        let ptr1 = stack.push(4097);
+        let ptr1 = stack.push(CAPACITY + 1);
         unsafe {
-            assert_unique(ptr1, 4097);
+            assert_unique(ptr1, CAPACITY + 1);
         }
         assert_eq!(stack.bounded_stack.len.get(), 0);
-        let ptr2 = unsafe { stack.replace_last(ptr1, 4097, 4097) };
+        let ptr2 = unsafe { stack.replace_last(ptr1, CAPACITY + 1, CAPACITY + 1) };
         unsafe {
-            assert_unique(ptr2, 4097);
+            assert_unique(ptr2, CAPACITY + 1);
         }
         assert_eq!(stack.bounded_stack.len.get(), 0);
         assert_eq!(ptr1, ptr2);
-        let ptr3 = unsafe { stack.replace_last(ptr2, 4097, 4098) };
+        let ptr3 = unsafe { stack.replace_last(ptr2, CAPACITY + 1, CAPACITY + 2) };
         unsafe {
-            assert_unique(ptr3, 4098);
+            assert_unique(ptr3, CAPACITY + 2);
         }
         assert_eq!(stack.bounded_stack.len.get(), 0);
-        let ptr4 = unsafe { stack.replace_last(ptr3, 4098, 4097) };
+        let ptr4 = unsafe { stack.replace_last(ptr3, CAPACITY + 2, CAPACITY + 1) };
         unsafe {
-            assert_unique(ptr4, 4097);
+            assert_unique(ptr4, CAPACITY + 1);
         }
         assert_eq!(stack.bounded_stack.len.get(), 0);
         unsafe {
-            stack.pop(ptr4, 4097);
+            stack.pop(ptr4, CAPACITY + 1);
         }
     }
 
     #[test]
+    #[cfg(not(no_heap))]
     fn replace_last_relocate() {
-        let stack = Stack::<u8>::new();
+        // The first element fills the bounded stack exactly, which needs a non-empty element.
+        let Some(n) = CAPACITY.checked_sub(CANARY).filter(|&n| n > 0) else {
+            return;
+        };
+        let stack = Stack::<u8, CAPACITY>::new();
         assert_eq!(stack.bounded_stack.len.get(), 0);
-        let ptr1 = stack.push(4096);
+        let ptr1 = stack.push(n);
         unsafe {
-            assert_unique(ptr1, 4096);
+            assert_unique(ptr1, n);
         }
-        assert_eq!(stack.bounded_stack.len.get(), 4096);
-        let ptr2 = unsafe { stack.replace_last(ptr1, 4096, 4097) };
+        assert_eq!(stack.bounded_stack.len.get(), CAPACITY);
+        let ptr2 = unsafe { stack.replace_last(ptr1, n, CAPACITY + 1) };
         unsafe {
-            assert_unique(ptr2, 4097);
+            assert_unique(ptr2, CAPACITY + 1);
         }
         assert_eq!(stack.bounded_stack.len.get(), 0);
         assert_ne!(ptr1, ptr2);
-        let ptr3 = unsafe { stack.replace_last(ptr2, 4097, 4096) };
+        let ptr3 = unsafe { stack.replace_last(ptr2, CAPACITY + 1, n) };
         unsafe {
-            assert_unique(ptr3, 4096);
+            assert_unique(ptr3, n);
         }
-        assert_eq!(stack.bounded_stack.len.get(), 4096);
+        assert_eq!(stack.bounded_stack.len.get(), CAPACITY);
         assert_eq!(ptr1.addr(), ptr3.addr());
     }
+
+    #[cfg(not(no_heap))]
+    #[test]
+    fn save_restore() {
+        if !fits(1) {
+            return;
+        }
+        let stack = Stack::<u8, CAPACITY>::new();
+        let ptr1 = stack.push(1);
+        let ptr2 = stack.push(CAPACITY);
+        let saved = stack.save();
+        assert_eq!(stack.elements(), 0);
+        let ptr3 = stack.push(1);
+        assert_eq!(ptr1, ptr3);
+        unsafe {
+            stack.pop(ptr3, 1);
+        }
+        unsafe {
+            stack.restore(&saved);
+        }
+        assert_eq!(stack.elements(), 2);
+        unsafe {
+            stack.pop(ptr2, CAPACITY);
+        }
+        unsafe {
+            stack.pop(ptr1, 1);
+        }
+    }
+
+    #[test]
+    #[should_panic = "restored while exceptions are in flight"]
+    fn restore_non_empty() {
+        let stack = Stack::<u8, CAPACITY>::new();
+        let saved = stack.save();
+        stack.push(1);
+        unsafe {
+            stack.restore(&saved);
+        }
+    }
 }
@@ -0,0 +1,95 @@
+//! Valgrind client requests.
+//!
+//! Memcheck tracks memory at the granularity of allocations made with `malloc`, so it can't see
+//! elements being pushed to and popped from the bounded exception stack: a stale pointer to a
+//! popped exception reads leftover bytes without a report, and uninitialized padding copied out of
+//! an exception is considered defined. With the `valgrind` feature enabled, the bounded stack
+//! issues client requests to mark pushed regions as undefined and popped regions as inaccessible.
+//!
+//! A client request is a magic instruction sequence that does nothing when the program doesn't run
+//! under Valgrind, so it's cheap enough to keep in release builds. Requests are only issued on
+//! `x86_64` and `aarch64`; on other targets, the functions in this module do nothing.
+
+/// The base of Memcheck requests, `VG_USERREQ_TOOL_BASE('M', 'C')`.
+const MEMCHECK_BASE: usize = (b'M' as usize) << 24u32 | (b'C' as usize) << 16u32;
+
+/// `VG_USERREQ__MAKE_MEM_NOACCESS`.
+const MAKE_MEM_NOACCESS: usize = MEMCHECK_BASE;
+
+/// `VG_USERREQ__MAKE_MEM_UNDEFINED`.
+const MAKE_MEM_UNDEFINED: usize = MEMCHECK_BASE | 1;
+
+/// `VG_USERREQ__RUNNING_ON_VALGRIND`.
+#[cfg(test)]
+const RUNNING_ON_VALGRIND: usize = 0x1001;
+
+/// Issue a client request, returning `default` if the program doesn't run under Valgrind.
+///
+/// The instruction sequences are taken from `valgrind.h`. The rotations of the preamble add up to a
+/// multiple of the register width, so they don't modify it.
+#[inline]
+fn client_request(default: usize, request: usize, arg1: usize, arg2: usize) -> usize {
+    let block: [usize; 6] = [request, arg1, arg2, 0, 0, 0];
+    #[allow(unused_mut, reason = "Only modified on some targets")]
+    let mut result = default;
+
+    // SAFETY: The sequence is a no-op natively. Under Valgrind, it reads `block` and writes the
+    // result of the request to the output register.
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!(
+            "rol rdi, 3",
+            "rol rdi, 13",
+            "rol rdi, 61",
+            "rol rdi, 51",
+            "xchg rbx, rbx",
+            inout("rdx") result,
+            in("rax") block.as_ptr(),
+            options(nostack),
+        );
+    }
+
+    // SAFETY: As above.
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!(
+            "ror x12, x12, #3",
+            "ror x12, x12, #13",
+            "ror x12, x12, #51",
+            "ror x12, x12, #61",
+            "orr x10, x10, x10",
+            inout("x3") result,
+            in("x4") block.as_ptr(),
+            options(nostack),
+        );
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let _ = block;
+
+    result
+}
+
+/// Mark `n` bytes starting at `ptr` as inaccessible.
+#[inline]
+pub fn make_mem_noaccess(ptr: *const u8, n: usize) {
+    client_request(0, MAKE_MEM_NOACCESS, ptr.addr(), n);
+}
+
+/// Mark `n` bytes starting at `ptr` as accessible, but uninitialized.
+#[inline]
+pub fn make_mem_undefined(ptr: *const u8, n: usize) {
+    client_request(0, MAKE_MEM_UNDEFINED, ptr.addr(), n);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn running_on_valgrind() {
+        // The tests are run under Valgrind in CI, so only check that the request is well-formed.
+        let running = client_request(0, RUNNING_ON_VALGRIND, 0, 0);
+        assert!(running <= 1);
+    }
+}
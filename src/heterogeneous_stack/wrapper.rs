@@ -0,0 +1,217 @@
+use super::unbounded::Stack as RawStack;
+use crate::guard::finally;
+use core::mem::MaybeUninit;
+
+/// The alignment of the allocations.
+///
+/// Values aligned to at most this are stored without padding.
+#[repr(C)]
+struct Align {
+    _word: [usize; 0],
+}
+
+/// A thread-local heterogeneous stack allocator.
+///
+/// See the [module-level documentation](super) for more information.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::heterogeneous_stack::Stack;
+///
+/// let stack = Stack::new();
+/// let len = stack.with(*b"hello", |word| {
+///     word[0] = b'j';
+///     stack.with_uninit_slice::<u16, _>(word.len(), |buffer| {
+///         for (slot, byte) in buffer.iter_mut().zip(word.iter()) {
+///             slot.write(u16::from(*byte));
+///         }
+///         assert_eq!(stack.len(), 2);
+///         buffer.len()
+///     })
+/// });
+/// assert_eq!(len, 5);
+/// assert!(stack.is_empty());
+/// ```
+pub struct Stack {
+    raw: RawStack<Align>,
+}
+
+impl Stack {
+    /// Create an empty stack.
+    ///
+    /// The buffer is stored inline, so `Stack` is as large as `LITHIUM_STACK_CAPACITY`. Store it
+    /// in a `static`, a thread local, or a `Box` if that's too large for the machine stack.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            raw: RawStack::new(),
+        }
+    }
+
+    /// Get the number of values on the stack.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.raw.elements()
+    }
+
+    /// Check if the stack is empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push `value` onto the stack, pass it to `func`, and pop it afterwards.
+    ///
+    /// The value is dropped after `func` returns or unwinds.
+    #[inline]
+    pub fn with<T, R>(&self, value: T, func: impl FnOnce(&mut T) -> R) -> R {
+        self.with_uninit(|slot| {
+            let value: *mut T = slot.write(value);
+            // SAFETY: `value` was initialized above and is not accessed after `func` returns
+            // or unwinds.
+            let _drop = finally(move || unsafe { value.drop_in_place() });
+            // SAFETY: `value` is initialized and unique, as `slot` is no longer used.
+            func(unsafe { &mut *value })
+        })
+    }
+
+    /// Allocate space for a `T` on the stack, pass it to `func`, and pop it afterwards.
+    ///
+    /// The space is uninitialized, and any value written to it is not dropped.
+    #[inline]
+    pub fn with_uninit<T, R>(&self, func: impl FnOnce(&mut MaybeUninit<T>) -> R) -> R {
+        self.with_uninit_slice(1, |slots| {
+            // SAFETY: The slice contains exactly one element.
+            func(unsafe { slots.get_unchecked_mut(0) })
+        })
+    }
+
+    /// Allocate space for `len` values of type `T` on the stack, pass it to `func`, and pop it
+    /// afterwards.
+    ///
+    /// The space is uninitialized, and any values written to it are not dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocation size overflows `isize`, or if allocating the space fails.
+    #[inline]
+    pub fn with_uninit_slice<T, R>(
+        &self,
+        len: usize,
+        func: impl FnOnce(&mut [MaybeUninit<T>]) -> R,
+    ) -> R {
+        let n = Self::allocation_size::<T>(len);
+        let ptr = self.raw.push(n);
+        // SAFETY: `ptr` was obtained from `push` to this stack with `n` bytes. It's the top
+        // element when the guard is dropped, as the values pushed by `func` are popped before
+        // it returns or unwinds. The element is not accessed afterwards.
+        let _pop = finally(|| unsafe { self.raw.pop(ptr, n) });
+        // SAFETY: `allocation_size` reserved enough padding to realign the pointer, so the
+        // result is in bounds of the allocation.
+        let data = unsafe { ptr.add(ptr.align_offset(align_of::<T>())) };
+        // SAFETY: `data` is aligned and valid for reads/writes for `len` elements of type `T`,
+        // and unique, as guaranteed by `push`. `MaybeUninit` doesn't need initialization.
+        func(unsafe { core::slice::from_raw_parts_mut(data.cast(), len) })
+    }
+
+    /// Compute the number of bytes to allocate for `len` values of type `T`, including the
+    /// padding needed to align them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size overflows `isize`.
+    fn allocation_size<T>(len: usize) -> usize {
+        let padding = align_of::<T>().saturating_sub(align_of::<Align>());
+        size_of::<T>()
+            .checked_mul(len)
+            .and_then(|size| size.checked_add(padding))
+            .and_then(|size| size.checked_next_multiple_of(align_of::<Align>()))
+            .filter(|size| isize::try_from(*size).is_ok())
+            .expect("Too big allocation")
+    }
+}
+
+impl Default for Stack {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, throw};
+    use alloc::rc::Rc;
+
+    #[test]
+    fn nested() {
+        let stack = Stack::new();
+        let result = stack.with(1u8, |a| {
+            stack.with(2u64, |b| {
+                assert_eq!(stack.len(), 2);
+                *a = a.wrapping_add(1);
+                u64::from(*a) + *b
+            })
+        });
+        assert_eq!(result, 4);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn overaligned() {
+        #[repr(align(256))]
+        struct Overaligned(u8);
+        let stack = Stack::new();
+        stack.with(0u8, |_| {
+            stack.with(Overaligned(1), |value| {
+                assert_eq!((&raw const *value).addr() % 256, 0);
+                assert_eq!(value.0, 1);
+            });
+        });
+    }
+
+    #[test]
+    fn zero_sized() {
+        let stack = Stack::new();
+        stack.with_uninit_slice::<(), _>(usize::MAX, |slots| {
+            assert_eq!(slots.len(), usize::MAX);
+        });
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    #[cfg(not(no_heap))]
+    fn spill() {
+        let stack = Stack::new();
+        stack.with_uninit_slice::<u8, _>(1 << 20u32, |slots| {
+            slots.fill(MaybeUninit::new(1));
+            stack.with(2i32, |value| assert_eq!(*value, 2));
+        });
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    #[should_panic = "Too big allocation"]
+    fn too_big() {
+        Stack::new().with_uninit_slice::<u64, _>(usize::MAX / 4, |_| {});
+    }
+
+    #[test]
+    fn unwind() {
+        let stack = Stack::new();
+        let value = Rc::new(());
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                stack.with(Rc::clone(&value), |_| throw(1));
+            })
+        };
+        assert_eq!(result, Err(1));
+        assert_eq!(Rc::strong_count(&value), 1);
+        assert!(stack.is_empty());
+    }
+}
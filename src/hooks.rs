@@ -0,0 +1,123 @@
+//! Bookkeeping on throw and catch.
+//!
+//! Every exception thrown or caught by Lithium passes through the hooks in this module, which keep
+//! the count of unwinding exceptions reported by `is_unwinding` in all builds, count events with
+//! the `stats` feature, emit throw events with the `tracing` and `log` features, and drive the
+//! checks in `debug` and `rethrows` in builds with the `checks` cfg.
+
+use super::debug::{self, Expected};
+use super::rethrows;
+#[cfg(feature = "stats")]
+use super::stats::{record, Event};
+#[cfg(any(feature = "tracing", feature = "log"))]
+use super::trace;
+use super::unwinding::{begin_unwind, end_unwind};
+
+/// A marker for an active Lithium catch frame.
+///
+/// Create the marker with [`CatchFrame::enter`] before invoking the callback, and consume it with
+/// [`CatchFrame::returned`] or [`CatchFrame::caught`] afterwards. If the callback unwinds due to a
+/// Rust panic, the marker is simply dropped.
+pub struct CatchFrame {
+    debug: debug::CatchFrame,
+    rethrows_on_entry: usize,
+}
+
+impl CatchFrame {
+    /// Enter a catch frame accepting exceptions of `expected` types.
+    #[inline]
+    pub fn enter(expected: Expected) -> Self {
+        Self {
+            debug: debug::CatchFrame::enter(expected),
+            rethrows_on_entry: rethrows::enter_frame(),
+        }
+    }
+
+    /// Leave the catch frame after the callback returned normally.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if an exception thrown inside the frame was caught by a foreign
+    /// frame.
+    #[inline]
+    pub fn returned(self) {
+        self.debug.returned();
+    }
+
+    /// Leave the catch frame after the callback threw an exception, which was caught by this frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if another exception thrown inside the frame was caught by a foreign
+    /// frame.
+    #[inline]
+    pub fn caught(self) {
+        end_unwind();
+        #[cfg(feature = "stats")]
+        record(Event::Catch);
+        rethrows::on_catch(self.rethrows_on_entry);
+        self.debug.caught();
+    }
+}
+
+/// Register a thrown exception of type `E`.
+///
+/// # Panics
+///
+/// Panics in debug builds if no Lithium catch frame is active on the current thread, since the
+/// exception would then escape to the system runtime, or if the nearest catch frame doesn't accept
+/// `E`.
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub fn on_throw<E>() {
+    begin_unwind();
+    #[cfg(feature = "stats")]
+    record(Event::Throw);
+    #[cfg(any(feature = "tracing", feature = "log"))]
+    trace::throw::<E>();
+    debug::on_throw::<E>();
+    rethrows::on_throw();
+}
+
+/// A marker for a live in-flight exception handle.
+///
+/// Create the marker along with the handle and release it when the handle is released, i.e. before
+/// the exception is rethrown or dropped. Zero-sized in release builds.
+pub struct InFlightMarker {
+    debug: debug::InFlightMarker,
+    rethrows: rethrows::Marker,
+}
+
+impl InFlightMarker {
+    /// Register a new in-flight exception.
+    #[cfg_attr(
+        not(checks),
+        expect(clippy::missing_const_for_fn, reason = "Not const with checks")
+    )]
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            debug: debug::InFlightMarker::new(),
+            rethrows: rethrows::Marker::new(),
+        }
+    }
+
+    /// Release the in-flight exception before rethrowing it.
+    ///
+    /// Call this right before [`on_throw`].
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if a handle created later is still live. Aborts the process if the
+    /// exception has been rethrown more times than the limit, if any.
+    #[cfg_attr(
+        not(checks),
+        expect(clippy::missing_const_for_fn, reason = "Not const with checks")
+    )]
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub fn rethrow(self) {
+        self.debug.rethrow();
+        self.rethrows.rethrow();
+    }
+}
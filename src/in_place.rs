@@ -0,0 +1,559 @@
+#[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+use super::api::{catch, throw};
+#[cfg(feature = "stats")]
+use super::stats::{record, Event};
+#[cfg(any(feature = "tracing", feature = "log"))]
+use super::trace;
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::{
+    api::in_catch_frame,
+    debug::{ExceptionType, Expected},
+    guard::{finally, on_unwind},
+    hooks::{on_throw, InFlightMarker},
+    stacked_exceptions::{
+        intercept_in_place as intercept_raw, pop, replace_last, throw_pushed, Exception,
+    },
+};
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use core::mem::ManuallyDrop;
+
+/// An intercepted exception with the cause kept in place.
+///
+/// This type is returned by [`intercept_in_place`]. Unlike with [`intercept`](crate::intercept),
+/// the cause is not moved out of the exception object, so it can be modified and rethrown without
+/// copying it, which matters for large causes. Use [`InPlaceException::peek`] to inspect the
+/// cause before deciding whether to rethrow the exception.
+///
+/// Dropping [`InPlaceException`] drops the cause and releases the exception.
+///
+/// On backends that don't use an exception stack, such as SEH, the cause is moved out of the
+/// exception when it's caught and stored in this type.
+pub struct InPlaceException<E> {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    ex: *mut Exception<E>,
+    // Released manually before the exception, so that misuse is reported first.
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    in_flight: ManuallyDrop<InFlightMarker>,
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    cause: E,
+}
+
+/// Begin exception catching, keeping the cause in place.
+///
+/// If `func` returns a value, this function wraps it in [`Ok`].
+///
+/// If `func` throws an exception, a handle to the exception, still containing the cause, is
+/// returned in [`Err`]. The handle can be used to modify the cause in place and rethrow the
+/// exception with [`InPlaceException::rethrow_same`].
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+///
+/// The same requirements apply to the returned [`InPlaceException`] as to the
+/// [`InFlightException`](crate::InFlightException) returned by [`intercept`](crate::intercept):
+/// no exceptions may be thrown between the moment this function returns and the moment the handle
+/// is dropped or rethrown. Caught exceptions and panics are allowed.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, intercept_in_place, throw};
+///
+/// struct Error {
+///     context: Vec<&'static str>,
+/// }
+///
+/// let result = unsafe {
+///     catch::<(), Error>(|| {
+///         match intercept_in_place::<(), Error>(|| throw(Error { context: vec!["inner"] })) {
+///             Ok(()) => {}
+///             Err(mut ex) => {
+///                 ex.modify(|err| err.context.push("outer"));
+///                 ex.rethrow_same();
+///             }
+///         }
+///     })
+/// };
+/// assert_eq!(result.unwrap_err().context, ["inner", "outer"]);
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn intercept_in_place<R, E>(func: impl FnOnce() -> R) -> Result<R, InPlaceException<E>> {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    // SAFETY: Requirements forwarded.
+    return in_catch_frame(
+        Expected::Types(const { &[ExceptionType::of::<E>()] }),
+        || unsafe { intercept_raw(func) },
+    )
+    .map_err(|ex| InPlaceException {
+        ex,
+        in_flight: ManuallyDrop::new(InFlightMarker::new()),
+    });
+
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    // SAFETY: Requirements forwarded.
+    return unsafe { catch(func) }.map_err(|cause| InPlaceException { cause });
+}
+
+/// Catch an exception, handling the cause by reference.
+///
+/// If `func` returns a value, this function returns it as-is. If `func` throws an exception,
+/// `handler` is invoked with a mutable reference to the cause, and its return value is returned.
+/// The cause is dropped in place afterwards.
+///
+/// This is equivalent to [`catch`](crate::catch) followed by handling the error, except that on
+/// backends that use an exception stack, the cause is not moved out of the exception object,
+/// which saves a copy of large causes that only need to be inspected.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+///
+/// `handler` must not throw exceptions, as the caught exception is still in flight while it runs.
+/// Caught exceptions and panics are allowed.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch_ref, throw};
+///
+/// // SAFETY: Only `[u8; 1024]` is thrown, and the handler doesn't throw.
+/// let sum = unsafe {
+///     catch_ref::<u32, [u8; 1024]>(
+///         || throw([1_u8; 1024]),
+///         |buffer| buffer.iter().map(|&byte| u32::from(byte)).sum(),
+///     )
+/// };
+/// assert_eq!(sum, 1024);
+/// ```
+#[inline]
+pub unsafe fn catch_ref<R, E>(func: impl FnOnce() -> R, handler: impl FnOnce(&mut E) -> R) -> R {
+    // SAFETY: Requirements forwarded.
+    match unsafe { intercept_in_place::<R, E>(func) } {
+        Ok(value) => value,
+        Err(mut ex) => handler(ex.peek_mut()),
+    }
+}
+
+/// Call a function until it returns, up to `attempts` times.
+///
+/// If `func` returns a value, this function wraps it in [`Ok`]. If `func` throws an exception, it's
+/// invoked again, until `attempts` invocations have failed, in which case the cause of the last
+/// exception is returned in [`Err`].
+///
+/// This is equivalent to calling [`catch`](crate::catch) in a loop, except that the causes of the
+/// failed attempts are dropped in place, like with [`intercept_in_place`], and only the last one
+/// is moved out. On backends that use an exception stack, each attempt reuses the storage released
+/// by the previous one.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Panics
+///
+/// Panics if `attempts` is zero.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{retry, throw};
+///
+/// let mut remaining_failures = 2;
+/// // SAFETY: Only `&str` is thrown.
+/// let result = unsafe {
+///     retry::<u32, &str>(3, || {
+///         if remaining_failures > 0 {
+///             remaining_failures -= 1;
+///             throw("Connection reset");
+///         }
+///         200
+///     })
+/// };
+/// assert_eq!(result, Ok(200));
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn retry<R, E>(attempts: usize, mut func: impl FnMut() -> R) -> Result<R, E> {
+    assert!(attempts > 0, "`retry` requires at least one attempt");
+    for _ in 1..attempts {
+        // SAFETY: Requirements forwarded. The exception is released immediately.
+        match unsafe { intercept_in_place::<R, E>(&mut func) } {
+            Ok(value) => return Ok(value),
+            Err(ex) => drop(ex),
+        }
+    }
+    // SAFETY: Requirements forwarded.
+    unsafe { intercept_in_place(func) }.map_err(InPlaceException::into_cause)
+}
+
+impl<E> InPlaceException<E> {
+    /// Get a reference to the cause.
+    ///
+    /// This allows inspecting the cause, e.g. to log it, before deciding whether to drop or
+    /// rethrow the exception, without moving it out.
+    #[inline]
+    #[must_use]
+    pub fn peek(&self) -> &E {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: `ex` is a unique pointer to a live exception object.
+        let cause = unsafe { Exception::cause_ptr(self.ex) };
+
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: `cause_ptr` returns an aligned pointer to the initialized cause. It's borrowed
+        // for as long as `self`.
+        return unsafe { &*cause };
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        return &self.cause;
+    }
+
+    /// Get a mutable reference to the cause.
+    #[inline]
+    pub fn peek_mut(&mut self) -> &mut E {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: `ex` is a unique pointer to a live exception object.
+        let cause = unsafe { Exception::cause_ptr(self.ex) };
+
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        // SAFETY: `cause_ptr` returns an aligned pointer to the initialized cause. It's borrowed
+        // mutably for as long as `self`.
+        return unsafe { &mut *cause };
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        return &mut self.cause;
+    }
+
+    /// Modify the cause in place.
+    ///
+    /// `f` must not throw exceptions. Panics, however, are allowed.
+    #[inline]
+    pub fn modify(&mut self, f: impl FnOnce(&mut E)) {
+        f(self.peek_mut());
+    }
+
+    /// Rethrow the exception with the current cause.
+    ///
+    /// On backends that use an exception stack, the cause is not copied.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of [`InFlightException::rethrow`](crate::InFlightException::rethrow)
+    /// apply, with `E` as the new type.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub unsafe fn rethrow_same(self) -> ! {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        {
+            let mut this = ManuallyDrop::new(self);
+            // SAFETY: `this` is not dropped, so the marker is only taken here.
+            unsafe { ManuallyDrop::take(&mut this.in_flight) }.rethrow();
+            #[cfg(feature = "stats")]
+            record(Event::Rethrow);
+            #[cfg(any(feature = "tracing", feature = "log"))]
+            trace::rethrow::<E>();
+            on_throw::<E>();
+            let ex = this.ex;
+            // SAFETY: The exception object is still at the top of the stack by the safety
+            // requirements of `intercept_in_place`, and the cause is in place. The rest is required
+            // transitively.
+            unsafe { throw_pushed(ex) }
+        }
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        {
+            #[cfg(feature = "stats")]
+            record(Event::Rethrow);
+            #[cfg(any(feature = "tracing", feature = "log"))]
+            trace::rethrow::<E>();
+            // SAFETY: Required transitively. No exception is in flight at this point, so a plain
+            // `throw` is equivalent to a rethrow.
+            unsafe { throw(self.cause) }
+        }
+    }
+
+    /// Convert the cause to another type, keeping the exception in flight.
+    ///
+    /// This defers the choice between rethrowing and dropping the exception until after the
+    /// conversion. On backends that use an exception stack, the converted cause is stored in the
+    /// same exception object.
+    ///
+    /// `f` must not throw exceptions. Panics, however, are allowed, in which case the exception is
+    /// released.
+    #[inline]
+    pub fn map<F>(self, f: impl FnOnce(E) -> F) -> InPlaceException<F> {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        {
+            let mut this = ManuallyDrop::new(self);
+            let ex = this.ex;
+            // SAFETY: `this` is not dropped, so the marker is only taken here.
+            let in_flight = unsafe { ManuallyDrop::take(&mut this.in_flight) };
+            // SAFETY: `ex` is a unique pointer to a live exception object.
+            let ex_ref = unsafe { &mut *ex };
+            // SAFETY: The cause is only read here, as `self` is not dropped.
+            let cause = unsafe { ex_ref.cause() };
+            // SAFETY: As above.
+            let location = unsafe { Exception::location(ex) };
+            let new_cause = on_unwind(
+                || f(cause),
+                || {
+                    // SAFETY: As in `into_cause`.
+                    unsafe {
+                        pop(ex);
+                    }
+                },
+            );
+            InPlaceException {
+                // SAFETY: The exception object is still at the top of the stack by the safety
+                // requirements of `intercept_in_place`, and the old cause has been moved out.
+                ex: unsafe { replace_last(ex, new_cause, location) },
+                in_flight: ManuallyDrop::new(in_flight),
+            }
+        }
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        InPlaceException {
+            cause: f(self.cause),
+        }
+    }
+
+    /// Take the cause and release the exception.
+    #[inline]
+    #[must_use]
+    pub fn into_cause(self) -> E {
+        #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+        {
+            let mut this = ManuallyDrop::new(self);
+            // SAFETY: `this` is not dropped, so the marker is only dropped here.
+            unsafe {
+                ManuallyDrop::drop(&mut this.in_flight);
+            }
+            let ex = this.ex;
+            // SAFETY: `ex` is a unique pointer to a live exception object.
+            let ex_ref = unsafe { &mut *ex };
+            // SAFETY: The cause is only read here, as `self` is not dropped.
+            let cause = unsafe { ex_ref.cause() };
+            // SAFETY: The exception object is still at the top of the stack by the safety
+            // requirements of `intercept_in_place`, and it's not accessed afterwards.
+            unsafe {
+                pop(ex);
+            }
+            cause
+        }
+
+        #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+        self.cause
+    }
+}
+
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+impl<E> Drop for InPlaceException<E> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: The marker is only dropped here, as `self` is not used afterwards.
+        unsafe {
+            ManuallyDrop::drop(&mut self.in_flight);
+        }
+        let ex = self.ex;
+        // Release the exception even if the destructor of the cause panics.
+        let _pop = finally(move || {
+            // SAFETY: As in `into_cause`.
+            unsafe {
+                pop(ex);
+            }
+        });
+        // SAFETY: As in `into_cause`.
+        let ex_ref = unsafe { &mut *ex };
+        // SAFETY: The cause is still in place, as `self` is not used afterwards. Dropping it in
+        // place avoids copying large causes.
+        unsafe {
+            ex_ref.drop_cause();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, throw};
+    use alloc::{rc::Rc, string::String};
+    use core::cell::Cell;
+
+    struct Tracked(Rc<Cell<usize>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn ok() {
+        let result = unsafe { intercept_in_place::<i32, String>(|| 123) };
+        assert_eq!(result.ok(), Some(123));
+    }
+
+    #[test]
+    fn modify_and_rethrow() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let mut ex = intercept_in_place::<(), String>(|| throw(String::from("Hello")))
+                    .err()
+                    .unwrap();
+                ex.modify(|cause| cause.push_str(", world!"));
+                ex.rethrow_same();
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn peek() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let mut ex = intercept_in_place::<(), String>(|| throw(String::from("Hello")))
+                    .err()
+                    .unwrap();
+                assert_eq!(ex.peek(), "Hello");
+                ex.peek_mut().push('!');
+                assert_eq!(ex.peek(), "Hello!");
+                ex.rethrow_same();
+            })
+        };
+        assert_eq!(result.unwrap_err(), "Hello!");
+    }
+
+    #[test]
+    fn peek_overaligned() {
+        #[derive(Debug, PartialEq)]
+        #[repr(align(64))]
+        struct Cause(i32);
+
+        let result: Result<(), Cause> = unsafe {
+            catch(|| {
+                let mut ex = intercept_in_place::<(), Cause>(|| throw(Cause(1)))
+                    .err()
+                    .unwrap();
+                assert_eq!(ex.peek(), &Cause(1));
+                ex.peek_mut().0 += 1;
+                ex.rethrow_same();
+            })
+        };
+        assert_eq!(result.unwrap_err(), Cause(2));
+    }
+
+    #[test]
+    fn map() {
+        let result: Result<(), String> = unsafe {
+            catch(|| {
+                let ex = intercept_in_place::<(), i32>(|| throw(123)).err().unwrap();
+                let ex = ex.map(|cause| alloc::format!("error {cause}"));
+                ex.rethrow_same();
+            })
+        };
+        assert_eq!(result.unwrap_err(), "error 123");
+    }
+
+    #[test]
+    #[cfg(not(no_heap))]
+    fn map_and_drop() {
+        let ex = unsafe { intercept_in_place::<(), i32>(|| throw(123)) };
+        let ex = ex.err().unwrap().map(|cause| [cause; 2000]);
+        assert_eq!(ex.into_cause()[1999], 123);
+        let result: Result<(), i32> = unsafe { catch(|| throw(1)) };
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn into_cause() {
+        let ex = unsafe { intercept_in_place::<(), String>(|| throw(String::from("Oops!"))) };
+        assert_eq!(ex.err().unwrap().into_cause(), "Oops!");
+        let result: Result<(), i32> = unsafe { catch(|| throw(1)) };
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn drop_releases() {
+        let ex = unsafe { intercept_in_place::<(), String>(|| throw(String::from("Oops!"))) };
+        drop(ex);
+        let result: Result<(), i32> = unsafe { catch(|| throw(1)) };
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn catch_ref_ok() {
+        let result = unsafe { catch_ref::<i32, String>(|| 123, |_| unreachable!()) };
+        assert_eq!(result, 123);
+    }
+
+    #[test]
+    fn catch_ref_err() {
+        let drops = Rc::new(Cell::new(0));
+        let result = unsafe {
+            catch_ref::<usize, Tracked>(
+                || throw(Tracked(Rc::clone(&drops))),
+                |tracked| {
+                    assert_eq!(drops.get(), 0);
+                    Rc::strong_count(&tracked.0)
+                },
+            )
+        };
+        assert_eq!(result, 2);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn retry_succeeds() {
+        let mut calls = 0;
+        let result = unsafe {
+            retry::<usize, String>(5, || {
+                calls += 1;
+                if calls < 3 {
+                    throw(String::from("Try again"));
+                }
+                calls
+            })
+        };
+        assert_eq!(result, Ok(3));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn retry_exhausted() {
+        let drops = Rc::new(Cell::new(0));
+        let mut calls: usize = 0;
+        let result = unsafe {
+            retry::<(), (usize, Tracked)>(3, || {
+                calls += 1;
+                throw((calls, Tracked(Rc::clone(&drops))));
+            })
+        };
+        assert_eq!(drops.get(), 2);
+        let (last, tracked) = result.err().unwrap();
+        assert_eq!(last, 3);
+        drop(tracked);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    #[should_panic = "`retry` requires at least one attempt"]
+    fn retry_zero() {
+        let _ = unsafe { retry::<(), i32>(0, || {}) };
+    }
+}
@@ -46,6 +46,50 @@ pub fn intercept<Func: FnOnce() -> T, Catch: FnOnce(*mut u8) -> E, T, E>(
     }
 }
 
+/// Catch any unwinding from a function, exposing the raw exception pointer.
+///
+/// This is the primitive the native backends are built on, for interop layers and custom
+/// discrimination schemes that need to see exceptions before Lithium interprets them. Runs `func`.
+/// If `func` doesn't unwind, wraps its return value in `Ok` and returns. If `func` unwinds, for any
+/// reason, runs `catch` inside the catch handler and wraps its return value in `Err`. If `catch`
+/// unwinds, the process aborts.
+///
+/// The pointer passed to `catch` is the one supplied by [`core::intrinsics::catch_unwind`]:
+///
+/// - On the Itanium backend, a pointer to the `_Unwind_Exception` object.
+/// - On the Emscripten backend, a pointer to a temporary structure whose first field is the pointer
+///   to be passed to `__cxa_begin_catch`.
+/// - On the SEH backend, a pointer to the thrown C++ object.
+///
+/// On Emscripten and SEH, the pointer is only valid until `catch` returns.
+///
+/// This function is only available on native backends, i.e. when [`IS_NATIVE_BACKEND`] is `true`.
+///
+/// [`IS_NATIVE_BACKEND`]: crate::IS_NATIVE_BACKEND
+///
+/// # Errors
+///
+/// Returns the value produced by `catch` if `func` unwinds.
+///
+/// # Safety
+///
+/// The caller becomes responsible for the caught exception and must dispose of it according to the
+/// conventions of the runtime that raised it, i.e. rethrow it or destroy it. In particular:
+///
+/// - Lithium exceptions must not be caught by this function, as their bookkeeping is only updated
+///   by Lithium's own catch functions. If `func` may throw them, it needs to catch them itself, or
+///   the exception needs to be rethrown to a Lithium catch frame before Lithium is used on the
+///   thread again.
+/// - Rust panics must be rethrown, as the standard library only updates its panic count when it
+///   catches panics itself.
+#[inline]
+pub unsafe fn catch_unwind_raw<R, E>(
+    func: impl FnOnce() -> R,
+    catch: impl FnOnce(*mut u8) -> E,
+) -> Result<R, E> {
+    intercept(func, catch)
+}
+
 // This function should be unsafe, but isn't due to the definition of `catch_unwind`.
 #[inline]
 fn do_call<Func: FnOnce() -> R, Catch: FnOnce(*mut u8) -> E, R, E>(data: *mut u8) {
@@ -70,3 +114,31 @@ fn do_catch<Func: FnOnce() -> R, Catch: FnOnce(*mut u8) -> E, R, E>(data: *mut u
     let catch = unsafe { ManuallyDrop::take(&mut data.init.1) };
     data.err = ManuallyDrop::new(catch(ex));
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn raw_ok() {
+        assert_eq!(unsafe { catch_unwind_raw(|| 1i32, |_| 2i32) }, Ok(1));
+    }
+
+    #[test]
+    #[cfg(all(backend = "itanium", not(target_arch = "wasm32")))]
+    fn raw_rethrow_panic() {
+        extern "C-unwind" {
+            #[link_name = "_Unwind_RaiseException"]
+            fn raise(ex: *mut u8) -> !;
+        }
+
+        let result = std::panic::catch_unwind(|| {
+            let ex =
+                unsafe { catch_unwind_raw(|| std::panic::panic_any(1i32), |ex| ex) }.unwrap_err();
+            let class = unsafe { ex.cast::<u64>().read_unaligned() };
+            assert_eq!(class, u64::from_ne_bytes(*b"MOZ\0RUST"));
+            unsafe { raise(ex) }
+        });
+        assert_eq!(*result.unwrap_err().downcast::<i32>().unwrap(), 1);
+    }
+}
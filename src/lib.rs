@@ -11,9 +11,73 @@
 //! # Usage
 //!
 //! Throw an exception with [`throw`], catch it with [`catch`] or the more low-level [`intercept`].
+//! Use [`catch_if`] to only catch exceptions matching a predicate, and [`intercept_map`] to convert
+//! exceptions while propagating them. [`intercept_cf`] returns
+//! [`ControlFlow`](core::ops::ControlFlow) instead of [`Result`]. [`intercept_in_place`] keeps the
+//! cause inside the exception, so it can be modified and rethrown without copying, and
+//! [`catch_ref`] handles the cause by reference.
+//! [`retry`] calls a function until it stops throwing.
+//! [`catch2`] and [`catch3`] catch exceptions of one of several types. [`RemoteException`] moves a
+//! caught exception to another thread to be rethrown there, and [`thread::spawn_catching`] spawns a
+//! thread that catches exceptions at the top level.
+//! [`install_thread_guard`] aborts cleanly if an exception escapes to the root of a thread.
+//! [`ExceptionStack`] lets executors give each task its own exception stack, and `fiber::save` and
+//! `fiber::restore` let stackful coroutines swap the exception state of the thread.
+//! [`reset_thread_state`] checks that no exceptions leaked at request boundaries of long-lived
+//! threads.
+//! [`emplace`] constructs large exceptions in place instead of moving them into [`throw`], and
+//! [`throw_boxed`] and [`catch_boxed`] keep huge causes behind a heap pointer, and
+//! [`throw_unsized`] and [`catch_unsized`] do the same for trait objects and other unsized causes.
+//! An [`ExceptionSlot`] returned by [`emplace`] can also be thrown and caught repeatedly, reusing
+//! its storage across loop iterations.
+//! [`catch_dyn`] shares the catching code between call sites to reduce binary size.
+//! [`Chain`] and [`with_context`] attach context messages to exceptions as they propagate.
+//! [`catch_unwind_into`] converts Rust panics from third-party code into exceptions.
+//! [`compat`] mirrors the `std::panic` unwinding API for incremental migrations from panics.
+//! [`bridge_unwind!`] defines `extern "C-unwind"` functions that let exceptions through as panics,
+//! which [`unbridge`] converts back.
+//! [`throw_err`] throws errors that are printed, along with their sources, if the process aborts
+//! due to a misplaced exception.
+//! [`catch_scoped`] hands out a [`ThrowToken`], which makes throwing safe within its scope, and
+//! [`scope`] does the same with a lifetime-branded [`Scope`] checked at compile time.
+//! [`ThrowExt`] and [`tri!`] convert [`Result`] and [`Option`] failures into exceptions.
+//! [`throw!`] formats a [`Message`] and throws it, sparing custom error types for one-off failures.
+//! [`bail!`] and [`ensure!`] throw with the exception type written out, so that it's not inferred
+//! by accident.
+//! [`Error`] is a dynamic error type in the spirit of `anyhow`, which keeps messages and context
+//! inline in the exception object instead of boxing them.
+//! [`assert_throws!`] and [`assert_throws_matches!`] check that code under test throws.
+//! With the `macros` feature enabled, the `#[throws(E)]` attribute marks functions that throw `E`.
+//! With the `rayon` feature enabled, the `lithium::rayon` module provides data-parallel combinators
+//! that catch exceptions on worker threads. With the `async` feature enabled, `lithium::future`
+//! provides `CatchFuture`, which catches exceptions thrown while polling a future. With the `stats`
+//! feature enabled, `stack_stats` reports the usage of the exception stack, and `stats` and
+//! `global_stats` count throws, catches, rethrows, and heap spills. With the `location`
+//! feature enabled, exceptions record where they were thrown, which `catch_located` and
+//! `InFlightException::location` report. With the `tracing` feature enabled, throws, rethrows,
+//! catches and aborts are reported as [`tracing`](https://docs.rs/tracing) events under the
+//! `lithium` target, and the `log` feature emits the same information as
+//! [`log`](https://docs.rs/log) records. With the `test-util` feature enabled, `lithium::test_util`
+//! injects exceptions at checkpoints to test exception handling paths. With the `ffi` feature
+//! enabled, `lithium::ffi` exports `extern "C"` functions for throwing and catching exceptions from
+//! other languages. With the `heterogeneous-stack` feature enabled, `lithium::heterogeneous_stack`
+//! exposes the allocator behind the exception stack for scratch allocations. With the `valgrind`
+//! feature enabled, the exception stack reports pushed and popped regions to Memcheck with client
+//! requests; pass `--suppressions=valgrind.supp`, shipped with the crate, to silence a known leak
+//! in `std`. With the `checked` feature enabled, the debug-build bookkeeping of catch frames is
+//! kept in release builds, and `try_throw` throws safely, handing the exception back if no matching
+//! catch frame is ready.
+//! On the Itanium backend, `catch_foreign` stops foreign exceptions, such as C++ exceptions,
+//! at a known boundary, and `intercept_with_policy` chooses what happens to them per call site. On
+//! native backends, `catch_unwind_raw` catches any unwinding and exposes the raw exception pointer
+//! for interop.
+//! [`is_unwinding`] tells destructors whether a Lithium exception is propagating through them.
 //! Unlike with Rust panics, non-[`Send`] and non-`'static` types can be used soundly.
 //!
-//! Using the `panic = "abort"` strategy breaks Lithium; avoid doing that.
+//! Using the `panic = "abort"` strategy breaks Lithium; avoid doing that. A `setjmp`/`longjmp`
+//! fallback for such builds is not provided: Rust cannot call `setjmp` soundly, as it has no notion
+//! of functions that return twice, and `longjmp` over frames with destructors is undefined
+//! behavior, which rules out running cleanups during propagation.
 //!
 //! For interop, all crates that depend on Lithium need to use the same version:
 //!
@@ -24,6 +88,11 @@
 //!
 //! If you break either of these two requirements, cargo will scream at you.
 //!
+//! Separately built copies of Lithium in one process, e.g. in different dynamic libraries, don't
+//! share state by default. On the Itanium backend, exceptions of incompatible versions pass through
+//! each other's frames as foreign exceptions; to let copies catch each other's exceptions, give
+//! them a common per-thread state with [`thread_state_provider!`], as described in [`tls`].
+//!
 //!
 //! # Platform support
 //!
@@ -41,6 +110,33 @@
 //! |Emscripten (new EH)|Wasm exceptions|2.5x faster than panics                      |
 //! |WASI               |Itanium EH ABI |2.5x faster than panics                      |
 //!
+//! Emscripten with native Wasm exceptions (`-fwasm-exceptions`) uses the Itanium implementation, as
+//! on other Wasm targets, rather than a dedicated one. Linking objects built with different
+//! exception handling modes is not supported by Emscripten, and thus not by Lithium either.
+//!
+//! Both WASI Preview 1 (`wasm32-wasip1`) and Preview 2 (`wasm32-wasip2`) are supported. Components
+//! wrap a core module that uses Wasm exceptions just like on Preview 1, so the same implementation
+//! applies.
+//!
+//! Other targets, including AIX, use the panic-based mechanism on nightly too.
+//! [`BACKEND_NAME`] and [`IS_NATIVE_BACKEND`] tell which mechanism was selected at compile time.
+//!
+//! The mechanism can be chosen explicitly with one of the `force-backend-itanium`,
+//! `force-backend-seh`, `force-backend-emscripten`, and `force-backend-panic` features, e.g. to
+//! benchmark backends against each other or to work around a bug. If several of them are enabled,
+//! e.g. by different dependents, `force-backend-panic` takes precedence, as it works on every
+//! target with `std`, followed by `force-backend-itanium`, `force-backend-seh`, and
+//! `force-backend-emscripten`. The `LITHIUM_BACKEND` environment variable, set to the name of a
+//! backend, takes precedence over the features. Forcing a backend that doesn't support the target
+//! leads to build errors or undefined behavior.
+//!
+//! By default, [`throw`] is never inlined, as unwinding dominates its cost anyway, while
+//! [`intercept`] and the catch frame around it are always inlined, so that the happy path costs
+//! close to nothing. The `opt-size` feature drops the forced inlining of [`intercept`] and outlines
+//! the code reading out caught exceptions, so that it's shared between call sites, which suits
+//! embedded targets. The `opt-speed` feature allows [`throw`] to be inlined into its callers. If
+//! both are enabled, e.g. by different dependents, `opt-size` takes precedence.
+//!
 //! Lithium strives to support all targets that Rust panics support. If Lithium does not work
 //! correctly on such a target, please [open an issue](https://github.com/iex-rs/lithium/issues/).
 //!
@@ -49,6 +145,60 @@
 //! basis: [open an issue](https://github.com/iex-rs/lithium/issues/) if you would like to see
 //! support for a certain `std`-less target.
 //!
+//! Bare-metal targets (`target_os = "none"`, e.g. `thumbv7em-none-eabihf`) built with
+//! `panic = "unwind"` use the Itanium backend on nightly, and the firmware has to link an unwinder,
+//! such as the one from the [`unwinding`](https://crates.io/crates/unwinding) crate. As such
+//! targets have a single thread of execution, the exception stack is stored in a global instead of
+//! a thread local. Wasm with shared memory (the `atomics` target feature) is multithreaded even
+//! without an OS, so thread locals are required there instead.
+//!
+//! Throwing exceptions from interrupt handlers requires the `critical-section` feature, which
+//! performs each update of the exception stack inside
+//! [`critical_section::with`](https://docs.rs/critical-section). The firmware has to provide a
+//! critical section implementation, and interrupt handlers have to catch all exceptions they throw
+//! before returning.
+//!
+//! The same global storage can be forced on any target by building with the
+//! `LITHIUM_THREAD_LOCAL=static` environment variable set. This is useful for other environments
+//! without threads or thread locals. Setting the variable is an unsafe promise that Lithium is only
+//! ever used from one thread of execution: throwing or catching exceptions from several threads is
+//! UB, even if they don't overlap. Since only the final binary controls the environment it's built
+//! in, no dependency can make this promise on its behalf, unlike with a Cargo feature.
+//!
+//! Targets with multiple threads but without thread locals, such as RTOSes, can supply the
+//! per-thread state themselves; see [`tls`].
+//!
+//! Except on SEH, in-flight exceptions are stored in a per-thread buffer of 4096 bytes. Exceptions
+//! that don't fit are stored in heap-allocated segments, the first one twice as large as the buffer
+//! and each next one twice as large as the previous, so deep nesting only allocates a few times.
+//! The size of the buffer can be changed by setting the `LITHIUM_STACK_CAPACITY` environment
+//! variable to a number of bytes at build time: shrink it to save memory on embedded targets, or
+//! grow it to avoid allocations when throwing large types. The capacity must be either 0, which
+//! disables the buffer, or at least the size of an exception with a zero-sized cause, which depends
+//! on the target and the enabled features; smaller values fail to compile. In debug builds, each
+//! exception also takes 8 bytes of canary in the buffer. Exceptions larger than
+//! `LITHIUM_INLINE_THRESHOLD` bytes, equal to the capacity by default, are always allocated on the
+//! heap individually, so that large but rare exceptions don't take up the space reserved for common
+//! ones.
+//! Heap allocations of exceptions are cached per thread and reused by later throws;
+//! [`reset_thread_state`] releases the cached memory, and [`in_flight_count`] reports the number of
+//! exceptions on the stack.
+//!
+//! With the `allocator-api` feature enabled, which requires nightly, exceptions are allocated with
+//! the allocator registered by [`exception_allocator!`] instead of the global allocator.
+//!
+//! With the `LITHIUM_NO_HEAP` environment variable set to `1` at build time, exceptions are never
+//! allocated on the heap. Throwing a type larger than the inline threshold fails to compile, and if
+//! nested exceptions overflow the buffer at runtime, the process is aborted with a message instead.
+//! Saving the state of a fiber with `fiber::save` panics while exceptions are in flight, as it
+//! copies them to the heap. This is a build-time setting rather than a feature, so that a
+//! dependency can't take `fiber` away from other dependents of Lithium.
+//!
+//! With the `strict-provenance` feature enabled, Lithium never exposes pointer provenance, so it
+//! can be used in crates that check for strict provenance. By default, the panic backend exposes
+//! provenance when it detects Miri running with Stacked Borrows, which is incompatible with this
+//! feature; run Miri with `-Zmiri-tree-borrows` instead. On SEH, pointers stored in exception
+//! tables are converted to addresses without exposing them, as only the MSVC runtime reads them.
 //!
 //! # Safety
 //!
@@ -66,8 +216,8 @@
 //! struct B;
 //!
 //! unsafe {
-//!     let _ = catch::<_, A>(|| {
-//!         let _ = catch::<_, B>(|| throw(B));
+//!     let _ = catch::<(), A>(|| {
+//!         let _ = catch::<(), B>(|| throw(B));
 //!         throw(A);
 //!     });
 //! }
@@ -82,6 +232,20 @@
 //! throwing and the catching functions are provided by one crate. As long as the exception types
 //! used by the crate match, all safe user-supplied callbacks are sound to call, because safe
 //! callbacks can only interact with exceptions in an isolated manner.
+//!
+//! In debug builds, Lithium checks that each thrown exception has a Lithium catch frame to land in
+//! and panics if an exception is swallowed by a foreign frame, such as
+//! [`std::panic::catch_unwind`]. It also panics if the nearest catch frame expects a different
+//! exception type than the one thrown, naming both types, if an exception is thrown past a live
+//! [`InFlightException`], or if in-flight exceptions are released out of order. If a limit is set
+//! with the `LITHIUM_MAX_RETHROWS` environment variable at build time, or per thread with
+//! `test_util::set_max_rethrows`, an exception rethrown more times than that, which usually
+//! indicates a runaway `intercept`/`rethrow` loop, aborts the process with a diagnostic. Exceptions
+//! leaked with [`core::mem::forget`] are reported on stderr when their thread exits or their
+//! [`ExceptionStack`] is dropped; they are released then in all builds. These checks are
+//! best-effort and are disabled in release builds.
+//!
+//! [`throw`]: fn@throw
 
 #![no_std]
 #![cfg_attr(all(thread_local = "attribute"), feature(thread_local))]
@@ -97,6 +261,7 @@
     feature(core_intrinsics, rustc_attrs)
 )]
 #![cfg_attr(backend = "seh", feature(fn_ptr_trait, std_internals))]
+#![cfg_attr(feature = "allocator-api", feature(allocator_api))]
 #![cfg_attr(
     all(backend = "itanium", target_arch = "wasm32"),
     feature(wasm_exception_handling_intrinsics)
@@ -145,7 +310,6 @@
     clippy::std_instead_of_alloc,
     clippy::std_instead_of_core,
     clippy::string_lit_chars_any,
-    clippy::string_to_string,
     clippy::tests_outside_test_module,
     clippy::try_err,
     clippy::undocumented_unsafe_blocks,
@@ -162,17 +326,85 @@
 )]
 
 #[cfg(panic = "abort")]
-compile_error!("Using Lithium with panic = \"abort\" is unsupported");
+compile_error!(
+    "Using Lithium with panic = \"abort\" is unsupported, as exceptions require unwinding support"
+);
+
+#[cfg(all(feature = "strict-provenance", feature = "sound-under-stacked-borrows"))]
+compile_error!(
+    "The `strict-provenance` and `sound-under-stacked-borrows` features are mutually exclusive, as \
+     soundness under Stacked Borrows requires exposing provenance"
+);
 
-#[cfg(any(abort = "std", backend = "panic", thread_local = "std", test))]
+#[cfg(any(
+    abort = "std",
+    backend = "panic",
+    catch_unwind = "std",
+    thread_local = "std",
+    thread_dtor = "std",
+    thread_spawn = "std",
+    feature = "rayon",
+    test
+))]
 extern crate std;
 
 extern crate alloc;
 
+#[macro_use]
+mod macros;
+
+mod abi;
 mod api;
 mod backend;
+mod chain;
+#[cfg(feature = "checked")]
+mod checked;
+pub mod compat;
+mod debug;
+mod describe;
+mod emplace;
+mod erased;
+mod error;
+mod exception_stack;
+mod ext;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fiber;
+#[cfg(all(backend = "itanium", not(target_arch = "wasm32")))]
+mod foreign;
+#[cfg(feature = "async")]
+pub mod future;
+pub mod guard;
+mod hooks;
+mod in_place;
+mod location;
+mod message;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+mod remote;
+mod rethrows;
+mod scoped;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(thread_spawn = "std")]
+pub mod thread;
+mod thread_guard;
+mod throwing_fn;
+pub mod tls;
+#[cfg(any(feature = "tracing", feature = "log"))]
+mod trace;
+#[cfg(catch_unwind = "std")]
+mod unwind;
+mod unwinding;
 
-#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+#[cfg(feature = "heterogeneous-stack")]
+pub mod heterogeneous_stack;
+#[cfg(all(
+    not(feature = "heterogeneous-stack"),
+    any(backend = "itanium", backend = "emscripten", backend = "panic")
+))]
 mod heterogeneous_stack;
 #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
 mod stacked_exceptions;
@@ -180,15 +412,59 @@ mod stacked_exceptions;
 #[cfg(any(backend = "itanium", backend = "seh", backend = "emscripten"))]
 mod intrinsic;
 
-pub use api::{catch, intercept, throw, InFlightException};
+#[cfg(feature = "location")]
+pub use api::catch_located;
+pub use api::{
+    catch, catch2, catch3, catch_boxed, catch_if, catch_unsized, intercept, intercept_cf,
+    intercept_map, throw, throw_boxed, throw_unsized, Either, Either3, InFlightException,
+};
+pub use backend::{BACKEND_NAME, IS_NATIVE_BACKEND};
+pub use chain::{with_context, Chain};
+#[cfg(feature = "checked")]
+pub use checked::try_throw;
+pub use describe::throw_err;
+pub use emplace::{emplace, ExceptionSlot};
+pub use erased::catch_dyn;
+pub use error::Error;
+pub use exception_stack::ExceptionStack;
+pub use ext::ThrowExt;
+#[cfg(all(backend = "itanium", not(target_arch = "wasm32")))]
+pub use foreign::{catch_foreign, intercept_with_policy, ForeignException, ForeignPolicy};
+pub use in_place::{catch_ref, intercept_in_place, retry, InPlaceException};
+#[cfg(any(backend = "itanium", backend = "seh", backend = "emscripten"))]
+pub use intrinsic::catch_unwind_raw;
+pub use message::Message;
+pub use remote::RemoteException;
+pub use scoped::{catch_scoped, scope, Scope, ThrowToken};
+#[cfg(all(feature = "stats", target_has_atomic = "ptr"))]
+pub use stats::global_stats;
+#[cfg(feature = "stats")]
+pub use stats::{stack_stats, stats, StackStats, ThrowStats};
+pub use thread_guard::install_thread_guard;
+pub use throwing_fn::{ThrowingFn, ThrowingFnMut, ThrowingFnOnce};
+pub use tls::{in_flight_count, reset_thread_state};
+#[cfg(catch_unwind = "std")]
+pub use unwind::{bridge, catch_unwind_into, unbridge};
+pub use unwinding::is_unwinding;
+
+#[cfg(feature = "macros")]
+pub use lithium_macros::throws;
 
 /// Abort the process with a message.
 ///
 /// If `std` is available, this also outputs a message to stderr before aborting.
-#[cfg(any(backend = "itanium", backend = "seh", backend = "emscripten"))]
+#[cfg(any(
+    backend = "itanium",
+    backend = "seh",
+    backend = "emscripten",
+    backend = "panic"
+))]
 #[cold]
 #[inline(never)]
 fn abort(message: &str) -> ! {
+    #[cfg(any(feature = "tracing", feature = "log"))]
+    trace::abort(message, None);
+
     #[cfg(abort = "std")]
     {
         use std::io::Write;
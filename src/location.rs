@@ -0,0 +1,38 @@
+//! Locations of throw sites.
+//!
+//! With the `location` feature enabled, exceptions record the [`Location`] of the [`throw`] call
+//! that produced them. Otherwise, locations are zero-sized, and recording them compiles to nothing.
+//!
+//! [`throw`]: fn@crate::throw
+
+#[cfg(feature = "location")]
+use core::panic::Location;
+
+/// The location of a throw site, stored in exception headers.
+#[derive(Clone, Copy, Debug)]
+pub struct ThrowLocation {
+    #[cfg(feature = "location")]
+    location: &'static Location<'static>,
+}
+
+impl ThrowLocation {
+    /// Get the recorded location.
+    #[cfg(feature = "location")]
+    #[inline]
+    pub const fn get(self) -> &'static Location<'static> {
+        self.location
+    }
+}
+
+/// Get the location of the caller.
+///
+/// All functions between the user-facing throw function and this call must be marked with
+/// `#[cfg_attr(feature = "location", track_caller)]` for the location to be accurate.
+#[cfg_attr(feature = "location", track_caller)]
+#[inline(always)]
+pub const fn caller() -> ThrowLocation {
+    ThrowLocation {
+        #[cfg(feature = "location")]
+        location: Location::caller(),
+    }
+}
@@ -0,0 +1,482 @@
+/// Unwrap a [`Result`] or throw its error.
+///
+/// `tri!(expr)` evaluates `expr`, which must be a `Result<T, E>`. On `Ok(value)`, the macro
+/// evaluates to `value`. On `Err(error)`, `error` is thrown with [`throw`](fn@crate::throw). This
+/// is the Lithium analogue of the `?` operator.
+///
+/// `tri!(expr, E)` additionally converts the error to `E` with [`From`] before throwing, just like
+/// `?` does, and pins the thrown type so that it is not inferred by accident.
+///
+/// The macro expands to a call to an `unsafe` function, so it has to be used in an `unsafe`
+/// context. The safety requirements of [`throw`](fn@crate::throw) apply to the thrown type.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, tri};
+///
+/// /// Throws `ParseIntError`.
+/// unsafe fn parse_sum(a: &str, b: &str) -> i32 {
+///     tri!(a.parse::<i32>()) + tri!(b.parse::<i32>())
+/// }
+///
+/// // SAFETY: `parse_sum` only throws `ParseIntError`
+/// let res = unsafe { catch::<_, core::num::ParseIntError>(|| parse_sum("1", "2")) };
+/// assert_eq!(res, Ok(3));
+/// ```
+#[macro_export]
+macro_rules! tri {
+    ($expr:expr $(,)?) => {
+        match $expr {
+            ::core::result::Result::Ok(value) => value,
+            ::core::result::Result::Err(error) => $crate::throw(error),
+        }
+    };
+    ($expr:expr, $ty:ty $(,)?) => {
+        match $expr {
+            ::core::result::Result::Ok(value) => value,
+            ::core::result::Result::Err(error) => {
+                $crate::throw::<$ty>(::core::convert::From::from(error))
+            }
+        }
+    };
+}
+
+/// Throw an exception, optionally formatting a message.
+///
+/// `throw!("format string", args...)` formats the arguments, like [`format!`](alloc::format), into
+/// a [`Message`](crate::Message) and throws it. Messages without arguments don't allocate.
+///
+/// `throw!(expr)` throws the value of `expr` as-is, with the type inferred as with
+/// [`throw`](fn@crate::throw).
+///
+/// The macro expands to a call to an `unsafe` function, so it has to be used in an `unsafe`
+/// context. The safety requirements of [`throw`](fn@crate::throw) apply to the thrown type: in
+/// particular, messages must be caught as [`Message`](crate::Message).
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw, Message};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error {
+///     NotFound,
+/// }
+///
+/// /// Throws `Message`.
+/// unsafe fn open(path: &str) {
+///     throw!("failed to open {path}");
+/// }
+///
+/// /// Throws `Error`.
+/// unsafe fn find() {
+///     throw!(Error::NotFound);
+/// }
+///
+/// // SAFETY: `open` only throws `Message`, and `find` only throws `Error`.
+/// unsafe {
+///     let message = catch::<(), Message>(|| open("config.toml")).unwrap_err();
+///     assert_eq!(message.as_str(), "failed to open config.toml");
+///     assert_eq!(catch::<(), Error>(|| find()), Err(Error::NotFound));
+/// }
+/// ```
+#[macro_export]
+macro_rules! throw {
+    ($fmt:literal $(, $($arg:tt)*)?) => {
+        $crate::throw::<$crate::Message>($crate::Message::from_args(
+            ::core::format_args!($fmt $(, $($arg)*)?),
+        ))
+    };
+    ($cause:expr $(,)?) => {
+        $crate::throw($cause)
+    };
+}
+
+/// Throw an exception, pinning its type.
+///
+/// `bail!(expr, E)` converts `expr` to `E` with [`From`] and throws it, just like
+/// [`tri!(expr, E)`](crate::tri) does with errors. Since the thrown type is written out, it's not
+/// inferred by accident, which is easy to get wrong with a bare [`throw`](fn@crate::throw) call
+/// missing a turbofish.
+///
+/// `bail!(expr)` and `bail!("format string", args...)` are equivalent to [`throw!`](crate::throw!).
+/// A literal passed as the first argument is always interpreted as a format string.
+///
+/// The macro expands to a call to an `unsafe` function, so it has to be used in an `unsafe`
+/// context. The safety requirements of [`throw`](fn@crate::throw) apply to the thrown type.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{bail, catch, Error};
+///
+/// /// Throws `Error`.
+/// unsafe fn parse_percent(s: &str) -> u8 {
+///     match s.parse::<u8>() {
+///         Ok(value) if value <= 100 => value,
+///         Ok(_) => bail!(lithium::Message::new("out of range"), Error),
+///         Err(error) => bail!(error, Error),
+///     }
+/// }
+///
+/// // SAFETY: `parse_percent` only throws `Error`.
+/// let error = unsafe { catch::<_, Error>(|| parse_percent("200")) }.unwrap_err();
+/// assert_eq!(error.to_string(), "out of range");
+/// ```
+#[macro_export]
+macro_rules! bail {
+    ($fmt:literal $(, $($arg:tt)*)?) => {
+        $crate::throw!($fmt $(, $($arg)*)?)
+    };
+    ($cause:expr, $ty:ty $(,)?) => {
+        $crate::throw::<$ty>(::core::convert::From::from($cause))
+    };
+    ($cause:expr $(,)?) => {
+        $crate::throw($cause)
+    };
+}
+
+/// Throw an exception if a condition doesn't hold.
+///
+/// `ensure!(cond, ...)` evaluates `cond`, and if it's `false`, expands to
+/// [`bail!(...)`](crate::bail) with the remaining arguments: an exception and, optionally, its
+/// type, or a format string with arguments.
+///
+/// `ensure!(cond)` throws a [`Message`](crate::Message) containing the condition, without
+/// allocating.
+///
+/// The macro expands to a call to an `unsafe` function, so it has to be used in an `unsafe`
+/// context. The safety requirements of [`throw`](fn@crate::throw) apply to the thrown type.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, ensure, Message};
+///
+/// /// Throws `Message`.
+/// unsafe fn check_len(buf: &[u8]) {
+///     ensure!(buf.len() >= 4, "buffer too short: {} bytes", buf.len());
+/// }
+///
+/// // SAFETY: `check_len` only throws `Message`.
+/// let error = unsafe { catch::<_, Message>(|| check_len(&[1, 2])) }.unwrap_err();
+/// assert_eq!(error.as_str(), "buffer too short: 2 bytes");
+/// ```
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::throw::<$crate::Message>($crate::Message::new(::core::concat!(
+                "condition failed: `",
+                ::core::stringify!($cond),
+                "`",
+            )))
+        }
+    };
+    ($cond:expr, $($rest:tt)+) => {
+        if !$cond {
+            $crate::bail!($($rest)+)
+        }
+    };
+}
+
+/// Assert that an expression throws an exception of a given type.
+///
+/// `assert_throws!(E, expr)` evaluates `expr` inside [`catch::<_, E>`](crate::catch). If `expr`
+/// throws, the macro evaluates to the caught exception. If `expr` returns normally, the macro
+/// panics, failing the test, and the returned value is discarded.
+///
+/// A custom panic message can be passed after `expr`, with the same syntax as in [`assert!`].
+///
+/// The macro expands to a call to an `unsafe` function, so it has to be used in an `unsafe`
+/// context. The safety requirements of [`catch`](crate::catch) apply: `expr` must only throw `E`.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{assert_throws, throw};
+///
+/// // SAFETY: Only `i32` is thrown.
+/// let error = unsafe { assert_throws!(i32, throw(1)) };
+/// assert_eq!(error, 1);
+/// ```
+#[macro_export]
+macro_rules! assert_throws {
+    ($ty:ty, $expr:expr $(,)?) => {
+        match $crate::catch::<(), $ty>(|| {
+            // The block keeps the attribute from applying to a macro invocation in `$expr`.
+            #[allow(unused_must_use, reason = "The value is discarded")]
+            { $expr };
+        }) {
+            ::core::result::Result::Ok(()) => ::core::panic!(
+                "expected `{}` to throw an exception of type `{}`, but it returned",
+                ::core::stringify!($expr),
+                ::core::any::type_name::<$ty>(),
+            ),
+            ::core::result::Result::Err(error) => error,
+        }
+    };
+    ($ty:ty, $expr:expr, $($arg:tt)+) => {
+        match $crate::catch::<(), $ty>(|| {
+            // The block keeps the attribute from applying to a macro invocation in `$expr`.
+            #[allow(unused_must_use, reason = "The value is discarded")]
+            { $expr };
+        }) {
+            ::core::result::Result::Ok(()) => ::core::panic!($($arg)+),
+            ::core::result::Result::Err(error) => error,
+        }
+    };
+}
+
+/// Assert that an expression throws an exception matching a pattern.
+///
+/// `assert_throws_matches!(expr, pattern)` evaluates `expr` inside [`catch`](crate::catch), with
+/// the exception type inferred from `pattern`. If `expr` returns normally, or throws an exception
+/// that doesn't match `pattern`, the macro panics, failing the test. The exception must implement
+/// [`Debug`](core::fmt::Debug), so that mismatches can be reported.
+///
+/// As in [`matches!`], the pattern can be followed by an `if` guard.
+///
+/// The macro expands to a call to an `unsafe` function, so it has to be used in an `unsafe`
+/// context. The safety requirements of [`catch`](crate::catch) apply: `expr` must only throw
+/// exceptions of the type of `pattern`.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{assert_throws_matches, throw};
+///
+/// #[derive(Debug)]
+/// enum Error {
+///     NotFound(u32),
+///     Denied,
+/// }
+///
+/// // SAFETY: Only `Error` is thrown.
+/// unsafe {
+///     assert_throws_matches!(throw(Error::NotFound(404)), Error::NotFound(code) if code >= 400);
+/// }
+/// ```
+#[macro_export]
+macro_rules! assert_throws_matches {
+    ($expr:expr, $pat:pat $(if $guard:expr)? $(,)?) => {
+        match $crate::catch::<(), _>(|| {
+            // The block keeps the attribute from applying to a macro invocation in `$expr`.
+            #[allow(unused_must_use, reason = "The value is discarded")]
+            { $expr };
+        }) {
+            ::core::result::Result::Ok(()) => ::core::panic!(
+                "expected `{}` to throw an exception matching `{}`, but it returned",
+                ::core::stringify!($expr),
+                ::core::stringify!($pat $(if $guard)?),
+            ),
+            ::core::result::Result::Err(error) => match error {
+                $pat $(if $guard)? => {}
+                ref error => ::core::panic!(
+                    "expected `{}` to throw an exception matching `{}`, but it threw {:?}",
+                    ::core::stringify!($expr),
+                    ::core::stringify!($pat $(if $guard)?),
+                    error,
+                ),
+            },
+        }
+    };
+}
+
+/// Register the allocator for exceptions that don't fit on the exception stack.
+///
+/// Exceptions too large for the statically allocated part of the exception stack are allocated on
+/// the heap. By default, the global allocator is used. With the `allocator-api` feature enabled,
+/// which requires nightly, the allocator is taken from a `static` implementing
+/// [`Allocator`](core::alloc::Allocator) and [`Sync`] instead, e.g. an arena or a pool, so that
+/// throwing large exceptions never calls `malloc`.
+///
+/// The allocator must be registered exactly once in the final binary when the feature is enabled.
+/// Memory is returned to the allocator when exceptions are released, or, for memory cached for
+/// reuse, when the thread exits.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #![feature(allocator_api)]
+///
+/// static POOL: my_pool::Pool = my_pool::Pool::new();
+///
+/// lithium::exception_allocator!(POOL);
+/// ```
+#[macro_export]
+macro_rules! exception_allocator {
+    ($alloc:path) => {
+        #[unsafe(no_mangle)]
+        fn __lithium_exception_allocator() -> &'static dyn ::core::alloc::Allocator {
+            &$alloc
+        }
+    };
+}
+
+/// Define an `extern "C-unwind"` function that lets exceptions through as Rust panics.
+///
+/// Lithium exceptions must not unwind across `extern "C-unwind"` boundaries. The function defined
+/// by this macro runs its body with [`bridge`](crate::bridge), so that exceptions of type `E`
+/// thrown by the body leave the function as Rust panics, which can cross the boundary. Wrap calls
+/// to the function in [`unbridge`](crate::unbridge) on the other side to convert the panics back
+/// into exceptions.
+///
+/// The first line of the invocation, `throws E;`, names the exception type. The body must only
+/// throw exceptions of this type: like the callback of [`catch`](crate::catch), this can't be
+/// checked, and by invoking the macro you assert that it holds. The body is not an `unsafe`
+/// context, so throwing still requires an `unsafe` block.
+///
+/// This macro requires `std`.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{bridge_unwind, throw};
+///
+/// bridge_unwind! {
+///     throws String;
+///     /// Parse an integer, throwing `String` on failure.
+///     #[unsafe(no_mangle)]
+///     pub extern "C-unwind" fn parse_digit(c: u8) -> u32 {
+///         match char::from(c).to_digit(10) {
+///             Some(digit) => digit,
+///             // SAFETY: `bridge_unwind!` catches `String`.
+///             None => unsafe { throw(format!("{c} is not a digit")) },
+///         }
+///     }
+/// }
+///
+/// assert_eq!(parse_digit(b'7'), 7);
+/// ```
+#[macro_export]
+macro_rules! bridge_unwind {
+    (
+        throws $err:ty;
+        $(#[$attr:meta])*
+        $vis:vis extern "C-unwind" fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?
+        $body:block
+    ) => {
+        $(#[$attr])*
+        $vis extern "C-unwind" fn $name($($arg: $ty),*) $(-> $ret)? {
+            // The closure is built outside of the `unsafe` block, so that the body isn't an
+            // `unsafe` context.
+            let body = move || $body;
+            // SAFETY: The invocation asserts that the body only throws `$err`.
+            unsafe { $crate::bridge::<_, $err>(body) }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{catch, throw};
+    use alloc::string::String;
+
+    #[test]
+    fn tri_ok() {
+        let result: Result<i32, String> = unsafe { catch(|| tri!(Ok::<i32, String>(1))) };
+        assert_eq!(result, Ok(1));
+    }
+
+    #[test]
+    fn tri_err() {
+        let result: Result<i32, String> =
+            unsafe { catch(|| tri!(Err::<i32, String>(String::from("Oops!")))) };
+        assert_eq!(result.unwrap_err(), "Oops!");
+    }
+
+    #[test]
+    fn tri_convert() {
+        let result: Result<i32, String> =
+            unsafe { catch(|| tri!(Err::<i32, &str>("Oops!"), String)) };
+        assert_eq!(result.unwrap_err(), "Oops!");
+    }
+
+    #[test]
+    fn throw_message() {
+        let path = "a.txt";
+        let message = unsafe { assert_throws!(crate::Message, throw!("cannot open {path}")) };
+        assert_eq!(message.as_str(), "cannot open a.txt");
+        let message = unsafe { assert_throws!(crate::Message, throw!("{} + {}", 1, 2,)) };
+        assert_eq!(message.as_str(), "1 + 2");
+    }
+
+    #[test]
+    fn throw_typed() {
+        let error = unsafe { assert_throws!(Code, throw!(Code(1))) };
+        assert_eq!(error.0, 1);
+    }
+
+    #[test]
+    fn bail_typed() {
+        let code = 1u8;
+        let error = unsafe { assert_throws!(i32, bail!(code, i32)) };
+        assert_eq!(error, 1);
+        let error = unsafe { assert_throws!(Code, bail!(Code(1))) };
+        assert_eq!(error.0, 1);
+    }
+
+    #[test]
+    fn bail_message() {
+        let message = unsafe { assert_throws!(crate::Message, bail!("error {}", 1)) };
+        assert_eq!(message.as_str(), "error 1");
+    }
+
+    #[test]
+    fn ensure() {
+        let mut x = 1i32;
+        unsafe {
+            ensure!(x > 0);
+        }
+        x = -1i32;
+        let message = unsafe { assert_throws!(crate::Message, ensure!(x > 0)) };
+        assert_eq!(message.as_str(), "condition failed: `x > 0`");
+        let message = unsafe { assert_throws!(crate::Message, ensure!(x > 0, "bad {x}")) };
+        assert_eq!(message.as_str(), "bad -1");
+        let error = unsafe { assert_throws!(Code, ensure!(x > 0, Code(x))) };
+        assert_eq!(error.0, -1);
+    }
+
+    #[test]
+    fn assert_throws() {
+        let error = unsafe { assert_throws!(String, throw(String::from("Oops!"))) };
+        assert_eq!(error, "Oops!");
+    }
+
+    #[test]
+    #[should_panic = "expected `i32::checked_add(1, 2)` to throw an exception of type `i32`, but it returned"]
+    fn assert_throws_returned() {
+        unsafe {
+            assert_throws!(i32, i32::checked_add(1, 2));
+        }
+    }
+
+    #[test]
+    #[should_panic = "custom 2"]
+    fn assert_throws_custom_message() {
+        unsafe {
+            assert_throws!(i32, i32::checked_add(1, 2), "custom {}", 2);
+        }
+    }
+
+    #[derive(Debug)]
+    struct Code(i32);
+
+    #[test]
+    fn assert_throws_matches() {
+        unsafe {
+            assert_throws_matches!(throw(Some(1)), Some(1));
+            assert_throws_matches!(throw(Code(2)), Code(x) if x > 1);
+        }
+    }
+
+    #[test]
+    #[should_panic = "expected `throw(Some(2))` to throw an exception matching `Some(1)`, but it threw Some(2)"]
+    fn assert_throws_matches_mismatch() {
+        unsafe {
+            assert_throws_matches!(throw(Some(2)), Some(1));
+        }
+    }
+}
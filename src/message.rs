@@ -0,0 +1,88 @@
+use alloc::{borrow::Cow, string::String};
+use core::fmt;
+
+/// A lightweight error carrying a message.
+///
+/// This is the exception type thrown by the format-string form of [`throw!`](crate::throw!).
+/// Messages without formatting arguments are stored as `&'static str` and don't allocate; others
+/// are formatted into a [`String`].
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw, Message};
+///
+/// let path = "config.toml";
+/// // SAFETY: `throw!` with a format string throws `Message`.
+/// let result = unsafe { catch::<(), Message>(|| throw!("failed to open {path}")) };
+/// assert_eq!(result.unwrap_err().as_str(), "failed to open config.toml");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Message(Cow<'static, str>);
+
+impl Message {
+    /// Create a message from a static string, without allocating.
+    #[inline]
+    #[must_use]
+    pub const fn new(message: &'static str) -> Self {
+        Self(Cow::Borrowed(message))
+    }
+
+    /// Create a message from format arguments, allocating only if there are arguments to format.
+    #[inline]
+    #[must_use]
+    pub fn from_args(args: fmt::Arguments<'_>) -> Self {
+        match args.as_str() {
+            Some(message) => Self::new(message),
+            None => Self(Cow::Owned(alloc::fmt::format(args))),
+        }
+    }
+
+    /// Get the message.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&'static str> for Message {
+    #[inline]
+    fn from(message: &'static str) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<String> for Message {
+    #[inline]
+    fn from(message: String) -> Self {
+        Self(Cow::Owned(message))
+    }
+}
+
+impl fmt::Display for Message {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl core::error::Error for Message {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn static_not_allocated() {
+        let message = Message::from_args(format_args!("Oops!"));
+        assert!(matches!(message.0, Cow::Borrowed("Oops!")));
+    }
+
+    #[test]
+    fn formatted() {
+        let code = 404;
+        let message = Message::from_args(format_args!("error {code}"));
+        assert_eq!(message.as_str(), "error 404");
+    }
+}
@@ -0,0 +1,137 @@
+//! Integration with [`rayon`](::rayon).
+//!
+//! Rayon runs closures on worker threads and propagates panics with `catch_unwind`, so letting a
+//! Lithium exception unwind out of a parallel closure is UB. The combinators in this module install
+//! a [`catch`] around each invocation of the closure, short-circuit the iteration on the first
+//! exception, and return it to the caller.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::{rayon::ThrowingParallelIterator, throw};
+//! use rayon::prelude::*;
+//!
+//! // SAFETY: the callback only throws `i32`
+//! let res = unsafe {
+//!     (0..100).into_par_iter().par_try_for_each_throwing::<i32>(|x| {
+//!         if x == 42 {
+//!             throw::<i32>(x);
+//!         }
+//!     })
+//! };
+//! assert_eq!(res, Err(42));
+//! ```
+
+use super::api::catch;
+use ::rayon::iter::{FromParallelIterator, ParallelIterator};
+
+/// Exception-aware combinators for parallel iterators.
+///
+/// This trait is implemented for all [`ParallelIterator`]s.
+pub trait ThrowingParallelIterator: ParallelIterator {
+    /// Invoke `op` on each item in parallel, catching exceptions.
+    ///
+    /// If `op` throws, the iteration stops as soon as possible and the exception is returned in
+    /// [`Err`]. If multiple invocations throw, one of the exceptions is returned and the others are
+    /// dropped.
+    ///
+    /// Rust panics are propagated as usual.
+    ///
+    /// # Safety
+    ///
+    /// `op` must only throw exceptions of type `E`. See the safety section of [this crate](crate)
+    /// for more information.
+    #[expect(
+        clippy::missing_errors_doc,
+        reason = "`Err` value is described immediately"
+    )]
+    unsafe fn par_try_for_each_throwing<E: Send>(
+        self,
+        op: impl Fn(Self::Item) + Sync + Send,
+    ) -> Result<(), E>;
+
+    /// Map each item with `op` in parallel and collect the results, catching exceptions.
+    ///
+    /// If `op` throws, the iteration stops as soon as possible and the exception is returned in
+    /// [`Err`]. If multiple invocations throw, one of the exceptions is returned and the others are
+    /// dropped.
+    ///
+    /// Rust panics are propagated as usual.
+    ///
+    /// # Safety
+    ///
+    /// `op` must only throw exceptions of type `E`. See the safety section of [this crate](crate)
+    /// for more information.
+    #[expect(
+        clippy::missing_errors_doc,
+        reason = "`Err` value is described immediately"
+    )]
+    unsafe fn par_map_collect_throwing<R: Send, C: FromParallelIterator<R>, E: Send>(
+        self,
+        op: impl Fn(Self::Item) -> R + Sync + Send,
+    ) -> Result<C, E>;
+}
+
+impl<I: ParallelIterator> ThrowingParallelIterator for I {
+    #[inline]
+    unsafe fn par_try_for_each_throwing<E: Send>(
+        self,
+        op: impl Fn(Self::Item) + Sync + Send,
+    ) -> Result<(), E> {
+        // SAFETY: `op` only throws `E` by the safety requirement.
+        self.try_for_each(|item| unsafe { catch(|| op(item)) })
+    }
+
+    #[inline]
+    unsafe fn par_map_collect_throwing<R: Send, C: FromParallelIterator<R>, E: Send>(
+        self,
+        op: impl Fn(Self::Item) -> R + Sync + Send,
+    ) -> Result<C, E> {
+        // SAFETY: `op` only throws `E` by the safety requirement.
+        self.map(|item| unsafe { catch(|| op(item)) }).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::throw;
+    use ::rayon::prelude::*;
+    use alloc::{string::String, vec::Vec};
+
+    #[test]
+    fn for_each_ok() {
+        let result: Result<(), String> =
+            unsafe { (0..1000).into_par_iter().par_try_for_each_throwing(|_| {}) };
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn for_each_throws() {
+        let result: Result<(), String> = unsafe {
+            (0..1000).into_par_iter().par_try_for_each_throwing(|x| {
+                if x == 500 {
+                    throw(alloc::format!("{x}"));
+                }
+            })
+        };
+        assert_eq!(result.unwrap_err(), "500");
+    }
+
+    #[test]
+    fn map_collect() {
+        let result: Result<Vec<i32>, String> =
+            unsafe { (0..5).into_par_iter().par_map_collect_throwing(|x| x * 2) };
+        assert_eq!(result.unwrap(), [0, 2, 4, 6, 8]);
+
+        let result: Result<Vec<i32>, String> = unsafe {
+            (0..5).into_par_iter().par_map_collect_throwing(|x| {
+                if x == 3 {
+                    throw(String::from("three"));
+                }
+                x
+            })
+        };
+        assert_eq!(result.unwrap_err(), "three");
+    }
+}
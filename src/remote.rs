@@ -0,0 +1,148 @@
+//! Moving caught exceptions between threads.
+//!
+//! Exceptions live on the exception stack of the thread that threw them, so they can't be rethrown
+//! on another thread as is. [`RemoteException`] keeps only the cause, and rethrowing it is a fresh
+//! [`throw`](fn@throw) on the target thread. The original throw location is thus lost, and the
+//! rethrow count tracked in debug builds starts over.
+
+use super::api::{intercept, throw, InFlightException};
+
+/// An exception detached from the thread it was thrown on.
+///
+/// Caught exceptions are tied to the exception stack of the catching thread until handling
+/// finishes. [`RemoteException`] finishes handling eagerly and keeps only the cause, so it can be
+/// moved to another thread and rethrown there with [`RemoteException::rethrow`], which pushes a
+/// fresh exception onto that thread's stack.
+///
+/// This is useful for worker pools, which need to forward errors to the thread that submitted the
+/// work without converting them to [`Result`] and back. [`RemoteException`] is [`Send`] if `E` is,
+/// and detaching a caught exception requires `E: Send`.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, throw, RemoteException};
+///
+/// let remote = std::thread::spawn(|| {
+///     // SAFETY: the exception type matches
+///     unsafe { RemoteException::<&'static str>::catch(|| throw::<&'static str>("Oops!")) }
+/// })
+/// .join()
+/// .unwrap();
+///
+/// // SAFETY: the exception type matches
+/// let res = unsafe { catch::<(), &'static str>(|| remote.unwrap_err().rethrow()) };
+/// assert_eq!(res, Err("Oops!"));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RemoteException<E> {
+    cause: E,
+}
+
+impl<E> RemoteException<E> {
+    /// Wrap a cause into a remote exception.
+    #[inline]
+    pub const fn new(cause: E) -> Self {
+        Self { cause }
+    }
+
+    /// Get a reference to the cause.
+    #[inline]
+    pub const fn cause(&self) -> &E {
+        &self.cause
+    }
+
+    /// Extract the cause.
+    #[inline]
+    pub fn into_cause(self) -> E {
+        self.cause
+    }
+
+    /// Rethrow the exception on the current thread.
+    ///
+    /// This throws the cause afresh, so the original throw location and rethrow count are lost.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of [`throw`](fn@throw) for type `E` apply.
+    #[inline]
+    pub unsafe fn rethrow(self) -> ! {
+        // SAFETY: Requirements forwarded.
+        unsafe { throw(self.cause) }
+    }
+}
+
+impl<E: Send> RemoteException<E> {
+    /// Detach an exception returned by [`intercept`].
+    ///
+    /// This finishes handling of the exception on the current thread.
+    #[inline]
+    pub fn from_in_flight(cause: E, in_flight: InFlightException<E>) -> Self {
+        drop(in_flight);
+        Self { cause }
+    }
+
+    /// Catch an exception, detaching it from the current thread.
+    ///
+    /// This is equivalent to [`catch`](crate::catch), but wraps the error in a
+    /// [`RemoteException`].
+    ///
+    /// # Safety
+    ///
+    /// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate)
+    /// for more information.
+    #[expect(
+        clippy::missing_errors_doc,
+        reason = "`Err` value is described immediately"
+    )]
+    #[inline]
+    pub unsafe fn catch<R>(func: impl FnOnce() -> R) -> Result<R, Self> {
+        // SAFETY: `func` only throws `E` by the safety requirement, and the in-flight exception is
+        // dropped immediately.
+        unsafe { intercept(func) }
+            .map_err(|(cause, in_flight)| Self::from_in_flight(cause, in_flight))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catch;
+    use alloc::string::String;
+
+    #[test]
+    fn cross_thread() {
+        let remote = std::thread::spawn(|| unsafe {
+            RemoteException::<String>::catch(|| throw::<String>(String::from("Oops!")))
+        })
+        .join()
+        .unwrap()
+        .unwrap_err();
+        assert_eq!(remote.cause(), "Oops!");
+        let result: Result<(), String> = unsafe { catch(|| remote.rethrow()) };
+        assert_eq!(result.unwrap_err(), "Oops!");
+    }
+
+    #[test]
+    fn catch_ok() {
+        let result = unsafe { RemoteException::<String>::catch(|| 123) };
+        assert_eq!(result, Ok(123));
+    }
+
+    #[test]
+    fn from_in_flight() {
+        let remote = match unsafe { intercept::<(), String>(|| throw(String::from("Oops!"))) } {
+            Ok(()) => unreachable!(),
+            Err((cause, in_flight)) => RemoteException::from_in_flight(cause, in_flight),
+        };
+        let result: Result<(), String> = std::thread::spawn(move || unsafe {
+            catch(|| {
+                let _: Result<(), i32> = catch(|| throw(1));
+                remote.rethrow();
+            })
+        })
+        .join()
+        .unwrap();
+        assert_eq!(result.unwrap_err(), "Oops!");
+    }
+}
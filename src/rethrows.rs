@@ -0,0 +1,251 @@
+//! Debug-mode detection of runaway rethrow chains.
+//!
+//! Each in-flight handle remembers how many times its exception has been rethrown, and the count is
+//! carried over to the next handle when the rethrown exception is intercepted again. If a limit is
+//! set with `LITHIUM_MAX_RETHROWS` at build time or with `test_util::set_max_rethrows`, a longer
+//! chain is treated as a runaway `intercept`/`rethrow` loop, which would otherwise unwind forever
+//! without a trace, and the process is aborted with a diagnostic. There is no limit by default, as
+//! deep recursion rethrows legitimately.
+//!
+//! The count of the exception currently unwinding is saved by catch frames, so that exceptions
+//! thrown and caught by destructors during unwinding don't affect it.
+//!
+//! Like the checks in `debug`, the limit is only enforced in builds with the `checks` cfg.
+
+use super::tls::{get_state, without_interrupts};
+#[cfg(checks)]
+use alloc::format;
+use core::cell::Cell;
+
+/// Per-thread rethrow counts.
+pub struct RethrowCounts {
+    /// The number of times the exception currently unwinding has been rethrown.
+    unwinding: Cell<usize>,
+    /// The rethrow count of the exception most recently caught, picked up by in-flight handles.
+    caught: Cell<usize>,
+    /// The rethrow count to assign to the next thrown exception, set by rethrows.
+    next: Cell<usize>,
+    /// The maximum number of times an exception can be rethrown, if limited.
+    #[cfg(checks)]
+    max: Cell<Option<usize>>,
+}
+
+impl RethrowCounts {
+    pub const fn new() -> Self {
+        Self {
+            unwinding: Cell::new(0),
+            caught: Cell::new(0),
+            next: Cell::new(0),
+            #[cfg(checks)]
+            max: Cell::new(MAX_RETHROWS),
+        }
+    }
+
+    /// Move the count of the unwinding exception out, leaving it zero.
+    pub const fn save(&self) -> usize {
+        self.unwinding.replace(0)
+    }
+
+    /// Put the count moved out by [`RethrowCounts::save`] back.
+    pub fn restore(&self, saved: usize) {
+        self.unwinding.set(saved);
+    }
+
+    /// Exchange the count of the unwinding exception with another set of counts.
+    pub fn swap(&self, other: &Self) {
+        self.unwinding.swap(&other.unwinding);
+    }
+}
+
+#[inline]
+fn with_counts<T>(f: impl FnOnce(&RethrowCounts) -> T) -> T {
+    // SAFETY: The reference is not used past the call frame.
+    without_interrupts(|| f(unsafe { &get_state().rethrows }))
+}
+
+/// Get the count of the exception currently unwinding, to be saved by a catch frame on entry.
+#[inline]
+pub fn enter_frame() -> usize {
+    if !cfg!(checks) {
+        return 0;
+    }
+    with_counts(|counts| counts.unwinding.get())
+}
+
+/// Register an exception caught by a catch frame that saved `on_entry` when it was entered.
+#[inline]
+pub fn on_catch(on_entry: usize) {
+    if cfg!(checks) {
+        with_counts(|counts| counts.caught.set(counts.unwinding.replace(on_entry)));
+    }
+}
+
+/// Register a thrown exception, picking up the count set by [`Marker::rethrow`], if any.
+#[inline]
+pub fn on_throw() {
+    if cfg!(checks) {
+        with_counts(|counts| counts.unwinding.set(counts.next.replace(0)));
+    }
+}
+
+/// The rethrow count of a live in-flight exception handle.
+///
+/// Zero-sized in release builds.
+pub struct Marker {
+    #[cfg(checks)]
+    count: usize,
+}
+
+#[cfg(checks)]
+impl Marker {
+    /// Pick up the count of the exception just caught.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            count: with_counts(|counts| counts.caught.get()),
+        }
+    }
+
+    /// Count a rethrow of the exception.
+    ///
+    /// Call this right before [`on_throw`]. Aborts the process if the exception has been rethrown
+    /// more times than the limit, if any.
+    #[cfg_attr(feature = "location", track_caller)]
+    #[inline]
+    pub fn rethrow(self) {
+        let count = self.count.wrapping_add(1);
+        let max = with_counts(|counts| {
+            counts.next.set(count);
+            counts.max.get()
+        });
+        if let Some(max) = max.filter(|&max| count > max) {
+            abort_runaway_rethrow(max);
+        }
+    }
+}
+
+#[cfg(not(checks))]
+impl Marker {
+    /// Pick up the count of the exception just caught.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    /// Count a rethrow of the exception.
+    #[expect(
+        clippy::unused_self,
+        reason = "Consumes the marker like in debug builds"
+    )]
+    #[inline]
+    pub const fn rethrow(self) {}
+}
+
+/// The default maximum number of times an exception can be rethrown in debug builds.
+///
+/// Configured with the `LITHIUM_MAX_RETHROWS` environment variable at build time. Unlimited if the
+/// variable is unset or 0.
+#[cfg(checks)]
+const MAX_RETHROWS: Option<usize> = match usize::from_str_radix(env!("LITHIUM_MAX_RETHROWS"), 10) {
+    Ok(0) => None,
+    Ok(max) => Some(max),
+    Err(_) => panic!("LITHIUM_MAX_RETHROWS must be a number"),
+};
+
+/// Override the maximum number of times an exception can be rethrown on the current thread.
+///
+/// `None` removes the limit. Does nothing in builds without checks.
+#[cfg(any(all(test, checks), feature = "test-util"))]
+#[cfg_attr(not(checks), expect(clippy::missing_const_for_fn, reason = "no-op"))]
+pub fn set_max_rethrows(max: Option<usize>) {
+    #[cfg(checks)]
+    with_counts(|counts| counts.max.set(max));
+    #[cfg(not(checks))]
+    let _ = max;
+}
+
+#[cfg(checks)]
+#[cold]
+#[inline(never)]
+#[cfg_attr(feature = "location", track_caller)]
+fn abort_runaway_rethrow(max: usize) -> ! {
+    #[cfg(feature = "location")]
+    let at = format!(" at {}", core::panic::Location::caller());
+    #[cfg(not(feature = "location"))]
+    let at = "";
+    crate::abort(&format!(
+        "Lithium exception rethrown more than {max} times{at}. This is likely a runaway \
+         `intercept`/`rethrow` loop. The limit is set with the `LITHIUM_MAX_RETHROWS` environment \
+         variable at build time or with `test_util::set_max_rethrows`. The process will now \
+         terminate.\n",
+    ))
+}
+
+#[cfg(all(test, checks))]
+mod test {
+    use super::set_max_rethrows;
+    use crate::{catch, intercept, throw};
+
+    const MAX_RETHROWS: usize = 100;
+
+    /// Rethrow an exception `n` times.
+    fn rethrow_chain(n: usize) {
+        let result = unsafe {
+            catch::<(), usize>(|| {
+                let (mut cause, mut ex) = intercept::<(), usize>(|| throw(0usize)).unwrap_err();
+                while cause < n - 1 {
+                    (cause, ex) = intercept::<(), usize>(|| ex.rethrow(cause + 1)).unwrap_err();
+                }
+                ex.rethrow(cause + 1);
+            })
+        };
+        assert_eq!(result, Err(n));
+    }
+
+    #[test]
+    fn long_rethrow_chain() {
+        set_max_rethrows(Some(MAX_RETHROWS));
+        rethrow_chain(MAX_RETHROWS);
+    }
+
+    #[test]
+    fn unlimited_rethrow_chain() {
+        set_max_rethrows(None);
+        rethrow_chain(MAX_RETHROWS * 100);
+    }
+
+    #[test]
+    fn rethrow_count_reset() {
+        struct ThrowOnDrop;
+        impl Drop for ThrowOnDrop {
+            fn drop(&mut self) {
+                let result = unsafe { catch::<(), i32>(|| throw(1)) };
+                assert_eq!(result, Err(1));
+            }
+        }
+
+        set_max_rethrows(Some(MAX_RETHROWS));
+
+        // Counts are not carried over to unrelated exceptions.
+        for _ in 0..2 {
+            rethrow_chain(MAX_RETHROWS);
+        }
+
+        // A fresh exception thrown by a destructor during unwinding doesn't affect the count of the
+        // unwinding exception.
+        let result = unsafe {
+            catch::<(), usize>(|| {
+                let (mut cause, mut ex) = intercept::<(), usize>(|| throw(0usize)).unwrap_err();
+                while cause < MAX_RETHROWS - 1 {
+                    (cause, ex) = intercept::<(), usize>(|| {
+                        let _guard = ThrowOnDrop;
+                        ex.rethrow(cause + 1)
+                    })
+                    .unwrap_err();
+                }
+                ex.rethrow(cause + 1);
+            })
+        };
+        assert_eq!(result, Err(MAX_RETHROWS));
+    }
+}
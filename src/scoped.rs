@@ -0,0 +1,269 @@
+use super::{
+    api::{in_catch_frame, throw},
+    backend::{ActiveBackend, CaughtException, FromCaught, ThrowByValue},
+    debug::Expected,
+    describe::abort_caught,
+};
+use core::any::TypeId;
+use core::marker::PhantomData;
+
+/// A capability to throw exceptions of type `E`.
+///
+/// Tokens are handed out by [`catch_scoped`] and prove that a matching catch frame encloses the
+/// call, which makes [`ThrowToken::throw`] safe. A token is only valid within the callback it was
+/// passed to: it cannot escape the callback, nor can it be sent to another thread.
+///
+/// `'scope` is invariant, so the token can't be coerced to a longer lifetime and smuggled out:
+///
+/// ```compile_fail
+/// use lithium::{catch_scoped, ThrowToken};
+/// use std::cell::Cell;
+///
+/// let escaped: Cell<Option<ThrowToken<'static, i32>>> = Cell::new(None);
+/// let _ = catch_scoped(|token| escaped.set(Some(token)));
+/// escaped.get().unwrap().throw(5);
+/// ```
+pub struct ThrowToken<'scope, E> {
+    scope: PhantomData<fn(&'scope ()) -> &'scope ()>,
+    cause: PhantomData<fn() -> E>,
+    not_send: PhantomData<*mut ()>,
+}
+
+impl<E> Clone for ThrowToken<'_, E> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for ThrowToken<'_, E> {}
+
+impl<E: 'static> ThrowToken<'_, E> {
+    /// Throw an exception, to be caught by the [`catch_scoped`] that created this token.
+    #[inline]
+    pub fn throw(self, cause: E) -> ! {
+        // SAFETY: The token can only exist inside the callback of `catch_scoped`, which catches `E`
+        // on the same thread. Exceptions crossing enclosed `catch_scoped` frames of other types
+        // are caught as `E` by type ID, see `Scoped`.
+        unsafe { throw(cause) }
+    }
+}
+
+/// Catch exceptions thrown via a [`ThrowToken`].
+///
+/// This is a safe alternative to [`catch`](crate::catch) for code that can pass the token through
+/// its call graph. `func` receives a token, and exceptions thrown with [`ThrowToken::throw`] are
+/// returned in [`Err`]. If `func` returns a value, it's wrapped in [`Ok`].
+///
+/// If an exception of a type other than `E` reaches this frame, e.g. because the token of an outer
+/// [`catch_scoped`] was used, or because unsafe code inside `func` threw a mismatched exception,
+/// the process is aborted.
+///
+/// Foreign frames, such as [`std::panic::catch_unwind`], must still not be placed between the
+/// token and the catch frame. Such frames can't cause undefined behavior here, but the exception
+/// is then lost or the process aborts, depending on the backend.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch_scoped, ThrowToken};
+///
+/// fn parse(s: &str, token: ThrowToken<'_, String>) -> i32 {
+///     s.parse().unwrap_or_else(|_| token.throw(format!("invalid number: {s}")))
+/// }
+///
+/// let res = catch_scoped(|token| parse("12", token) + parse("x", token));
+/// assert_eq!(res.unwrap_err(), "invalid number: x");
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub fn catch_scoped<R, E: 'static>(
+    func: impl for<'scope> FnOnce(ThrowToken<'scope, E>) -> R,
+) -> Result<R, E> {
+    let token = ThrowToken {
+        scope: PhantomData,
+        cause: PhantomData,
+        not_send: PhantomData,
+    };
+    catch_checked(|| func(token))
+}
+
+/// A branded handle to a catch frame catching exceptions of type `E`.
+///
+/// Created by [`scope`]. Each call to [`scope`] produces a fresh, invariant `'brand` lifetime, so
+/// a function or closure taking `Scope<'brand, E>` can only be called with the handle of the frame
+/// it was written for. Unlike [`ThrowToken`], [`Scope`] is neither [`Copy`] nor [`Clone`], which
+/// lets [`Scope::scope`] lock the outer handle while a nested frame is active.
+pub struct Scope<'brand, E> {
+    brand: PhantomData<fn(&'brand ()) -> &'brand ()>,
+    cause: PhantomData<fn(E)>,
+    not_send: PhantomData<*mut ()>,
+}
+
+impl<E: 'static> Scope<'_, E> {
+    const fn new() -> Self {
+        Self {
+            brand: PhantomData,
+            cause: PhantomData,
+            not_send: PhantomData,
+        }
+    }
+
+    /// Throw an exception, to be caught by the [`scope`] that created this handle.
+    #[inline]
+    pub fn throw(&self, cause: E) -> ! {
+        // SAFETY: The handle can only exist inside the callback of `scope`, which catches `E` on
+        // the same thread. Nested frames started with `Scope::scope` borrow the handle mutably, so
+        // it can't be used until they return.
+        unsafe { throw(cause) }
+    }
+
+    /// Open a nested scope catching exceptions of type `F`.
+    ///
+    /// The outer handle is borrowed for the duration of the call, so exceptions of type `E` can't
+    /// be thrown through the nested frame: this is checked at compile time.
+    #[expect(
+        clippy::missing_errors_doc,
+        reason = "`Err` value is described immediately"
+    )]
+    #[inline]
+    pub fn scope<R, F: 'static>(
+        &mut self,
+        func: impl for<'inner> FnOnce(Scope<'inner, F>) -> R,
+    ) -> Result<R, F> {
+        scope(func)
+    }
+}
+
+/// Catch exceptions thrown via a branded [`Scope`].
+///
+/// This is a compile-time checked alternative to [`catch_scoped`]. `func` receives a handle with a
+/// fresh `'brand` lifetime, and exceptions thrown with [`Scope::throw`] are returned in [`Err`].
+/// If `func` returns a value, it's wrapped in [`Ok`].
+///
+/// Nested frames should be opened with [`Scope::scope`], which statically prevents throwing
+/// through them with the outer handle. Calling [`scope`] or [`catch_scoped`] directly inside
+/// `func` is allowed, but mismatched exceptions are then only detected at runtime and abort the
+/// process, as with [`catch_scoped`].
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{scope, Scope};
+///
+/// fn check(n: i32, s: &Scope<'_, String>) -> i32 {
+///     if n < 0 {
+///         s.throw(format!("negative: {n}"));
+///     }
+///     n
+/// }
+///
+/// let res = scope(|mut s| {
+///     let inner = s.scope::<_, i32>(|inner| inner.throw(1));
+///     assert_eq!(inner, Err(1));
+///     check(1, &s) + check(-2, &s)
+/// });
+/// assert_eq!(res.unwrap_err(), "negative: -2");
+/// ```
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub fn scope<R, E: 'static>(func: impl for<'brand> FnOnce(Scope<'brand, E>) -> R) -> Result<R, E> {
+    catch_checked(|| func(Scope::new()))
+}
+
+/// Catch exceptions of type `E`, aborting on exceptions of other types.
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+fn catch_checked<R, E: 'static>(func: impl FnOnce() -> R) -> Result<R, E> {
+    // SAFETY: `Scoped` accepts exceptions of all types, aborting on mismatch.
+    in_catch_frame(Expected::Any, || unsafe {
+        ActiveBackend::intercept_dispatch(func)
+    })
+    .map_err(|Scoped(cause)| cause)
+}
+
+/// An exception caught by [`catch_scoped`] or [`scope`].
+struct Scoped<E>(E);
+
+impl<E: 'static> FromCaught for Scoped<E> {
+    #[inline]
+    unsafe fn from_caught(type_id: TypeId, ex: impl CaughtException) -> Self {
+        if type_id != typeid::of::<E>() {
+            abort_caught(
+                "Lithium exception of an unexpected type reached a scoped catch. Aborting.\n",
+                &ex,
+            );
+        }
+        // SAFETY: `E` is `'static`, so type IDs match exactly.
+        Self(unsafe { ex.take::<E>() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::String;
+
+    #[test]
+    fn ok() {
+        let result = catch_scoped::<i32, String>(|_| 123);
+        assert_eq!(result, Ok(123));
+    }
+
+    #[test]
+    fn throws() {
+        fn inner(token: ThrowToken<'_, String>) {
+            token.throw(String::from("Hello, world!"));
+        }
+        let result = catch_scoped(inner);
+        assert_eq!(result.unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn nested() {
+        let result = catch_scoped::<(), String>(|outer| {
+            let inner = catch_scoped::<(), i32>(|inner| inner.throw(1));
+            assert_eq!(inner, Err(1));
+            outer.throw(String::from("outer"));
+        });
+        assert_eq!(result.unwrap_err(), "outer");
+    }
+
+    #[test]
+    fn branded() {
+        fn check(n: i32, s: &Scope<'_, String>) -> i32 {
+            if n < 0 {
+                s.throw(String::from("negative"));
+            }
+            n
+        }
+        assert_eq!(scope(|s| check(1, &s) + check(2, &s)), Ok(3));
+        assert_eq!(
+            scope(|s| check(1, &s) + check(-1, &s)).unwrap_err(),
+            "negative"
+        );
+    }
+
+    #[test]
+    fn branded_nested() {
+        let result = scope::<(), String>(|mut outer| {
+            let inner = outer.scope::<(), i32>(|inner| inner.throw(1));
+            assert_eq!(inner, Err(1));
+            outer.throw(String::from("outer"));
+        });
+        assert_eq!(result.unwrap_err(), "outer");
+    }
+}
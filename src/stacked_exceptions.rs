@@ -1,7 +1,19 @@
+use super::heterogeneous_stack::unbounded::SavedStack;
 use super::{
-    backend::{ActiveBackend, RethrowHandle, ThrowByPointer, ThrowByValue},
-    heterogeneous_stack::unbounded::Stack,
+    backend::{
+        ActiveBackend, CaughtException, FromCaught, RethrowHandle, ThrowByPointer, ThrowByValue,
+    },
+    describe::{abort_described, Describe, Described},
+    heterogeneous_stack::unbounded::{Stack, CAPACITY, INLINE_THRESHOLD},
+    location::{caller, ThrowLocation},
 };
+use crate::{
+    guard::finally,
+    tls::{get_state as get_thread_state, without_interrupts},
+};
+use alloc::boxed::Box;
+use core::any::TypeId;
+use core::cell::Cell;
 use core::mem::{offset_of, ManuallyDrop};
 
 // SAFETY:
@@ -10,9 +22,16 @@ use core::mem::{offset_of, ManuallyDrop};
 unsafe impl ThrowByValue for ActiveBackend {
     type RethrowHandle<E> = PointerRethrowHandle<E>;
 
+    #[cfg_attr(feature = "location", track_caller)]
     #[inline]
     unsafe fn throw<E>(cause: E) -> ! {
-        let ex = push(cause);
+        // Even if `E` is a ZST, we still have to allocate the exception object: the unwinder writes
+        // to the header while the exception is propagated, and the header must stay alive and
+        // unique across nested throws, e.g. from destructors. It can't live in the frame of
+        // `throw` either, as that frame is unwound before the exception is caught. For ZSTs this
+        // amounts to bumping the bounded stack by `size_of::<Exception<()>>()`, i.e. all fields
+        // except the cause.
+        let ex = push(cause, caller());
         // SAFETY: Just allocated.
         let ex = unsafe { Exception::header(ex) };
         // SAFETY:
@@ -31,18 +50,81 @@ unsafe impl ThrowByValue for ActiveBackend {
             // SAFETY: By the safety requirement, unwinding could only happen from `throw` with type
             // `E`. Backend guarantees the pointer is passed as-is, and `throw` only throws unique
             // pointers to valid instances of `Exception<E>` via the backend.
-            let ex = unsafe { Exception::<E>::from_header(ex) };
-            let cause = {
-                // SAFETY: Same as above.
-                let ex_ref = unsafe { &mut *ex };
-                // SAFETY: We only read the cause here once.
-                unsafe { ex_ref.cause() }
-            };
-            (cause, PointerRethrowHandle { ex })
+            unsafe { take_intercepted(ex) }
+        })
+    }
+
+    #[inline]
+    unsafe fn intercept_dispatch<Func: FnOnce() -> R, R, D: FromCaught>(
+        func: Func,
+    ) -> Result<R, D> {
+        <Self as ThrowByPointer>::intercept(func).map_err(|header| {
+            // SAFETY: By the safety requirement, unwinding could only happen from `throw`, which
+            // only throws unique pointers to valid instances of `Exception<E>` for some `E`.
+            let type_id = unsafe { Exception::type_id_from_header(header) };
+            // SAFETY: `type_id` is the ID of the thrown type, and `D` recognizes it by the safety
+            // requirement.
+            unsafe { D::from_caught(type_id, CaughtPointer { header }) }
         })
     }
 }
 
+/// Read out the cause of an intercepted exception, keeping the exception object for rethrowing.
+///
+/// With the `opt-size` feature, this is outlined and marked cold, so that the catch path is shared
+/// between all `intercept` calls with the same exception type instead of being inlined into each.
+///
+/// # Safety
+///
+/// `header` must have been produced by [`Exception::header`] for an exception of type `E` with the
+/// cause initialized, and the corresponding object must be alive.
+#[cfg_attr(opt = "size", cold, inline(never))]
+#[cfg_attr(not(opt = "size"), inline)]
+unsafe fn take_intercepted<E>(header: *mut Header) -> (E, PointerRethrowHandle<E>) {
+    // SAFETY: Required transitively.
+    let ex = unsafe { Exception::<E>::from_caught_header(header) };
+    let cause = {
+        // SAFETY: Same as above.
+        let ex_ref = unsafe { &mut *ex };
+        // SAFETY: We only read the cause here once.
+        unsafe { ex_ref.cause() }
+    };
+    (cause, PointerRethrowHandle { ex })
+}
+
+// Type invariant: `header` is a unique pointer to the header of an exception object on the
+// exception stack.
+struct CaughtPointer {
+    header: *mut Header,
+}
+
+impl CaughtException for CaughtPointer {
+    #[inline]
+    unsafe fn take<E>(self) -> E {
+        // SAFETY: `header` was produced by `throw` with type `E`, as required transitively.
+        let ex = unsafe { Exception::<E>::from_header(self.header) };
+        let cause = {
+            // SAFETY: Same as above.
+            let ex_ref = unsafe { &mut *ex };
+            // SAFETY: `take` consumes `self`, so the cause is only read once.
+            unsafe { ex_ref.cause() }
+        };
+        // SAFETY: The exception was at the top of the stack when it was caught, and no other
+        // exceptions could have been thrown since then.
+        unsafe {
+            pop(ex);
+        }
+        cause
+    }
+
+    #[inline]
+    fn describe(&self) -> Option<Described> {
+        // SAFETY: `header` points at a live exception object by the type invariant, and its cause
+        // hasn't been taken yet, as `take` consumes `self`.
+        unsafe { Exception::describe_from_header(self.header) }
+    }
+}
+
 // Type invariant: `ex` is a unique pointer to the exception object on the exception stack.
 #[derive(Debug)]
 pub(crate) struct PointerRethrowHandle<E> {
@@ -62,40 +144,128 @@ impl<E> Drop for PointerRethrowHandle<E> {
 }
 
 impl<E> RethrowHandle for PointerRethrowHandle<E> {
+    #[cfg_attr(feature = "location", track_caller)]
     #[inline]
     unsafe fn rethrow<F>(self, new_cause: F) -> ! {
         let ex = core::mem::ManuallyDrop::new(self);
         // SAFETY: The same logic that proves `pop` in `drop` is valid applies here. We're not
         // *really* dropping `self`, but the user code does not know that.
-        let ex = unsafe { replace_last(ex.ex, new_cause) };
+        let ex = unsafe { replace_last(ex.ex, new_cause, caller()) };
         // SAFETY: Just allocated.
         let ex = unsafe { Exception::header(ex) };
         // SAFETY:
         // - `ex` is a unique pointer to the exception object because it was just produced by
         //   `replace_last`.
-        // - "Don't mess with exceptions" is required transitively.
+        // - "Don't mess with exceptions" is required transitively. In particular, the exception
+        //   is caught by Lithium.
         unsafe {
-            <ActiveBackend as ThrowByPointer>::throw(ex);
+            <ActiveBackend as ThrowByPointer>::rethrow(ex);
         }
     }
+
+    #[cfg(feature = "location")]
+    #[inline]
+    fn location(&self) -> ThrowLocation {
+        // SAFETY: `ex` points at a live exception object by the type invariant.
+        unsafe { Exception::location(self.ex) }
+    }
 }
 
 type Header = <ActiveBackend as ThrowByPointer>::ExceptionHeader;
 
+/// The alignment of exception objects.
+///
+/// This is the alignment of the header, raised to at least that of `usize`, so that causes of most
+/// types are aligned within the exception object and [`Exception::cause_ptr`] doesn't need to
+/// realign them.
+#[repr(C)]
+pub struct Align {
+    _header: [Header; 0],
+    _word: [usize; 0],
+}
+
 /// An exception object, to be used by the backend.
+// This is `repr(C)` so that the header and the type ID are located at the same offsets regardless
+// of `E`.
+#[repr(C)]
 pub struct Exception<E> {
+    _align: [Align; 0],
     header: Header,
+    type_id: Unaligned<TypeId>,
+    location: ThrowLocation,
+    describe: Option<Describe>,
+    /// The exception below this one on the stack, or null.
+    below: *mut Exception<()>,
+    /// Releases the exception if it's leaked, see [`TrackedStack`].
+    release: Release,
+    /// The cause, if it's aligned at this offset, see [`Exception::CAUSE_ALIGNED`]. Otherwise, the
+    /// cause is stored at the next aligned address, in the padding allocated after the object.
     cause: ManuallyDrop<Unaligned<E>>,
 }
 
+/// A type-erased function releasing a leaked exception object from a stack.
+type Release = unsafe fn(&Stack<Align>, *mut Exception<()>);
+
+/// Drop the cause of a leaked exception of type `E` and pop it from the stack.
+///
+/// # Safety
+///
+/// `ex` must be a unique pointer to an exception of type `E` at the top of `stack`, with the cause
+/// initialized. The exception must not be accessed afterwards.
+unsafe fn release_with_cause<E>(stack: &Stack<Align>, ex: *mut Exception<()>) {
+    let ex = ex.cast::<Exception<E>>();
+    // SAFETY: Required transitively.
+    let ex_ref = unsafe { &mut *ex };
+    // SAFETY: The cause is initialized and not accessed afterwards.
+    let cause = unsafe { ex_ref.cause() };
+    // SAFETY: Required transitively.
+    unsafe {
+        release_storage::<E>(stack, ex.cast());
+    }
+    // The cause is dropped after the exception is popped, so that the stack stays consistent even
+    // if the destructor panics.
+    drop(cause);
+}
+
+/// Pop a leaked exception of type `E`, whose cause is uninitialized or moved out, from the stack.
+///
+/// # Safety
+///
+/// `ex` must be a unique pointer to an exception of type `E` at the top of `stack`. The exception
+/// must not be accessed afterwards.
+unsafe fn release_storage<E>(stack: &Stack<Align>, ex: *mut Exception<()>) {
+    // SAFETY: Required transitively.
+    unsafe {
+        stack.pop(ex.cast(), get_alloc_size::<E>());
+    }
+}
+
 #[repr(C, packed)]
 struct Unaligned<T>(T);
 
 impl<E> Exception<E> {
-    /// Create a new exception to be thrown.
-    fn new(cause: E) -> Self {
+    /// Whether the `cause` field is aligned for `E`.
+    ///
+    /// This holds for all types except over-aligned ones, e.g. SIMD vectors or
+    /// `#[repr(align(64))]` structs. Their causes are stored at the first address after the
+    /// `cause` field offset that is aligned for `E`, which depends on the address of the exception
+    /// object. [`get_alloc_size`] allocates enough padding for that.
+    const CAUSE_ALIGNED: bool = align_of::<E>() <= align_of::<Align>()
+        && offset_of!(Self, cause).is_multiple_of(align_of::<E>());
+
+    /// Create a new exception to be thrown, placed on a stack above `below`.
+    ///
+    /// The cause is stored in the `cause` field, so this must only be used if
+    /// [`Exception::CAUSE_ALIGNED`] holds.
+    fn new(cause: E, location: ThrowLocation, below: *mut Exception<()>) -> Self {
         Self {
+            _align: [],
             header: ActiveBackend::new_header(),
+            type_id: Unaligned(typeid::of::<E>()),
+            location,
+            describe: None,
+            below,
+            release: release_with_cause::<E>,
             cause: ManuallyDrop::new(Unaligned(cause)),
         }
     }
@@ -121,6 +291,109 @@ impl<E> Exception<E> {
         unsafe { header.byte_sub(offset_of!(Self, header)) }.cast()
     }
 
+    /// Restore pointer from pointer to header of a caught exception, checking its type.
+    ///
+    /// Catching an exception as a type it wasn't thrown with is undefined behavior, and usually
+    /// goes unnoticed, as the cause is silently transmuted. Comparing type IDs is cheap enough to
+    /// do in release builds too, so this aborts the process instead.
+    ///
+    /// # Safety
+    ///
+    /// `header` must have been produced by [`Exception::header`] for any `E`, and the
+    /// corresponding object must be alive.
+    #[inline]
+    pub unsafe fn from_caught_header(header: *mut Header) -> *mut Self {
+        // SAFETY: Required transitively.
+        let type_id = unsafe { Exception::type_id_from_header(header) };
+        if type_id != typeid::of::<E>() {
+            // SAFETY: The exception was thrown, so its cause is initialized.
+            let described = unsafe { Exception::describe_from_header(header) };
+            abort_described(
+                "A Lithium exception was caught as a type it wasn't thrown with. This is undefined \
+                 behavior. The process will now terminate.\n",
+                described,
+            );
+        }
+        // SAFETY: Required transitively, the type is checked above.
+        unsafe { Self::from_header(header) }
+    }
+
+    /// Get pointer to cause.
+    ///
+    /// Unlike the `cause` field itself, the returned pointer is guaranteed to be aligned.
+    ///
+    /// # Safety
+    ///
+    /// `ex` must be a unique pointer at an exception object allocated with [`get_alloc_size`]
+    /// bytes.
+    #[inline(always)]
+    pub unsafe fn cause_ptr(ex: *mut Self) -> *mut E {
+        // SAFETY: Required transitively.
+        let field = unsafe { ex.byte_add(offset_of!(Self, cause)) }.cast::<u8>();
+        if Self::CAUSE_ALIGNED {
+            return field.cast();
+        }
+        // SAFETY: `get_alloc_size` allocates `align_of::<E>()` bytes of padding after the object,
+        // so the aligned cause is within the allocation.
+        unsafe { field.add(field.align_offset(align_of::<E>())) }.cast()
+    }
+
+    /// Get the location the exception was thrown from.
+    ///
+    /// # Safety
+    ///
+    /// `ex` must point at a live exception object.
+    pub const unsafe fn location(ex: *mut Self) -> ThrowLocation {
+        // SAFETY: Required transitively.
+        let location = unsafe { ex.byte_add(offset_of!(Self, location)) }.cast::<ThrowLocation>();
+        // SAFETY: Required transitively. The field is aligned, as `Exception` is `repr(C)` and
+        // aligned at least to `usize`.
+        unsafe { location.read() }
+    }
+
+    /// Set the location the exception was thrown from.
+    ///
+    /// # Safety
+    ///
+    /// `ex` must be a unique pointer at an exception object.
+    pub const unsafe fn set_location(ex: *mut Self, location: ThrowLocation) {
+        // SAFETY: Required transitively.
+        let ptr = unsafe { ex.byte_add(offset_of!(Self, location)) }.cast::<ThrowLocation>();
+        // SAFETY: Required transitively. The field is aligned, as in `location`.
+        unsafe {
+            ptr.write(location);
+        }
+    }
+
+    /// Set the function describing the cause in abort messages.
+    ///
+    /// # Safety
+    ///
+    /// `ex` must be a unique pointer at an exception object. `describe` must accept a pointer to
+    /// the `cause` field of `Exception<E>`, see [`Exception::describe_from_header`].
+    pub unsafe fn set_describe(ex: *mut Self, describe: Describe) {
+        // SAFETY: Required transitively.
+        let ptr = unsafe { &raw mut (*ex).describe };
+        // SAFETY: Required transitively. The field is aligned, as in `location`.
+        unsafe {
+            ptr.write(Some(describe));
+        }
+    }
+
+    /// Mark the cause as initialized, so that it's dropped if the exception is leaked.
+    ///
+    /// # Safety
+    ///
+    /// `ex` must be a unique pointer at an exception object with the cause initialized.
+    pub unsafe fn set_cause_initialized(ex: *mut Self) {
+        // SAFETY: Required transitively.
+        let ptr = unsafe { &raw mut (*ex).release };
+        // SAFETY: Required transitively. The field is aligned, as in `location`.
+        unsafe {
+            ptr.write(release_with_cause::<E>);
+        }
+    }
+
     /// Get the cause of the exception.
     ///
     /// # Safety
@@ -128,71 +401,598 @@ impl<E> Exception<E> {
     /// This function returns a bitwise copy of the cause. This means that it can only be called
     /// once on each exception.
     pub unsafe fn cause(&mut self) -> E {
+        self.release = release_storage::<E>;
+        if Self::CAUSE_ALIGNED {
+            // SAFETY: We transitively require that the cause is not read twice.
+            return unsafe { ManuallyDrop::take(&mut self.cause).0 };
+        }
+        // SAFETY: The exception object is allocated on a stack, as only over-aligned causes are
+        // stored outside of the field, so `cause_ptr` returns an aligned pointer to the cause.
+        let cause = unsafe { Self::cause_ptr(self) };
         // SAFETY: We transitively require that the cause is not read twice.
-        unsafe { ManuallyDrop::take(&mut self.cause).0 }
+        unsafe { cause.read() }
+    }
+
+    /// Drop the cause of the exception in place.
+    ///
+    /// # Safety
+    ///
+    /// The cause must not have been read by [`Exception::cause`] or dropped before, and must not
+    /// be accessed afterwards.
+    pub unsafe fn drop_cause(&mut self) {
+        // Mark the cause as dropped first, so that it's not dropped again if its destructor panics
+        // and the exception is released by the stack.
+        self.release = release_storage::<E>;
+        if Self::CAUSE_ALIGNED {
+            // SAFETY: Required transitively.
+            unsafe {
+                ManuallyDrop::drop(&mut self.cause);
+            }
+            return;
+        }
+        // SAFETY: As in `cause`.
+        let cause = unsafe { Self::cause_ptr(self) };
+        // SAFETY: Required transitively.
+        unsafe { cause.drop_in_place() }
+    }
+}
+
+impl Exception<()> {
+    /// Get the ID of the thrown type from pointer to header.
+    ///
+    /// # Safety
+    ///
+    /// `header` must have been produced by [`Exception::header`] for any `E`, and the corresponding
+    /// object must be alive.
+    pub unsafe fn type_id_from_header(header: *mut Header) -> TypeId {
+        // SAFETY: `Exception` is `repr(C)` and `header` is its first non-ZST field, so the header
+        // pointer is also a pointer to the exception object. `type_id` is located at the same
+        // offset for all `E`.
+        unsafe { (*header.cast::<Exception<()>>()).type_id.0 }
+    }
+
+    /// Get the description of the cause from pointer to header, if the exception was thrown by
+    /// [`throw_err`](crate::throw_err).
+    ///
+    /// # Safety
+    ///
+    /// `header` must have been produced by [`Exception::header`] for any `E`, and the corresponding
+    /// object must be alive, with the cause initialized.
+    pub unsafe fn describe_from_header(header: *mut Header) -> Option<Described> {
+        let ex = header.cast::<Exception<()>>();
+        // SAFETY: As in `type_id_from_header`, `describe` is located at the same offset for all
+        // `E`.
+        let describe = unsafe { (*ex).describe }?;
+        // SAFETY: The offset of `cause` doesn't depend on `E`, as `Unaligned` has alignment 1.
+        // `describe` realigns the pointer for the actual `E` if necessary.
+        let cause = unsafe { ex.byte_add(offset_of!(Self, cause)) };
+        // SAFETY: `describe` was set for the type of the cause, which is alive.
+        Some(unsafe { Described::new(describe, cause.cast()) })
+    }
+}
+
+/// An exception stack that keeps track of the exceptions allocated on it.
+///
+/// The exceptions form an intrusive list from the top of the stack down. Exceptions are normally
+/// released by their handles, but if a handle is leaked, e.g. with [`core::mem::forget`], or the
+/// thread exits while an exception is still on the stack, the exception is released when the stack
+/// is dropped, dropping the cause if it's still in place.
+pub struct TrackedStack {
+    stack: Stack<Align>,
+    /// The exception at the top of the stack, or null if the stack is empty.
+    top: Cell<*mut Exception<()>>,
+}
+
+impl TrackedStack {
+    pub const fn new() -> Self {
+        Self {
+            stack: Stack::new(),
+            top: Cell::new(core::ptr::null_mut()),
+        }
     }
 }
 
-#[cfg(thread_local = "std")]
+impl TrackedStack {
+    /// Release all exceptions on the stack and the memory cached by it.
+    ///
+    /// This is called when the stack is dropped, and on thread exit for thread-local stacks that
+    /// are never dropped.
+    fn release_all(&self) {
+        let mut leaked: usize = 0;
+        let mut ex = self.top.replace(core::ptr::null_mut());
+        while !ex.is_null() {
+            // SAFETY: Exceptions on the list are alive. `below` is located at the same offset for
+            // all `E`.
+            let below = unsafe { (*ex).below };
+            // SAFETY: As above.
+            let release = unsafe { (*ex).release };
+            // SAFETY: `ex` is at the top of the stack, as exceptions above it have been released,
+            // and `release` matches its type and the state of the cause.
+            unsafe {
+                release(&self.stack, ex);
+            }
+            ex = below;
+            leaked = leaked.wrapping_add(1);
+        }
+
+        #[cfg(all(checks, abort = "std"))]
+        if leaked > 0 {
+            use std::io::Write;
+            let _ = writeln!(
+                std::io::stderr(),
+                "Lithium exception stack released with {leaked} exception(s) in flight. The \
+                 exceptions were leaked, e.g. with `mem::forget`, and have been released now.",
+            );
+        }
+        #[cfg(not(all(checks, abort = "std")))]
+        let _ = leaked;
+
+        self.stack.reset();
+    }
+}
+
+impl Drop for TrackedStack {
+    fn drop(&mut self) {
+        self.release_all();
+    }
+}
+
+/// Per-thread exception state.
+pub struct ExceptionState {
+    /// The default exception stack of the thread.
+    stack: TrackedStack,
+    /// The stack installed by [`OwnedStack::with_stack`], or null if the default stack is in use.
+    current: Cell<*const TrackedStack>,
+    /// Whether [`ReleaseOnExit`] has been registered for this thread.
+    #[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
+    release_registered: Cell<bool>,
+}
+
+impl ExceptionState {
+    pub const fn new() -> Self {
+        Self {
+            stack: TrackedStack::new(),
+            current: Cell::new(core::ptr::null()),
+            #[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
+            release_registered: Cell::new(false),
+        }
+    }
+}
+
+impl ExceptionState {
+    /// Move the exceptions of the thread out, leaving the default stack empty and uninstalling the
+    /// stack installed by [`OwnedStack::with_stack`], if any.
+    pub fn save(&self) -> SavedExceptions {
+        SavedExceptions {
+            stack: self.stack.stack.save(),
+            top: self.stack.top.replace(core::ptr::null_mut()),
+            current: self.current.replace(core::ptr::null()),
+        }
+    }
+
+    /// Put the exceptions moved out by [`ExceptionState::save`] back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if exceptions are in flight on the default stack, or if a stack installed by
+    /// [`OwnedStack::with_stack`] is current.
+    ///
+    /// # Safety
+    ///
+    /// `saved` must have been returned by [`ExceptionState::save`] on this instance, and the stack
+    /// it refers to, if any, must still be alive.
+    pub unsafe fn restore(&self, saved: &SavedExceptions) {
+        assert!(
+            self.current.get().is_null(),
+            "Lithium exception state restored inside `ExceptionStack::enter`",
+        );
+        // SAFETY: Required transitively.
+        unsafe {
+            self.stack.stack.restore(&saved.stack);
+        }
+        self.stack.top.set(saved.top);
+        self.current.set(saved.current);
+    }
+}
+
+/// The exceptions of a thread moved out by [`ExceptionState::save`].
+pub struct SavedExceptions {
+    stack: SavedStack,
+    top: *mut Exception<()>,
+    current: *const TrackedStack,
+}
+
+/// Releases the default exception stack of the thread on thread exit.
+///
+/// `#[thread_local]` statics are never dropped, so without this, heap-spilled exceptions and the
+/// memory cached by the stack would leak whenever a thread exits. `std::thread_local!` values are
+/// dropped on thread exit, so we register a zero-sized one on the first push to the stack.
+#[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
+struct ReleaseOnExit;
+
+#[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
 std::thread_local! {
-    /// Thread-local exception stack.
-    static STACK: Stack<Header> = const { Stack::new() };
+    static RELEASE_ON_EXIT: ReleaseOnExit = const { ReleaseOnExit };
 }
 
-#[cfg(thread_local = "attribute")]
-#[thread_local]
-static STACK: Stack<Header> = const { Stack::new() };
+#[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
+impl Drop for ReleaseOnExit {
+    fn drop(&mut self) {
+        // SAFETY: `#[thread_local]` statics stay alive until the thread is gone, after
+        // `std::thread_local!` destructors run.
+        let state = unsafe { get_state() };
+        state.stack.release_all();
+    }
+}
+
+#[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
+/// Make sure the default exception stack of the thread is released on thread exit.
+#[cold]
+#[inline(never)]
+fn register_release(state: &ExceptionState) {
+    // This fails if the thread is already exiting, in which case there's nothing we can do.
+    let _ = RELEASE_ON_EXIT.try_with(|_| {});
+    state.release_registered.set(true);
+}
 
-/// Get a reference to the thread-local exception stack.
+/// Get a reference to the exception state of the current thread.
 ///
 /// # Safety
 ///
 /// The reference is lifetime-extended to `'static` and is only valid for access until the end of
 /// the thread. This includes at least the call frame of the immediate caller.
-// Unfortunately, replacing this unsafe API with a safe `with_stack` doesn't work, as `with` fails
-// to inline.
 #[inline]
-unsafe fn get_stack() -> &'static Stack<Header> {
-    #[cfg(thread_local = "std")]
-    // SAFETY: We require the caller to not use the reference anywhere near the end of the thread,
-    // so as long as `with` succeeds, there is no problem.
-    return STACK.with(|r| unsafe { core::mem::transmute(r) });
+unsafe fn get_state() -> &'static ExceptionState {
+    // SAFETY: Required transitively.
+    unsafe { &get_thread_state().exceptions }
+}
 
-    #[cfg(thread_local = "attribute")]
-    // SAFETY: We require the caller to not use the reference anywhere near the end of the thread,
-    // so if `&STACK` is sound in the first place, there is no problem.
-    return unsafe { core::mem::transmute::<&Stack<Header>, &'static Stack<Header>>(&STACK) };
+/// Get a reference to the current exception stack.
+///
+/// This is the stack installed by [`OwnedStack::with_stack`], if any, and the thread-local stack
+/// otherwise.
+///
+/// # Safety
+///
+/// The reference is lifetime-extended to `'static` and is only valid for access until the end of
+/// the thread or the end of the [`OwnedStack::with_stack`] scope, whichever is earlier. This
+/// includes at least the call frame of the immediate caller.
+#[inline]
+unsafe fn get_stack() -> &'static TrackedStack {
+    // SAFETY: Required transitively.
+    let state = unsafe { get_state() };
+    let current = state.current.get();
+    if current.is_null() {
+        &state.stack
+    } else {
+        // SAFETY: `OwnedStack::with_stack` only installs pointers to stacks that outlive its scope,
+        // and we require the caller not to use the reference past the scope.
+        unsafe { &*current }
+    }
+}
+
+/// Check whether a stack installed by [`OwnedStack::with_stack`] is current.
+#[cfg(checks)]
+pub fn is_owned_stack_current() -> bool {
+    // SAFETY: The reference is not used past the call frame.
+    !unsafe { get_state() }.current.get().is_null()
+}
+
+/// Call `func` with the current exception stack.
+#[cfg(feature = "stats")]
+pub fn with_stack<T>(func: impl FnOnce(&Stack<Align>) -> T) -> T {
+    // SAFETY: The reference is not used past the call frame.
+    func(&unsafe { get_stack() }.stack)
+}
 
-    #[cfg(thread_local = "unimplemented")]
-    compile_error!("Unable to compile Lithium on a platform does not support thread locals")
+/// Get the number of exceptions on the current exception stack.
+pub fn in_flight_count() -> usize {
+    // SAFETY: The reference is not used past the call frame.
+    unsafe { get_stack() }.stack.elements()
 }
 
+/// Assert that the current exception stack is empty and release cached memory.
+///
+/// # Panics
+///
+/// Panics if exceptions are in flight, see [`Stack::reset`].
+pub fn reset() {
+    // SAFETY: The reference is not used past the call frame.
+    unsafe { get_stack() }.stack.reset();
+}
+
+/// An exception stack not bound to a thread.
+pub struct OwnedStack(Box<TrackedStack>);
+
+impl OwnedStack {
+    /// Allocate an empty stack.
+    pub fn new() -> Self {
+        Self(Box::new(TrackedStack::new()))
+    }
+
+    /// Get the number of exceptions on the stack.
+    pub fn in_flight_count(&self) -> usize {
+        self.0.stack.elements()
+    }
+
+    /// Install the stack as current on this thread for the duration of `func`.
+    ///
+    /// # Safety
+    ///
+    /// Exceptions thrown by `func` must not escape `with_stack`, and exceptions caught outside
+    /// `func` must not be rethrown or dropped inside `func`.
+    pub unsafe fn with_stack<R>(&self, func: impl FnOnce() -> R) -> R {
+        // SAFETY: We don't let the state leak past the call frame.
+        let state = unsafe { get_state() };
+        let previous = state.current.replace(&raw const *self.0);
+        let _guard = finally(|| state.current.set(previous));
+        func()
+    }
+}
+
+// `build.rs` doesn't know the layout of exception objects, so the lower bound on the capacity is
+// checked here. A smaller buffer could never hold an exception and would only waste memory.
+const _: () = assert!(
+    CAPACITY == 0 || CAPACITY >= alloc_size::<()>(),
+    "LITHIUM_STACK_CAPACITY is too small to hold an exception; use 0 to disable the buffer",
+);
+
+/// Get the number of bytes to allocate for an exception object of type `E`.
+///
+/// This includes padding for over-aligned causes, see [`Exception::CAUSE_ALIGNED`].
 const fn get_alloc_size<E>() -> usize {
     const {
         assert!(
-            align_of::<Exception<E>>() == align_of::<Header>(),
+            align_of::<Exception<E>>() == align_of::<Align>(),
             "Exception<E> has unexpected alignment",
         );
+        assert!(
+            !cfg!(no_heap) || alloc_size::<E>() <= INLINE_THRESHOLD,
+            "Exception<E> doesn't fit on the exception stack, and `LITHIUM_NO_HEAP` forbids \
+             allocating it on the heap",
+        );
+    }
+    alloc_size::<E>()
+}
+
+/// Compute [`get_alloc_size`] without checks.
+const fn alloc_size<E>() -> usize {
+    // This is a multiple of align_of::<Exception<E>>(), which `get_alloc_size` checks to be equal
+    // to the alignment used for the stack. The padding is rounded up to a multiple of it too. The
+    // cause is at most `align_of::<E>() - 1` bytes past the field, as the field offset is a
+    // multiple of the alignment of the object.
+    if Exception::<E>::CAUSE_ALIGNED {
+        size_of::<Exception<E>>()
+    } else {
+        size_of::<Exception<E>>()
+            .wrapping_add(align_of::<E>().next_multiple_of(align_of::<Align>()))
+    }
+}
+
+/// Get a reference to the current exception stack, before pushing to it.
+///
+/// # Safety
+///
+/// The same as for [`get_stack`].
+#[inline(always)]
+unsafe fn get_stack_for_push() -> &'static TrackedStack {
+    #[cfg(all(thread_local = "attribute", thread_dtor = "std"))]
+    {
+        // SAFETY: Required transitively.
+        let state = unsafe { get_state() };
+        if !state.release_registered.get() {
+            register_release(state);
+        }
     }
-    // This is a multiple of align_of::<Exception<E>>(), which we've just checked to be equal to the
-    // alignment used for the stack.
-    size_of::<Exception<E>>()
+    // SAFETY: Required transitively.
+    unsafe { get_stack() }
 }
 
 /// Push an exception onto the thread-local exception stack.
 #[inline(always)]
-pub fn push<E>(cause: E) -> *mut Exception<E> {
-    // SAFETY: We don't let the stack leak past the call frame.
-    let stack = unsafe { get_stack() };
-    let ex: *mut Exception<E> = stack.push(get_alloc_size::<E>()).cast();
-    // SAFETY:
-    // - The stack allocator guarantees the pointer is dereferenceable and unique.
-    // - The stack is configured to align like Header, which get_alloc_size verifies to be the
-    //   alignment of Exception<E>.
+pub fn push<E>(cause: E, location: ThrowLocation) -> *mut Exception<E> {
+    without_interrupts(|| {
+        // SAFETY: We don't let the stack leak past the call frame.
+        let stack = unsafe { get_stack_for_push() };
+        let ex: *mut Exception<E> = stack.stack.push(get_alloc_size::<E>()).cast();
+        // SAFETY:
+        // - The stack allocator guarantees the pointer is dereferenceable and unique.
+        // - The stack is configured to align like `Align`, which get_alloc_size verifies to be the
+        //   alignment of Exception<E>.
+        unsafe {
+            write_exception(ex, cause, location, stack.top.get());
+        }
+        stack.top.set(ex.cast());
+        ex
+    })
+}
+
+/// Initialize an exception object placed on a stack above `below`.
+///
+/// # Safety
+///
+/// `ex` must be a unique aligned pointer to [`get_alloc_size`] bytes of storage.
+#[inline(always)]
+unsafe fn write_exception<E>(
+    ex: *mut Exception<E>,
+    cause: E,
+    location: ThrowLocation,
+    below: *mut Exception<()>,
+) {
+    if Exception::<E>::CAUSE_ALIGNED {
+        // SAFETY: Required transitively.
+        unsafe {
+            ex.write(Exception::new(cause, location, below));
+        }
+        return;
+    }
+    // SAFETY: Required transitively.
+    unsafe {
+        write_fields(ex, below);
+    }
+    // SAFETY: Required transitively.
+    unsafe {
+        Exception::set_location(ex, location);
+    }
+    // SAFETY: Required transitively.
+    let cause_ptr = unsafe { Exception::cause_ptr(ex) };
+    // SAFETY: `cause_ptr` returns an aligned pointer to storage for `E`.
+    unsafe {
+        cause_ptr.write(cause);
+    }
+    // SAFETY: Required transitively. The cause is initialized.
+    unsafe {
+        Exception::set_cause_initialized(ex);
+    }
+}
+
+/// Initialize the fields of an exception object placed on a stack above `below`, except for the
+/// cause and the location.
+///
+/// # Safety
+///
+/// `ex` must be a unique aligned pointer to [`get_alloc_size`] bytes of storage.
+#[inline(always)]
+unsafe fn write_fields<E>(ex: *mut Exception<E>, below: *mut Exception<()>) {
+    // SAFETY: Required transitively.
+    let header = unsafe { Exception::header(ex) };
+    // SAFETY: The header is aligned, as the exception object is.
+    unsafe {
+        header.write(ActiveBackend::new_header());
+    }
+    // SAFETY: In bounds of the allocation.
+    let type_id: *mut Unaligned<TypeId> =
+        unsafe { ex.byte_add(offset_of!(Exception<E>, type_id)) }.cast();
+    // SAFETY: `Unaligned` has alignment 1. We don't touch the cause.
+    unsafe {
+        type_id.write(Unaligned(typeid::of::<E>()));
+    }
+    // SAFETY: In bounds of the allocation.
+    let describe = unsafe { &raw mut (*ex).describe };
+    // SAFETY: The field is aligned, as the exception object is.
+    unsafe {
+        describe.write(None);
+    }
+    // SAFETY: In bounds of the allocation.
+    let below_ptr = unsafe { &raw mut (*ex).below };
+    // SAFETY: As above.
+    unsafe {
+        below_ptr.write(below);
+    }
+    // SAFETY: In bounds of the allocation.
+    let release = unsafe { &raw mut (*ex).release };
+    // SAFETY: As above. The cause is not initialized yet.
+    unsafe {
+        release.write(release_storage::<E>);
+    }
+}
+
+/// Push an exception with an uninitialized cause onto the thread-local exception stack.
+///
+/// The cause has to be initialized via [`Exception::cause_ptr`], and the location via
+/// [`Exception::set_location`], before the exception is thrown with [`throw_pushed`].
+#[inline(always)]
+pub fn push_uninit<E>() -> *mut Exception<E> {
+    without_interrupts(|| {
+        // SAFETY: We don't let the stack leak past the call frame.
+        let stack = unsafe { get_stack_for_push() };
+        let ex: *mut Exception<E> = stack.stack.push(get_alloc_size::<E>()).cast();
+        // SAFETY: The stack allocator guarantees the pointer is dereferenceable and unique, and
+        // aligned as in `push`.
+        unsafe {
+            write_fields(ex, stack.top.get());
+        }
+        stack.top.set(ex.cast());
+        ex
+    })
+}
+
+/// Catch an exception, leaving the cause in the exception object.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. The returned exception is at the top of the
+/// stack, and the caller must ensure it stays there until it's released with [`pop`] or rethrown
+/// with [`throw_pushed`].
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub unsafe fn intercept_in_place<Func: FnOnce() -> R, R, E>(
+    func: Func,
+) -> Result<R, *mut Exception<E>> {
+    <ActiveBackend as ThrowByPointer>::intercept(func).map_err(|header| {
+        // SAFETY: By the safety requirement, unwinding could only happen from `throw` with type
+        // `E`, which only throws pointers to valid instances of `Exception<E>`.
+        unsafe { Exception::<E>::from_caught_header(header) }
+    })
+}
+
+/// Catch an exception of any type, leaving the cause in the exception object.
+///
+/// This is a type-erased version of [`intercept_in_place`]: since `func` is a trait object, the
+/// function is only instantiated once. The returned exception is at the top of the stack, and the
+/// caller must ensure it stays there until it's released with [`take_caught`].
+#[expect(
+    clippy::missing_errors_doc,
+    reason = "`Err` value is described immediately"
+)]
+#[inline]
+pub fn intercept_erased(func: &mut dyn FnMut()) -> Result<(), *mut Exception<()>> {
+    <ActiveBackend as ThrowByPointer>::intercept(func).map_err(|header| {
+        // SAFETY: Unwinding could only happen from `throw`, which only throws pointers to valid
+        // exception objects. The header is located at the same offset for all cause types.
+        unsafe { Exception::from_header(header) }
+    })
+}
+
+/// Read out the cause of an exception returned by [`intercept_erased`] and release it.
+///
+/// The process is aborted if the exception was thrown with a type other than `E`.
+///
+/// # Safety
+///
+/// `erased` must have been returned by [`intercept_erased`] and still be at the top of the stack.
+/// The exception must not be accessed afterwards.
+#[inline]
+pub unsafe fn take_caught<E>(erased: *mut Exception<()>) -> E {
+    // SAFETY: `erased` points at a live exception object by the safety requirement.
+    let header = unsafe { Exception::header(erased) };
+    // SAFETY: Same as above. The type is checked here.
+    let ex = unsafe { Exception::<E>::from_caught_header(header) };
+    let cause = {
+        // SAFETY: Same as above.
+        let ex_ref = unsafe { &mut *ex };
+        // SAFETY: The cause is only read once, as the exception is popped right after.
+        unsafe { ex_ref.cause() }
+    };
+    // SAFETY: The exception is at the top of the stack by the safety requirement.
+    unsafe {
+        pop(ex);
+    }
+    cause
+}
+
+/// Throw an exception allocated on the exception stack.
+///
+/// # Safety
+///
+/// `ex` must be a unique pointer to an exception at the top of the stack, as returned by [`push`],
+/// by [`push_uninit`] with the cause initialized since, or by [`intercept_in_place`] with the cause
+/// still in place. The requirements of [`ThrowByPointer::throw`] apply.
+#[inline]
+pub unsafe fn throw_pushed<E>(ex: *mut Exception<E>) -> ! {
+    // SAFETY: Required transitively.
+    unsafe {
+        Exception::set_cause_initialized(ex);
+    }
+    // SAFETY: Required transitively.
+    let ex = unsafe { Exception::header(ex) };
+    // SAFETY: Required transitively.
     unsafe {
-        ex.write(Exception::new(cause));
+        <ActiveBackend as ThrowByPointer>::throw(ex);
     }
-    ex
 }
 
 /// Remove an exception from the thread-local exception stack.
@@ -203,12 +1003,16 @@ pub fn push<E>(cause: E) -> *mut Exception<E> {
 /// [`push`] or [`replace_last`] with the same exception type. In addition, the exception must not
 /// be accessed after `pop`.
 pub unsafe fn pop<E>(ex: *mut Exception<E>) {
-    // SAFETY: We don't let the stack leak past the call frame.
-    let stack = unsafe { get_stack() };
-    // SAFETY: We require `ex` to be correctly obtained and unused after `pop`.
-    unsafe {
-        stack.pop(ex.cast(), get_alloc_size::<E>());
-    }
+    without_interrupts(|| {
+        // SAFETY: We don't let the stack leak past the call frame.
+        let stack = unsafe { get_stack() };
+        // SAFETY: `ex` points at a live exception object by the safety requirements.
+        stack.top.set(unsafe { (*ex).below });
+        // SAFETY: We require `ex` to be correctly obtained and unused after `pop`.
+        unsafe {
+            stack.stack.pop(ex.cast(), get_alloc_size::<E>());
+        }
+    });
 }
 
 /// Replace the exception on the top of the thread-local exception stack.
@@ -218,38 +1022,69 @@ pub unsafe fn pop<E>(ex: *mut Exception<E>) {
 /// The caller must ensure `ex` corresponds to the exception at the top of the stack, as returned by
 /// [`push`] or [`replace_last`] with the same exception type. In addition, the old exception must
 /// not be accessed after `replace_last`.
-pub unsafe fn replace_last<E, F>(ex: *mut Exception<E>, cause: F) -> *mut Exception<F> {
-    // SAFETY: We don't let the stack leak past the call frame.
-    let stack = unsafe { get_stack() };
-    let ex: *mut Exception<F> =
+pub unsafe fn replace_last<E, F>(
+    ex: *mut Exception<E>,
+    cause: F,
+    location: ThrowLocation,
+) -> *mut Exception<F> {
+    without_interrupts(|| {
+        // SAFETY: We don't let the stack leak past the call frame.
+        let stack = unsafe { get_stack() };
+        // SAFETY: `ex` points at a live exception object by the safety requirements.
+        let below = unsafe { (*ex).below };
         // SAFETY: We require `ex` to be correctly obtained and unused after `replace_last`.
-        unsafe { stack.replace_last(ex.cast(), get_alloc_size::<E>(), get_alloc_size::<F>()) }
-            .cast();
-    // SAFETY: `replace_last` returns unique aligned storage, good for Exception<F> as per the
-    // return value of `get_alloc_size`.
-    unsafe {
-        ex.write(Exception::new(cause));
-    }
-    ex
+        let ex: *mut Exception<F> = unsafe {
+            stack
+                .stack
+                .replace_last(ex.cast(), get_alloc_size::<E>(), get_alloc_size::<F>())
+        }
+        .cast();
+        // SAFETY: `replace_last` returns unique aligned storage, good for Exception<F> as per the
+        // return value of `get_alloc_size`.
+        unsafe {
+            write_exception(ex, cause, location, below);
+        }
+        stack.top.set(ex.cast());
+        ex
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use alloc::rc::Rc;
     use alloc::string::String;
 
     #[test]
     fn exception_cause() {
-        let mut ex = Exception::new(String::from("Hello, world!"));
+        let mut ex = Exception::new(
+            String::from("Hello, world!"),
+            caller(),
+            core::ptr::null_mut(),
+        );
         assert_eq!(unsafe { ex.cause() }, "Hello, world!");
     }
 
+    #[test]
+    fn exception_type_id() {
+        let ex = push(String::from("Hello, world!"), caller());
+        let header = unsafe { Exception::header(ex) };
+        assert_eq!(
+            unsafe { Exception::<()>::type_id_from_header(header) },
+            typeid::of::<String>(),
+        );
+        drop(unsafe { (*ex).cause() });
+        unsafe {
+            pop(ex);
+        }
+    }
+
     #[test]
     fn stack() {
-        let ex1 = push(String::from("Hello, world!"));
-        let ex2 = push(123i32);
+        let ex1 = push(String::from("Hello, world!"), caller());
+        let ex2 = push(123i32, caller());
         assert_eq!(unsafe { (*ex2).cause() }, 123);
-        let ex3 = unsafe { replace_last(ex2, "Third time's a charm") };
+        let ex3 = unsafe { replace_last(ex2, "Third time's a charm", caller()) };
         assert_eq!(unsafe { (*ex3).cause() }, "Third time's a charm");
         unsafe {
             pop(ex3);
@@ -259,4 +1094,87 @@ mod test {
             pop(ex1);
         }
     }
+
+    #[test]
+    fn overaligned() {
+        #[derive(Debug, PartialEq)]
+        #[repr(align(64))]
+        struct Cause(u8);
+
+        // Misalign the stack on purpose.
+        let ex1 = push(1u8, caller());
+        let ex2 = push(Cause(2), caller());
+        assert!(unsafe { Exception::cause_ptr(ex2) }.is_aligned());
+        let ex3 = unsafe { replace_last(ex2, Cause(3), caller()) };
+        assert_eq!(unsafe { (*ex3).cause() }, Cause(3));
+        unsafe {
+            pop(ex3);
+        }
+        assert_eq!(unsafe { (*ex1).cause() }, 1);
+        unsafe {
+            pop(ex1);
+        }
+    }
+
+    #[test]
+    fn release_leaked() {
+        struct Cause(Rc<Cell<usize>>);
+
+        impl Drop for Cause {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let dropped = Rc::new(Cell::new(0));
+        let stack = OwnedStack::new();
+        unsafe {
+            stack.with_stack(|| {
+                push(Cause(Rc::clone(&dropped)), caller());
+                let taken = push(Cause(Rc::clone(&dropped)), caller());
+                drop((*taken).cause());
+                push_uninit::<Cause>();
+                push(Cause(Rc::clone(&dropped)), caller());
+            });
+        }
+        assert_eq!(dropped.get(), 1);
+        drop(stack);
+        assert_eq!(dropped.get(), 3);
+    }
+
+    // Run under `cargo valgrind test` to check that the heap-spilled storage is freed too.
+    #[cfg(any(
+        thread_local = "std",
+        all(thread_local = "attribute", thread_dtor = "std")
+    ))]
+    #[cfg(not(no_heap))]
+    #[test]
+    fn release_on_thread_exit() {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+
+        // Too large for the statically allocated part of the stack.
+        struct Cause {
+            _data: [u8; 5000],
+        }
+
+        impl Drop for Cause {
+            fn drop(&mut self) {
+                DROPPED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        std::thread::spawn(|| {
+            // The storage of this exception is cached for reuse after `pop`.
+            let ex = push([0u8; 5000], caller());
+            unsafe {
+                pop(ex);
+            }
+            push(Cause { _data: [0; 5000] }, caller());
+        })
+        .join()
+        .unwrap();
+        assert!(DROPPED.load(Ordering::Relaxed));
+    }
 }
@@ -0,0 +1,234 @@
+//! Exception stack usage and event statistics.
+
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::stacked_exceptions::with_stack;
+use super::tls::get_state;
+use core::cell::Cell;
+#[cfg(target_has_atomic = "ptr")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Usage of the exception stack of the current thread.
+///
+/// Returned by [`stack_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct StackStats {
+    /// The number of bytes used in the statically allocated part of the stack.
+    ///
+    /// The capacity of this part is configured with `LITHIUM_STACK_CAPACITY`.
+    pub bounded_len: usize,
+
+    /// The number of exceptions that didn't fit in the statically allocated part of the stack, or
+    /// were larger than `LITHIUM_INLINE_THRESHOLD`, and were allocated on the heap.
+    pub heap_spills: usize,
+
+    /// The number of exceptions stored on the stack.
+    ///
+    /// This includes exceptions that are being propagated, as well as exceptions kept alive by
+    /// [`InFlightException`](crate::InFlightException).
+    pub in_flight: usize,
+}
+
+/// Get usage statistics of the exception stack of the current thread.
+///
+/// If an [`ExceptionStack`](crate::ExceptionStack) is entered, its statistics are returned instead.
+///
+/// This is useful for tuning the sizes of exception types and `LITHIUM_STACK_CAPACITY`. On
+/// backends that don't use an exception stack, such as SEH, all statistics are zero.
+///
+/// This function is only available with the `stats` feature enabled.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{intercept, stack_stats, throw};
+///
+/// // SAFETY: the closure only throws `i32`
+/// let (_, in_flight) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+/// # #[cfg(not(target_env = "msvc"))]
+/// assert_eq!(stack_stats().in_flight, 1);
+/// drop(in_flight);
+/// assert_eq!(stack_stats().in_flight, 0);
+/// ```
+#[inline]
+#[must_use]
+pub fn stack_stats() -> StackStats {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    return with_stack(|stack| StackStats {
+        bounded_len: stack.bounded_len(),
+        heap_spills: stack.heap_elements(),
+        in_flight: stack.elements(),
+    });
+
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    return StackStats::default();
+}
+
+/// Counts of exception events.
+///
+/// Returned by [`stats`] for the current thread and by [`global_stats`] for the whole process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ThrowStats {
+    /// The number of thrown exceptions, including rethrows.
+    pub throws: usize,
+
+    /// The number of exceptions caught by Lithium, including those caught only to be rethrown.
+    pub catches: usize,
+
+    /// The number of rethrown exceptions, via
+    /// [`InFlightException::rethrow`](crate::InFlightException::rethrow) or
+    /// [`InPlaceException::rethrow_same`](crate::InPlaceException::rethrow_same).
+    pub rethrows: usize,
+
+    /// The number of exceptions that didn't fit in the statically allocated part of the exception
+    /// stack and were allocated on the heap.
+    ///
+    /// Unlike [`StackStats::heap_spills`], this counts all spills so far, not just the live ones.
+    pub heap_spills: usize,
+}
+
+/// An exception event counted by [`ThrowStats`].
+#[derive(Clone, Copy)]
+pub enum Event {
+    Throw,
+    Catch,
+    Rethrow,
+    HeapSpill,
+}
+
+/// Per-thread event counters.
+pub struct EventCounters([Cell<usize>; 4]);
+
+impl EventCounters {
+    pub const fn new() -> Self {
+        Self([const { Cell::new(0) }; 4])
+    }
+}
+
+/// Process-wide event counters.
+#[cfg(target_has_atomic = "ptr")]
+static GLOBAL: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+
+/// Count an event on the current thread and in the process-wide aggregate.
+#[inline]
+pub fn record(event: Event) {
+    // SAFETY: The reference is not used past the call frame.
+    let counter = &unsafe { get_state() }.stats.0[event as usize];
+    counter.set(counter.get().wrapping_add(1));
+    #[cfg(target_has_atomic = "ptr")]
+    GLOBAL[event as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Get counts of exception events on the current thread.
+///
+/// This is useful for monitoring how often the exception path is actually taken. The counters are
+/// cheap to maintain, as they are only updated by the thread they belong to.
+///
+/// This function is only available with the `stats` feature enabled.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, stats, throw};
+///
+/// let before = stats();
+/// // SAFETY: the closure only throws `i32`
+/// let _ = unsafe { catch::<(), i32>(|| throw(1)) };
+/// let after = stats();
+/// assert_eq!(after.throws - before.throws, 1);
+/// assert_eq!(after.catches - before.catches, 1);
+/// ```
+#[inline]
+#[must_use]
+pub fn stats() -> ThrowStats {
+    // SAFETY: The reference is not used past the call frame.
+    let [throws, catches, rethrows, heap_spills] = &unsafe { get_state() }.stats.0;
+    ThrowStats {
+        throws: throws.get(),
+        catches: catches.get(),
+        rethrows: rethrows.get(),
+        heap_spills: heap_spills.get(),
+    }
+}
+
+/// Get counts of exception events across all threads of the process.
+///
+/// The aggregate is maintained with relaxed atomic operations, so it may lag behind the per-thread
+/// counters of other threads, but it never misses events.
+///
+/// This function is only available with the `stats` feature enabled, on targets with atomic
+/// pointer-sized integers.
+#[cfg(target_has_atomic = "ptr")]
+#[inline]
+#[must_use]
+pub fn global_stats() -> ThrowStats {
+    let [throws, catches, rethrows, heap_spills] = &GLOBAL;
+    ThrowStats {
+        throws: throws.load(Ordering::Relaxed),
+        catches: catches.load(Ordering::Relaxed),
+        rethrows: rethrows.load(Ordering::Relaxed),
+        heap_spills: heap_spills.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+#[cfg(not(no_heap))]
+mod test {
+    use super::*;
+    use crate::{catch, intercept, throw};
+    use alloc::string::String;
+
+    #[test]
+    fn counts() {
+        assert_eq!(stack_stats(), StackStats::default());
+
+        let (_, small) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+        let stats = stack_stats();
+        assert_ne!(stats.bounded_len, 0);
+        assert_eq!(stats.heap_spills, 0);
+        assert_eq!(stats.in_flight, 1);
+
+        let (_, large) = unsafe { intercept::<(), [u8; 5000]>(|| throw([0u8; 5000])) }.unwrap_err();
+        let stats2 = stack_stats();
+        assert_eq!(stats2.bounded_len, stats.bounded_len);
+        assert_eq!(stats2.heap_spills, 1);
+        assert_eq!(stats2.in_flight, 2);
+
+        let result: Result<(), String> = unsafe { catch(|| throw(String::from("Oops!"))) };
+        assert_eq!(result.unwrap_err(), "Oops!");
+        assert_eq!(stack_stats(), stats2);
+
+        drop(large);
+        assert_eq!(stack_stats(), stats);
+        drop(small);
+        assert_eq!(stack_stats(), StackStats::default());
+    }
+
+    #[test]
+    fn events() {
+        let before = stats();
+        let global_before = global_stats();
+
+        let result = unsafe {
+            catch::<(), i32>(|| match intercept::<(), i32>(|| throw(1)) {
+                Ok(()) => {}
+                Err((cause, in_flight)) => in_flight.rethrow(cause + 1),
+            })
+        };
+        assert_eq!(result, Err(2));
+        let (_, large) = unsafe { intercept::<(), [u8; 5000]>(|| throw([0u8; 5000])) }.unwrap_err();
+        drop(large);
+
+        let after = stats();
+        assert_eq!(after.throws - before.throws, 3);
+        assert_eq!(after.catches - before.catches, 3);
+        assert_eq!(after.rethrows - before.rethrows, 1);
+        assert_eq!(after.heap_spills - before.heap_spills, 1);
+
+        let global_after = global_stats();
+        assert!(global_after.throws - global_before.throws >= 3);
+        assert!(global_after.heap_spills - global_before.heap_spills >= 1);
+    }
+}
@@ -0,0 +1,259 @@
+//! Fault injection for tests.
+//!
+//! Code paths that handle exceptions are hard to reach in tests, as the exceptions they handle are
+//! usually thrown by code deep inside the callee. This module lets tests throw exceptions on demand
+//! instead.
+//!
+//! Library code marks the places where a failure may occur with [`checkpoint`]. Unless a test arms
+//! an injection on the current thread with [`inject_throw`], a checkpoint does nothing. Once armed,
+//! checkpoints throw exceptions constructed by the test, according to a [`Trigger`].
+//!
+//! Injections are per-thread and deterministic: the same sequence of checkpoints always throws at
+//! the same points.
+//!
+//! This module is only available with the `test-util` feature enabled.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::{catch, test_util::{checkpoint, inject_throw}, throw};
+//!
+//! /// Sums values, failing with `&str` if a value is negative.
+//! ///
+//! /// # Safety
+//! ///
+//! /// Only `&'static str` is thrown.
+//! unsafe fn sum(values: &[i32]) -> i32 {
+//!     let mut total = 0;
+//!     for &value in values {
+//!         // SAFETY: Tests only inject `&'static str`.
+//!         unsafe { checkpoint() };
+//!         if value < 0 {
+//!             // SAFETY: Requirements forwarded.
+//!             unsafe { throw("negative value") };
+//!         }
+//!         total += value;
+//!     }
+//!     total
+//! }
+//!
+//! inject_throw::<&'static str>(1, || "injected");
+//! // SAFETY: Only `&'static str` is thrown.
+//! assert_eq!(unsafe { catch::<_, &str>(|| sum(&[1, 2])) }, Err("injected"));
+//! // The injection fired once and disarmed itself.
+//! assert_eq!(unsafe { catch::<_, &str>(|| sum(&[1, 2])) }, Ok(3));
+//! ```
+
+use super::{api::throw, rethrows, tls::get_state};
+use alloc::boxed::Box;
+use core::any::Any;
+
+/// When an armed injection throws.
+///
+/// Integers convert to [`Trigger::Count`] and floats to [`Trigger::Probability`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Trigger {
+    /// Throw at each of the next `n` checkpoints, then disarm.
+    Count(usize),
+
+    /// Throw at each checkpoint with the given probability, until disarmed.
+    ///
+    /// The decisions are made by a pseudo-random generator, seeded anew by each call to
+    /// [`inject_throw`], so they are reproducible.
+    Probability(f64),
+}
+
+impl From<usize> for Trigger {
+    #[inline]
+    fn from(n: usize) -> Self {
+        Self::Count(n)
+    }
+}
+
+impl From<f64> for Trigger {
+    #[inline]
+    fn from(probability: f64) -> Self {
+        Self::Probability(probability)
+    }
+}
+
+/// An injection armed on a thread.
+pub struct Injection {
+    trigger: Trigger,
+    /// The state of the xorshift generator used by [`Trigger::Probability`].
+    rng: u64,
+    /// Constructs the cause, boxed as `dyn Any`.
+    payload: Box<dyn FnMut() -> Box<dyn Any>>,
+    /// Throws the cause constructed by `payload`.
+    throw: unsafe fn(Box<dyn Any>) -> !,
+}
+
+/// The initial state of the generator.
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl Injection {
+    /// Decide whether the current checkpoint throws.
+    ///
+    /// Returns `None` if the injection is exhausted and should be disarmed.
+    fn fire(&mut self) -> Option<bool> {
+        match &mut self.trigger {
+            Trigger::Count(0) => None,
+            Trigger::Count(n) => {
+                *n = n.wrapping_sub(1);
+                Some(true)
+            }
+            Trigger::Probability(probability) => {
+                self.rng ^= self.rng << 13u32;
+                self.rng ^= self.rng >> 7u32;
+                self.rng ^= self.rng << 17u32;
+                // Build a float in [1, 2) from the top 52 bits and shift it to [0, 1).
+                let sample = f64::from_bits(0x3ff0_0000_0000_0000 | (self.rng >> 12u32)) - 1.0;
+                Some(sample < *probability)
+            }
+        }
+    }
+}
+
+/// Throw the cause constructed by an injection.
+///
+/// # Safety
+///
+/// `cause` must hold an `E`. The requirements of [`throw`](fn@throw) apply.
+unsafe fn throw_injected<E: 'static>(cause: Box<dyn Any>) -> ! {
+    let Ok(cause) = cause.downcast::<E>() else {
+        unreachable!("Injected cause has an unexpected type");
+    };
+    // SAFETY: Required transitively.
+    unsafe { throw(*cause) }
+}
+
+/// Arm an injection on the current thread.
+///
+/// After this call, [`checkpoint`]s on the current thread throw exceptions of type `E`, constructed
+/// by `payload`, as determined by `trigger`: either at each of the next `n` checkpoints, or at
+/// each checkpoint with a probability until [`disarm`] is called. An integer or a float can be
+/// passed directly.
+///
+/// Arming an injection replaces the injection armed previously, if any.
+///
+/// # Panics
+///
+/// Panics if the probability is not within `0.0..=1.0`.
+#[inline]
+pub fn inject_throw<E: 'static>(
+    trigger: impl Into<Trigger>,
+    mut payload: impl FnMut() -> E + 'static,
+) {
+    let trigger = trigger.into();
+    if let Trigger::Probability(probability) = trigger {
+        assert!(
+            (0.0f64..=1.0f64).contains(&probability),
+            "Probability {probability} is out of range",
+        );
+    }
+    let injection = Injection {
+        trigger,
+        rng: SEED,
+        payload: Box::new(move || Box::new(payload())),
+        throw: throw_injected::<E>,
+    };
+    // SAFETY: The reference is not used past the call frame.
+    drop(unsafe { get_state() }.injection.replace(Some(injection)));
+}
+
+/// Disarm the injection on the current thread, if any.
+#[inline]
+pub fn disarm() {
+    // SAFETY: The reference is not used past the call frame.
+    drop(unsafe { get_state() }.injection.take());
+}
+
+/// Set the maximum number of times an exception can be rethrown on the current thread.
+///
+/// In debug builds, rethrowing an exception more times than this aborts the process, which catches
+/// runaway `intercept`/`rethrow` loops. `None` removes the limit. This overrides the
+/// `LITHIUM_MAX_RETHROWS` environment variable, which sets the limit at build time and is unset by
+/// default. Does nothing in release builds.
+#[inline]
+pub fn set_max_rethrows(max: Option<usize>) {
+    rethrows::set_max_rethrows(max);
+}
+
+/// A point at which an injected exception may be thrown.
+///
+/// Does nothing unless an injection is armed on the current thread with [`inject_throw`]. The
+/// check is cheap, but not free, so checkpoints are best placed outside of hot loops.
+///
+/// # Safety
+///
+/// The requirements of [`throw`](fn@throw) apply to the exception types that may be injected
+/// into this checkpoint.
+#[inline]
+pub unsafe fn checkpoint() {
+    // SAFETY: The reference is not used past the call frame.
+    let injection = unsafe { &get_state().injection };
+    let Some(mut armed) = injection.take() else {
+        return;
+    };
+    let Some(fire) = armed.fire() else {
+        return;
+    };
+    if !fire {
+        injection.set(Some(armed));
+        return;
+    }
+    // Construct the cause while disarmed, so that checkpoints reached by `payload` don't recurse.
+    let cause = (armed.payload)();
+    let throw = armed.throw;
+    injection.set(Some(armed));
+    // SAFETY: `cause` was constructed by the matching `payload`. The rest is required
+    // transitively.
+    unsafe { throw(cause) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::catch;
+
+    fn run() -> Result<(), i32> {
+        unsafe { catch::<(), i32>(|| checkpoint()) }
+    }
+
+    #[test]
+    fn disarmed() {
+        assert_eq!(run(), Ok(()));
+    }
+
+    #[test]
+    fn count() {
+        let mut next = 0;
+        inject_throw::<i32>(2, move || {
+            next += 1;
+            next
+        });
+        assert_eq!(run(), Err(1));
+        assert_eq!(run(), Err(2));
+        assert_eq!(run(), Ok(()));
+    }
+
+    #[test]
+    fn probability() {
+        let fired = || {
+            inject_throw::<i32>(0.5, || 1);
+            let fired: usize = (0..1000).map(|_| usize::from(run().is_err())).sum();
+            disarm();
+            fired
+        };
+        let first = fired();
+        assert!((400..600).contains(&first));
+        assert_eq!(fired(), first);
+        inject_throw::<i32>(0.0, || 1);
+        assert_eq!(run(), Ok(()));
+        inject_throw::<i32>(1.0, || 1);
+        assert_eq!(run(), Err(1));
+        disarm();
+        assert_eq!(run(), Ok(()));
+    }
+}
@@ -0,0 +1,66 @@
+//! Threads that catch exceptions.
+//!
+//! Letting an exception unwind into the frame of [`std::thread::spawn`] is UB, as the runtime
+//! catches it in place of Lithium. [`spawn_catching`] installs a top-level [`catch`] in the spawned
+//! thread, so exceptions are returned to the joining thread instead, and guards the thread with
+//! [`install_thread_guard`] as a last resort.
+//!
+//! # Example
+//!
+//! ```rust
+//! use lithium::{thread::spawn_catching, throw};
+//!
+//! // SAFETY: the callback only throws `&'static str`
+//! let handle = unsafe { spawn_catching::<(), &'static str>(|| throw("Oops!")) };
+//! assert_eq!(handle.join().unwrap(), Err("Oops!"));
+//! ```
+
+use super::{api::catch, thread_guard::install_thread_guard};
+use std::thread::{spawn, JoinHandle};
+
+/// Spawn a thread, catching exceptions thrown by `func`.
+///
+/// The returned handle yields `Ok(value)` if `func` returns, and `Err(cause)` if `func` throws.
+/// Rust panics are not caught by Lithium and are reported by [`JoinHandle::join`] as usual.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+#[inline]
+pub unsafe fn spawn_catching<R, E>(
+    func: impl FnOnce() -> R + Send + 'static,
+) -> JoinHandle<Result<R, E>>
+where
+    R: Send + 'static,
+    E: Send + 'static,
+{
+    // SAFETY: `func` only throws `E` by the safety requirement.
+    spawn(move || install_thread_guard(|| unsafe { catch(func) }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::throw;
+    use alloc::string::String;
+
+    #[test]
+    fn returns() {
+        let handle = unsafe { spawn_catching::<_, String>(|| 123) };
+        assert_eq!(handle.join().unwrap(), Ok(123));
+    }
+
+    #[test]
+    fn throws() {
+        let handle =
+            unsafe { spawn_catching::<(), String>(|| throw(String::from("Hello, world!"))) };
+        assert_eq!(handle.join().unwrap().unwrap_err(), "Hello, world!");
+    }
+
+    #[test]
+    fn panics() {
+        let handle = unsafe { spawn_catching::<(), String>(|| panic!("Hello, world!")) };
+        handle.join().unwrap_err();
+    }
+}
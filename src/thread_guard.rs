@@ -0,0 +1,76 @@
+use super::{
+    api::in_catch_frame,
+    backend::{ActiveBackend, CaughtException, FromCaught, ThrowByValue},
+    debug::Expected,
+    describe::abort_caught,
+};
+use core::any::TypeId;
+
+/// Guard the root of a thread against escaped exceptions.
+///
+/// Call this function at the very top of a thread's entry point, wrapping the rest of the thread.
+/// Exceptions that escape all user catches would otherwise unwind into the runtime's thread entry
+/// code, e.g. the frame of [`std::thread::spawn`], which is undefined behavior. This function
+/// catches them instead and aborts the process with a message saying that an exception escaped.
+/// If the exception was thrown by [`throw_err`](crate::throw_err), the message includes the error.
+///
+/// The exception can't be recovered, since its type is unknown at this point. To handle exceptions
+/// of a known type, use [`catch`](crate::catch) inside the guard.
+///
+/// [`spawn_catching`](crate::thread::spawn_catching) installs this guard automatically.
+///
+/// Rust panics are propagated as-is and not caught.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, install_thread_guard, throw};
+///
+/// let handle = std::thread::spawn(|| {
+///     install_thread_guard(|| {
+///         // SAFETY: Only `i32` is thrown.
+///         unsafe { catch::<(), i32>(|| throw(1)) }
+///     })
+/// });
+/// assert_eq!(handle.join().unwrap(), Err(1));
+/// ```
+#[inline]
+pub fn install_thread_guard<R>(func: impl FnOnce() -> R) -> R {
+    // SAFETY: `Escaped` accepts exceptions of all types.
+    match in_catch_frame(Expected::Any, || unsafe {
+        ActiveBackend::intercept_dispatch::<_, R, Escaped>(func)
+    }) {
+        Ok(value) => value,
+        Err(escaped) => match escaped {},
+    }
+}
+
+/// An exception that reached [`install_thread_guard`].
+enum Escaped {}
+
+impl FromCaught for Escaped {
+    #[inline]
+    unsafe fn from_caught(_type_id: TypeId, ex: impl CaughtException) -> Self {
+        abort_caught(
+            "A Lithium exception escaped to the root of a thread. The process will now terminate.\n",
+            &ex,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, throw};
+
+    #[test]
+    fn returns() {
+        assert_eq!(install_thread_guard(|| 123), 123);
+    }
+
+    #[test]
+    fn nested_catch() {
+        let result = install_thread_guard(|| unsafe { catch::<(), i32>(|| throw(1)) });
+        assert_eq!(result, Err(1));
+    }
+}
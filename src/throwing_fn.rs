@@ -0,0 +1,199 @@
+use super::api::catch;
+use core::marker::PhantomData;
+
+macro_rules! define_throwing_fn {
+    (
+        $(#[$outer:meta])*
+        $name:ident, $fn_trait:ident, [$($self_ref:tt)*]
+    ) => {
+        $(#[$outer])*
+        ///
+        /// `Args` is a tuple of argument types, `R` is the return type, and `E` is the type of
+        /// exceptions the callback may throw. `F` is the type of the wrapped callback, usually
+        /// spelled as `impl Trait` in signatures.
+        ///
+        /// Constructing the wrapper is `unsafe`, since the creator vouches that the callback only
+        /// throws `E`. In exchange, the callback can be invoked under [`catch`] without an `unsafe`
+        /// block.
+        pub struct $name<Args, R, E, F> {
+            func: F,
+            phantom: PhantomData<fn(Args) -> (R, E)>,
+        }
+
+        // Not derived, as that would require `Args`, `R` and `E` to be `Clone` too.
+        impl<Args, R, E, F: Clone> Clone for $name<Args, R, E, F> {
+            #[inline]
+            fn clone(&self) -> Self {
+                Self {
+                    func: self.func.clone(),
+                    phantom: PhantomData,
+                }
+            }
+        }
+
+        impl<Args, R, E, F: Copy> Copy for $name<Args, R, E, F> {}
+
+        impl<Args, R, E, F> $name<Args, R, E, F> {
+            /// Extract the wrapped callback.
+            #[inline]
+            pub fn into_inner(self) -> F {
+                self.func
+            }
+        }
+
+        define_throwing_fn!(@arity $name, $fn_trait, [$($self_ref)*]);
+        define_throwing_fn!(@arity $name, $fn_trait, [$($self_ref)*] A1 a1);
+        define_throwing_fn!(@arity $name, $fn_trait, [$($self_ref)*] A1 a1, A2 a2);
+        define_throwing_fn!(@arity $name, $fn_trait, [$($self_ref)*] A1 a1, A2 a2, A3 a3);
+        define_throwing_fn!(@arity $name, $fn_trait, [$($self_ref)*] A1 a1, A2 a2, A3 a3, A4 a4);
+        define_throwing_fn!(
+            @arity $name, $fn_trait, [$($self_ref)*] A1 a1, A2 a2, A3 a3, A4 a4, A5 a5
+        );
+        define_throwing_fn!(
+            @arity $name, $fn_trait, [$($self_ref)*] A1 a1, A2 a2, A3 a3, A4 a4, A5 a5, A6 a6
+        );
+    };
+
+    (@arity $name:ident, $fn_trait:ident, [$($self_ref:tt)*] $($ty:ident $arg:ident),*) => {
+        impl<$($ty,)* R, E, F: $fn_trait($($ty),*) -> R> $name<($($ty,)*), R, E, F> {
+            /// Wrap a callback.
+            ///
+            /// # Safety
+            ///
+            /// `func` must only throw exceptions of type `E`. See the safety section of
+            /// [this crate](crate) for more information.
+            #[inline]
+            pub const unsafe fn new(func: F) -> Self {
+                Self {
+                    func,
+                    phantom: PhantomData,
+                }
+            }
+
+            /// Invoke the callback, letting exceptions propagate.
+            ///
+            /// # Safety
+            ///
+            /// The caller must uphold the safety requirements of [`throw`](fn@crate::throw) for
+            /// exceptions of type `E`.
+            #[inline]
+            pub unsafe fn call($($self_ref)* self, $($arg: $ty),*) -> R {
+                (self.func)($($arg),*)
+            }
+
+            /// Invoke the callback, catching exceptions.
+            ///
+            /// This is equivalent to calling [`catch`] on the callback.
+            #[expect(
+                clippy::missing_errors_doc,
+                reason = "`Err` value is described immediately"
+            )]
+            #[inline]
+            pub fn catch($($self_ref)* self, $($arg: $ty),*) -> Result<R, E> {
+                // SAFETY: The callback only throws `E` by the safety requirement of `new`.
+                unsafe { catch(|| (self.func)($($arg),*)) }
+            }
+        }
+    };
+}
+
+define_throwing_fn!(
+    /// A callback that may throw an exception, callable once.
+    ThrowingFnOnce, FnOnce, []
+);
+
+define_throwing_fn!(
+    /// A callback that may throw an exception, callable multiple times by mutable reference.
+    ThrowingFnMut, FnMut, [&mut]
+);
+
+define_throwing_fn!(
+    /// A callback that may throw an exception, callable multiple times by shared reference.
+    ThrowingFn, Fn, [&]
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::throw;
+    use alloc::string::String;
+
+    fn sum_all(
+        f: ThrowingFnMut<(i32,), i32, String, impl FnMut(i32) -> i32>,
+    ) -> Result<i32, String> {
+        let mut f = f;
+        let mut sum = 0;
+        for i in 0..5 {
+            sum += f.catch(i)?;
+        }
+        Ok(sum)
+    }
+
+    #[test]
+    fn once() {
+        let f =
+            unsafe { ThrowingFnOnce::<(), (), String, _>::new(|| throw(String::from("Oops!"))) };
+        assert_eq!(f.catch().unwrap_err(), "Oops!");
+    }
+
+    #[test]
+    fn once_ok() {
+        let s = String::from("Hello");
+        let f = unsafe { ThrowingFnOnce::<(&str,), String, (), _>::new(|x: &str| s + x) };
+        assert_eq!(f.catch(", world!").unwrap(), "Hello, world!");
+    }
+
+    #[test]
+    fn mutable() {
+        let f = unsafe {
+            ThrowingFnMut::<(i32,), _, _, _>::new(|x: i32| {
+                if x == 3 {
+                    throw(String::from("Three"));
+                }
+                x
+            })
+        };
+        assert_eq!(sum_all(f).unwrap_err(), "Three");
+
+        let f = unsafe { ThrowingFnMut::<(i32,), _, _, _>::new(|x: i32| x) };
+        assert_eq!(sum_all(f).unwrap(), 10);
+    }
+
+    #[test]
+    fn shared() {
+        let f = unsafe {
+            ThrowingFn::<(i32, i32), i32, i32, _>::new(|a, b| {
+                if b == 0 {
+                    throw(a);
+                }
+                a / b
+            })
+        };
+        assert_eq!(f.catch(6, 3), Ok(2));
+        assert_eq!(f.catch(6, 0), Err(6));
+    }
+
+    #[test]
+    fn call_propagates() {
+        let f = unsafe { ThrowingFn::<(), (), i32, _>::new(|| throw(1)) };
+        let result: Result<(), i32> = unsafe { catch(|| f.call()) };
+        assert_eq!(result, Err(1));
+    }
+
+    #[test]
+    fn copy_with_non_clone_cause() {
+        #[derive(Debug, PartialEq)]
+        struct NotClone;
+
+        let f = unsafe {
+            ThrowingFn::<(bool,), (), NotClone, _>::new(|ok| {
+                if !ok {
+                    throw(NotClone)
+                }
+            })
+        };
+        let g = f;
+        assert_eq!(f.catch(false), Err(NotClone));
+        assert_eq!(g.catch(true), Ok(()));
+    }
+}
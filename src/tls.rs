@@ -0,0 +1,333 @@
+//! Per-thread state.
+//!
+//! Lithium keeps a small amount of state per thread: the exception stack on most backends, the
+//! number of unwinding exceptions, and debugging counters in debug builds. By default, this state
+//! is stored in a thread local, using `#[thread_local]` on nightly and `std::thread_local!` on
+//! stable.
+//!
+//! Targets that support neither, such as RTOSes and custom kernels, can supply the storage
+//! themselves. Build with the `LITHIUM_THREAD_LOCAL=extern` environment variable set, allocate a
+//! [`ThreadState`] for each thread of execution, and register a provider with
+//! [`thread_state_provider!`](crate::thread_state_provider):
+//!
+//! ```rust,ignore
+//! use lithium::tls::ThreadState;
+//!
+//! fn current_thread_state() -> &'static ThreadState {
+//!     // Look up the state of the current task in the RTOS task control block, initialized with
+//!     // `ThreadState::new()` when the task was created.
+//!     my_rtos::current_task().lithium_state()
+//! }
+//!
+//! // SAFETY: Each task has its own state, which lives as long as the task.
+//! lithium::thread_state_provider!(unsafe current_thread_state);
+//! ```
+//!
+//! The provider must return the same object each time it's invoked from the same thread, must not
+//! return the same object for different threads, and must keep the object alive until the thread
+//! exits. It must not throw exceptions or panic. Lithium can't check any of this, so registering
+//! the provider is unsafe.
+//!
+//! This is also how separately built copies of Lithium in one process, such as those linked into
+//! different dynamic libraries, can share state and catch each other's exceptions: the provider is
+//! looked up by a symbol name that includes the ABI version, so all copies with the same version
+//! use a single provider. The copies must be built with the same backend, features, and
+//! configuration; a copy given state created by an incompatible build aborts the process on first
+//! use. The provider is a Rust function rather than a C ABI symbol, so the copies must also be
+//! built by compatible compilers, and mismatches are only detected when a thread first touches its
+//! state, not at startup.
+//!
+//! Exceptions that are still on the exception stack when the state is dropped, e.g. because an
+//! [`InFlightException`](crate::InFlightException) was leaked with [`core::mem::forget`], are
+//! released then, and their causes are dropped if they are still in place. In debug builds and with
+//! the `checked` feature, the leak is reported on stderr. Memory cached for heap-spilled exceptions
+//! is freed at the same time. With `std::thread_local!`, this happens when the thread exits.
+//! `#[thread_local]` statics don't run destructors, so when `std` is available, Lithium registers a
+//! `std::thread_local!` destructor releasing the state on the first throw; without `std`, the state
+//! is only released if it's dropped by the embedder with `LITHIUM_THREAD_LOCAL=extern`. The
+//! destructors of leaked causes must not use Lithium.
+
+#[cfg(thread_local = "extern")]
+use super::abi::check_thread_state;
+use super::abi::AbiTag;
+use super::debug::Counters;
+use super::rethrows::RethrowCounts;
+#[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+use super::stacked_exceptions::{in_flight_count as in_flight_count_impl, reset, ExceptionState};
+#[cfg(feature = "stats")]
+use super::stats::EventCounters;
+#[cfg(feature = "test-util")]
+use super::test_util::Injection;
+use core::cell::Cell;
+
+/// Per-thread Lithium state.
+///
+/// The contents are opaque. This type is only public for use with
+/// [`thread_state_provider!`](crate::thread_state_provider).
+// `repr(C)` keeps the tag at the start, so that other builds of Lithium can check it.
+#[repr(C)]
+pub struct ThreadState {
+    pub(crate) abi: AbiTag,
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    pub(crate) exceptions: ExceptionState,
+    pub(crate) debug: Counters,
+    pub(crate) rethrows: RethrowCounts,
+    /// The number of exceptions thrown on this thread and not yet caught.
+    pub(crate) unwinding: Cell<usize>,
+    /// The address of the exception last thrown by the panic backend, or zero if it was caught.
+    #[cfg(backend = "panic")]
+    pub(crate) pending_throw: Cell<usize>,
+    #[cfg(feature = "stats")]
+    pub(crate) stats: EventCounters,
+    /// The fault injection armed on this thread.
+    #[cfg(feature = "test-util")]
+    pub(crate) injection: Cell<Option<Injection>>,
+}
+
+impl ThreadState {
+    /// Create state for a new thread.
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            abi: AbiTag::CURRENT,
+            #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+            exceptions: ExceptionState::new(),
+            debug: Counters::new(),
+            rethrows: RethrowCounts::new(),
+            unwinding: Cell::new(0),
+            #[cfg(backend = "panic")]
+            pending_throw: Cell::new(0),
+            #[cfg(feature = "stats")]
+            stats: EventCounters::new(),
+            #[cfg(feature = "test-util")]
+            injection: Cell::new(None),
+        }
+    }
+}
+
+impl Default for ThreadState {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Release auxiliary per-thread allocations, checking that no exceptions are in flight.
+///
+/// Long-running threads, such as server workers, can call this at request boundaries to verify
+/// that no exceptions leaked, e.g. through a forgotten
+/// [`InFlightException`](crate::InFlightException).
+///
+/// If an [`ExceptionStack`](crate::ExceptionStack) is entered, it is reset instead of the stack of
+/// the thread.
+///
+/// # Panics
+///
+/// Panics if exceptions are in flight on the current exception stack. On backends that don't use an
+/// exception stack, such as SEH, this function does nothing.
+#[inline]
+pub fn reset_thread_state() {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    reset();
+}
+
+/// Get the number of exceptions in flight on the current thread.
+///
+/// This counts exceptions that have been thrown and not yet finalized: both exceptions that are
+/// being propagated and exceptions kept alive by handles such as
+/// [`InFlightException`](crate::InFlightException). Frameworks building cleanup on top of
+/// [`intercept`](crate::intercept) can use this to check their invariants, and tests can use it to
+/// detect leaked exceptions.
+///
+/// If an [`ExceptionStack`](crate::ExceptionStack) is entered, its exceptions are counted instead
+/// of those of the thread. On backends that don't use an exception stack, such as SEH, this
+/// function always returns zero.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{in_flight_count, intercept, throw};
+///
+/// // SAFETY: the closure only throws `i32`
+/// let (_, in_flight) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+/// # #[cfg(not(target_env = "msvc"))]
+/// assert_eq!(in_flight_count(), 1);
+/// drop(in_flight);
+/// assert_eq!(in_flight_count(), 0);
+/// ```
+#[inline]
+#[must_use]
+pub fn in_flight_count() -> usize {
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    return in_flight_count_impl();
+
+    #[cfg(not(any(backend = "itanium", backend = "emscripten", backend = "panic")))]
+    return 0;
+}
+
+/// Register the function providing [`ThreadState`] for the current thread.
+///
+/// This is only used when Lithium is built with `LITHIUM_THREAD_LOCAL=extern`. See
+/// [the module documentation](crate::tls) for the requirements on the provider.
+///
+/// The provider must be registered exactly once in the final binary. Separately built copies of
+/// Lithium with the same ABI version share the provider, and thus the per-thread state; see
+/// [the module documentation](crate::tls).
+///
+/// # Safety
+///
+/// The invocation must be marked with `unsafe`, as in `thread_state_provider!(unsafe func)`. The
+/// provider must return the same [`ThreadState`] each time it's called from the same thread, must
+/// never return the same object for different threads, and must keep the object alive until the
+/// thread exits. It must not throw exceptions or panic.
+///
+/// Registering a provider without `unsafe` doesn't compile:
+///
+/// ```compile_fail
+/// use lithium::tls::ThreadState;
+///
+/// fn leak() -> &'static ThreadState {
+///     Box::leak(Box::new(ThreadState::new()))
+/// }
+///
+/// lithium::thread_state_provider!(leak);
+/// ```
+#[macro_export]
+macro_rules! thread_state_provider {
+    (unsafe $func:path) => {
+        // The version suffix is `ABI_VERSION`.
+        #[unsafe(no_mangle)]
+        fn __lithium_thread_state_v1() -> &'static $crate::tls::ThreadState {
+            $func()
+        }
+    };
+}
+
+#[cfg(thread_local = "extern")]
+extern "Rust" {
+    // The version suffix is `ABI_VERSION`.
+    fn __lithium_thread_state_v1() -> &'static ThreadState;
+}
+
+/// A wrapper asserting `Sync` for the global that replaces thread locals on single-threaded
+/// targets.
+#[cfg(thread_local = "static")]
+struct SingleThreaded<T>(T);
+
+// SAFETY: `thread_local = "static"` is only selected for targets with a single thread of execution,
+// or when the final binary is built with `LITHIUM_THREAD_LOCAL=static` and thereby promises that
+// Lithium is used from a single thread. Either way, the value is never accessed concurrently.
+// Interrupt handlers can only access it reentrantly, which `without_interrupts` makes sound with
+// the `critical-section` feature.
+#[cfg(thread_local = "static")]
+unsafe impl<T> Sync for SingleThreaded<T> {}
+
+#[cfg(thread_local = "std")]
+std::thread_local! {
+    /// Thread-local state.
+    static STATE: ThreadState = const { ThreadState::new() };
+}
+
+#[cfg(thread_local = "attribute")]
+#[thread_local]
+static STATE: ThreadState = const { ThreadState::new() };
+
+#[cfg(thread_local = "static")]
+static STATE: SingleThreaded<ThreadState> = SingleThreaded(ThreadState::new());
+
+/// Get a reference to the state of the current thread.
+///
+/// # Safety
+///
+/// The reference is lifetime-extended to `'static` and is only valid for access until the end of
+/// the thread. This includes at least the call frame of the immediate caller.
+// Unfortunately, replacing this unsafe API with a safe `with_state` doesn't work, as `with` fails
+// to inline.
+#[inline]
+pub(crate) unsafe fn get_state() -> &'static ThreadState {
+    #[cfg(thread_local = "std")]
+    // SAFETY: We require the caller to not use the reference anywhere near the end of the thread,
+    // so as long as `with` succeeds, there is no problem.
+    return STATE.with(|r| unsafe { core::mem::transmute(r) });
+
+    #[cfg(thread_local = "attribute")]
+    // SAFETY: We require the caller to not use the reference anywhere near the end of the thread,
+    // so if `&STATE` is sound in the first place, there is no problem.
+    return unsafe { core::mem::transmute::<&ThreadState, &'static ThreadState>(&STATE) };
+
+    #[cfg(thread_local = "static")]
+    return &STATE.0;
+
+    #[cfg(thread_local = "extern")]
+    {
+        // SAFETY: The embedder guarantees that the provider returns the state of the current
+        // thread, which lives until the thread exits.
+        let state = unsafe { __lithium_thread_state_v1() };
+        check_thread_state(state);
+        state
+    }
+
+    #[cfg(thread_local = "unimplemented")]
+    compile_error!("Unable to compile Lithium on a platform does not support thread locals")
+}
+
+/// Run an operation on the thread state without being interrupted, if the `critical-section`
+/// feature is enabled.
+///
+/// Pushing and popping exceptions update the state in several steps, so an interrupt handler that
+/// throws in the middle of an operation would corrupt it. Interrupt handlers run to completion, so
+/// as long as each operation is atomic, the exceptions of a handler are pushed and popped above the
+/// exceptions of the code it interrupted, and the state stays consistent.
+#[inline(always)]
+pub(crate) fn without_interrupts<T>(func: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "critical-section")]
+    return critical_section::with(|_| func());
+
+    #[cfg(not(feature = "critical-section"))]
+    func()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reset_empty() {
+        let result: Result<(), i32> = unsafe { crate::catch(|| crate::throw(1)) };
+        assert_eq!(result, Err(1));
+        reset_thread_state();
+    }
+
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    #[test]
+    #[should_panic = "reset while exceptions are in flight"]
+    fn reset_in_flight() {
+        let (_, _in_flight) =
+            unsafe { crate::intercept::<(), i32>(|| crate::throw(1)) }.unwrap_err();
+        reset_thread_state();
+    }
+
+    #[cfg(any(backend = "itanium", backend = "emscripten", backend = "panic"))]
+    #[test]
+    fn in_flight() {
+        assert_eq!(in_flight_count(), 0);
+        let (_, outer) = unsafe { crate::intercept::<(), i32>(|| crate::throw(1)) }.unwrap_err();
+        let (_, inner) = unsafe { crate::intercept::<(), i32>(|| crate::throw(2)) }.unwrap_err();
+        assert_eq!(in_flight_count(), 2);
+        drop(inner);
+        assert_eq!(in_flight_count(), 1);
+        drop(outer);
+        assert_eq!(in_flight_count(), 0);
+    }
+
+    #[test]
+    fn per_thread() {
+        let state = core::ptr::from_ref(unsafe { get_state() }).addr();
+        assert_eq!(state, core::ptr::from_ref(unsafe { get_state() }).addr());
+        let other = std::thread::spawn(|| core::ptr::from_ref(unsafe { get_state() }).addr())
+            .join()
+            .unwrap();
+        assert_ne!(state, other);
+    }
+}
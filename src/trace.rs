@@ -0,0 +1,311 @@
+//! Integration with [`tracing`] and [`log`].
+//!
+//! With the `tracing` feature enabled, Lithium emits events under the `lithium` target when
+//! exceptions are thrown, rethrown, or caught, and when the process is aborted due to a misplaced
+//! exception. Throws, rethrows and catches are logged at the `TRACE` level, aborts at the `ERROR`
+//! level. Each event records the name of the active backend and, where known, the type of the
+//! exception and the location it was thrown from.
+//!
+//! The `log` feature emits the same information as log records under the same target, at the
+//! `Debug` and `Error` levels respectively.
+
+use super::backend::BACKEND_NAME;
+use super::debug::Expected;
+use super::location::caller;
+#[cfg(feature = "log")]
+use super::location::ThrowLocation;
+use core::any::type_name;
+#[cfg(feature = "log")]
+use core::fmt;
+use core::fmt::Display;
+
+/// Formats a throw site as ` at <location>` if locations are recorded, and as nothing otherwise.
+#[cfg(feature = "log")]
+struct At(ThrowLocation);
+
+#[cfg(feature = "log")]
+impl Display for At {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "location")]
+        return write!(f, " at {}", self.0.get());
+
+        #[cfg(not(feature = "location"))]
+        {
+            let _ = f;
+            Ok(())
+        }
+    }
+}
+
+/// Emit an event for a thrown exception of type `E`.
+///
+/// This is called for rethrows too, after [`rethrow`].
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub fn throw<E>() {
+    let location = caller();
+
+    #[cfg(all(feature = "tracing", feature = "location"))]
+    tracing::trace!(
+        target: "lithium",
+        backend = BACKEND_NAME,
+        exception = type_name::<E>(),
+        location = %location.get(),
+        "exception thrown",
+    );
+
+    #[cfg(all(feature = "tracing", not(feature = "location")))]
+    tracing::trace!(
+        target: "lithium",
+        backend = BACKEND_NAME,
+        exception = type_name::<E>(),
+        "exception thrown",
+    );
+
+    #[cfg(feature = "log")]
+    log::debug!(
+        target: "lithium",
+        "exception of type `{}` thrown{} ({BACKEND_NAME} backend)",
+        type_name::<E>(),
+        At(location),
+    );
+
+    #[cfg(not(any(feature = "log", feature = "location")))]
+    let _ = location;
+}
+
+/// Emit an event for an exception rethrown as type `E`.
+#[cfg_attr(feature = "location", track_caller)]
+#[inline]
+pub fn rethrow<E>() {
+    let location = caller();
+
+    #[cfg(all(feature = "tracing", feature = "location"))]
+    tracing::trace!(
+        target: "lithium",
+        backend = BACKEND_NAME,
+        exception = type_name::<E>(),
+        location = %location.get(),
+        "exception rethrown",
+    );
+
+    #[cfg(all(feature = "tracing", not(feature = "location")))]
+    tracing::trace!(
+        target: "lithium",
+        backend = BACKEND_NAME,
+        exception = type_name::<E>(),
+        "exception rethrown",
+    );
+
+    #[cfg(feature = "log")]
+    log::debug!(
+        target: "lithium",
+        "exception rethrown as type `{}`{} ({BACKEND_NAME} backend)",
+        type_name::<E>(),
+        At(location),
+    );
+
+    #[cfg(not(any(feature = "log", feature = "location")))]
+    let _ = location;
+}
+
+/// Emit an event for an exception caught by a frame accepting `expected` types.
+#[inline]
+pub fn caught(expected: Expected) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        target: "lithium",
+        backend = BACKEND_NAME,
+        expected = %expected,
+        "exception caught",
+    );
+
+    #[cfg(feature = "log")]
+    log::debug!(
+        target: "lithium",
+        "exception caught by a frame expecting {expected} ({BACKEND_NAME} backend)",
+    );
+}
+
+/// Emit an event for an abort, optionally with a description of the offending exception.
+#[cold]
+pub fn abort(message: &str, described: Option<&dyn Display>) {
+    let message = message.trim_end();
+
+    #[cfg(feature = "tracing")]
+    if let Some(described) = described {
+        tracing::error!(
+            target: "lithium",
+            backend = BACKEND_NAME,
+            exception = %described,
+            "{message}",
+        );
+    } else {
+        tracing::error!(target: "lithium", backend = BACKEND_NAME, "{message}");
+    }
+
+    #[cfg(feature = "log")]
+    if let Some(described) = described {
+        log::error!(
+            target: "lithium",
+            "{message} The exception was: {described} ({BACKEND_NAME} backend)",
+        );
+    } else {
+        log::error!(target: "lithium", "{message} ({BACKEND_NAME} backend)");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "tracing")]
+    mod events {
+        use crate::{catch, intercept, throw};
+        use alloc::{format, string::String, sync::Arc, vec::Vec};
+        use core::fmt;
+        use std::sync::Mutex;
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        /// Records the messages of all events.
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        /// Extracts the message of an event.
+        struct Message(Option<String>);
+
+        impl Visit for Message {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = Some(format!("{value:?}"));
+                }
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut message = Message(None);
+                event.record(&mut message);
+                self.0.lock().unwrap().extend(message.0);
+            }
+
+            fn enter(&self, _span: &span::Id) {}
+
+            fn exit(&self, _span: &span::Id) {}
+        }
+
+        fn record(func: impl FnOnce()) -> Vec<String> {
+            let recorder = Arc::new(Recorder::default());
+            tracing::subscriber::with_default(Arc::clone(&recorder), func);
+            let events = recorder.0.lock().unwrap().clone();
+            events
+        }
+
+        #[test]
+        fn throw_catch() {
+            let events = record(|| {
+                let result = unsafe { catch::<(), i32>(|| throw(1)) };
+                assert_eq!(result, Err(1));
+            });
+            assert_eq!(events, ["exception thrown", "exception caught"]);
+        }
+
+        #[test]
+        fn rethrow() {
+            let events = record(|| {
+                let result = unsafe {
+                    catch::<(), i32>(|| {
+                        let (_, ex) = intercept::<(), i32>(|| throw(1)).unwrap_err();
+                        ex.rethrow(2)
+                    })
+                };
+                assert_eq!(result, Err(2));
+            });
+            assert_eq!(
+                events,
+                [
+                    "exception thrown",
+                    "exception caught",
+                    "exception rethrown",
+                    "exception thrown",
+                    "exception caught",
+                ],
+            );
+        }
+    }
+
+    #[cfg(feature = "log")]
+    mod records {
+        use crate::BACKEND_NAME;
+        use crate::{catch, throw};
+        use alloc::{
+            format,
+            string::{String, ToString},
+            vec::Vec,
+        };
+        use core::cell::RefCell;
+        use log::{LevelFilter, Log, Metadata, Record};
+        use std::sync::Once;
+
+        std::thread_local! {
+            static RECORDS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        }
+
+        /// Records the messages of log records emitted on the current thread.
+        struct Logger;
+
+        impl Log for Logger {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record<'_>) {
+                if record.target() == "lithium" {
+                    RECORDS.with_borrow_mut(|records| records.push(record.args().to_string()));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        fn record(func: impl FnOnce()) -> Vec<String> {
+            static INIT: Once = Once::new();
+            INIT.call_once(|| {
+                log::set_logger(&Logger).unwrap();
+                log::set_max_level(LevelFilter::Trace);
+            });
+            RECORDS.with_borrow_mut(Vec::clear);
+            func();
+            RECORDS.take()
+        }
+
+        #[test]
+        fn throw_catch() {
+            let records = record(|| {
+                let result = unsafe { catch::<(), i32>(|| throw(1)) };
+                assert_eq!(result, Err(1));
+            });
+            assert_eq!(records.len(), 2);
+            assert!(records[0].starts_with("exception of type `i32` thrown"));
+            assert!(records[0].ends_with(&format!("({BACKEND_NAME} backend)")));
+            assert_eq!(
+                records[1],
+                format!("exception caught by a frame expecting `i32` ({BACKEND_NAME} backend)"),
+            );
+        }
+    }
+}
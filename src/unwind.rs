@@ -0,0 +1,218 @@
+use super::api::{catch, intercept, throw};
+use alloc::boxed::Box;
+use core::any::Any;
+use core::panic::{AssertUnwindSafe, UnwindSafe};
+use std::panic::{catch_unwind, resume_unwind};
+
+/// Call a function, converting Rust panics into exceptions.
+///
+/// If `func` returns a value, it's returned as-is. If `func` panics, the panic is caught, and its
+/// payload is converted to `E` and thrown as an exception. Exceptions thrown by `func` are
+/// propagated unchanged.
+///
+/// This is useful for calling third-party code that reports errors by panicking from code that
+/// uses Lithium as its error channel.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`, and the requirements of [`throw`](fn@throw) apply
+/// to the exceptions this function throws. See the safety section of [this crate](crate) for more
+/// information.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, catch_unwind_into};
+/// use std::any::Any;
+///
+/// struct Panicked(Box<dyn Any + Send>);
+///
+/// impl From<Box<dyn Any + Send>> for Panicked {
+///     fn from(payload: Box<dyn Any + Send>) -> Self {
+///         Self(payload)
+///     }
+/// }
+///
+/// // SAFETY: `catch_unwind_into` only throws `Panicked`.
+/// let result = unsafe {
+///     catch::<(), Panicked>(|| catch_unwind_into::<(), Panicked>(|| panic!("Oops!")))
+/// };
+/// assert_eq!(*result.err().unwrap().0.downcast::<&str>().unwrap(), "Oops!");
+/// ```
+#[inline]
+pub unsafe fn catch_unwind_into<R, E: From<Box<dyn Any + Send>>>(
+    func: impl FnOnce() -> R + UnwindSafe,
+) -> R {
+    // Exceptions may be implemented as panics or foreign exceptions, neither of which should reach
+    // `catch_unwind`, so intercept them before they leave `func` and rethrow them afterwards.
+    // SAFETY: `func` only throws `E` by the safety requirements.
+    let intercepted = AssertUnwindSafe(|| unsafe { intercept::<R, E>(func) });
+    match catch_unwind(intercepted) {
+        Ok(Ok(value)) => value,
+        // SAFETY: No exceptions were thrown since `intercept` returned. The rest is required
+        // transitively.
+        Ok(Err((cause, in_flight))) => unsafe { in_flight.rethrow(cause) },
+        // SAFETY: Required transitively.
+        Err(payload) => unsafe { throw(E::from(payload)) },
+    }
+}
+
+/// The panic payload carrying an exception across an `extern "C-unwind"` boundary.
+struct Bridged<E>(E);
+
+/// Call a function, converting exceptions into Rust panics.
+///
+/// If `func` returns a value, it's returned as-is. If `func` throws an exception, it's caught, and
+/// its cause is resumed as a panic with [`resume_unwind`], which doesn't invoke the panic hook.
+/// Panics are propagated unchanged.
+///
+/// Lithium exceptions must not cross `extern "C-unwind"` boundaries, as the other side can't catch
+/// them correctly, but Rust panics can. Call this function at the top of an `extern "C-unwind"`
+/// function to let its exceptions through, and [`unbridge`] around the call on the other side to
+/// turn them back into exceptions. [`bridge_unwind!`](crate::bridge_unwind) generates such
+/// functions.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`. See the safety section of [this crate](crate) for
+/// more information.
+#[inline]
+pub unsafe fn bridge<R, E: Send + 'static>(func: impl FnOnce() -> R) -> R {
+    // SAFETY: `func` only throws `E` by the safety requirements.
+    match unsafe { catch::<R, E>(func) } {
+        Ok(value) => value,
+        Err(cause) => resume_unwind(Box::new(Bridged(cause))),
+    }
+}
+
+/// Call a function, converting panics raised by [`bridge`] back into exceptions.
+///
+/// If `func` returns a value, it's returned as-is. If `func` panics with a cause of type `E`
+/// converted by [`bridge`], the cause is thrown as an exception. Other panics are propagated
+/// unchanged.
+///
+/// # Safety
+///
+/// `func` must only throw exceptions of type `E`, and the requirements of [`throw`](fn@throw) apply
+/// to the exceptions this function throws. See the safety section of [this crate](crate) for more
+/// information.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{bridge_unwind, catch, throw, unbridge};
+///
+/// bridge_unwind! {
+///     throws &'static str;
+///     extern "C-unwind" fn checked_sqrt(x: f64) -> f64 {
+///         if x < 0.0 {
+///             // SAFETY: `bridge_unwind!` catches `&'static str`.
+///             unsafe { throw::<&'static str>("negative input") };
+///         }
+///         x.sqrt()
+///     }
+/// }
+///
+/// // SAFETY: `checked_sqrt` only throws `&'static str`, converted to a panic and back.
+/// let result = unsafe {
+///     catch::<_, &'static str>(|| unbridge::<_, &'static str>(|| checked_sqrt(-1.0)))
+/// };
+/// assert_eq!(result, Err("negative input"));
+/// ```
+#[inline]
+pub unsafe fn unbridge<R, E: Send + 'static>(func: impl FnOnce() -> R) -> R {
+    // As in `catch_unwind_into`, exceptions thrown by `func` directly must not reach
+    // `catch_unwind`.
+    // SAFETY: `func` only throws `E` by the safety requirements.
+    let intercepted = AssertUnwindSafe(|| unsafe { intercept::<R, E>(func) });
+    match catch_unwind(intercepted) {
+        Ok(Ok(value)) => value,
+        // SAFETY: No exceptions were thrown since `intercept` returned. The rest is required
+        // transitively.
+        Ok(Err((cause, in_flight))) => unsafe { in_flight.rethrow(cause) },
+        Err(payload) => match payload.downcast::<Bridged<E>>() {
+            // SAFETY: Required transitively.
+            Ok(bridged) => unsafe { throw(bridged.0) },
+            Err(payload) => resume_unwind(payload),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::String;
+
+    struct Panicked(Box<dyn Any + Send>);
+
+    impl From<Box<dyn Any + Send>> for Panicked {
+        fn from(payload: Box<dyn Any + Send>) -> Self {
+            Self(payload)
+        }
+    }
+
+    #[test]
+    fn returns() {
+        let result = unsafe { catch::<i32, Panicked>(|| catch_unwind_into::<_, Panicked>(|| 1)) };
+        assert_eq!(result.ok(), Some(1));
+    }
+
+    #[test]
+    fn converts_panic() {
+        let result = unsafe {
+            catch::<(), Panicked>(|| {
+                catch_unwind_into::<(), Panicked>(|| std::panic::panic_any(String::from("Oops!")));
+            })
+        };
+        let payload = result.err().unwrap().0;
+        assert_eq!(*payload.downcast::<String>().unwrap(), "Oops!");
+    }
+
+    #[test]
+    fn passes_exceptions() {
+        let result = unsafe {
+            catch::<(), Panicked>(|| {
+                catch_unwind_into::<(), Panicked>(|| throw(Panicked(Box::new(123i32))));
+            })
+        };
+        let payload = result.err().unwrap().0;
+        assert_eq!(*payload.downcast::<i32>().unwrap(), 123);
+    }
+
+    bridge_unwind! {
+        throws i32;
+        extern "C-unwind" fn negate(x: i32) -> i32 {
+            if x == 0 {
+                unsafe { throw::<i32>(-1) };
+            }
+            if x == 1 {
+                std::panic::resume_unwind(Box::new("Oops!"));
+            }
+            -x
+        }
+    }
+
+    #[test]
+    fn bridge_returns() {
+        let result = unsafe { catch::<i32, i32>(|| unbridge::<_, i32>(|| negate(2))) };
+        assert_eq!(result, Ok(-2));
+    }
+
+    #[test]
+    fn bridge_throws() {
+        let result = unsafe { catch::<i32, i32>(|| unbridge::<_, i32>(|| negate(0))) };
+        assert_eq!(result, Err(-1));
+    }
+
+    #[test]
+    fn bridge_passes_panics() {
+        let result = catch_unwind(|| unsafe { unbridge::<_, i32>(|| negate(1)) });
+        assert_eq!(*result.unwrap_err().downcast::<&str>().unwrap(), "Oops!");
+    }
+
+    #[test]
+    fn bridge_is_panic() {
+        let result = catch_unwind(|| negate(0));
+        assert!(result.unwrap_err().is::<Bridged<i32>>());
+    }
+}
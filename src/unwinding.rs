@@ -0,0 +1,102 @@
+use super::tls::get_state;
+
+/// Check whether a Lithium exception is unwinding on the current thread.
+///
+/// This returns `true` while an exception thrown on this thread is propagating towards its catch
+/// frame, e.g. when called from a [`Drop`] implementation that runs during unwinding, and `false`
+/// otherwise. Exceptions that have been caught, such as those held in an
+/// [`InFlightException`](crate::InFlightException), are not considered unwinding.
+///
+/// Unlike [`std::thread::panicking`], the answer is derived from Lithium's own per-thread state,
+/// so it's the same on all backends: Lithium exceptions are reported even if the backend doesn't
+/// implement them as panics, and Rust panics are never reported.
+///
+/// # Example
+///
+/// ```rust
+/// use lithium::{catch, is_unwinding, throw};
+///
+/// struct Check;
+///
+/// impl Drop for Check {
+///     fn drop(&mut self) {
+///         assert!(is_unwinding());
+///     }
+/// }
+///
+/// assert!(!is_unwinding());
+/// // SAFETY: Only `i32` is thrown.
+/// let result = unsafe {
+///     catch::<(), i32>(|| {
+///         let _check = Check;
+///         throw(1)
+///     })
+/// };
+/// assert_eq!(result, Err(1));
+/// assert!(!is_unwinding());
+/// ```
+#[inline]
+#[must_use]
+pub fn is_unwinding() -> bool {
+    // SAFETY: The reference is not used past the call frame.
+    unsafe { get_state() }.unwinding.get() > 0
+}
+
+/// Register an exception that starts unwinding.
+#[inline]
+pub fn begin_unwind() {
+    // SAFETY: The reference is not used past the call frame.
+    let unwinding = unsafe { &get_state().unwinding };
+    unwinding.set(unwinding.get().wrapping_add(1));
+}
+
+/// Register an exception caught by a Lithium catch frame.
+#[inline]
+pub fn end_unwind() {
+    // SAFETY: The reference is not used past the call frame.
+    let unwinding = unsafe { &get_state().unwinding };
+    unwinding.set(unwinding.get().wrapping_sub(1));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{catch, intercept, throw};
+
+    #[test]
+    fn not_unwinding() {
+        assert!(!is_unwinding());
+        let result = unsafe { catch::<i32, ()>(|| i32::from(is_unwinding())) };
+        assert_eq!(result, Ok(0));
+    }
+
+    #[test]
+    fn caught_is_not_unwinding() {
+        let (_, in_flight) = unsafe { intercept::<(), i32>(|| throw(1)) }.unwrap_err();
+        assert!(!is_unwinding());
+        drop(in_flight);
+    }
+
+    #[test]
+    fn nested_in_drop() {
+        struct Nested;
+
+        impl Drop for Nested {
+            fn drop(&mut self) {
+                assert!(is_unwinding());
+                let result = unsafe { catch::<(), i32>(|| throw(2)) };
+                assert_eq!(result, Err(2));
+                assert!(is_unwinding());
+            }
+        }
+
+        let result = unsafe {
+            catch::<(), i32>(|| {
+                let _nested = Nested;
+                throw(1)
+            })
+        };
+        assert_eq!(result, Err(1));
+        assert!(!is_unwinding());
+    }
+}